@@ -208,6 +208,10 @@ impl DirFileEntryData {
         self.size = size;
     }
 
+    fn set_attrs(&mut self, attrs: FileAttributes) {
+        self.attrs = attrs;
+    }
+
     pub(crate) fn is_dir(&self) -> bool {
         self.attrs.contains(FileAttributes::DIRECTORY)
     }
@@ -492,6 +496,16 @@ impl DirEntryEditor {
         }
     }
 
+    pub(crate) fn set_attributes(&mut self, attrs: FileAttributes) {
+        // entry type cannot be changed
+        let type_mask = FileAttributes::DIRECTORY | FileAttributes::VOLUME_ID;
+        let attrs = (attrs & !type_mask) | (self.data.attrs & type_mask);
+        if attrs != self.data.attrs {
+            self.data.set_attrs(attrs);
+            self.dirty = true;
+        }
+    }
+
     pub(crate) fn set_created(&mut self, date_time: DateTime) {
         if date_time != self.data.created() {
             self.data.set_created(date_time);
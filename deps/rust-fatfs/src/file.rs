@@ -1,7 +1,7 @@
 use core::cmp;
 use core::convert::TryFrom;
 
-use crate::dir_entry::DirEntryEditor;
+use crate::dir_entry::{DirEntryEditor, FileAttributes};
 use crate::error::Error;
 use crate::fs::{FileSystem, ReadWriteSeek};
 use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
@@ -179,6 +179,15 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         }
     }
 
+    /// Sets attributes of this file.
+    ///
+    /// `DIRECTORY` and `VOLUME_ID` bits are ignored. Changes are written on flush.
+    pub fn set_attributes(&mut self, attrs: FileAttributes) {
+        if let Some(ref mut e) = self.entry {
+            e.set_attributes(attrs);
+        }
+    }
+
     fn size(&self) -> Option<u32> {
         match self.entry {
             Some(ref e) => e.inner().size(),
@@ -12,6 +12,7 @@ MODE = "debug" #debug/release
 KERNEL_ELF = "target/"+TARGET+"/"+MODE+"/kernel"
 KERNEL_BIN = KERNEL_ELF+".bin"
 TRACE = False
+QEMU_MEM = "128M"
 
 def mode_update():
     global KERNEL_BIN
@@ -139,6 +140,7 @@ def qemu_run(self,debug):
     cmd = "qemu-system-riscv64\
                -machine virt \
               -nographic \
+              -m "+QEMU_MEM+" \
               -bios ../bootloader/new_opensbi.elf \
               -device loader,addr=0x80200000,file="+KERNEL_BIN+" " + \
     "-drive file=fs.img,if=none,format=raw,id=x0 -device virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0"
@@ -240,6 +242,7 @@ parser.add_argument("-p","--platform",help="use --show-platforms option to show
                                            "platform is qemu",default="qemu")
 parser.add_argument("--trace",help="trace kernel running info",action="store_true")
 parser.add_argument("--release",help="build release version",action="store_true")
+parser.add_argument("-m","--mem",help="qemu memory size, default 128M",default="128M")
 run_build_group.add_argument("-b","--build",help="build project",action="store_true")
 run_build_group.add_argument("-r","--run",help="run project",action="store_true")
 run_build_group.add_argument("--graph",help="run project with graph",action="store_true")
@@ -257,6 +260,8 @@ if platforms.get(platform) == None:
 if args.trace:
     TRACE = True
 
+QEMU_MEM = args.mem
+
 if args.release:
     MODE = "release"
     mode_update()
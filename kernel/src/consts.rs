@@ -32,11 +32,15 @@ pub const CPUS:usize = 2;
 // PF allocator
 pub const MAX_ORDER:usize = 11;
 pub const MAX_ORDER_NR_PAGES:usize = 1<<(MAX_ORDER-1);
+// 2MB大页
+pub const HUGE_PAGE_ORDER:usize = 9;
+pub const HUGE_PAGE_SIZE:usize = PAGE_SIZE<<HUGE_PAGE_ORDER;
 
 // boot stack
 pub const BOOT_STACK_NR_PAGES:usize = 8;
+// 修改时需要同步修改trap_asm.s中的栈溢出检查
 pub const KERNEL_STACK_SIZE_ORDER:usize = 3;
-pub const STACK_MAGIC:u64 = 0xABCD1122ABCD1122;
+pub const KERNEL_STACK_SIZE:usize = PAGE_SIZE<<KERNEL_STACK_SIZE_ORDER;
 
 // boot map
 pub const PHY_MEM_OFFSET:usize = 0xFFFFFFD800000000;
@@ -136,7 +136,8 @@ reset_regs:
 	ret
 
     .section .bss.stack
-    .align 12
+    # 和kernel stack一样按32KB对齐，trap_kern依赖这个对齐检查栈溢出
+    .align 15
 boot_stack:
     .space 4096 * 8 * 2
     .globl boot_stack_top
@@ -149,10 +150,12 @@ boot_stack_top:
     .section .data
     .align 12
     .global boot_pagetable
+# boot pagetable只在启动阶段使用RWX的1GB大页
+# mm_init中会按段重新设置内核镜像的权限，并去掉0x80000000的恒等映射
 boot_pagetable:
     #.quad (0 << 10) | 0xcf # VRWXAD
     .zero 8 * 1
-    .quad (0x40000 << 10) | 0xcf # VRWXAD
+    .quad (0x40000 << 10) | 0xc7 # VRWAD
     .quad (0x80000 << 10) | 0xcf # VRWXAD
     .zero 8 * 351
     .quad (0x80000 << 10) | 0xcf # VRWXAD
@@ -0,0 +1,174 @@
+// flattened device tree解析
+// 只在启动时解析内核需要的信息，之后dtb所在的物理页可能被buddy分配出去
+use crate::consts::PHY_MEM_OFFSET;
+use crate::{info_sync, SpinLock};
+
+const FDT_MAGIC:u32 = 0xd00dfeed;
+const FDT_BEGIN_NODE:u32 = 1;
+const FDT_END_NODE:u32 = 2;
+const FDT_PROP:u32 = 3;
+const FDT_NOP:u32 = 4;
+
+#[derive(Copy, Clone)]
+pub struct FdtInfo{
+    pub mem_start:usize,
+    pub mem_size:usize,
+    // goldfish rtc寄存器的物理地址，没有时为0
+    pub rtc_base:usize,
+    // plic寄存器的物理地址和大小
+    pub plic_base:usize,
+    pub plic_size:usize,
+    // ns16550串口寄存器的物理地址和中断号
+    pub uart_base:usize,
+    pub uart_irq:usize,
+}
+
+impl FdtInfo {
+    const fn empty()->Self{
+        FdtInfo{
+            mem_start: 0,
+            mem_size: 0,
+            rtc_base: 0,
+            plic_base: 0,
+            plic_size: 0,
+            uart_base: 0,
+            uart_irq: 0
+        }
+    }
+}
+
+lazy_static!{
+    static ref FDT_INFO:SpinLock<FdtInfo> = SpinLock::new(FdtInfo::empty());
+}
+
+unsafe fn be32(addr:usize)->u32{
+    u32::from_be((addr as *const u32).read_volatile())
+}
+
+// 属性值只保证4字节对齐
+unsafe fn be64(addr:usize)->u64{
+    ((be32(addr) as u64)<<32)|(be32(addr+4) as u64)
+}
+
+unsafe fn cstr_len(addr:usize)->usize{
+    let mut len = 0;
+    while *((addr+len) as *const u8) != 0 {
+        len+=1;
+    }
+    len
+}
+
+unsafe fn cstr_starts_with(addr:usize,s:&[u8])->bool{
+    for i in 0..s.len(){
+        if *((addr+i) as *const u8) != s[i] {
+            return false;
+        }
+    }
+    true
+}
+
+unsafe fn cstr_eq(addr:usize,s:&[u8])->bool{
+    cstr_starts_with(addr,s) && *((addr+s.len()) as *const u8) == 0
+}
+
+fn align4(v:usize)->usize{
+    (v+3)&!3
+}
+
+struct FdtProp{
+    depth:usize,
+    node_name:usize,
+    prop_name:usize,
+    value:usize,
+    len:usize
+}
+
+// 顺序遍历structure block，对每个属性调用f
+unsafe fn fdt_walk(base:usize,f:&mut dyn FnMut(&FdtProp)){
+    let off_struct = be32(base+8) as usize;
+    let off_strings = be32(base+12) as usize;
+    let strings = base+off_strings;
+    let mut p = base+off_struct;
+    let mut depth = 0usize;
+    // 只记录当前节点名
+    let mut node_name = 0usize;
+    loop {
+        let token = be32(p);
+        p+=4;
+        match token {
+            FDT_BEGIN_NODE => {
+                depth+=1;
+                node_name = p;
+                p = align4(p+cstr_len(p)+1);
+            }
+            FDT_END_NODE => {
+                depth-=1;
+            }
+            FDT_PROP => {
+                let len = be32(p) as usize;
+                let name_off = be32(p+4) as usize;
+                p+=8;
+                f(&FdtProp{
+                    depth,
+                    node_name,
+                    prop_name: strings+name_off,
+                    value: p,
+                    len
+                });
+                p = align4(p+len);
+            }
+            FDT_NOP => {}
+            _ => {
+                // FDT_END
+                break;
+            }
+        }
+    }
+}
+
+// dtb_paddr为sbi传入的物理地址
+pub fn fdt_init(dtb_paddr:usize){
+    if dtb_paddr == 0 {
+        return;
+    }
+    let base = dtb_paddr+PHY_MEM_OFFSET;
+    let mut info = FdtInfo::empty();
+    unsafe {
+        if be32(base) != FDT_MAGIC {
+            return;
+        }
+        fdt_walk(base,&mut |prop|{
+            // 默认#address-cells和#size-cells均为2
+            if prop.depth == 2 && cstr_starts_with(prop.node_name,b"memory")
+                && cstr_eq(prop.prop_name,b"reg") && prop.len>=16 && info.mem_size == 0 {
+                info.mem_start = be64(prop.value) as usize;
+                info.mem_size = be64(prop.value+8) as usize;
+            }
+            if cstr_starts_with(prop.node_name,b"rtc@") && cstr_eq(prop.prop_name,b"reg") && prop.len>=8 {
+                info.rtc_base = be64(prop.value) as usize;
+            }
+            // 较新的qemu把plic节点命名为interrupt-controller@，cpu节点下的同名节点没有地址
+            let is_plic = cstr_starts_with(prop.node_name,b"plic@")
+                || cstr_starts_with(prop.node_name,b"interrupt-controller@");
+            if is_plic && cstr_eq(prop.prop_name,b"reg") && prop.len>=16 {
+                info.plic_base = be64(prop.value) as usize;
+                info.plic_size = be64(prop.value+8) as usize;
+            }
+            // 只使用第一个串口
+            let is_uart = cstr_starts_with(prop.node_name,b"serial@") || cstr_starts_with(prop.node_name,b"uart@");
+            if is_uart && cstr_eq(prop.prop_name,b"reg") && prop.len>=8 && info.uart_base == 0 {
+                info.uart_base = be64(prop.value) as usize;
+            }
+            if is_uart && cstr_eq(prop.prop_name,b"interrupts") && prop.len>=4 && info.uart_irq == 0 {
+                info.uart_irq = be32(prop.value) as usize;
+            }
+        });
+    }
+    info_sync!("fdt: memory {:#X} size {:#X} rtc {:#X}",info.mem_start,info.mem_size,info.rtc_base);
+    info_sync!("fdt: plic {:#X} size {:#X} uart {:#X} irq {}",info.plic_base,info.plic_size,info.uart_base,info.uart_irq);
+    *FDT_INFO.lock().unwrap() = info;
+}
+
+pub fn get_fdt_info()->FdtInfo{
+    *FDT_INFO.lock().unwrap()
+}
@@ -0,0 +1,118 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::fs::inode::Inode;
+use crate::fs::superblock::Superblock;
+use crate::SpinLock;
+
+// 目录项缓存
+// 目录项保存在父inode的children中，这里按LRU顺序持有最近使用的目录项：
+// 正目录项持有inode的强引用，没有用户时inode仍留在children中
+// 负目录项记录查找失败的名字，淘汰时从父目录的children中删除
+const DCACHE_MAX:usize = 1024;
+
+// inode用地址标识，缓存持有引用期间地址不会被复用
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum DcacheKey{
+    Positive(usize),
+    Negative(usize,String),
+}
+
+// 负目录项不持有父目录，只记录父目录所在的文件系统
+enum DcacheEntry{
+    Positive(Arc<Inode>),
+    Negative(Weak<Inode>,Weak<Superblock>,String),
+}
+
+impl DcacheEntry {
+    fn in_sb(&self,sb:&Arc<Superblock>)->bool{
+        match self {
+            DcacheEntry::Positive(node) => Arc::ptr_eq(&node.get_sb(),sb),
+            DcacheEntry::Negative(_,s,_) => Weak::ptr_eq(s,&Arc::downgrade(sb))
+        }
+    }
+}
+
+struct Dcache{
+    seq:u64,
+    // 序号最小的最久未使用
+    lru:BTreeMap<u64,DcacheKey>,
+    entries:BTreeMap<DcacheKey,(u64,DcacheEntry)>,
+}
+
+impl Dcache {
+    fn touch(&mut self,key:DcacheKey,entry:DcacheEntry)->Vec<DcacheEntry>{
+        self.seq += 1;
+        let seq = self.seq;
+        if let Some((old,_)) = self.entries.insert(key.clone(),(seq,entry)) {
+            self.lru.remove(&old);
+        }
+        self.lru.insert(seq,key);
+        let mut evicted = Vec::new();
+        while self.entries.len() > DCACHE_MAX {
+            let seq = *self.lru.keys().next().unwrap();
+            let key = self.lru.remove(&seq).unwrap();
+            evicted.push(self.entries.remove(&key).unwrap().1);
+        }
+        evicted
+    }
+    fn remove(&mut self,key:&DcacheKey)->Option<DcacheEntry>{
+        let (seq,entry) = self.entries.remove(key)?;
+        self.lru.remove(&seq);
+        Some(entry)
+    }
+}
+
+lazy_static!{
+    static ref DCACHE:SpinLock<Dcache> = SpinLock::new(Dcache{
+        seq: 0,
+        lru: BTreeMap::new(),
+        entries: BTreeMap::new()
+    });
+}
+
+// 被淘汰的目录项在释放缓存锁之后处理，释放inode可能访问设备
+fn release(evicted:Vec<DcacheEntry>){
+    for e in evicted {
+        if let DcacheEntry::Negative(parent,_,name) = e {
+            if let Some(p) = parent.upgrade() {
+                p._forget_negative(&name);
+            }
+        }
+    }
+}
+
+fn node_key(node:&Arc<Inode>)->usize{
+    Arc::as_ptr(node) as usize
+}
+
+pub fn dcache_add(node:&Arc<Inode>){
+    let evicted = DCACHE.lock_irq().unwrap().touch(
+        DcacheKey::Positive(node_key(node)),DcacheEntry::Positive(node.clone()));
+    release(evicted);
+}
+
+pub fn dcache_add_negative(parent:&Arc<Inode>,name:&str){
+    let evicted = DCACHE.lock_irq().unwrap().touch(
+        DcacheKey::Negative(node_key(parent),name.to_string()),
+        DcacheEntry::Negative(Arc::downgrade(parent),Arc::downgrade(&parent.get_sb()),name.to_string()));
+    release(evicted);
+}
+
+// 删除或改名后不再保留旧的inode
+pub fn dcache_remove(node:&Arc<Inode>){
+    let entry = DCACHE.lock_irq().unwrap().remove(&DcacheKey::Positive(node_key(node)));
+    drop(entry);
+}
+
+// umount前释放该文件系统的所有目录项，否则缓存的inode会让挂载一直忙
+pub fn dcache_shrink_sb(sb:&Arc<Superblock>){
+    let mut dcache = DCACHE.lock_irq().unwrap();
+    let keys:Vec<DcacheKey> = dcache.entries.iter()
+        .filter(|(_,(_,e))| e.in_sb(sb))
+        .map(|(k,_)| k.clone()).collect();
+    let evicted:Vec<DcacheEntry> = keys.iter().filter_map(|k| dcache.remove(k)).collect();
+    drop(dcache);
+    release(evicted);
+}
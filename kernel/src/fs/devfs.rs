@@ -0,0 +1,172 @@
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use crate::fs::poll::{DEFAULT_POLLMASK, PollEvents};
+use crate::fs::superblock::SuperBlockOps;
+use crate::fs::vfs::{DirEntryInfo, FileOps, InodeAttr, InodeOps, InodeType};
+use crate::io::bcache::bcache_sync_dev;
+use crate::io::device::{blk_read_at, blk_write_at, Device, DeviceEntry, DevId, find_device, list_devices};
+use crate::syscall::errno::{EISDIR, ENOENT, ENOTDIR, ENOTTY};
+use crate::task::wait::Waiter;
+use crate::task::info::{S_IRGRP, S_IROTH, S_IRUSR, S_IRWXU, S_IWGRP, S_IWOTH, S_IWUSR, S_IXGRP, S_IXOTH};
+
+// 设备文件系统，根目录列出所有注册的设备
+// 另外有几个空目录作为其他文件系统的挂载点
+pub struct DevfsSuperBlock;
+
+const DEVFS_DIRS:[&str;2] = ["shm","pts"];
+
+impl SuperBlockOps for DevfsSuperBlock {
+    fn fs_type(&self) -> &'static str {
+        "devtmpfs"
+    }
+    // 设备随时可能注册
+    fn cache_negative(&self) -> bool {
+        false
+    }
+    fn root_inode(&self) -> Box<dyn InodeOps> {
+        Box::new(DevfsInode::Root)
+    }
+}
+
+pub enum DevfsInode{
+    Root,
+    Dir,
+    Dev(DeviceEntry),
+}
+
+impl InodeOps for DevfsInode {
+    fn get_type(&self) -> InodeType {
+        match self {
+            DevfsInode::Root|DevfsInode::Dir => InodeType::Dir,
+            DevfsInode::Dev(d) => {
+                match d.dev {
+                    Device::Char(_) => InodeType::CharDev,
+                    Device::Block(_) => InodeType::BlockDev,
+                }
+            }
+        }
+    }
+    fn getattr(&self) -> InodeAttr {
+        let mut attr = InodeAttr::new(self.get_type(),0);
+        attr.mode = match self {
+            DevfsInode::Root|DevfsInode::Dir => {
+                S_IRWXU | S_IRGRP | S_IXGRP | S_IROTH | S_IXOTH
+            }
+            DevfsInode::Dev(d) => {
+                match d.dev {
+                    Device::Char(_) => S_IRUSR | S_IWUSR | S_IRGRP | S_IWGRP | S_IROTH | S_IWOTH,
+                    Device::Block(_) => S_IRUSR | S_IWUSR | S_IRGRP | S_IWGRP,
+                }
+            }
+        };
+        attr
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn lookup(&self, name: &str) -> Result<Box<dyn InodeOps>, isize> {
+        match self {
+            DevfsInode::Root => {
+                if DEVFS_DIRS.contains(&name) {
+                    return Ok(Box::new(DevfsInode::Dir));
+                }
+                find_device(name).map(|d|{
+                    let ops:Box<dyn InodeOps> = Box::new(DevfsInode::Dev(d));
+                    ops
+                }).ok_or(-ENOENT)
+            }
+            DevfsInode::Dir => Err(-ENOENT),
+            DevfsInode::Dev(_) => Err(-ENOTDIR)
+        }
+    }
+    fn readdir(&self) -> Result<Vec<DirEntryInfo>, isize> {
+        match self {
+            DevfsInode::Root => {
+                let mut ret:Vec<DirEntryInfo> = DEVFS_DIRS.iter().map(|name| DirEntryInfo{
+                    name: name.to_string(),
+                    itype: InodeType::Dir
+                }).collect();
+                for d in list_devices() {
+                    ret.push(DirEntryInfo{
+                        itype: DevfsInode::Dev(d.clone()).get_type(),
+                        name: d.name
+                    });
+                }
+                Ok(ret)
+            }
+            DevfsInode::Dir => Ok(Vec::new()),
+            DevfsInode::Dev(_) => Err(-ENOTDIR)
+        }
+    }
+    fn file_ops(&self) -> Option<&dyn FileOps> {
+        match self {
+            DevfsInode::Dev(_) => Some(self),
+            _ => None
+        }
+    }
+    fn dev_id(&self) -> Option<DevId> {
+        match self {
+            DevfsInode::Dev(d) => Some(d.id),
+            _ => None
+        }
+    }
+}
+
+// 字符设备忽略偏移
+impl FileOps for DevfsInode {
+    fn read_at(&self, off: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        match self {
+            DevfsInode::Dev(d) => {
+                match &d.dev {
+                    Device::Char(c) => c.read(buf),
+                    Device::Block(b) => blk_read_at(b,off,buf)
+                }
+            }
+            _ => Err(-EISDIR)
+        }
+    }
+    fn write_at(&self, off: usize, buf: &[u8]) -> Result<usize, isize> {
+        match self {
+            DevfsInode::Dev(d) => {
+                match &d.dev {
+                    Device::Char(c) => c.write(buf),
+                    Device::Block(b) => blk_write_at(b,off,buf)
+                }
+            }
+            _ => Err(-EISDIR)
+        }
+    }
+    fn flush(&self) -> Result<(), isize> {
+        if let DevfsInode::Dev(d) = self {
+            if let Device::Block(b) = &d.dev {
+                return bcache_sync_dev(b);
+            }
+        }
+        Ok(())
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, isize> {
+        match self {
+            DevfsInode::Dev(d) => {
+                match &d.dev {
+                    Device::Char(c) => c.ioctl(cmd,arg),
+                    Device::Block(_) => Err(-ENOTTY)
+                }
+            }
+            _ => Err(-ENOTTY)
+        }
+    }
+    fn poll(&self, waiter:Option<&Arc<Waiter>>)->PollEvents{
+        match self {
+            DevfsInode::Dev(d) => {
+                match &d.dev {
+                    Device::Char(c) => c.poll(waiter),
+                    Device::Block(_) => DEFAULT_POLLMASK
+                }
+            }
+            _ => DEFAULT_POLLMASK
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use crate::fs::poll::PollEvents;
+use crate::fs::superblock::SuperBlockOps;
+use crate::fs::vfs::{DirEntryInfo, FileOps, InodeAttr, InodeOps, InodeType};
+use crate::io::device::DevId;
+use crate::io::pty::{find_pty, list_ptys, PTS_MAJOR};
+use crate::io::tty::Tty;
+use crate::syscall::errno::{EIO, ENOENT, ENOTDIR, ENOTTY};
+use crate::task::wait::Waiter;
+use crate::task::info::{S_IRGRP, S_IROTH, S_IRUSR, S_IRWXU, S_IWGRP, S_IWUSR, S_IXGRP, S_IXOTH};
+
+// 伪终端的slave，根目录下的名字是伪终端的编号
+// 通过open打开时由DFile换成PtySlave，这里的读写只用于没有经过open的访问
+pub struct DevptsSuperBlock;
+
+impl SuperBlockOps for DevptsSuperBlock {
+    fn fs_type(&self) -> &'static str {
+        "devpts"
+    }
+    // 伪终端随时创建和释放
+    fn cache_negative(&self) -> bool {
+        false
+    }
+    fn root_inode(&self) -> Box<dyn InodeOps> {
+        Box::new(DevptsInode::Root)
+    }
+}
+
+pub enum DevptsInode{
+    Root,
+    // master关闭后编号可能被重新分配，每次按编号查找
+    Slave(usize),
+}
+
+impl DevptsInode {
+    fn tty(&self)->Result<Arc<Tty>,isize>{
+        match self {
+            DevptsInode::Slave(i) => find_pty(*i).map(|p| p.get_tty()).ok_or(-EIO),
+            DevptsInode::Root => Err(-ENOTTY)
+        }
+    }
+}
+
+impl InodeOps for DevptsInode {
+    fn get_type(&self) -> InodeType {
+        match self {
+            DevptsInode::Root => InodeType::Dir,
+            DevptsInode::Slave(_) => InodeType::CharDev
+        }
+    }
+    // slave为crw--w----，属于打开ptmx的用户
+    fn getattr(&self) -> InodeAttr {
+        let mut attr = InodeAttr::new(self.get_type(),0);
+        match self {
+            DevptsInode::Root => {
+                attr.mode = S_IRWXU | S_IRGRP | S_IXGRP | S_IROTH | S_IXOTH;
+            }
+            DevptsInode::Slave(i) => {
+                attr.mode = S_IRUSR | S_IWUSR | S_IWGRP;
+                if let Some(p) = find_pty(*i) {
+                    let (uid,gid) = p.get_owner();
+                    attr.uid = uid;
+                    attr.gid = gid;
+                }
+            }
+        }
+        attr
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn lookup(&self, name: &str) -> Result<Box<dyn InodeOps>, isize> {
+        match self {
+            DevptsInode::Root => {
+                let index = name.parse::<usize>().map_err(|_| -ENOENT)?;
+                find_pty(index).ok_or(-ENOENT)?;
+                Ok(Box::new(DevptsInode::Slave(index)))
+            }
+            DevptsInode::Slave(_) => Err(-ENOTDIR)
+        }
+    }
+    fn readdir(&self) -> Result<Vec<DirEntryInfo>, isize> {
+        match self {
+            DevptsInode::Root => {
+                Ok(list_ptys().iter().map(|p| DirEntryInfo{
+                    name: p.get_index().to_string(),
+                    itype: InodeType::CharDev
+                }).collect())
+            }
+            DevptsInode::Slave(_) => Err(-ENOTDIR)
+        }
+    }
+    fn file_ops(&self) -> Option<&dyn FileOps> {
+        match self {
+            DevptsInode::Slave(_) => Some(self),
+            DevptsInode::Root => None
+        }
+    }
+    fn dev_id(&self) -> Option<DevId> {
+        match self {
+            DevptsInode::Slave(i) => Some(DevId::new(PTS_MAJOR,*i as u32)),
+            DevptsInode::Root => None
+        }
+    }
+}
+
+impl FileOps for DevptsInode {
+    fn read_at(&self, off: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        self.tty()?.read(buf,false)
+    }
+    fn write_at(&self, off: usize, buf: &[u8]) -> Result<usize, isize> {
+        self.tty()?.write(buf,false)
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, isize> {
+        self.tty()?.ioctl(cmd,arg)
+    }
+    fn poll(&self, waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        match self.tty() {
+            Ok(t) => t.poll(waiter),
+            Err(_) => PollEvents::POLLERR | PollEvents::POLLHUP
+        }
+    }
+}
@@ -1,20 +1,30 @@
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
-use core::cmp::min;
-use fatfs::{Date, DateTime, DefaultTimeProvider, Dir, File, FileAttributes, LossyOemCpConverter, Read, SeekFrom, Time, Write};
-use crate::{print, println, SpinLock};
+use alloc::vec;
+use fatfs::SeekFrom;
+use crate::SpinLock;
 use crate::consts::DIRECT_MAP_START;
 use crate::fs::dfile::DFILE_TYPE::*;
-use crate::fs::{DirAlias, FileAlias, get_dentry_from_dir};
 use crate::fs::dfile::DFileClass::ClassPipe;
-use crate::fs::fat::{BlkStorage, get_fatfs};
-use crate::fs::fcntl::OpenFlags;
+use crate::fs::fcntl::{OpenFlags, OpenMode};
 use crate::fs::inode::{Inode};
+use crate::fs::namei::{namei, namei_parent};
+use crate::fs::vfs::{DirEntryInfo, InodeType};
 use crate::fs::pipe::Pipe;
-use crate::io::virtio::VirtioDev;
-use crate::task::info::{NewStat, S_IFDIR, S_IFREG, S_IRWXG, S_IRWXO, S_IRWXU};
+use crate::fs::epoll::Epoll;
+use crate::fs::eventfd::EventFd;
+use crate::fs::signalfd::SignalFd;
+use crate::fs::timerfd::TimerFd;
+use crate::fs::poll::{DEFAULT_POLLMASK, PollEvents};
+use crate::io::chardev::CTTY_DEV;
+use crate::io::pty::{find_pty, PTMX_DEV, PTS_MAJOR, PtyMaster, PtySlave, session_pty};
+use crate::io::tty::console_tty;
+use crate::syscall::errno::{EBADF, EEXIST, EINVAL, EIO, EISDIR, ELOOP, ENOENT, ENOTDIR, ENOTTY, ENXIO, EPIPE, ESPIPE};
+use crate::task::info::{NewStat, S_IFCHR, S_IFIFO, S_IRGRP, S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR};
+use crate::task::cred::{current_cred, current_umask};
+use crate::task::signal::{send_signal_self, SIGPIPE};
 use crate::task::task::get_running;
-use crate::utils::{date2second, datetime2second};
+use crate::task::wait::Waiter;
 
 pub enum DFILE_TYPE{
     DFTYPE_STDIN,
@@ -24,45 +34,6 @@ pub enum DFILE_TYPE{
     DFTYPE_PIPE
 }
 
-pub struct DirEntryWrapper<'a> {
-    pub dir: Option<DirAlias<'a>>,
-    pub file: Option<FileAlias<'a>>,
-    pub attributes:FileAttributes,
-    pub accessd:Date,
-    pub created:DateTime,
-    pub modified:DateTime,
-    pub len:usize
-}
-
-impl<'a> Default for DirEntryWrapper<'a> {
-    fn default() -> Self {
-        DirEntryWrapper{
-            dir: None,
-            file: None,
-            attributes: Default::default(),
-            accessd: Date::new(1980,1,1),
-            created: DateTime::new(Date::new(1980,1,1),Time::new(0,0,0,0)),
-            modified: DateTime::new(Date::new(1980,1,1),Time::new(0,0,0,0)),
-            len:0
-        }
-    }
-}
-
-impl<'a> DirEntryWrapper<'a> {
-    pub fn is_dir(&self)->bool{
-        self.dir.is_some()
-    }
-    pub fn is_file(&self)->bool{
-        self.file.is_some()
-    }
-    pub fn to_dir(self)->DirAlias<'a>{
-        self.dir.unwrap()
-    }
-    pub fn to_file(self)->FileAlias<'a>{
-        self.file.unwrap()
-    }
-}
-
 #[derive(Clone)]
 pub enum TerminalType{
     STDIN,
@@ -70,37 +41,31 @@ pub enum TerminalType{
     STDERR
 }
 
-const TERMINAL_INTER_BUF_LEN:usize = 10;
+const DIRENT64_NAME_OFF:usize = 19;
 #[derive(Clone)]
 pub struct Terminal{
     ttype:TerminalType
 }
 
+// 标准输入输出都由控制台tty处理
 impl Terminal {
-    pub fn read(&mut self,buf:&[u8])->usize{
+    pub fn read(&mut self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
         match self.ttype {
             TerminalType::STDIN => {
-                0
+                console_tty().read(buf,nonblock)
             }
             _ => {
-                0
+                Ok(0)
             }
         }
     }
-    pub fn write(&mut self,buf:&[u8])->usize{
+    pub fn write(&mut self,buf:&[u8])->Result<usize,isize>{
         match self.ttype {
             TerminalType::STDOUT|TerminalType::STDERR => {
-                let buf_len = buf.len();
-                let mut i = 0;
-                while i<buf_len{
-                    let real_write = min(TERMINAL_INTER_BUF_LEN,buf_len-i);
-                    print!("{}",String::from_utf8_lossy(&buf[i..(i+real_write)]));
-                    i+=real_write;
-                }
-                buf_len
+                console_tty().write(buf,false)
             }
             _ => {
-                0
+                Ok(0)
             }
         }
     }
@@ -113,6 +78,15 @@ pub enum DFileClass{
     ClassInode(Arc<Inode>),
     ClassTerminal(Terminal),
     ClassPipe(Arc<Pipe>),
+    // 打开的命名FIFO，pipe在所有打开者之间共享
+    ClassFifo(Arc<Inode>,Arc<Pipe>),
+    ClassEpoll(Arc<Epoll>),
+    ClassEventFd(Arc<EventFd>),
+    ClassTimerFd(Arc<TimerFd>),
+    ClassSignalFd(Arc<SignalFd>),
+    ClassPtyMaster(Arc<PtyMaster>),
+    // 打开的伪终端slave，inode是/dev/pts/N或者/dev/tty
+    ClassPtySlave(Arc<Inode>,Arc<PtySlave>),
 }
 
 pub struct DFileMutInner{
@@ -133,9 +107,19 @@ impl DFileMutInner {
     pub fn writeable(&self)->bool{
         self.open_flags.writeable()
     }
+    pub fn nonblock(&self)->bool{
+        self.open_flags.contains(OpenFlags::O_NONBLOCK)
+    }
+    // 匿名pipe和FIFO的读写可能sleep，需要在DFile的锁外进行
+    fn clone_pipe(&self)->Option<Arc<Pipe>>{
+        match &self.class {
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => Some(p.clone()),
+            _ => None
+        }
+    }
     pub fn clone_inode(&self)->Option<Arc<Inode>>{
         match &self.class{
-            DFileClass::ClassInode(i) => {
+            DFileClass::ClassInode(i)|DFileClass::ClassFifo(i,_)|DFileClass::ClassPtySlave(i,_) => {
                 Some(i.clone())
             }
             _=>{
@@ -143,49 +127,79 @@ impl DFileMutInner {
             }
         }
     }
-    pub fn read(&mut self,buf:&mut [u8])->Result<usize,()>{
+    pub fn read(&mut self,buf:&mut [u8])->Result<usize,isize>{
         if !self.readable(){
-            return Err(());
+            return Err(-EBADF);
         }
         match &mut self.class {
             DFileClass::ClassInode(inode) => {
-                if inode.is_file(){
+                if inode.is_file() || inode.is_device(){
                     inode.read_off(buf,self.pos).map(|x|{
                         self.pos+=x;
                         x
-                    })
+                    }).map_err(|_| -EIO)
                 } else {
                     panic!("can`t read dir");
                 }
             }
             DFileClass::ClassTerminal(t) => {
-                Ok(t.read(buf))
+                t.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
             }
-            DFileClass::ClassPipe(p) => {
-                p.read_exact(buf)
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => {
+                p.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassEventFd(e) => {
+                e.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassTimerFd(t) => {
+                t.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassSignalFd(s) => {
+                s.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassPtyMaster(m) => {
+                m.read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassPtySlave(_,s) => {
+                s.get_tty().read(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassEpoll(_) => {
+                Err(-EINVAL)
             }
         }
     }
-    pub fn write(&mut self,buf:&[u8])->Result<usize,()> {
+    pub fn write(&mut self,buf:&[u8])->Result<usize,isize> {
         if !self.writeable(){
-            return Err(());
+            return Err(-EBADF);
         }
         match &mut self.class {
             DFileClass::ClassInode(inode) => {
-                if inode.is_file(){
+                if inode.is_file() || inode.is_device(){
                     inode.write_off(buf,self.pos).map(|x|{
                         self.pos+=x;
                         x
-                    })
+                    }).map_err(|_| -EIO)
                 } else {
                     panic!("can`t write dir");
                 }
             }
             DFileClass::ClassTerminal(t) => {
-                Ok(t.write(buf))
+                t.write(buf)
+            }
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => {
+                p.write(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassEventFd(e) => {
+                e.write(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
             }
-            DFileClass::ClassPipe(p)=>{
-                p.write_exact(buf)
+            DFileClass::ClassPtyMaster(m) => {
+                m.write(buf)
+            }
+            DFileClass::ClassPtySlave(_,s) => {
+                s.get_tty().write(buf,self.open_flags.contains(OpenFlags::O_NONBLOCK))
+            }
+            DFileClass::ClassEpoll(_)|DFileClass::ClassTimerFd(_)|DFileClass::ClassSignalFd(_) => {
+                Err(-EINVAL)
             }
         }
     }
@@ -225,142 +239,204 @@ impl DFileMutInner {
         }
         return Ok(buf_pos);
     }
-    pub fn seek(&mut self,pos:SeekFrom)->Result<usize,()>{
-        match &self.class{
-            DFileClass::ClassInode(inode) => {
-                if inode.is_file(){
-                    let max_pos = inode.get_dentry().len() as usize;
-                    match pos{
-                        SeekFrom::Start(v) => {
-                            if v<=(max_pos as u64){
-                                self.pos = v as usize;
-                                Ok(self.pos)
-                            }else{
-                                Err(())
-                            }
-                        }
-                        SeekFrom::End(v) => {
-                            match max_pos.checked_add_signed(v as isize){
-                                None => {
-                                    // overflow
-                                    Err(())
-                                }
-                                Some(s) => {
-                                    if s>max_pos{
-                                        Err(())
-                                    } else {
-                                        self.pos = s;
-                                        Ok(s as usize)
-                                    }
-                                }
-                            }
-                        }
-                        SeekFrom::Current(v) => {
-                            match self.pos.checked_add_signed(v as isize){
-                                None => {
-                                    // overflow
-                                    Err(())
-                                }
-                                Some(s) => {
-                                    if s>max_pos || s<0 {
-                                        Err(())
-                                    } else {
-                                        self.pos = s;
-                                        Ok(s as usize)
-                                    }
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    panic!("can`t seek dir");
-                }
+    // 普通文件可以seek到末尾之后，之后写入时中间是空洞
+    // 目录的位置是目录项的序号，设备和目录没有末尾
+    pub fn seek(&mut self,pos:SeekFrom)->Result<usize,isize>{
+        let inode = match &self.class {
+            DFileClass::ClassInode(inode) => inode.clone(),
+            _ => {
+                return Err(-ESPIPE);
             }
-            DFileClass::ClassTerminal(_) => {
-                Ok(0)
+        };
+        let new_pos = match pos {
+            SeekFrom::Start(v) => Some(v as usize),
+            SeekFrom::Current(v) => self.pos.checked_add_signed(v as isize),
+            SeekFrom::End(v) => {
+                if !inode.is_file() {
+                    return Err(-EINVAL);
+                }
+                inode.get_size().checked_add_signed(v as isize)
             }
-            DFileClass::ClassPipe(_) =>{
-                // not support seek pipe
-                todo!()
+        };
+        match new_pos {
+            Some(p) if p <= isize::MAX as usize => {
+                self.pos = p;
+                Ok(p)
             }
+            _ => Err(-EINVAL)
         }
     }
 }
 
 lazy_static!{
-    static ref root_dfile:Arc<DFile> = Arc::new(DFile {
-            inner: SpinLock::new(DFileMutInner {
-                class: DFileClass::ClassInode(Inode::get_root()),
-                pos: 0,
-                open_flags: OpenFlags::O_RDONLY,
-                cloexec:false
-            })
-        });
+    static ref root_dfile:Arc<DFile> = Arc::new(DFile::from_inner(DFileMutInner {
+        class: DFileClass::ClassInode(Inode::get_root()),
+        pos: 0,
+        open_flags: OpenFlags::O_RDONLY,
+        cloexec:false
+    }));
 }
 
 // Terminal类型不需要OpenFlags
 impl DFile {
+    // 打开的文件让inode所在的文件系统保持忙，drop时释放
+    fn from_inner(inner:DFileMutInner)->Self{
+        if let Some(i) = inner.clone_inode() {
+            i.get_sb().get_user();
+        }
+        Self{
+            inner: SpinLock::new(inner)
+        }
+    }
     pub fn clone_inode(&self)->Option<Arc<Inode>>{
         self.inner.lock_irq().unwrap().clone_inode()
     }
+    // /proc/<pid>/fd/<n>的链接目标
+    pub fn get_path(&self)->String{
+        match &self.inner.lock_irq().unwrap().class {
+            DFileClass::ClassInode(i) => i.get_path(),
+            DFileClass::ClassTerminal(_) => String::from("/dev/console"),
+            DFileClass::ClassPipe(p) => alloc::format!("pipe:[{}]",Arc::as_ptr(p) as usize),
+            DFileClass::ClassFifo(i,_) => i.get_path(),
+            DFileClass::ClassEpoll(_) => String::from("anon_inode:[eventpoll]"),
+            DFileClass::ClassEventFd(_) => String::from("anon_inode:[eventfd]"),
+            DFileClass::ClassTimerFd(_) => String::from("anon_inode:[timerfd]"),
+            DFileClass::ClassSignalFd(_) => String::from("anon_inode:[signalfd]"),
+            DFileClass::ClassPtyMaster(_) => String::from("/dev/ptmx"),
+            DFileClass::ClassPtySlave(i,_) => i.get_path(),
+        }
+    }
     // return (read_end,write_end)
-    pub fn new_pipe()->(Self,Self){
+    // flags中只有O_NONBLOCK和O_CLOEXEC有效
+    pub fn new_pipe(flags:OpenFlags)->(Self,Self){
         let p = Arc::new(Pipe::new());
+        p.inc_read();
         p.inc_write();
-        let read = Self{
-            inner: SpinLock::new(DFileMutInner{
-                class: ClassPipe(p.clone()),
-                pos: 0,
-                open_flags: OpenFlags::O_RDONLY,
-                cloexec: false
-            })
+        let nonblock = flags & OpenFlags::O_NONBLOCK;
+        let cloexec = flags.contains(OpenFlags::O_CLOEXEC);
+        let read = Self::from_inner(DFileMutInner{
+            class: ClassPipe(p.clone()),
+            pos: 0,
+            open_flags: OpenFlags::O_RDONLY | nonblock,
+            cloexec
+        });
+        let write = Self::from_inner(DFileMutInner{
+            class: ClassPipe(p.clone()),
+            pos: 0,
+            open_flags: OpenFlags::O_WRONLY | nonblock,
+            cloexec
+        });
+        (read,write)
+    }
+    // 没有inode的文件，flags中只有O_NONBLOCK和O_CLOEXEC有效
+    fn new_anon(class:DFileClass,open_flags:OpenFlags,flags:OpenFlags)->Self{
+        Self::from_inner(DFileMutInner{
+            class,
+            pos: 0,
+            open_flags: open_flags | (flags & OpenFlags::O_NONBLOCK),
+            cloexec: flags.contains(OpenFlags::O_CLOEXEC)
+        })
+    }
+    pub fn new_epoll(cloexec:bool)->Self{
+        let flags = if cloexec { OpenFlags::O_CLOEXEC } else { OpenFlags::empty() };
+        Self::new_anon(DFileClass::ClassEpoll(Epoll::new()),OpenFlags::O_RDONLY,flags)
+    }
+    pub fn new_eventfd(e:EventFd,flags:OpenFlags)->Self{
+        Self::new_anon(DFileClass::ClassEventFd(Arc::new(e)),OpenFlags::O_RDWR,flags)
+    }
+    pub fn new_timerfd(t:Arc<TimerFd>,flags:OpenFlags)->Self{
+        Self::new_anon(DFileClass::ClassTimerFd(t),OpenFlags::O_RDONLY,flags)
+    }
+    pub fn new_signalfd(s:SignalFd,flags:OpenFlags)->Self{
+        Self::new_anon(DFileClass::ClassSignalFd(Arc::new(s)),OpenFlags::O_RDONLY,flags)
+    }
+    // /dev/ptmx每次打开新建一个伪终端，slave和控制终端是伪终端的/dev/tty按伪终端打开
+    // 其他设备返回None
+    fn open_pty(node:&Arc<Inode>,open_flags:OpenFlags)->Result<Option<DFileClass>,isize>{
+        let id = match node.dev_id() {
+            Some(id) if node.get_type() == InodeType::CharDev => id,
+            _ => {
+                return Ok(None);
+            }
         };
-        let write = Self{
-            inner: SpinLock::new(DFileMutInner{
-                class: ClassPipe(p.clone()),
-                pos: 0,
-                open_flags: OpenFlags::O_WRONLY,
-                cloexec: false
-            })
+        let noctty = open_flags.contains(OpenFlags::O_NOCTTY);
+        let pty = if id == PTMX_DEV {
+            return Ok(Some(DFileClass::ClassPtyMaster(Arc::new(PtyMaster::new()?))));
+        } else if id.major == PTS_MAJOR {
+            find_pty(id.minor as usize).ok_or(-EIO)?
+        } else if id == CTTY_DEV {
+            let sid = get_running().lock_irq().unwrap().get_sid();
+            match session_pty(sid) {
+                Some(p) => p,
+                None => {
+                    return Ok(None);
+                }
+            }
+        } else {
+            return Ok(None);
         };
-        (read,write)
+        let slave = PtySlave::open(pty,noctty)?;
+        Ok(Some(DFileClass::ClassPtySlave(node.clone(),Arc::new(slave))))
+    }
+    // 阻塞打开时等待另一端也被打开，读写打开不等待
+    // O_NONBLOCK只写打开时没有reader返回ENXIO
+    fn open_fifo(node:Arc<Inode>,open_flags:OpenFlags)->Result<Self,isize>{
+        let p = node.get_fifo();
+        let nonblock = open_flags.contains(OpenFlags::O_NONBLOCK);
+        let (read,write) = (open_flags.readable(),open_flags.writeable());
+        if write && !read && nonblock && !p.have_reader() {
+            return Err(-ENXIO);
+        }
+        if read {
+            p.inc_read();
+        }
+        if write {
+            p.inc_write();
+        }
+        // 先建立DFile，计数由drop释放
+        let file = Self::from_inner(DFileMutInner{
+            class: DFileClass::ClassFifo(node,p.clone()),
+            pos: 0,
+            open_flags,
+            cloexec: open_flags.contains(OpenFlags::O_CLOEXEC)
+        });
+        if read && !write && !nonblock {
+            p.wait_for_writer();
+        } else if write && !read {
+            p.wait_for_reader();
+        }
+        Ok(file)
     }
 
     pub fn new_stdin() -> Self {
-        Self {
-            inner: SpinLock::new(DFileMutInner {
-                class: DFileClass::ClassTerminal(Terminal {
-                    ttype: TerminalType::STDIN
-                }),
-                pos: 0,
-                open_flags: OpenFlags::O_RDONLY,
-                cloexec: false
-            })
-        }
+        Self::from_inner(DFileMutInner {
+            class: DFileClass::ClassTerminal(Terminal {
+                ttype: TerminalType::STDIN
+            }),
+            pos: 0,
+            open_flags: OpenFlags::O_RDONLY,
+            cloexec: false
+        })
     }
     pub fn new_stdout() -> Self {
-        Self {
-            inner: SpinLock::new(DFileMutInner {
-                class: DFileClass::ClassTerminal(Terminal {
-                    ttype: TerminalType::STDOUT
-                }),
-                pos: 0,
-                open_flags: OpenFlags::O_WRONLY,
-                cloexec: false
-            })
-        }
+        Self::from_inner(DFileMutInner {
+            class: DFileClass::ClassTerminal(Terminal {
+                ttype: TerminalType::STDOUT
+            }),
+            pos: 0,
+            open_flags: OpenFlags::O_WRONLY,
+            cloexec: false
+        })
     }
     pub fn new_stderr() -> Self {
-        Self {
-            inner: SpinLock::new(DFileMutInner {
-                class: DFileClass::ClassTerminal(Terminal {
-                    ttype: TerminalType::STDERR
-                }),
-                pos: 0,
-                open_flags: OpenFlags::O_WRONLY,
-                cloexec: false
-            })
-        }
+        Self::from_inner(DFileMutInner {
+            class: DFileClass::ClassTerminal(Terminal {
+                ttype: TerminalType::STDERR
+            }),
+            pos: 0,
+            open_flags: OpenFlags::O_WRONLY,
+            cloexec: false
+        })
     }
     pub fn get_cloexec(&self)->bool{
         self.inner.lock_irq().unwrap().cloexec
@@ -389,29 +465,23 @@ impl DFile {
         root_dfile.clone()
     }
     pub fn from_inode(inode: Arc<Inode>, open_flags: OpenFlags) -> Self {
-        Self {
-            inner: SpinLock::new(DFileMutInner {
-                class: DFileClass::ClassInode(inode),
-                pos: 0,
-                open_flags,
-                cloexec: false
-            })
-        }
+        Self::from_inner(DFileMutInner {
+            class: DFileClass::ClassInode(inode),
+            pos: 0,
+            open_flags,
+            cloexec: open_flags.contains(OpenFlags::O_CLOEXEC)
+        })
     }
     pub fn open_name(&self, name: &str, open_flags: OpenFlags) -> Option<Self> {
         match &self.inner.lock_irq().unwrap().class {
             DFileClass::ClassInode(inode) => {
                 inode.get_sub_node(name).map(|x| {
-                    Self {
-                        inner: SpinLock::new(
-                            DFileMutInner {
-                                class: DFileClass::ClassInode(inode.clone()),
-                                pos: 0,
-                                open_flags,
-                                cloexec: false
-                            }
-                        )
-                    }
+                    Self::from_inner(DFileMutInner {
+                        class: DFileClass::ClassInode(inode.clone()),
+                        pos: 0,
+                        open_flags,
+                        cloexec: false
+                    })
                 })
             }
             DFileClass::ClassTerminal(_) => {
@@ -422,35 +492,155 @@ impl DFile {
             }
         }
     }
-    pub fn open_path(&self, path: &str, open_flags: OpenFlags) -> Option<Self> {
-        match &self.inner.lock_irq().unwrap().class {
-            DFileClass::ClassInode(inode) => {
-                inode.get_node_by_path(path).map(|x| {
-                    Self {
-                        inner: SpinLock::new(
-                            DFileMutInner {
-                                class: DFileClass::ClassInode(x.clone()),
-                                pos: 0,
-                                open_flags,
-                                cloexec: false
-                            }
-                        )
+    pub fn open_path(&self, path: &str, open_flags: OpenFlags, mode: OpenMode) -> Result<Self,isize> {
+        let dir = self.clone_inode().ok_or(-ENOTDIR)?;
+        Self::open_at(&dir,path,open_flags,mode)
+    }
+    // 相对于dir解析路径，O_CREATE时在已存在的父目录中以mode创建文件
+    // 新建的文件不检查权限，mode去掉umask
+    pub fn open_at(dir: &Arc<Inode>, path: &str, open_flags: OpenFlags, mode: OpenMode) -> Result<Self,isize> {
+        let follow = !open_flags.contains(OpenFlags::O_NOFOLLOW);
+        let cred = current_cred();
+        let mut created = false;
+        let node = if open_flags.contains(OpenFlags::O_CREATE) {
+            if path.ends_with('/') {
+                return Err(-EISDIR);
+            }
+            let (parent,name) = namei_parent(dir,path)?;
+            match namei(&parent,&name,follow) {
+                Ok(n) => {
+                    if open_flags.contains(OpenFlags::O_EXCL) {
+                        return Err(-EEXIST);
                     }
-                })
+                    n
+                }
+                Err(e) if e == -ENOENT => {
+                    parent.may_create(&cred)?;
+                    created = true;
+                    parent.create(&name,mode.bits() & !current_umask())?
+                }
+                Err(e) => return Err(e)
             }
-            DFileClass::ClassTerminal(_) => {
-                None
+        } else {
+            namei(dir,path,follow)?
+        };
+        if !created && node.get_type() != InodeType::SymLink {
+            node.permission(&cred,open_flags.may_mask())?;
+        }
+        if node.get_type() == InodeType::SymLink {
+            return Err(-ELOOP);
+        }
+        if open_flags.contains(OpenFlags::O_DIRECTROY) && !node.is_dir() {
+            return Err(-ENOTDIR);
+        }
+        if node.is_fifo() {
+            return Self::open_fifo(node,open_flags);
+        }
+        if let Some(class) = Self::open_pty(&node,open_flags)? {
+            return Ok(Self::new_anon(class,open_flags,open_flags));
+        }
+        if node.is_dir() && open_flags.writeable() {
+            return Err(-EISDIR);
+        }
+        if open_flags.contains(OpenFlags::O_TRUNC) && open_flags.writeable() && node.is_file() {
+            // 不支持truncate的文件系统保持原内容
+            let _ = node.truncate(0);
+        }
+        Ok(Self::from_inode(node,open_flags))
+    }
+    // 可能sleep的文件在锁外读写
+    pub fn read(&self,buf:&mut [u8])->Result<usize,isize>{
+        let mut inner = self.inner.lock_irq().unwrap();
+        if !inner.readable() {
+            return Err(-EBADF);
+        }
+        let nonblock = inner.nonblock();
+        match &inner.class {
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => {
+                let p = p.clone();
+                drop(inner);
+                p.read(buf,nonblock)
             }
-            _ => {
-                None
+            DFileClass::ClassEventFd(e) => {
+                let e = e.clone();
+                drop(inner);
+                e.read(buf,nonblock)
+            }
+            DFileClass::ClassTimerFd(t) => {
+                let t = t.clone();
+                drop(inner);
+                t.read(buf,nonblock)
+            }
+            DFileClass::ClassSignalFd(s) => {
+                let s = s.clone();
+                drop(inner);
+                s.read(buf,nonblock)
+            }
+            DFileClass::ClassTerminal(t) => {
+                let mut t = t.clone();
+                drop(inner);
+                t.read(buf,nonblock)
+            }
+            DFileClass::ClassPtyMaster(m) => {
+                let m = m.clone();
+                drop(inner);
+                m.read(buf,nonblock)
+            }
+            DFileClass::ClassPtySlave(_,s) => {
+                let tty = s.get_tty();
+                drop(inner);
+                tty.read(buf,nonblock)
+            }
+            // 字符设备没有偏移，读终端时可能sleep
+            DFileClass::ClassInode(i) if i.get_type() == InodeType::CharDev => {
+                let i = i.clone();
+                drop(inner);
+                i.read_off(buf,0).map_err(|_| -EIO)
+            }
+            _ => inner.read(buf)
+        }
+    }
+    // 写没有reader的pipe时进程收到SIGPIPE
+    pub fn write(&self,buf:&[u8])->Result<usize,isize>{
+        let mut inner = self.inner.lock_irq().unwrap();
+        if !inner.writeable() {
+            return Err(-EBADF);
+        }
+        let nonblock = inner.nonblock();
+        let ret = match &inner.class {
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => {
+                let p = p.clone();
+                drop(inner);
+                p.write(buf,nonblock)
+            }
+            DFileClass::ClassEventFd(e) => {
+                let e = e.clone();
+                drop(inner);
+                e.write(buf,nonblock)
             }
+            DFileClass::ClassPtySlave(_,s) => {
+                let tty = s.get_tty();
+                drop(inner);
+                tty.write(buf,nonblock)
+            }
+            _ => inner.write(buf)
+        };
+        if ret == Err(-EPIPE) {
+            send_signal_self(SIGPIPE);
         }
+        ret
+    }
+    // F_GETFL，不包括O_CLOEXEC
+    pub fn get_flags(&self)->OpenFlags{
+        self.inner.lock_irq().unwrap().open_flags - OpenFlags::O_CLOEXEC
     }
-    pub fn read(&self,buf:&mut [u8])->Result<usize,()>{
-        self.inner.lock_irq().unwrap().read(buf)
+    // F_SETFL只能修改O_NONBLOCK
+    pub fn set_nonblock(&self,nonblock:bool){
+        self.inner.lock_irq().unwrap().open_flags.set(OpenFlags::O_NONBLOCK,nonblock);
     }
-    pub fn write(&self,buf:&[u8])->Result<usize,()>{
-        self.inner.lock_irq().unwrap().write(buf)
+    // 匿名pipe或FIFO的pipe
+    pub fn get_pipe(&self)->Option<Arc<Pipe>>{
+        self.inner.lock_irq().unwrap().clone_pipe()
     }
     pub fn read_all(&self,buf:&mut [u8])->Result<usize,usize>{
         self.inner.lock_irq().unwrap().read_all(buf)
@@ -458,41 +648,220 @@ impl DFile {
     pub fn write_all(&self,buf:&[u8])->Result<usize,usize>{
         self.inner.lock_irq().unwrap().write_all(buf)
     }
-    pub fn seek(&self,pos:SeekFrom)->Result<usize,()> {
+    pub fn seek(&self,pos:SeekFrom)->Result<usize,isize> {
         self.inner.lock_irq().unwrap().seek(pos)
     }
+    pub fn get_epoll(&self)->Option<Arc<Epoll>>{
+        match &self.inner.lock_irq().unwrap().class {
+            DFileClass::ClassEpoll(ep) => Some(ep.clone()),
+            _ => None
+        }
+    }
+    pub fn get_timerfd(&self)->Option<Arc<TimerFd>>{
+        match &self.inner.lock_irq().unwrap().class {
+            DFileClass::ClassTimerFd(t) => Some(t.clone()),
+            _ => None
+        }
+    }
+    pub fn get_signalfd(&self)->Option<Arc<SignalFd>>{
+        match &self.inner.lock_irq().unwrap().class {
+            DFileClass::ClassSignalFd(s) => Some(s.clone()),
+            _ => None
+        }
+    }
+    // 普通文件和目录总是就绪，不能加入epoll
+    pub fn can_poll(&self)->bool{
+        match &self.inner.lock_irq().unwrap().class {
+            DFileClass::ClassInode(i) => i.is_device(),
+            _ => true
+        }
+    }
+    // 当前就绪的事件，waiter注册到状态变化时唤醒的等待队列
+    // 没有等待队列的文件总是可读写
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        let inner = self.inner.lock_irq().unwrap();
+        let (read,write) = (inner.readable(),inner.writeable());
+        if let Some(p) = inner.clone_pipe() {
+            drop(inner);
+            return p.poll(read,write,waiter);
+        }
+        match &inner.class {
+            DFileClass::ClassEpoll(ep) => {
+                let ep = ep.clone();
+                drop(inner);
+                ep.poll(waiter)
+            }
+            DFileClass::ClassEventFd(e) => e.poll(waiter),
+            DFileClass::ClassTimerFd(t) => t.poll(waiter),
+            DFileClass::ClassSignalFd(s) => s.poll(waiter),
+            DFileClass::ClassTerminal(_) => {
+                drop(inner);
+                console_tty().poll(waiter)
+            }
+            DFileClass::ClassPtyMaster(m) => m.poll(waiter),
+            DFileClass::ClassPtySlave(_,s) => s.get_tty().poll(waiter),
+            DFileClass::ClassInode(i) if i.is_device() => {
+                let i = i.clone();
+                drop(inner);
+                i.poll(waiter)
+            }
+            _ => DEFAULT_POLLMASK
+        }
+    }
+    // 指定位置读写的文件，pipe和终端不能指定位置
+    fn positional_inode(&self,write:bool)->Result<Arc<Inode>,isize>{
+        let inner = self.inner.lock_irq().unwrap();
+        let permitted = if write { inner.writeable() } else { inner.readable() };
+        if !permitted {
+            return Err(-EBADF);
+        }
+        if let DFileClass::ClassPtySlave(..) = inner.class {
+            return Err(-ESPIPE);
+        }
+        let inode = inner.clone_inode().ok_or(-ESPIPE)?;
+        if inode.is_dir() {
+            return Err(-EISDIR);
+        }
+        if inode.is_fifo() {
+            return Err(-ESPIPE);
+        }
+        Ok(inode)
+    }
+    // 不改变文件位置，也不持有DFile的锁
+    pub fn pread(&self,buf:&mut [u8],off:usize)->Result<usize,isize>{
+        let inode = self.positional_inode(false)?;
+        inode.read_off(buf,off).map_err(|_| -EIO)
+    }
+    pub fn pwrite(&self,buf:&[u8],off:usize)->Result<usize,isize>{
+        let inode = self.positional_inode(true)?;
+        inode.write_off(buf,off).map_err(|_| -EIO)
+    }
+    // ftruncate只能修改以写方式打开的普通文件
+    pub fn truncate(&self,len:usize)->Result<(),isize>{
+        let inode = match self.positional_inode(true) {
+            Ok(v) if v.is_file() => v,
+            _ => {
+                return Err(-EINVAL);
+            }
+        };
+        inode.truncate(len)
+    }
+    // pipe和终端没有需要写回的内容
+    pub fn fsync(&self)->Result<(),isize>{
+        self.clone_inode().ok_or(-EINVAL)?.fsync()
+    }
+    pub fn fallocate(&self,mode:u32,off:usize,len:usize)->Result<(),isize>{
+        let inode = self.positional_inode(true)?;
+        inode.fallocate(mode,off,len)
+    }
+    // 不持有锁调用驱动，驱动可能访问用户内存或者sleep
+    // 终端由控制台tty处理
+    pub fn ioctl(&self,cmd:usize,arg:usize)->Result<usize,isize>{
+        let inner = self.inner.lock_irq().unwrap();
+        match &inner.class {
+            DFileClass::ClassTerminal(_) => {
+                drop(inner);
+                console_tty().ioctl(cmd,arg)
+            }
+            DFileClass::ClassPtyMaster(m) => {
+                let m = m.clone();
+                drop(inner);
+                m.ioctl(cmd,arg)
+            }
+            DFileClass::ClassPtySlave(_,s) => {
+                let tty = s.get_tty();
+                drop(inner);
+                tty.ioctl(cmd,arg)
+            }
+            _ => {
+                let inode = inner.clone_inode().ok_or(-ENOTTY)?;
+                drop(inner);
+                inode.ioctl(cmd,arg)
+            }
+        }
+    }
+    // 从当前位置开始填充linux_dirent64，位置是目录项的序号，前两项是.和..
+    // 文件系统不提供目录项的inode号，d_ino使用序号
+    pub fn getdents(&self,buf:&mut [u8])->Result<usize,isize>{
+        let mut inner = self.inner.lock_irq().unwrap();
+        let inode = inner.clone_inode().ok_or(-ENOTDIR)?;
+        let mut entries = vec![
+            DirEntryInfo{ name: String::from("."), itype: InodeType::Dir },
+            DirEntryInfo{ name: String::from(".."), itype: InodeType::Dir },
+        ];
+        entries.append(&mut inode.readdir()?);
+        let mut len = 0;
+        while inner.pos < entries.len() {
+            let e = &entries[inner.pos];
+            // d_ino,d_off,d_reclen,d_type之后是以0结尾的名字，按8字节对齐
+            let reclen = (DIRENT64_NAME_OFF+e.name.len()+1+7)&!7;
+            if len+reclen > buf.len() {
+                if len == 0 {
+                    return Err(-EINVAL);
+                }
+                break;
+            }
+            let rec = &mut buf[len..len+reclen];
+            rec.fill(0);
+            rec[0..8].copy_from_slice(&(inner.pos as u64+1).to_ne_bytes());
+            rec[8..16].copy_from_slice(&(inner.pos as i64+1).to_ne_bytes());
+            rec[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+            rec[18] = e.itype.dirent_type();
+            rec[DIRENT64_NAME_OFF..DIRENT64_NAME_OFF+e.name.len()].copy_from_slice(e.name.as_bytes());
+            len += reclen;
+            inner.pos += 1;
+        }
+        Ok(len)
+    }
     pub fn fill_stat(&self,stat: &mut NewStat)->Result<(),()>{
+        let cred = current_cred();
         match &self.inner.lock_irq().unwrap().class {
-            DFileClass::ClassInode(inode) => {
-                if inode.get_parent().is_some() {
-                    let dentry = inode.get_dentry();
-                    let mode = if dentry.is_dir() {
-                        S_IFDIR | S_IRWXU | S_IRWXG | S_IRWXO
-                    } else {
-                        S_IFREG | S_IRWXU | S_IRWXG | S_IRWXO
-                    };
-                    // s_ino是 inode的指针地址减去偏移
-                    stat.fill_info(0,
-                                   (inode.as_ref() as *const Inode as usize - DIRECT_MAP_START) as u64,
-                                   mode,
-                                   1,
-                                   dentry.len() as i64,
-                                   date2second(dentry.accessed()) as i64,
-                                   datetime2second(dentry.modified()) as i64,
-                                   datetime2second(dentry.created()) as i64);
+            DFileClass::ClassInode(inode)|DFileClass::ClassFifo(inode,_)|DFileClass::ClassPtySlave(inode,_) => {
+                let attr = inode.getattr();
+                // 文件系统没有inode号时s_ino是 inode的指针地址减去偏移，根目录为1
+                let ino = if attr.ino != 0 {
+                    attr.ino
+                } else if inode.get_parent().is_some() {
+                    (inode.as_ref() as *const Inode as usize - DIRECT_MAP_START) as u64
                 } else {
-                    let mode = S_IFDIR | S_IRWXU | S_IRWXG | S_IRWXO;
-                    // s_ino是 inode的指针地址减去偏移
-                    stat.fill_info(0,
-                                   1,
-                                   mode,
-                                   1,
-                                   0,
-                                   1000,
-                                   1000,
-                                   1000
-                    )
-                }
+                    1
+                };
+                stat.fill_info(0,
+                               ino,
+                               attr.st_mode(),
+                               attr.nlink as u64,
+                               attr.uid,
+                               attr.gid,
+                               attr.size as i64,
+                               attr.atime as i64,
+                               attr.mtime as i64,
+                               attr.ctime as i64);
+            }
+            // 匿名pipe属于创建者，inode号使用pipe的地址
+            DFileClass::ClassPipe(p) => {
+                stat.fill_info(0,
+                               (Arc::as_ptr(p) as usize - DIRECT_MAP_START) as u64,
+                               S_IFIFO | S_IRUSR | S_IWUSR,
+                               1,
+                               cred.fsuid,
+                               cred.fsgid,
+                               0,
+                               0,
+                               0,
+                               0);
+            }
+            // master没有inode，按/dev/ptmx填充
+            DFileClass::ClassPtyMaster(m) => {
+                stat.fill_info(0,
+                               (Arc::as_ptr(m) as usize - DIRECT_MAP_START) as u64,
+                               S_IFCHR | S_IRUSR | S_IWUSR | S_IRGRP | S_IWGRP | S_IROTH | S_IWOTH,
+                               1,
+                               0,
+                               0,
+                               0,
+                               0,
+                               0,
+                               0);
             }
             _ => {
                 return Err(());
@@ -516,20 +885,48 @@ impl DFile {
                 DFileClass::ClassTerminal(terminal.clone())
             }
             ClassPipe(pipe) => {
+                if inner.readable(){
+                    pipe.inc_read();
+                }
                 if inner.writeable(){
                     pipe.inc_write();
                 }
                 DFileClass::ClassPipe(pipe.clone())
             }
+            DFileClass::ClassFifo(inode,pipe) => {
+                if inner.readable(){
+                    pipe.inc_read();
+                }
+                if inner.writeable(){
+                    pipe.inc_write();
+                }
+                DFileClass::ClassFifo(inode.clone(),pipe.clone())
+            }
+            DFileClass::ClassEpoll(ep) => {
+                DFileClass::ClassEpoll(ep.clone())
+            }
+            DFileClass::ClassEventFd(e) => {
+                DFileClass::ClassEventFd(e.clone())
+            }
+            DFileClass::ClassTimerFd(t) => {
+                DFileClass::ClassTimerFd(t.clone())
+            }
+            DFileClass::ClassSignalFd(s) => {
+                DFileClass::ClassSignalFd(s.clone())
+            }
+            DFileClass::ClassPtyMaster(m) => {
+                DFileClass::ClassPtyMaster(m.clone())
+            }
+            DFileClass::ClassPtySlave(inode,s) => {
+                DFileClass::ClassPtySlave(inode.clone(),s.clone())
+            }
         };
-        Self{
-            inner: SpinLock::new(DFileMutInner{
-                class: new_class,
-                pos: inner.pos,
-                open_flags: inner.open_flags,
-                cloexec: inner.cloexec
-            })
-        }
+        Self::from_inner(DFileMutInner{
+            class: new_class,
+            pos: inner.pos,
+            open_flags: inner.open_flags,
+            cloexec: inner.cloexec
+        })
     }
 }
 
@@ -538,12 +935,18 @@ impl Drop for DFile {
         //主要针对pipe
         let inner = self.inner.lock_irq().unwrap();
         match &inner.class {
-            DFileClass::ClassPipe(p) => {
+            DFileClass::ClassPipe(p)|DFileClass::ClassFifo(_,p) => {
+                if inner.readable() {
+                    p.dec_read();
+                }
                 if inner.writeable() {
                     p.dec_write();
                 }
             }
             _=>{}
         }
+        if let Some(i) = inner.clone_inode() {
+            i.get_sb().put_user();
+        }
     }
 }
\ No newline at end of file
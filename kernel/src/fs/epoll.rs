@@ -0,0 +1,188 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use crate::SpinLock;
+use crate::fs::dfile::DFile;
+use crate::fs::poll::PollEvents;
+use crate::syscall::errno::{EEXIST, EINVAL, ENOENT, EPERM};
+use crate::task::wait::{Waiter, WaitQueue};
+
+pub const EPOLL_CTL_ADD:usize = 1;
+pub const EPOLL_CTL_DEL:usize = 2;
+pub const EPOLL_CTL_MOD:usize = 3;
+
+pub const EPOLLEXCLUSIVE:u32 = 1 << 28;
+pub const EPOLLWAKEUP:u32 = 1 << 29;
+pub const EPOLLONESHOT:u32 = 1 << 30;
+pub const EPOLLET:u32 = 1 << 31;
+// 不是事件的控制位
+const EP_CTL_BITS:u32 = EPOLLEXCLUSIVE | EPOLLWAKEUP | EPOLLONESHOT | EPOLLET;
+
+// struct epoll_event，riscv64上不是packed，events之后有4字节填充
+#[derive(Copy, Clone, Default, Debug)]
+#[repr(C)]
+pub struct EpollEvent{
+    pub events:u32,
+    _pad:u32,
+    pub data:u64,
+}
+
+impl EpollEvent {
+    pub fn new(events:u32,data:u64)->Self{
+        Self{
+            events,
+            _pad: 0,
+            data
+        }
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8,core::mem::size_of::<Self>()) }
+    }
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8,core::mem::size_of::<Self>()) }
+    }
+}
+
+// 监视的一个fd，文件全部关闭后自动失效
+struct EpItem{
+    file:Weak<DFile>,
+    events:u32,
+    data:u64,
+    // 注册在文件的等待队列上，唤醒标志表示上次报告之后有新事件
+    waiter:Arc<Waiter>,
+}
+
+pub struct Epoll{
+    items:SpinLock<BTreeMap<usize,EpItem>>,
+    // epoll_wait中的task和监视这个epoll的waiter
+    wq:WaitQueue,
+}
+
+impl Epoll {
+    pub fn new()->Arc<Self>{
+        Arc::new(Self{
+            items: SpinLock::new(BTreeMap::new()),
+            wq: WaitQueue::new()
+        })
+    }
+    fn new_item(self:&Arc<Self>,file:&Arc<DFile>,ev:&EpollEvent)->EpItem{
+        let ep = Arc::downgrade(self);
+        let waiter = Waiter::with_notify(Box::new(move ||{
+            if let Some(ep) = ep.upgrade() {
+                ep.wq.wake_all();
+            }
+        }));
+        EpItem{
+            file: Arc::downgrade(file),
+            events: ev.events,
+            data: ev.data,
+            waiter
+        }
+    }
+    // 文件不支持poll时返回EPERM
+    pub fn ctl(self:&Arc<Self>,op:usize,fd:usize,file:&Arc<DFile>,ev:&EpollEvent)->Result<(),isize>{
+        if !file.can_poll() {
+            return Err(-EPERM);
+        }
+        let mut items = self.items.lock_irq().unwrap();
+        // fd关闭后留下的项
+        if items.get(&fd).map_or(false,|i| i.file.strong_count() == 0) {
+            items.remove(&fd);
+        }
+        let waiter = match op {
+            EPOLL_CTL_ADD => {
+                if items.contains_key(&fd) {
+                    return Err(-EEXIST);
+                }
+                let item = self.new_item(file,ev);
+                let waiter = item.waiter.clone();
+                items.insert(fd,item);
+                waiter
+            }
+            EPOLL_CTL_MOD => {
+                let item = items.get_mut(&fd).ok_or(-ENOENT)?;
+                if item.events & EPOLLEXCLUSIVE != 0 || ev.events & EPOLLEXCLUSIVE != 0 {
+                    return Err(-EINVAL);
+                }
+                item.events = ev.events;
+                item.data = ev.data;
+                item.waiter.clone()
+            }
+            EPOLL_CTL_DEL => {
+                items.remove(&fd).ok_or(-ENOENT)?;
+                return Ok(());
+            }
+            _ => {
+                return Err(-EINVAL);
+            }
+        };
+        drop(items);
+        // 注册到文件的等待队列，并按有新事件处理以报告当前状态
+        file.poll(Some(&waiter));
+        waiter.wake();
+        Ok(())
+    }
+    // 收集最多max个就绪的项，consume为false时不改变边沿触发和oneshot的状态
+    fn collect(&self,max:usize,consume:bool)->Vec<EpollEvent>{
+        let mut ret = Vec::new();
+        let mut items = self.items.lock_irq().unwrap();
+        items.retain(|_,i| i.file.strong_count() != 0);
+        for item in items.values_mut() {
+            if ret.len() >= max {
+                break;
+            }
+            let file = match item.file.upgrade() {
+                Some(f) => f,
+                None => {
+                    continue;
+                }
+            };
+            let wanted = PollEvents::from_bits_truncate(item.events & !EP_CTL_BITS) | PollEvents::always();
+            if item.events & EPOLLET != 0 && !item.waiter.is_woken() {
+                continue;
+            }
+            let events = file.poll(Some(&item.waiter)) & wanted;
+            if events.is_empty() {
+                continue;
+            }
+            if consume {
+                if item.events & EPOLLET != 0 {
+                    item.waiter.take_woken();
+                }
+                // oneshot报告一次后停用，直到EPOLL_CTL_MOD
+                if item.events & EPOLLONESHOT != 0 {
+                    item.events &= EP_CTL_BITS;
+                }
+            }
+            ret.push(EpollEvent::new(events.bits(),item.data));
+        }
+        ret
+    }
+    // deadline是开机以来的纳秒，None时一直等待
+    pub fn wait(&self,max:usize,deadline:Option<u64>)->Vec<EpollEvent>{
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.wq.add(&waiter);
+            let ret = self.collect(max,true);
+            if !ret.is_empty() {
+                return ret;
+            }
+            if waiter.sleep_until(deadline) {
+                return self.collect(max,true);
+            }
+        }
+    }
+    // 有就绪的项时epoll fd本身可读
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        if let Some(w) = waiter {
+            self.wq.add(w);
+        }
+        if self.collect(1,false).is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::POLLIN | PollEvents::POLLRDNORM
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::sync::Arc;
+use crate::{info_sync, SpinLock};
+use crate::fs::poll::PollEvents;
+use crate::syscall::errno::{EAGAIN, EINVAL};
+use crate::task::wait::{Waiter, WaitQueue};
+
+// eventfd2 flags，后两个与O_CLOEXEC和O_NONBLOCK相同
+pub const EFD_SEMAPHORE:u32 = 1;
+pub const EFD_NONBLOCK:u32 = 1 << 11;
+pub const EFD_CLOEXEC:u32 = 0o2000000;
+
+// 计数器的最大值
+const EFD_MAX:u64 = u64::MAX - 1;
+
+// 读写都以8字节的计数为单位
+pub struct EventFd {
+    count:SpinLock<u64>,
+    semaphore:bool,
+    // 计数变化时唤醒读写双方
+    wq:WaitQueue,
+}
+
+impl EventFd {
+    pub fn new(init:u64,semaphore:bool)->Self{
+        Self{
+            count: SpinLock::new(init),
+            semaphore,
+            wq: WaitQueue::new()
+        }
+    }
+    // 计数为0时等待，semaphore模式每次读出1
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
+        if buf.len() < 8 {
+            return Err(-EINVAL);
+        }
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.wq.add(&waiter);
+            let mut count = self.count.lock_irq().unwrap();
+            if *count != 0 {
+                let v = if self.semaphore { 1 } else { *count };
+                *count -= v;
+                drop(count);
+                buf[..8].copy_from_slice(&v.to_ne_bytes());
+                self.wq.wake_all();
+                return Ok(8);
+            }
+            drop(count);
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            waiter.sleep();
+        }
+    }
+    // 计数会超过EFD_MAX时等待
+    pub fn write(&self,buf:&[u8],nonblock:bool)->Result<usize,isize>{
+        if buf.len() < 8 {
+            return Err(-EINVAL);
+        }
+        let mut bytes = [0u8;8];
+        bytes.copy_from_slice(&buf[..8]);
+        let v = u64::from_ne_bytes(bytes);
+        if v == u64::MAX {
+            return Err(-EINVAL);
+        }
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.wq.add(&waiter);
+            let mut count = self.count.lock_irq().unwrap();
+            if EFD_MAX - *count >= v {
+                *count += v;
+                drop(count);
+                if v != 0 {
+                    self.wq.wake_all();
+                }
+                return Ok(8);
+            }
+            drop(count);
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            waiter.sleep();
+        }
+    }
+    // 计数非0时可读，还能加1时可写
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        if let Some(w) = waiter {
+            self.wq.add(w);
+        }
+        let count = *self.count.lock_irq().unwrap();
+        let mut events = PollEvents::empty();
+        if count != 0 {
+            events |= PollEvents::POLLIN | PollEvents::POLLRDNORM;
+        }
+        if count < EFD_MAX {
+            events |= PollEvents::POLLOUT | PollEvents::POLLWRNORM;
+        }
+        events
+    }
+}
+
+fn read_count(efd:&EventFd)->Result<u64,isize>{
+    let mut buf = [0u8;8];
+    efd.read(&mut buf,true)?;
+    Ok(u64::from_ne_bytes(buf))
+}
+
+// 只使用非阻塞读写
+pub fn eventfd_test(){
+    let efd = EventFd::new(3,false);
+    assert_eq!(efd.read(&mut [0u8;4],true),Err(-EINVAL));
+    assert_eq!(read_count(&efd),Ok(3));
+    assert_eq!(read_count(&efd),Err(-EAGAIN));
+    assert!(!efd.poll(None).contains(PollEvents::POLLIN));
+    assert_eq!(efd.write(&2u64.to_ne_bytes(),true),Ok(8));
+    assert_eq!(efd.write(&5u64.to_ne_bytes(),true),Ok(8));
+    assert_eq!(read_count(&efd),Ok(7));
+
+    // 计数不能超过EFD_MAX，u64::MAX本身不能写入
+    assert_eq!(efd.write(&u64::MAX.to_ne_bytes(),true),Err(-EINVAL));
+    assert_eq!(efd.write(&EFD_MAX.to_ne_bytes(),true),Ok(8));
+    assert_eq!(efd.write(&1u64.to_ne_bytes(),true),Err(-EAGAIN));
+    assert!(!efd.poll(None).contains(PollEvents::POLLOUT));
+    assert_eq!(read_count(&efd),Ok(EFD_MAX));
+
+    // semaphore模式每次读出1
+    let sem = EventFd::new(2,true);
+    assert_eq!(read_count(&sem),Ok(1));
+    assert!(sem.poll(None).contains(PollEvents::POLLIN));
+    assert_eq!(read_count(&sem),Ok(1));
+    assert_eq!(read_count(&sem),Err(-EAGAIN));
+    info_sync!("eventfd test OK!");
+}
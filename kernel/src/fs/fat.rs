@@ -1,66 +1,37 @@
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
 use core::cell::RefCell;
 use core::cmp::{max, min};
-use fatfs::{DefaultTimeProvider, FileSystem, FsOptions, IntoStorage, IoBase, LossyOemCpConverter, SeekFrom};
-use crate::{debug_sync, SpinLock, trace_sync};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use fatfs::{Date, DateTime, Error as FatError, FileAttributes, FileSystem, FsOptions, IntoStorage, IoBase, LossyOemCpConverter, Read, Seek, SeekFrom, TimeProvider, Write};
+use crate::{SpinLock, trace_sync};
 use crate::debug;
-use crate::fs::{FatDev, FatFs};
+use crate::fs::{DirAlias, DirEntryAlias, FatDev, FatFs, FileAlias};
+use crate::fs::superblock::SuperBlockOps;
+use crate::fs::vfs::{DirEntryInfo, FileOps, InodeAttr, InodeOps, InodeType, SetAttr};
+use crate::syscall::errno::{EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENAMETOOLONG, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EPERM, EXDEV};
+use crate::fs::fcntl::RENAME_EXCHANGE;
+use crate::fs::namei::PATH_MAX;
+use crate::trap::timer::get_realtime_sec;
+use crate::utils::{date2second, datetime2second, second2datetime};
 use crate::io::{ BlockReadWrite};
-use crate::io::sdcard::SDCardDev;
-use crate::io::virtio::VirtioDev;
-
-#[cfg(feature = "qemu")]
-lazy_static!{
-    static ref GLOBALFATFS:GlobalFatfs<VirtioDev> = GlobalFatfs{
-        inner: Arc::new(SpinLock::new(fatfs::FileSystem::new(VirtioDev::new(),FsOptions::new()).unwrap()))
-    };
-}
-
-#[cfg(feature = "k210")]
-lazy_static!{
-    static ref GLOBALFATFS:GlobalFatfs<SDCardDev> = GlobalFatfs{
-        inner: Arc::new(SpinLock::new(fatfs::FileSystem::new(SDCardDev::new(),FsOptions::new()).unwrap()))
-    };
-}
-
-struct GlobalFatfs<T:BlockReadWrite>{
-    inner : Arc<SpinLock<FileSystem<BlkStorage<T>,DefaultTimeProvider,LossyOemCpConverter>>>
-}
-
-unsafe impl<T:BlockReadWrite> Sync for GlobalFatfs<T> {}
-
-impl<T:BlockReadWrite> GlobalFatfs<T>{
-    fn get_fs(&self)->Arc<SpinLock<FileSystem<BlkStorage<T>,DefaultTimeProvider,LossyOemCpConverter>>>{
-        self.inner.clone()
-    }
-}
-
-#[cfg(feature = "qemu")]
-pub fn get_fatfs()->Arc<SpinLock<FileSystem<BlkStorage<VirtioDev>,DefaultTimeProvider,LossyOemCpConverter>>>{
-    GLOBALFATFS.get_fs()
-}
-
-#[cfg(feature = "k210")]
-pub fn get_fatfs()->Arc<SpinLock<FileSystem<BlkStorage<SDCardDev>,DefaultTimeProvider,LossyOemCpConverter>>>{
-    GLOBALFATFS.get_fs()
-}
+use crate::io::bcache::bcache_sync_dev;
+use crate::io::device::{blk_read_at, blk_write_at};
+use crate::mm::swap::NoReclaimGuard;
+use crate::sync::SpinLockGuard;
 
 pub fn fat_init(){
 
 }
 
-impl IntoStorage<BlkStorage<VirtioDev>> for VirtioDev {
-    fn into_storage(self) -> BlkStorage<VirtioDev> {
-        BlkStorage{
-            blk_dev: self,
-            pos:0
-        }
-    }
-}
-
-impl IntoStorage<BlkStorage<SDCardDev>> for SDCardDev {
-    fn into_storage(self) -> BlkStorage<SDCardDev> {
+impl IntoStorage<BlkStorage<FatDev>> for FatDev {
+    fn into_storage(self) -> BlkStorage<FatDev> {
         BlkStorage{
             blk_dev: self,
             pos:0
@@ -79,55 +50,24 @@ impl<T:BlockReadWrite> BlkStorage<T> {
 
 impl<T:BlockReadWrite> IoBase for BlkStorage<T> { type Error = (); }
 
-impl<T:BlockReadWrite> fatfs::Read for BlkStorage<T>{
+// 经过块缓存读写，不对齐的写入不再每次读出整个扇区
+impl fatfs::Read for BlkStorage<FatDev>{
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut pos = self.pos as usize;
-        let read_max = pos+buf.len();
-        let mut read_pos:usize = 0;
-        while pos < read_max {
-            let b_no = (pos/512) as usize;
-            let b_off = (pos%512) as usize;
-            let mut _read_buf = [0 as u8;512];
-            self.blk_dev.read_block(b_no,&mut _read_buf);
-            let read_res = read_max-pos;
-            let real_read_len = min(read_res,512-b_off);
-            buf[read_pos..read_pos+real_read_len].copy_from_slice(&_read_buf[b_off..b_off+real_read_len]);
-            pos+=real_read_len;
-            read_pos+=real_read_len;
-        }
-        self.pos += (buf.len()) as u64;
-        Ok(buf.len())
-    }
-}
-
-impl<T:BlockReadWrite> fatfs::Write for BlkStorage<T> {
+        let len = blk_read_at(&self.blk_dev,self.pos as usize,buf).map_err(|_| ())?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl fatfs::Write for BlkStorage<FatDev> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        let mut pos = self.pos as usize;
-        let write_max = pos+buf.len();
-        let mut write_pos:usize = 0;
-        debug_sync!("write block {}, off {}, len {}",(pos/512),(pos%512),buf.len());
-        while pos < write_max {
-            let b_no = (pos/512) as usize;
-            let b_off = (pos%512) as usize;
-
-            let mut _read_buf = [0 as u8;512];
-            self.blk_dev.read_block(b_no,&mut _read_buf);
-            let write_res = write_max -pos;
-            let mut real_write_len = min(write_res, 512-b_off);
-            _read_buf[b_off..b_off+real_write_len].copy_from_slice(
-                &buf[write_pos..write_pos+real_write_len]
-            );
-            //write back
-            self.blk_dev.write_block(b_no,& _read_buf);
-            pos+= real_write_len;
-            write_pos += real_write_len;
-        }
-        self.pos += (buf.len()) as u64;
-        Ok(buf.len())
+        let len = blk_write_at(&self.blk_dev,self.pos as usize,buf).map_err(|_| ())?;
+        self.pos += len as u64;
+        Ok(len)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        bcache_sync_dev(&self.blk_dev).map_err(|_| ())
     }
 }
 
@@ -149,6 +89,647 @@ impl<T:BlockReadWrite> fatfs::Seek for BlkStorage<T> {
     }
 }
 
-pub fn new_fat_fs()->FatFs{
-    fatfs::FileSystem::new(FatDev::new(),FsOptions::new()).unwrap()
-}
\ No newline at end of file
+// fatfs创建和修改文件时取墙上时间
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelTimeProvider;
+
+impl TimeProvider for KernelTimeProvider {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+    fn get_current_date_time(&self) -> DateTime {
+        second2datetime(get_realtime_sec())
+    }
+}
+
+// 设备上不是FAT文件系统时返回EINVAL
+// 访问日期只在日期变化时写回目录项
+pub fn new_fat_fs(dev:FatDev)->Result<FatFs,isize>{
+    let options = FsOptions::new()
+        .time_provider(KernelTimeProvider)
+        .update_accessed_date(true);
+    fatfs::FileSystem::new(dev,options).map_err(|_| -EINVAL)
+}
+
+// FAT没有属主和权限，由挂载选项给出
+// 支持uid=,gid=和八进制的umask=,fmask=,dmask=
+#[derive(Copy, Clone)]
+struct FatMountOpts{
+    uid:u32,
+    gid:u32,
+    fmask:u32,
+    dmask:u32,
+}
+
+impl FatMountOpts {
+    fn parse(data:&str)->Result<Self,isize>{
+        let mut opts = Self{
+            uid: 0,
+            gid: 0,
+            fmask: 0o022,
+            dmask: 0o022
+        };
+        for opt in data.split(',').filter(|x| !x.is_empty()) {
+            let (key,v) = opt.split_once('=').ok_or(-EINVAL)?;
+            let dec = || v.parse::<u32>().map_err(|_| -EINVAL);
+            let oct = || u32::from_str_radix(v,8).map(|m| m & 0o777).map_err(|_| -EINVAL);
+            match key {
+                "uid" => opts.uid = dec()?,
+                "gid" => opts.gid = dec()?,
+                "umask" => {
+                    opts.fmask = oct()?;
+                    opts.dmask = opts.fmask;
+                }
+                "fmask" => opts.fmask = oct()?,
+                "dmask" => opts.dmask = oct()?,
+                _ => {
+                    return Err(-EINVAL);
+                }
+            }
+        }
+        Ok(opts)
+    }
+}
+
+// FAT文件系统，superblock和所有inode共享
+// Dir和File借用fs，生命周期写成'static：FatShared在Arc中不会移动，
+// 借用fs的FatNode只保存在持有这个Arc的FatInode中，并且在FatInode::drop中先于Arc释放
+struct FatShared{
+    fs:ManuallyDrop<FatFs>,
+    dev:FatDev,
+    opts:FatMountOpts,
+    // FatFs内部使用RefCell，对fs的所有访问(包括Dir和File的drop)都在这个锁内
+    lock:SpinLock<()>,
+}
+
+// fs只在lock内访问
+unsafe impl Send for FatShared {}
+unsafe impl Sync for FatShared {}
+
+impl FatShared {
+    fn root_dir(&self)->DirAlias<'static>{
+        let fs:&'static FatFs = unsafe { &*(&*self.fs as *const FatFs) };
+        fs.root_dir()
+    }
+}
+
+// 最后一个inode释放后才会drop，此时没有Dir和File借用fs
+impl Drop for FatShared {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.fs); }
+        // 卸载时fatfs写回的fs info还在块缓存中
+        let _ = bcache_sync_dev(&self.dev);
+    }
+}
+
+pub struct FatSuperBlock{
+    sb:Arc<FatShared>,
+}
+
+impl FatSuperBlock {
+    // data是挂载选项
+    pub fn new(dev:FatDev,data:&str)->Result<Self,isize>{
+        let opts = FatMountOpts::parse(data)?;
+        let fs = new_fat_fs(dev.clone())?;
+        Ok(Self{
+            sb: Arc::new(FatShared{
+                fs: ManuallyDrop::new(fs),
+                dev,
+                opts,
+                lock: SpinLock::new(())
+            })
+        })
+    }
+}
+
+impl SuperBlockOps for FatSuperBlock {
+    fn fs_type(&self) -> &'static str {
+        "vfat"
+    }
+    // 文件的数据和目录项都直接写入块缓存
+    fn sync(&self) -> Result<(), isize> {
+        bcache_sync_dev(&self.sb.dev)
+    }
+    fn root_inode(&self) -> Box<dyn InodeOps> {
+        // 根目录没有目录项，时间为0
+        let _fs = self.sb.lock.lock_irq().unwrap();
+        Box::new(FatInode::new(FatNode::Dir(self.sb.root_dir()),FatTimes::default(),self.sb.clone()))
+    }
+}
+
+// FAT上的符号链接是带SYSTEM属性的普通文件，内容为SYMLINK_MAGIC加上目标路径
+// 与cygwin旧格式的符号链接相同，没有这个前缀的系统文件仍然是普通文件
+const SYMLINK_MAGIC:&[u8] = b"!<symlink>";
+
+enum FatNode{
+    Dir(DirAlias<'static>),
+    File(FileAlias<'static>),
+    SymLink(FileAlias<'static>),
+}
+
+// 链接的内容不超过前缀加上PATH_MAX，更大的系统文件不用读取
+fn may_be_symlink(dentry:&DirEntryAlias<'static>)->bool{
+    let len = dentry.len() as usize;
+    dentry.is_file() && dentry.attributes().contains(FileAttributes::SYSTEM) &&
+        len >= SYMLINK_MAGIC.len() && len <= SYMLINK_MAGIC.len()+PATH_MAX
+}
+
+fn read_symlink_magic(dentry:&DirEntryAlias<'static>)->bool{
+    let mut magic = [0u8;SYMLINK_MAGIC.len()];
+    dentry.to_file().read_exact(&mut magic).is_ok() && magic == SYMLINK_MAGIC
+}
+
+// 访问时间只精确到日期，修改时间精确到2秒
+// FAT没有ctime，与linux的vfat一样使用修改时间
+#[derive(Copy, Clone, Default)]
+struct FatTimes{
+    atime:u64,
+    mtime:u64,
+}
+
+impl FatTimes {
+    fn now()->Self{
+        let now = get_realtime_sec();
+        Self{
+            atime: fat_atime(now),
+            mtime: fat_mtime(now)
+        }
+    }
+}
+
+fn fat_atime(s:u64)->u64{
+    date2second(second2datetime(s).date)
+}
+
+fn fat_mtime(s:u64)->u64{
+    datetime2second(second2datetime(s))&!1
+}
+
+// FAT中没有inode，时间在lookup时从目录项中取得，之后由读写更新
+pub struct FatInode{
+    // 在drop中持有fs锁释放
+    node:ManuallyDrop<SpinLock<FatNode>>,
+    times:SpinLock<FatTimes>,
+    // 目录中的系统文件是否为符号链接，按名字缓存读到的前缀，大小或修改时间变化时重新读
+    links:SpinLock<BTreeMap<String,(u64,u64,bool)>>,
+    sb:Arc<FatShared>,
+}
+
+// 按节点锁、fs锁的顺序释放，最后才允许回收
+struct FatNodeGuard<'a>{
+    node:SpinLockGuard<'a,FatNode>,
+    _fs:SpinLockGuard<'a,()>,
+    _noio:NoReclaimGuard,
+}
+
+impl Deref for FatNodeGuard<'_> {
+    type Target = FatNode;
+    fn deref(&self) -> &FatNode {
+        &self.node
+    }
+}
+
+impl DerefMut for FatNodeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut FatNode {
+        &mut self.node
+    }
+}
+
+// node借用的fs只在sb.lock内访问，见FatShared
+unsafe impl Send for FatInode {}
+unsafe impl Sync for FatInode {}
+
+impl FatInode {
+    fn new(node:FatNode,times:FatTimes,sb:Arc<FatShared>)->Self{
+        Self{
+            node: ManuallyDrop::new(SpinLock::new(node)),
+            times: SpinLock::new(times),
+            links: SpinLock::new(BTreeMap::new()),
+            sb
+        }
+    }
+    fn from_dentry(dentry:&DirEntryAlias<'static>,is_link:bool,sb:Arc<FatShared>)->Self{
+        let node = if dentry.is_dir() {
+            FatNode::Dir(dentry.to_dir())
+        } else if is_link {
+            FatNode::SymLink(dentry.to_file())
+        } else {
+            FatNode::File(dentry.to_file())
+        };
+        Self::new(node,FatTimes{
+            atime: date2second(dentry.accessed()),
+            mtime: datetime2second(dentry.modified())
+        },sb)
+    }
+    // fatfs操作都在fs锁和节点锁内进行，期间分配页不能换出到swap文件
+    fn lock_node(&self)->FatNodeGuard<'_>{
+        let noio = NoReclaimGuard::new();
+        let fs = self.sb.lock.lock_irq().unwrap();
+        FatNodeGuard{
+            node: self.node.lock_irq().unwrap(),
+            _fs: fs,
+            _noio: noio
+        }
+    }
+    // 在目录的节点锁内调用
+    fn is_symlink_entry(&self,dentry:&DirEntryAlias<'static>)->bool{
+        if !may_be_symlink(dentry) {
+            return false;
+        }
+        let name = dentry.file_name();
+        let stamp = (dentry.len(),datetime2second(dentry.modified()));
+        if let Some((len,mtime,link)) = self.links.lock_irq().unwrap().get(&name) {
+            if (*len,*mtime) == stamp {
+                return *link;
+            }
+        }
+        let link = read_symlink_magic(dentry);
+        self.links.lock_irq().unwrap().insert(name,(stamp.0,stamp.1,link));
+        link
+    }
+    // 名字被删除或者指向了新的文件
+    fn forget_link(&self,name:&str){
+        self.links.lock_irq().unwrap().remove(name);
+    }
+    // 与fatfs写目录项时取的时间一致
+    fn touch_mtime(&self){
+        self.times.lock_irq().unwrap().mtime = fat_mtime(get_realtime_sec());
+    }
+}
+
+// File在drop时会写回目录项，需要在fs锁内释放，并且先于sb
+impl Drop for FatInode {
+    fn drop(&mut self) {
+        let _noio = NoReclaimGuard::new();
+        let _fs = self.sb.lock.lock_irq().unwrap();
+        unsafe { ManuallyDrop::drop(&mut self.node); }
+    }
+}
+
+impl InodeOps for FatInode {
+    fn get_type(&self) -> InodeType {
+        match &*self.lock_node() {
+            FatNode::Dir(_) => InodeType::Dir,
+            FatNode::File(_) => InodeType::File,
+            FatNode::SymLink(_) => InodeType::SymLink,
+        }
+    }
+    fn getattr(&self) -> InodeAttr {
+        let mut lock = self.lock_node();
+        let mut attr = match &mut *lock {
+            FatNode::Dir(_) => InodeAttr::new(InodeType::Dir,0),
+            FatNode::File(f) => {
+                // 读写前都会重新seek，这里可以修改文件位置
+                let size = f.seek(SeekFrom::End(0)).unwrap_or(0);
+                InodeAttr::new(InodeType::File,size as usize)
+            }
+            // 长度是目标路径的长度
+            FatNode::SymLink(f) => {
+                let size = f.seek(SeekFrom::End(0)).unwrap_or(0) as usize;
+                InodeAttr::new(InodeType::SymLink,size.saturating_sub(SYMLINK_MAGIC.len()))
+            }
+        };
+        drop(lock);
+        let times = *self.times.lock_irq().unwrap();
+        attr.atime = times.atime;
+        attr.mtime = times.mtime;
+        attr.ctime = times.mtime;
+        let opts = &self.sb.opts;
+        attr.uid = opts.uid;
+        attr.gid = opts.gid;
+        attr.mode = 0o777 & !match attr.itype {
+            InodeType::Dir => opts.dmask,
+            InodeType::SymLink => 0,
+            _ => opts.fmask
+        };
+        attr
+    }
+    // 只支持修改时间，目录的时间fatfs不能修改，只保存在内存中
+    // 属主和权限不能修改，与当前值相同时忽略
+    #[allow(deprecated)]
+    fn setattr(&self, attr: &SetAttr) -> Result<(), isize> {
+        let cur = self.getattr();
+        if attr.mode.map_or(false,|m| m != cur.mode) ||
+            attr.uid.map_or(false,|u| u != cur.uid) ||
+            attr.gid.map_or(false,|g| g != cur.gid) {
+            return Err(-EPERM);
+        }
+        let mut times = *self.times.lock_irq().unwrap();
+        if let Some(atime) = attr.atime {
+            times.atime = fat_atime(atime);
+        }
+        if let Some(mtime) = attr.mtime {
+            times.mtime = fat_mtime(mtime);
+        }
+        if let FatNode::File(f) | FatNode::SymLink(f) = &mut *self.lock_node() {
+            if attr.atime.is_some() {
+                f.set_accessed(second2datetime(times.atime).date);
+            }
+            if attr.mtime.is_some() {
+                f.set_modified(second2datetime(times.mtime));
+            }
+            f.flush().map_err(fat_errno)?;
+        }
+        *self.times.lock_irq().unwrap() = times;
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn lookup(&self, name: &str) -> Result<Box<dyn InodeOps>, isize> {
+        let lock = self.lock_node();
+        let dir = match &*lock {
+            FatNode::Dir(d) => d,
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        for item in dir.iter() {
+            let dentry = item.map_err(|_| -EIO)?;
+            // 跳过卷标
+            if (dentry.is_dir() || dentry.is_file()) && dentry.file_name().eq(name) {
+                let is_link = self.is_symlink_entry(&dentry);
+                return Ok(Box::new(FatInode::from_dentry(&dentry,is_link,self.sb.clone())));
+            }
+        }
+        Err(-ENOENT)
+    }
+    fn readdir(&self) -> Result<Vec<DirEntryInfo>, isize> {
+        let lock = self.lock_node();
+        let dir = match &*lock {
+            FatNode::Dir(d) => d,
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        let mut ret = Vec::new();
+        for item in dir.iter() {
+            let dentry = item.map_err(|_| -EIO)?;
+            let name = dentry.file_name();
+            if name.eq(".") || name.eq("..") || !(dentry.is_dir() || dentry.is_file()) {
+                continue;
+            }
+            let itype = if dentry.is_dir() {
+                InodeType::Dir
+            } else if self.is_symlink_entry(&dentry) {
+                InodeType::SymLink
+            } else {
+                InodeType::File
+            };
+            ret.push(DirEntryInfo{
+                name,
+                itype
+            });
+        }
+        Ok(ret)
+    }
+    fn create(&self, name: &str, mode: u32) -> Result<Box<dyn InodeOps>, isize> {
+        let lock = self.lock_node();
+        let dir = as_dir(&lock)?;
+        // create_file会打开已存在的文件
+        if find_entry(dir,name)?.is_some() {
+            return Err(-EEXIST);
+        }
+        let new_file = dir.create_file(name).map_err(fat_errno)?;
+        Ok(Box::new(FatInode::new(FatNode::File(new_file),FatTimes::now(),self.sb.clone())))
+    }
+    fn mkdir(&self, name: &str, mode: u32) -> Result<Box<dyn InodeOps>, isize> {
+        let lock = self.lock_node();
+        let dir = as_dir(&lock)?;
+        // create_dir对已存在的目录也会成功
+        if find_entry(dir,name)?.is_some() {
+            return Err(-EEXIST);
+        }
+        let new_dir = dir.create_dir(name).map_err(fat_errno)?;
+        Ok(Box::new(FatInode::new(FatNode::Dir(new_dir),FatTimes::now(),self.sb.clone())))
+    }
+    fn unlink(&self, name: &str) -> Result<(), isize> {
+        let lock = self.lock_node();
+        let dir = as_dir(&lock)?;
+        if find_entry(dir,name)?.ok_or(-ENOENT)?.is_dir() {
+            return Err(-EISDIR);
+        }
+        self.forget_link(name);
+        dir.remove(name).map_err(fat_errno)
+    }
+    fn rmdir(&self, name: &str) -> Result<(), isize> {
+        let lock = self.lock_node();
+        let dir = as_dir(&lock)?;
+        if !find_entry(dir,name)?.ok_or(-ENOENT)?.is_dir() {
+            return Err(-ENOTDIR);
+        }
+        dir.remove(name).map_err(fat_errno)
+    }
+    // FAT没有原子交换两个目录项的方法
+    fn rename(&self, old_name: &str, new_dir: &dyn InodeOps, new_name: &str, flags: u32) -> Result<(), isize> {
+        if flags & RENAME_EXCHANGE != 0 {
+            return Err(-EINVAL);
+        }
+        let new_dir = new_dir.as_any().downcast_ref::<FatInode>().ok_or(-EXDEV)?;
+        if !Arc::ptr_eq(&self.sb,&new_dir.sb) {
+            return Err(-EXDEV);
+        }
+        if core::ptr::eq(self,new_dir) {
+            let lock = self.lock_node();
+            let dir = as_dir(&lock)?;
+            if old_name == new_name {
+                return find_entry(dir,old_name)?.map(|_| ()).ok_or(-ENOENT);
+            }
+            self.forget_link(old_name);
+            self.forget_link(new_name);
+            return fat_rename(dir,old_name,dir,new_name);
+        }
+        // 节点锁只在fs锁内获取，持有fs锁时两个节点锁都不会被其他人持有
+        let _noio = NoReclaimGuard::new();
+        let _fs = self.sb.lock.lock_irq().unwrap();
+        let src = self.node.lock_irq().unwrap();
+        let dst = new_dir.node.lock_irq().unwrap();
+        self.forget_link(old_name);
+        new_dir.forget_link(new_name);
+        fat_rename(as_dir(&src)?,old_name,as_dir(&dst)?,new_name)
+    }
+    fn symlink(&self, name: &str, target: &str) -> Result<Box<dyn InodeOps>, isize> {
+        let lock = self.lock_node();
+        let dir = as_dir(&lock)?;
+        if find_entry(dir,name)?.is_some() {
+            return Err(-EEXIST);
+        }
+        self.forget_link(name);
+        let mut f = dir.create_file(name).map_err(fat_errno)?;
+        let ret = f.write_all(SYMLINK_MAGIC)
+            .and_then(|_| f.write_all(target.as_bytes()))
+            .map(|_| f.set_attributes(FileAttributes::SYSTEM))
+            .and_then(|_| f.flush());
+        // 写入失败时不留下不完整的链接
+        if let Err(e) = ret {
+            drop(f);
+            let _ = dir.remove(name);
+            return Err(fat_errno(e));
+        }
+        Ok(Box::new(FatInode::new(FatNode::SymLink(f),FatTimes::now(),self.sb.clone())))
+    }
+    fn readlink(&self) -> Result<String, isize> {
+        match &mut *self.lock_node() {
+            FatNode::SymLink(f) => {
+                f.seek(SeekFrom::Start(SYMLINK_MAGIC.len() as u64)).map_err(|_| -EIO)?;
+                let mut buf = Vec::new();
+                let mut chunk = [0u8;64];
+                loop {
+                    let n = f.read(&mut chunk).map_err(|_| -EIO)?;
+                    if n == 0 {
+                        break;
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                String::from_utf8(buf).map_err(|_| -EIO)
+            }
+            _ => Err(-EINVAL)
+        }
+    }
+    fn file_ops(&self) -> Option<&dyn FileOps> {
+        match self.get_type() {
+            InodeType::File => Some(self),
+            _ => None
+        }
+    }
+}
+
+fn as_dir(node:&FatNode)->Result<&DirAlias<'static>,isize>{
+    match node {
+        FatNode::Dir(d) => Ok(d),
+        _ => Err(-ENOTDIR)
+    }
+}
+
+fn find_entry(dir:&DirAlias<'static>,name:&str)->Result<Option<DirEntryAlias<'static>>,isize>{
+    for item in dir.iter() {
+        let dentry = item.map_err(fat_errno)?;
+        if dentry.file_name().eq(name) {
+            return Ok(Some(dentry));
+        }
+    }
+    Ok(None)
+}
+
+// fatfs不允许覆盖已存在的目标
+// 先把目标改名为临时名，源移动成功后再删除，失败时恢复目标
+fn fat_rename(src:&DirAlias<'static>,old_name:&str,dst:&DirAlias<'static>,new_name:&str)->Result<(),isize>{
+    let old = find_entry(src,old_name)?.ok_or(-ENOENT)?;
+    let target = match find_entry(dst,new_name)? {
+        Some(t) => t,
+        None => {
+            return src.rename(old_name,dst,new_name).map_err(fat_errno);
+        }
+    };
+    if old.is_dir() != target.is_dir() {
+        return Err(if target.is_dir() { -EISDIR } else { -ENOTDIR });
+    }
+    // 移动之后才删除目标，非空目录要提前拒绝
+    if target.is_dir() {
+        for item in target.to_dir().iter() {
+            let name = item.map_err(fat_errno)?.file_name();
+            if name != "." && name != ".." {
+                return Err(-ENOTEMPTY);
+            }
+        }
+    }
+    let mut n = 0usize;
+    let tmp = loop {
+        let name = format!(".rename-{}",n);
+        if find_entry(dst,&name)?.is_none() {
+            break name;
+        }
+        n += 1;
+    };
+    dst.rename(new_name,dst,&tmp).map_err(fat_errno)?;
+    if let Err(e) = src.rename(old_name,dst,new_name) {
+        let _ = dst.rename(&tmp,dst,new_name);
+        return Err(fat_errno(e));
+    }
+    dst.remove(&tmp).map_err(fat_errno)
+}
+
+fn fat_errno(e:FatError<()>)->isize{
+    match e {
+        FatError::NotFound => -ENOENT,
+        FatError::AlreadyExists => -EEXIST,
+        FatError::DirectoryIsNotEmpty => -ENOTEMPTY,
+        FatError::NotEnoughSpace => -ENOSPC,
+        FatError::InvalidInput|FatError::UnsupportedFileNameCharacter => -EINVAL,
+        FatError::InvalidFileNameLength => -ENAMETOOLONG,
+        _ => -EIO
+    }
+}
+
+impl FileOps for FatInode {
+    fn read_at(&self, off: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        match &mut *self.lock_node() {
+            FatNode::File(f) => {
+                f.seek(SeekFrom::Start(off as u64)).map_err(|_| -EIO)?;
+                let n = f.read(buf).map_err(|_| -EIO)?;
+                self.times.lock_irq().unwrap().atime = fat_atime(get_realtime_sec());
+                Ok(n)
+            }
+            FatNode::Dir(_) => Err(-EISDIR),
+            FatNode::SymLink(_) => Err(-EINVAL)
+        }
+    }
+    fn write_at(&self, off: usize, buf: &[u8]) -> Result<usize, isize> {
+        match &mut *self.lock_node() {
+            FatNode::File(f) => {
+                // fatfs的seek不能越过文件末尾，先把空洞补0
+                if off > fat_file_size(f)? {
+                    fat_set_len(f,off)?;
+                }
+                f.seek(SeekFrom::Start(off as u64)).map_err(|_| -EIO)?;
+                let n = f.write(buf).map_err(fat_errno)?;
+                self.touch_mtime();
+                Ok(n)
+            }
+            FatNode::Dir(_) => Err(-EISDIR),
+            FatNode::SymLink(_) => Err(-EINVAL)
+        }
+    }
+    fn flush(&self) -> Result<(), isize> {
+        match &mut *self.lock_node() {
+            FatNode::File(f) => f.flush().map_err(|_| -EIO),
+            _ => Ok(())
+        }
+    }
+    fn truncate(&self, len: usize) -> Result<(), isize> {
+        match &mut *self.lock_node() {
+            FatNode::File(f) => {
+                fat_set_len(f,len)?;
+                self.touch_mtime();
+                Ok(())
+            }
+            FatNode::Dir(_) => Err(-EISDIR),
+            FatNode::SymLink(_) => Err(-EINVAL)
+        }
+    }
+}
+
+fn fat_file_size(f:&mut FileAlias<'static>)->Result<usize,isize>{
+    f.seek(SeekFrom::End(0)).map(|s| s as usize).map_err(|_| -EIO)
+}
+
+// 缩短时由fatfs释放多余的簇，变长时写0分配新簇
+// FAT的文件长度是32位的
+fn fat_set_len(f:&mut FileAlias<'static>,len:usize)->Result<(),isize>{
+    if len > u32::MAX as usize {
+        return Err(-EFBIG);
+    }
+    let size = fat_file_size(f)?;
+    if len < size {
+        f.seek(SeekFrom::Start(len as u64)).map_err(|_| -EIO)?;
+        return f.truncate().map_err(fat_errno);
+    }
+    let zero = [0u8;512];
+    let mut cur = size;
+    while cur < len {
+        let n = min(zero.len(),len-cur);
+        f.write_all(&zero[..n]).map_err(fat_errno)?;
+        cur += n;
+    }
+    Ok(())
+}
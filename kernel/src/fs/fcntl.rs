@@ -1,6 +1,36 @@
 
 
 pub const AT_FDCWD:isize = -100;
+// *at系统调用的flags
+pub const AT_SYMLINK_NOFOLLOW:u32 = 0x100;
+pub const AT_EMPTY_PATH:u32 = 0x1000;
+pub const AT_REMOVEDIR:u32 = 0x200;
+pub const AT_SYMLINK_FOLLOW:u32 = 0x400;
+// faccessat2使用有效id检查
+pub const AT_EACCESS:u32 = 0x200;
+
+// access mode
+pub const F_OK:u32 = 0;
+pub const X_OK:u32 = 1;
+pub const W_OK:u32 = 2;
+pub const R_OK:u32 = 4;
+
+// 权限检查的mask，与access mode的取值相同
+pub const MAY_EXEC:u32 = 1;
+pub const MAY_WRITE:u32 = 2;
+pub const MAY_READ:u32 = 4;
+
+// renameat2 flags
+pub const RENAME_NOREPLACE:u32 = 1 << 0;
+pub const RENAME_EXCHANGE:u32 = 1 << 1;
+
+// lseek whence
+pub const SEEK_SET:usize = 0;
+pub const SEEK_CUR:usize = 1;
+pub const SEEK_END:usize = 2;
+
+// fallocate mode
+pub const FALLOC_FL_KEEP_SIZE:u32 = 1 << 0;
 
 bitflags! {
     pub struct OpenFlags: u32 {
@@ -8,8 +38,12 @@ bitflags! {
         const O_WRONLY = 1 << 0;
         const O_RDWR = 1 << 1;
         const O_CREATE = 1 << 6;
+        const O_EXCL = 1 << 7;
+        const O_NOCTTY = 1 << 8;
         const O_TRUNC = 1 << 10;
+        const O_NONBLOCK = 1 << 11;
         const O_DIRECTROY = 0200000;
+        const O_NOFOLLOW = 0400000;
         const O_LARGEFILE  = 0100000;
         const O_CLOEXEC = 02000000;
     }
@@ -33,9 +67,37 @@ impl OpenFlags {
             self.contains(Self::O_WRONLY)
         }
     }
+    // 打开已存在的文件时需要的权限
+    pub fn may_mask(&self)->u32{
+        let mut mask = 0;
+        if self.readable() {
+            mask |= MAY_READ;
+        }
+        if self.writeable() || self.contains(Self::O_TRUNC) {
+            mask |= MAY_WRITE;
+        }
+        mask
+    }
 }
 
 bitflags! {
+    // O_CREATE时新文件的权限
     pub struct OpenMode: u32 {
+        const S_ISUID = 0o4000;
+        const S_ISGID = 0o2000;
+        const S_ISVTX = 0o1000;
+        const S_IRUSR = 0o400;
+        const S_IWUSR = 0o200;
+        const S_IXUSR = 0o100;
+        const S_IRGRP = 0o40;
+        const S_IWGRP = 0o20;
+        const S_IXGRP = 0o10;
+        const S_IROTH = 0o4;
+        const S_IWOTH = 0o2;
+        const S_IXOTH = 0o1;
     }
-}
\ No newline at end of file
+}
+
+// utimensat中tv_nsec的特殊值
+pub const UTIME_NOW:i64 = (1<<30)-1;
+pub const UTIME_OMIT:i64 = (1<<30)-2;
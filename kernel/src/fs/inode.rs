@@ -1,50 +1,79 @@
-use alloc::collections::LinkedList;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::borrow::BorrowMut;
-use fatfs::{Error, Read, Seek, SeekFrom, Write};
-use crate::fs::{DirAlias, DirEntryAlias, FileAlias, get_dentry_from_dir, get_sub_dentry, get_unsafe_global_fatfs};
-use crate::fs::dfile::DirEntryWrapper;
+use core::cmp::max;
+use crate::fs::dcache::{dcache_add, dcache_add_negative, dcache_remove};
+use crate::fs::mount::follow_mount;
+use crate::fs::namei::namei;
+use crate::fs::pipe::Pipe;
+use crate::fs::poll::{DEFAULT_POLLMASK, PollEvents};
+use crate::io::device::DevId;
+use crate::fs::root_superblock;
+use crate::fs::superblock::Superblock;
+use crate::fs::vfs::{DirEntryInfo, InodeAttr, InodeOps, InodeType, SetAttr};
+use crate::fs::fcntl::{FALLOC_FL_KEEP_SIZE, MAY_EXEC, MAY_WRITE, RENAME_EXCHANGE, RENAME_NOREPLACE};
+use crate::task::cred::Cred;
+use crate::task::wait::Waiter;
+use crate::task::info::{S_ISVTX, S_IXGRP, S_IXOTH, S_IXUSR};
+use crate::syscall::errno::{EACCES, EBUSY, EEXIST, EFBIG, EINVAL, EISDIR, ENODEV, ENOENT, ENOTDIR, ENOTTY, EOPNOTSUPP, EPERM, EXDEV};
 use crate::{info_sync, SpinLock};
 
 lazy_static!{
-    static ref root_inode:Arc<Inode> = Inode::_create_root();
-}
-
-pub enum InodeClass{
-    Dir(DirAlias<'static>),
-    File(FileAlias<'static>),
+    static ref root_inode:Arc<Inode> = Inode::_create_root(root_superblock());
 }
 
 // index node
+// 具体文件系统的操作通过ops完成
 pub struct Inode{
     parent:Option<Arc<Inode>>,
     name:String,
     this:Weak<Inode>,
+    // ops可能借用superblock中的数据，必须先于sb释放
+    ops:Box<dyn InodeOps>,
+    sb:Arc<Superblock>,
     inner:SpinLock<InodeMutInner>
 }
 
-pub struct InodeMutInner{
-    children:LinkedList<Weak<Inode>>,
-    class:InodeClass
+// 目录项，负目录项表示文件系统中没有这个名字
+enum Dentry{
+    Positive(Weak<Inode>),
+    Negative,
 }
 
-// todo 检查是否同步
-unsafe impl Send for InodeMutInner {}
+// children超过这个数量后插入时清理失效的正目录项
+const CHILDREN_PRUNE_MIN:usize = 16;
+
+pub struct InodeMutInner{
+    children:BTreeMap<String,Dentry>,
+    // 下次清理时children的大小，清理后取剩余数量的两倍，均摊到每次插入
+    prune_at:usize,
+    // 打开中的FIFO共用的pipe，全部关闭后释放
+    fifo:Weak<Pipe>,
+}
 
 impl InodeMutInner {
-    pub fn new_by_file(file:FileAlias<'static>)->Self{
+    pub fn new()->Self{
         Self{
-            children: LinkedList::new(),
-            class: InodeClass::File(file)
+            children: BTreeMap::new(),
+            prune_at: CHILDREN_PRUNE_MIN,
+            fifo: Weak::new()
         }
     }
-    pub fn new_by_dir(dir:DirAlias<'static>) ->Self{
-        Self{
-            children: LinkedList::new(),
-            class: InodeClass::Dir(dir)
+    // inode释放时通常会删除自己的目录项，改名后名字对不上的由这里清理
+    fn insert_child(&mut self,name:&str,dentry:Dentry){
+        if self.children.len() >= self.prune_at {
+            self.children.retain(|_,d|{
+                match d {
+                    Dentry::Positive(w) => w.strong_count() > 0,
+                    Dentry::Negative => true
+                }
+            });
+            self.prune_at = max(CHILDREN_PRUNE_MIN,self.children.len()*2);
         }
+        self.children.insert(name.to_string(),dentry);
     }
 }
 
@@ -53,40 +82,46 @@ impl Inode {
         let ret = root_inode.clone();
         ret
     }
-    fn _create_root()->Arc<Self>{
-        let mut s =unsafe {
-             Self {
-                parent: None,
-                name: "".to_string(),
-                this: Default::default(),
-                inner: SpinLock::new(InodeMutInner::new_by_dir(get_unsafe_global_fatfs().root_dir()))
-            }
+    fn _create_root(sb:Arc<Superblock>)->Arc<Self>{
+        let ops = sb.root_inode();
+        let mut s = Self {
+            parent: None,
+            name: "".to_string(),
+            this: Default::default(),
+            ops,
+            sb,
+            inner: SpinLock::new(InodeMutInner::new())
         };
         let mut arc = Arc::new(s);
         let mut_ptr = arc.as_ref() as *const Inode as *mut Inode;
         unsafe { (*mut_ptr).this = Arc::downgrade(&arc); }
         arc
     }
-    // 不检查是否有重合inode 需要调用者管理
-    pub fn _new_dir_inode(&self, dir:DirAlias<'static>, name:&str) ->Arc<Self>{
-        let self_node = self.this.upgrade().unwrap();
+    // 挂载的文件系统的根，名字和父节点与挂载点相同，所以..和路径都能跨过挂载点
+    pub fn _create_mount_root(sb:Arc<Superblock>,mountpoint:&Arc<Inode>)->Arc<Self>{
+        let ops = sb.root_inode();
         let mut node = Arc::new(Self{
-            parent: Some(self_node),
-            name:name.to_string(),
+            parent: mountpoint.get_parent(),
+            name: mountpoint.name.clone(),
             this: Default::default(),
-            inner:SpinLock::new(InodeMutInner::new_by_dir(dir))
+            ops,
+            sb,
+            inner: SpinLock::new(InodeMutInner::new())
         });
         let mut_ptr = node.as_ref() as *const Inode as *mut Inode;
         unsafe { (*mut_ptr).this = Arc::downgrade(&node); }
         node
     }
-    pub fn _new_file_inode(&self, file:FileAlias<'static>, name:&str) ->Arc<Self>{
+    // 不检查是否有重合inode 需要调用者管理
+    pub fn _new_child_inode(&self, ops:Box<dyn InodeOps>, name:&str) ->Arc<Self>{
         let self_node = self.this.upgrade().unwrap();
         let mut node = Arc::new(Self{
             parent: Some(self_node),
             name:name.to_string(),
             this: Default::default(),
-            inner:SpinLock::new(InodeMutInner::new_by_file(file))
+            ops,
+            sb: self.sb.clone(),
+            inner:SpinLock::new(InodeMutInner::new())
         });
         let mut_ptr = node.as_ref() as *const Inode as *mut Inode;
         unsafe { (*mut_ptr).this = Arc::downgrade(&node); }
@@ -95,119 +130,349 @@ impl Inode {
     pub fn get_parent(&self)->Option<Arc<Inode>>{
         self.parent.as_ref().map(|v|{v.clone()})
     }
-    pub fn get_dentry(&self)->DirEntryAlias{
-        match &self.parent.as_ref().unwrap().inner.lock_irq().unwrap().class{
-            InodeClass::Dir(dir) => {
-                get_sub_dentry(dir,&self.name).unwrap()
+    pub fn get_name(&self)->&str{
+        &self.name
+    }
+    // 从根目录开始的绝对路径
+    pub fn get_path(&self)->String{
+        match &self.parent {
+            None => {
+                "/".to_string()
             }
-            _ => {
-                panic!("bug");
+            Some(p) => {
+                let mut path = p.get_path();
+                if !path.ends_with('/') {
+                    path.push('/');
+                }
+                path.push_str(&self.name);
+                path
             }
         }
     }
-    // todo 优化sub node获取方式
+    pub fn get_sb(&self)->Arc<Superblock>{
+        self.sb.clone()
+    }
+    pub fn get_type(&self)->InodeType{
+        self.ops.get_type()
+    }
+    pub fn getattr(&self)->InodeAttr{
+        self.ops.getattr()
+    }
+    // 检查cred是否有mask中的权限，mask为MAY_READ/MAY_WRITE/MAY_EXEC的组合
+    // root可以读写任何文件，但只能执行至少有一个执行位的文件
+    pub fn permission(&self,cred:&Cred,mask:u32)->Result<(),isize>{
+        let attr = self.getattr();
+        if cred.fs_capable() {
+            if mask & MAY_EXEC != 0 && attr.itype != InodeType::Dir && attr.mode & (S_IXUSR|S_IXGRP|S_IXOTH) == 0 {
+                return Err(-EACCES);
+            }
+            return Ok(());
+        }
+        let bits = if attr.uid == cred.fsuid {
+            attr.mode >> 6
+        } else if cred.in_group(attr.gid) {
+            attr.mode >> 3
+        } else {
+            attr.mode
+        };
+        if mask & !bits & 0o7 != 0 {
+            return Err(-EACCES);
+        }
+        Ok(())
+    }
+    // 在本目录中创建或删除目录项需要写和搜索权限
+    pub fn may_create(&self,cred:&Cred)->Result<(),isize>{
+        self.permission(cred,MAY_WRITE|MAY_EXEC)
+    }
+    // 有粘滞位的目录中只有目录或文件的所有者可以删除文件
+    pub fn may_delete(&self,victim:&Arc<Inode>,cred:&Cred)->Result<(),isize>{
+        self.may_create(cred)?;
+        let attr = self.getattr();
+        if attr.mode & S_ISVTX != 0 && !cred.fs_capable() &&
+            attr.uid != cred.fsuid && victim.getattr().uid != cred.fsuid {
+            return Err(-EPERM);
+        }
+        Ok(())
+    }
+    // 属主或root可以修改权限和时间
+    pub fn is_owner(&self,cred:&Cred)->bool{
+        cred.fs_capable() || self.getattr().uid == cred.fsuid
+    }
+    pub fn get_size(&self)->usize{
+        self.ops.getattr().size
+    }
+    pub fn readdir(&self)->Result<Vec<DirEntryInfo>,isize>{
+        self.ops.readdir()
+    }
     pub fn get_sub_node(&self,name:&str)->Option<Arc<Self>>{
+        self.lookup(name).ok()
+    }
+    // 先查目录项缓存，不命中时由文件系统查找
+    // 新建的inode不会是挂载点，挂载点一直被挂载表持有
+    pub fn lookup(&self,name:&str)->Result<Arc<Self>,isize>{
+        if !self.is_dir() {
+            return Err(-ENOTDIR);
+        }
         let mut inner = self.inner.lock_irq().unwrap();
-        // check if this node is a dir
-        match &inner.class {
-            InodeClass::Dir(_) => {}
-            _ => {
-                return None;
+        match inner.children.get(name) {
+            Some(Dentry::Positive(w)) => {
+                if let Some(v) = w.upgrade() {
+                    drop(inner);
+                    dcache_add(&v);
+                    return Ok(follow_mount(v));
+                }
+            }
+            Some(Dentry::Negative) => {
+                drop(inner);
+                dcache_add_negative(&self.get_self(),name);
+                return Err(-ENOENT);
+            }
+            None => {}
+        }
+        match self.ops.lookup(name) {
+            Ok(ops) => {
+                let new_node = self._new_child_inode(ops, name);
+                inner.insert_child(name,Dentry::Positive(Arc::downgrade(&new_node)));
+                drop(inner);
+                dcache_add(&new_node);
+                Ok(new_node)
+            }
+            Err(e) => {
+                if e == -ENOENT && self.sb.cache_negative() {
+                    inner.insert_child(name,Dentry::Negative);
+                    drop(inner);
+                    dcache_add_negative(&self.get_self(),name);
+                }
+                Err(e)
+            }
+        }
+    }
+    fn _add_child(&self, ops:Box<dyn InodeOps>, name:&str)->Arc<Self>{
+        let new_node = self._new_child_inode(ops, name);
+        self.inner.lock_irq().unwrap().insert_child(name,Dentry::Positive(Arc::downgrade(&new_node)));
+        dcache_add(&new_node);
+        new_node
+    }
+    // name已经从文件系统中删除，已经打开的inode仍然可以使用
+    fn _remove_child(&self, name:&str){
+        let old = self.inner.lock_irq().unwrap().children.remove(name);
+        if let Some(Dentry::Positive(w)) = old {
+            if let Some(node) = w.upgrade() {
+                dcache_remove(&node);
+            }
+        }
+    }
+    // 负目录项被dcache淘汰
+    pub fn _forget_negative(&self, name:&str){
+        let mut inner = self.inner.lock_irq().unwrap();
+        if let Some(Dentry::Negative) = inner.children.get(name) {
+            inner.children.remove(name);
+        }
+    }
+    pub fn create(&self,name:&str,mode:u32)->Result<Arc<Self>,isize>{
+        if self.get_sub_node(name).is_some() {
+            return Err(-EEXIST);
+        }
+        let ops = self.ops.create(name,mode)?;
+        Ok(self._add_child(ops,name))
+    }
+    pub fn mkdir(&self,name:&str,mode:u32)->Result<Arc<Self>,isize>{
+        if self.get_sub_node(name).is_some() {
+            return Err(-EEXIST);
+        }
+        let ops = self.ops.mkdir(name,mode)?;
+        Ok(self._add_child(ops,name))
+    }
+    pub fn symlink(&self,name:&str,target:&str)->Result<Arc<Self>,isize>{
+        if self.get_sub_node(name).is_some() {
+            return Err(-EEXIST);
+        }
+        let ops = self.ops.symlink(name,target)?;
+        Ok(self._add_child(ops,name))
+    }
+    pub fn mknod(&self,name:&str,itype:InodeType,mode:u32)->Result<Arc<Self>,isize>{
+        if self.get_sub_node(name).is_some() {
+            return Err(-EEXIST);
+        }
+        let ops = self.ops.mknod(name,itype,mode)?;
+        Ok(self._add_child(ops,name))
+    }
+    // 取得FIFO当前的pipe，没有打开者时新建
+    pub fn get_fifo(&self)->Arc<Pipe>{
+        let mut inner = self.inner.lock_irq().unwrap();
+        match inner.fifo.upgrade() {
+            Some(p) => p,
+            None => {
+                let p = Arc::new(Pipe::new());
+                inner.fifo = Arc::downgrade(&p);
+                p
+            }
+        }
+    }
+    // 跨文件系统时返回EXDEV
+    pub fn link(&self,name:&str,target:&Arc<Inode>)->Result<(),isize>{
+        if !Arc::ptr_eq(&self.sb,&target.sb) {
+            return Err(-EXDEV);
+        }
+        if self.get_sub_node(name).is_some() {
+            return Err(-EEXIST);
+        }
+        self.ops.link(name,target.ops.as_ref())?;
+        // 去掉负目录项，下次lookup时建立新的inode
+        self._remove_child(name);
+        Ok(())
+    }
+    pub fn unlink(&self,name:&str)->Result<(),isize>{
+        self.ops.unlink(name)?;
+        self._remove_child(name);
+        Ok(())
+    }
+    pub fn rmdir(&self,name:&str)->Result<(),isize>{
+        if let Some(node) = self.get_sub_node(name) {
+            // 挂载点和挂载的根不能删除
+            if !Arc::ptr_eq(&node.sb,&self.sb) {
+                return Err(-EBUSY);
+            }
+        }
+        self.ops.rmdir(name)?;
+        self._remove_child(name);
+        Ok(())
+    }
+    // 把本目录中的old_name移动到new_dir中的new_name
+    // 已经打开的inode保留原来的名字和父节点
+    pub fn rename(&self,old_name:&str,new_dir:&Arc<Inode>,new_name:&str,flags:u32)->Result<(),isize>{
+        if flags & !(RENAME_NOREPLACE|RENAME_EXCHANGE) != 0 ||
+            (flags & RENAME_NOREPLACE != 0 && flags & RENAME_EXCHANGE != 0) {
+            return Err(-EINVAL);
+        }
+        for name in [old_name,new_name] {
+            if name.eq(".") || name.eq("..") {
+                return Err(-EBUSY);
+            }
+        }
+        if !Arc::ptr_eq(&self.sb,&new_dir.sb) {
+            return Err(-EXDEV);
+        }
+        let old = self.lookup(old_name)?;
+        let target = match new_dir.lookup(new_name) {
+            Ok(t) => Some(t),
+            Err(e) if e == -ENOENT => None,
+            Err(e) => {
+                return Err(e);
             }
         };
-        let mut cursor = inner.children.cursor_front_mut();
-        let mut current = cursor.current();
-        while current.is_some(){
-            match current.unwrap().upgrade(){
-                None => {
-                    // 原对象已经回收 delete这个节点
-                    cursor.remove_current();
+        // 挂载点和挂载的根不能移动
+        if !Arc::ptr_eq(&old.sb,&self.sb) || target.as_ref().map_or(false,|t| !Arc::ptr_eq(&t.sb,&self.sb)) {
+            return Err(-EBUSY);
+        }
+        if core::ptr::eq(self,new_dir.as_ref()) && old_name.eq(new_name) {
+            return Ok(());
+        }
+        match &target {
+            None => {
+                if flags & RENAME_EXCHANGE != 0 {
+                    return Err(-ENOENT);
+                }
+            }
+            Some(t) => {
+                if flags & RENAME_NOREPLACE != 0 {
+                    return Err(-EEXIST);
                 }
-                Some(v) => {
-                    if v.name.eq(name) {
-                        // find target
-                        return Some(v.clone());
+                if flags & RENAME_EXCHANGE == 0 {
+                    if old.is_dir() && !t.is_dir() {
+                        return Err(-ENOTDIR);
+                    }
+                    if !old.is_dir() && t.is_dir() {
+                        return Err(-EISDIR);
                     }
                 }
             }
-            cursor.move_next();
-            current = cursor.current();
-        }
-        // not find in children
-        match get_sub_dentry(match &inner.class{
-            InodeClass::Dir(v) => {v}
-            _ => {
-                panic!("bug");
-            }
-        }, name){
-            None => {
-                None
+        }
+        // 目录不能移动到自己的子目录中
+        if new_dir.is_descendant_of(&old) {
+            return Err(-EINVAL);
+        }
+        if let Some(t) = &target {
+            if flags & RENAME_EXCHANGE != 0 && self.is_descendant_of(t) {
+                return Err(-EINVAL);
             }
-            Some(s) => {
-                if s.is_dir(){
-                    let new_node = self._new_dir_inode(s.to_dir(), name);
-                    inner.children.push_back(Arc::downgrade(&new_node));
-                    Some(new_node)
-                } else if s.is_file(){
-                    let new_node = self._new_file_inode(s.to_file(), name);
-                    inner.children.push_back(Arc::downgrade(&new_node));
-                    Some(new_node)
-                } else {
-                    None
+        }
+        self.ops.rename(old_name,new_dir.ops.as_ref(),new_name,flags)?;
+        self._remove_child(old_name);
+        new_dir._remove_child(new_name);
+        Ok(())
+    }
+    pub fn readlink(&self)->Result<String,isize>{
+        self.ops.readlink()
+    }
+    pub fn dev_id(&self)->Option<DevId>{
+        self.ops.dev_id()
+    }
+    pub fn setattr(&self,attr:&SetAttr)->Result<(),isize>{
+        self.ops.setattr(attr)
+    }
+    pub fn ioctl(&self,cmd:usize,arg:usize)->Result<usize,isize>{
+        match self.ops.file_ops() {
+            Some(f) => f.ioctl(cmd,arg),
+            None => Err(-ENOTTY)
+        }
+    }
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        match self.ops.file_ops() {
+            Some(f) => f.poll(waiter),
+            None => DEFAULT_POLLMASK
+        }
+    }
+    pub fn truncate(&self,len:usize)->Result<(),isize>{
+        match self.ops.file_ops() {
+            Some(f) => f.truncate(len),
+            None => Err(-EISDIR)
+        }
+    }
+    // 先写回文件自己缓存的内容，再写回所在文件系统
+    pub fn fsync(&self)->Result<(),isize>{
+        if let Some(f) = self.ops.file_ops() {
+            f.flush()?;
+        }
+        self.get_sb().sync()
+    }
+    // 文件系统不预留空间，只保证[off,off+len)在文件长度之内
+    pub fn fallocate(&self,mode:u32,off:usize,len:usize)->Result<(),isize>{
+        if !self.is_file() {
+            return Err(if self.is_dir() { -EISDIR } else { -ENODEV });
+        }
+        let end = off.checked_add(len).ok_or(-EFBIG)?;
+        match mode {
+            0 => {
+                if end > self.get_size() {
+                    self.truncate(end)?;
                 }
+                Ok(())
             }
+            FALLOC_FL_KEEP_SIZE => Ok(()),
+            _ => Err(-EOPNOTSUPP)
         }
     }
-    // read write seek 有mutinner的spinlock保护
+    // 文件内容的锁由具体文件系统管理
     // 所以只需要 imut即可
-    // 从start开始的off读写，可以被锁保护
+    // 从start开始的off读写
     pub fn read_off(&self,buf: &mut [u8],off:usize)->Result<usize,()>{
-        let mut lock = self.inner.lock_irq().unwrap();
-        match &mut lock.class {
-            InodeClass::File(f) => {
-                match f.seek(SeekFrom::Start(off as u64)) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        return Err(());
-                    }
-                }
-                let len = match f.read(buf){
-                    Ok(l) => {
-                        l
-                    }
-                    Err(_) => {
-                        return Err(());
-                    }
-                };
-                Ok(len)
+        match self.ops.file_ops() {
+            Some(f) => {
+                f.read_at(off,buf).map_err(|_|())
             }
-            _ => {
-                todo!()
+            None => {
+                Err(())
             }
         }
     }
     pub fn write_off(&self,buf: &[u8],off:usize)->Result<usize,()>{
-        let mut lock = self.inner.lock_irq().unwrap();
-        match &mut lock.class {
-            InodeClass::File(f) => {
-                match f.seek(SeekFrom::Start(off as u64)) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        return Err(());
-                    }
-                }
-                let len = match f.write(buf){
-                    Ok(l) => {
-                        l
-                    }
-                    Err(_) => {
-                        return Err(());
-                    }
-                };
-                Ok(len)
+        match self.ops.file_ops() {
+            Some(f) => {
+                f.write_at(off,buf).map_err(|_|())
             }
-            _ => {
-                todo!()
+            None => {
+                Err(())
             }
         }
     }
@@ -231,50 +496,71 @@ impl Inode {
         Ok(need_len)
     }
     pub fn write_off_exact(&self,buf: &[u8],off:usize)->Result<usize,()>{
-        Err(())
+        let need_len = buf.len();
+        let mut buf_pos:usize = 0;
+        while buf_pos<need_len {
+            match self.write_off(&buf[buf_pos..],off+buf_pos) {
+                Ok(len) => {
+                    if len==0{
+                        //无法继续写
+                        return Ok(buf_pos);
+                    }
+                    buf_pos+=len;
+                }
+                Err(_) => {
+                    return Err(());
+                }
+            }
+        }
+        Ok(need_len)
     }
 
     pub fn get_self(&self)->Arc<Self>{
         self.this.upgrade().unwrap()
     }
+    // 相对于self解析路径，跟随符号链接
     pub fn get_node_by_path(&self,path:&str)->Option<Arc<Self>>{
-        let name_array_pre:Vec<&str> = path.split("/").collect();
-        let name_array:Vec<&str> = name_array_pre.into_iter().filter(
-            |x| {
-                if (*x).is_empty()||(*x).eq("."){
-                    false
-                } else {
-                    true
-                }
-            }
-        ).collect();
-        if name_array.is_empty(){
-            // get the path node
-            return Some(self.get_self());
-        }
-        let mut node_probe = self.get_self();
-        for name in name_array {
-            match  node_probe.get_sub_node(name){
-                Some(n) => {
-                    node_probe = n;
-                }
-                None => {
-                    return None;
-                }
+        namei(&self.get_self(),path,true).ok()
+    }
+    // node是自身或者祖先
+    fn is_descendant_of(&self,node:&Arc<Inode>)->bool{
+        if core::ptr::eq(self,node.as_ref()) {
+            return true;
+        }
+        let mut p = self.get_parent();
+        while let Some(dir) = p {
+            if Arc::ptr_eq(&dir,node) {
+                return true;
             }
+            p = dir.get_parent();
         }
-        Some(node_probe)
+        false
     }
     pub fn is_file(&self)->bool {
-        match &self.inner.lock_irq().unwrap().class{
-            InodeClass::File(_) => {true}
-            _=> {false}
-        }
+        self.get_type() == InodeType::File
     }
     pub fn is_dir(&self)->bool{
-        match &self.inner.lock_irq().unwrap().class{
-            InodeClass::Dir(_) => {true}
-            _=> {false}
+        self.get_type() == InodeType::Dir
+    }
+    pub fn is_fifo(&self)->bool{
+        self.get_type() == InodeType::Fifo
+    }
+    pub fn is_device(&self)->bool{
+        let t = self.get_type();
+        t == InodeType::CharDev || t == InodeType::BlockDev
+    }
+}
+
+// 从父目录中删除自己的目录项，父目录可能已经换成了同名的新inode
+impl Drop for Inode {
+    fn drop(&mut self) {
+        if let Some(p) = &self.parent {
+            let mut inner = p.inner.lock_irq().unwrap();
+            if let Some(Dentry::Positive(w)) = inner.children.get(&self.name) {
+                if w.as_ptr() == self as *const Inode {
+                    inner.children.remove(&self.name);
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,219 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::fs::dcache::dcache_shrink_sb;
+use crate::fs::devfs::DevfsSuperBlock;
+use crate::fs::devpts::DevptsSuperBlock;
+use crate::fs::fat::FatSuperBlock;
+use crate::fs::inode::Inode;
+use crate::fs::procfs::ProcSuperBlock;
+use crate::fs::superblock::{PinnedInode, Superblock};
+use crate::fs::tmpfs::TmpfsSuperBlock;
+use crate::fs::{root_dev_name, root_superblock};
+use crate::io::get_blk_dev;
+use crate::io::bcache::bcache_sync_all;
+use crate::syscall::errno::{EAGAIN, EBUSY, EINVAL, ENODEV, ENOENT, ENOTDIR};
+use crate::{info_sync, SpinLock};
+
+// umount2 flags
+pub const MNT_FORCE:usize = 1;
+pub const MNT_DETACH:usize = 2;
+pub const MNT_EXPIRE:usize = 4;
+pub const UMOUNT_NOFOLLOW:usize = 8;
+
+// 挂载表中的一项
+// root的父节点和名字与mountpoint相同，根文件系统没有mountpoint
+// mountpoint让父文件系统保持忙
+pub struct Mount{
+    source:String,
+    sb:Arc<Superblock>,
+    root:Arc<Inode>,
+    mountpoint:Option<PinnedInode>,
+}
+
+lazy_static!{
+    // 按挂载顺序排列，同一挂载点上后挂载的覆盖先挂载的
+    static ref MOUNTS:SpinLock<Vec<Arc<Mount>>> = SpinLock::new(Vec::new());
+}
+
+impl Mount {
+    pub fn get_root(&self)->Arc<Inode>{
+        self.root.clone()
+    }
+}
+
+// 挂载根文件系统，/proc，/dev，/dev/pts和tmpfs
+pub fn mount_init(){
+    let root = Inode::get_root();
+    MOUNTS.lock_irq().unwrap().push(Arc::new(Mount{
+        source: format!("/dev/{}",root_dev_name()),
+        sb: root_superblock(),
+        root: root.clone(),
+        mountpoint: None
+    }));
+    boot_mount("proc","/proc","proc","");
+    boot_mount("devtmpfs","/dev","devtmpfs","");
+    boot_mount("devpts","/dev/pts","devpts","");
+    boot_mount("tmpfs","/tmp","tmpfs","mode=1777");
+    boot_mount("tmpfs","/dev/shm","tmpfs","mode=1777");
+}
+
+// 挂载点不存在时在根文件系统中创建
+fn boot_mount(source:&str,path:&str,fstype:&str,data:&str){
+    let mut dir = Inode::get_root();
+    for name in path.split('/').filter(|x| !x.is_empty()) {
+        let next = match dir.get_sub_node(name) {
+            Some(v) => Ok(v),
+            None => dir.mkdir(name,0o755)
+        };
+        dir = match next {
+            Ok(v) => v,
+            Err(e) => {
+                info_sync!("mount {} fail: mkdir {} {}",path,name,e);
+                return;
+            }
+        };
+    }
+    if let Err(e) = do_mount(source,dir,fstype,0,data) {
+        info_sync!("mount {} fail: {}",path,e);
+    }
+}
+
+// 挂载在node上的最上层文件系统
+pub fn lookup_mount(node:&Arc<Inode>)->Option<Arc<Mount>>{
+    MOUNTS.lock_irq().unwrap().iter().rev().find(|m|{
+        m.mountpoint.as_ref().map_or(false,|p| Arc::ptr_eq(p,node))
+    }).map(|m| m.clone())
+}
+
+// node是挂载点时返回挂载的根
+pub fn follow_mount(node:Arc<Inode>)->Arc<Inode>{
+    let mut node = node;
+    while let Some(m) = lookup_mount(&node) {
+        node = m.get_root();
+    }
+    node
+}
+
+fn dev_name(source:&str)->&str{
+    source.strip_prefix("/dev/").unwrap_or(source)
+}
+
+fn new_superblock(source:&str,fstype:&str,data:&str)->Result<Arc<Superblock>,isize>{
+    match fstype {
+        "vfat"|"fat32"|"msdos" => {
+            // 同一个设备不能同时被两个FatFs使用
+            let busy = MOUNTS.lock_irq().unwrap().iter().any(|m|{
+                dev_name(&m.source).eq(dev_name(source))
+            });
+            if busy {
+                return Err(-EBUSY);
+            }
+            let dev = get_blk_dev(source).ok_or(-ENOENT)?;
+            Ok(Superblock::new(Box::new(FatSuperBlock::new(dev,data)?)))
+        }
+        "proc" => {
+            Ok(Superblock::new(Box::new(ProcSuperBlock)))
+        }
+        "devtmpfs" => {
+            Ok(Superblock::new(Box::new(DevfsSuperBlock)))
+        }
+        "devpts" => {
+            Ok(Superblock::new(Box::new(DevptsSuperBlock)))
+        }
+        "tmpfs"|"ramfs" => {
+            Ok(Superblock::new(Box::new(TmpfsSuperBlock::new(data)?)))
+        }
+        _ => {
+            Err(-ENODEV)
+        }
+    }
+}
+
+// 把source上的文件系统挂载到target目录，data是文件系统相关的选项
+// 挂载期间target的原有内容被隐藏
+pub fn do_mount(source:&str,target:Arc<Inode>,fstype:&str,flags:usize,data:&str)->Result<(),isize>{
+    if !target.is_dir() {
+        return Err(-ENOTDIR);
+    }
+    let sb = new_superblock(source,fstype,data)?;
+    let root = Inode::_create_mount_root(sb.clone(),&target);
+    info_sync!("mount {} at {} type {}",source,target.get_path(),fstype);
+    MOUNTS.lock_irq().unwrap().push(Arc::new(Mount{
+        source: source.to_string(),
+        sb,
+        root,
+        mountpoint: Some(PinnedInode::new(target))
+    }));
+    Ok(())
+}
+
+// target必须是挂载的根
+// 有打开的文件，工作目录或子挂载在其中时返回EBUSY，MNT_DETACH时不检查
+// 写回失败时不卸载，MNT_FORCE时忽略写回错误
+pub fn do_umount(target:Arc<Inode>,flags:usize)->Result<(),isize>{
+    if flags & !(MNT_FORCE|MNT_DETACH|MNT_EXPIRE|UMOUNT_NOFOLLOW) != 0 {
+        return Err(-EINVAL);
+    }
+    // 没有实现过期标记，MNT_EXPIRE不能与MNT_FORCE和MNT_DETACH一起使用
+    if flags & MNT_EXPIRE != 0 {
+        return Err(if flags & (MNT_FORCE|MNT_DETACH) != 0 { -EINVAL } else { -EAGAIN });
+    }
+    let detach = flags & MNT_DETACH != 0;
+    let m = find_umount(&target,detach)?;
+    drop(target);
+    // 写回需要I/O，不能持有挂载表的锁
+    if let Err(e) = m.sb.sync() {
+        if flags & MNT_FORCE == 0 {
+            return Err(e);
+        }
+        info_sync!("umount {}: sync fail {}, forced",m.root.get_path(),e);
+    }
+    // 写回期间可能有新的使用者
+    let mut mounts = MOUNTS.lock_irq().unwrap();
+    let index = match mounts.iter().position(|x| Arc::ptr_eq(x,&m)) {
+        None => {
+            return Err(-EINVAL);
+        }
+        Some(v) => v
+    };
+    if !detach && m.sb.users() != 0 {
+        return Err(-EBUSY);
+    }
+    mounts.remove(index);
+    drop(mounts);
+    info_sync!("umount {}",m.root.get_path());
+    // 卸载成功后才释放缓存的目录项
+    dcache_shrink_sb(&m.sb);
+    Ok(())
+}
+
+fn find_umount(target:&Arc<Inode>,detach:bool)->Result<Arc<Mount>,isize>{
+    let mounts = MOUNTS.lock_irq().unwrap();
+    let m = mounts.iter().find(|m| Arc::ptr_eq(&m.root,target)).ok_or(-EINVAL)?;
+    if m.mountpoint.is_none() || (!detach && m.sb.users() != 0) {
+        return Err(-EBUSY);
+    }
+    Ok(m.clone())
+}
+
+// sync(2)，设备文件写入的块不属于任何文件系统，最后全部写回
+pub fn do_sync(){
+    let mounts:Vec<Arc<Mount>> = MOUNTS.lock_irq().unwrap().clone();
+    for m in mounts {
+        let _ = m.sb.sync();
+    }
+    let _ = bcache_sync_all();
+}
+
+// /proc/mounts的内容
+pub fn mounts_text()->String{
+    let mounts:Vec<Arc<Mount>> = MOUNTS.lock_irq().unwrap().clone();
+    let mut text = String::new();
+    for m in mounts {
+        text.push_str(&format!("{} {} {} rw 0 0\n",m.source,m.root.get_path(),m.sb.fs_type()));
+    }
+    text
+}
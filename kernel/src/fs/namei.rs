@@ -0,0 +1,152 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::fs::dcache::dcache_shrink_sb;
+use crate::fs::inode::Inode;
+use crate::fs::superblock::Superblock;
+use crate::fs::tmpfs::TmpfsSuperBlock;
+use crate::fs::vfs::InodeType;
+use crate::info_sync;
+use crate::syscall::errno::{ELOOP, ENAMETOOLONG, ENOENT, ENOTDIR};
+
+// 路径解析
+// 一次解析中最多跟随的符号链接数，与linux的MAXSYMLINKS一致
+const MAX_SYMLINK_FOLLOW:usize = 40;
+pub const NAME_MAX:usize = 255;
+pub const PATH_MAX:usize = 4096;
+
+// 从start开始解析path，绝对路径从根目录开始
+// follow为false时不跟随最后一个分量的符号链接，以/结尾的路径总是跟随并且要求是目录
+pub fn namei(start:&Arc<Inode>,path:&str,follow:bool)->Result<Arc<Inode>,isize>{
+    let mut links = 0;
+    walk(start.clone(),path,follow,&mut links)
+}
+
+// 返回最后一个分量所在的目录和它的名字，最后一个分量不解析
+// 只有/的路径返回根目录和"."，结尾的/被忽略
+pub fn namei_parent(start:&Arc<Inode>,path:&str)->Result<(Arc<Inode>,String),isize>{
+    if path.is_empty() {
+        return Err(-ENOENT);
+    }
+    if path.len() >= PATH_MAX {
+        return Err(-ENAMETOOLONG);
+    }
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Ok((Inode::get_root(),".".to_string()));
+    }
+    let (dir,name) = match trimmed.rsplit_once('/') {
+        Some(("",name)) => (Inode::get_root(),name),
+        Some((parent,name)) => (namei(start,parent,true)?,name),
+        None => (start.clone(),trimmed)
+    };
+    if name.len() > NAME_MAX {
+        return Err(-ENAMETOOLONG);
+    }
+    if !dir.is_dir() {
+        return Err(-ENOTDIR);
+    }
+    Ok((dir,name.to_string()))
+}
+
+fn walk(start:Arc<Inode>,path:&str,follow:bool,links:&mut usize)->Result<Arc<Inode>,isize>{
+    if path.is_empty() {
+        return Err(-ENOENT);
+    }
+    if path.len() >= PATH_MAX {
+        return Err(-ENAMETOOLONG);
+    }
+    let mut dir = if path.starts_with('/') {
+        Inode::get_root()
+    } else {
+        start
+    };
+    let must_dir = path.ends_with('/');
+    let names:Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+    for (i,name) in names.iter().enumerate() {
+        if !dir.is_dir() {
+            return Err(-ENOTDIR);
+        }
+        if name.len() > NAME_MAX {
+            return Err(-ENAMETOOLONG);
+        }
+        let next = match *name {
+            "." => dir.clone(),
+            // 根目录的..是自身，挂载的根的父节点是挂载点的父节点
+            ".." => dir.get_parent().unwrap_or(dir.clone()),
+            _ => dir.lookup(name)?
+        };
+        let last = i+1 == names.len();
+        dir = if next.get_type() == InodeType::SymLink && (!last || follow || must_dir) {
+            follow_link(&dir,&next,links)?
+        } else {
+            next
+        };
+    }
+    if must_dir && !dir.is_dir() {
+        return Err(-ENOTDIR);
+    }
+    Ok(dir)
+}
+
+// 相对路径的链接从链接所在的目录开始解析
+fn follow_link(dir:&Arc<Inode>,link:&Arc<Inode>,links:&mut usize)->Result<Arc<Inode>,isize>{
+    *links += 1;
+    if *links > MAX_SYMLINK_FOLLOW {
+        return Err(-ELOOP);
+    }
+    let target = link.readlink()?;
+    walk(dir.clone(),&target,true,links)
+}
+
+fn same(ret:Result<Arc<Inode>,isize>,node:&Arc<Inode>)->bool{
+    ret.map_or(false,|v| Arc::ptr_eq(&v,node))
+}
+
+// 在不挂载的tmpfs上解析，包括符号链接，创建文件需要当前task的身份，要在内核task中运行
+pub fn namei_test(){
+    let sb = Superblock::new(Box::new(TmpfsSuperBlock::new("size=64k").unwrap()));
+    let root = Inode::_create_mount_root(sb.clone(),&Inode::get_root());
+    let a = root.mkdir("a",0o755).unwrap();
+    let b = a.mkdir("b",0o755).unwrap();
+    let f = b.create("f",0o644).unwrap();
+
+    assert!(same(namei(&root,"a/b/f",true),&f));
+    assert!(same(namei(&root,"a/./b/../b//f",true),&f));
+    assert!(same(namei(&b,"../..",true),&root));
+    // 根目录的..是自身
+    assert!(same(namei(&b,"../../..",true),&root));
+    assert!(same(namei(&root,"a/b/",true),&b));
+    assert_eq!(namei(&root,"a/b/f/",true).err(),Some(-ENOTDIR));
+    assert_eq!(namei(&root,"a/b/f/x",true).err(),Some(-ENOTDIR));
+    assert_eq!(namei(&root,"a/none",true).err(),Some(-ENOENT));
+    // 负目录项命中时结果相同
+    assert_eq!(namei(&root,"a/none",true).err(),Some(-ENOENT));
+    assert_eq!(namei(&root,"",true).err(),Some(-ENOENT));
+    let long = "x".repeat(NAME_MAX+1);
+    assert_eq!(namei(&a,&long,true).err(),Some(-ENAMETOOLONG));
+
+    let (dir,name) = namei_parent(&root,"a/b/new").unwrap();
+    assert!(Arc::ptr_eq(&dir,&b) && name == "new");
+    let (dir,name) = namei_parent(&root,"a/b//").unwrap();
+    assert!(Arc::ptr_eq(&dir,&a) && name == "b");
+    assert_eq!(namei_parent(&root,"a/b/f/x").err(),Some(-ENOTDIR));
+
+    // 相对链接从链接所在目录开始解析，结尾的/总是跟随
+    a.symlink("lb","b").unwrap();
+    a.symlink("lf","b/f").unwrap();
+    a.symlink("up","../a/b").unwrap();
+    root.symlink("loop","loop").unwrap();
+    assert!(same(namei(&root,"a/lb/f",true),&f));
+    assert!(same(namei(&root,"a/lf",true),&f));
+    assert!(same(namei(&root,"a/up/f",true),&f));
+    assert_eq!(namei(&root,"a/lf",false).map(|v| v.get_type()),Ok(InodeType::SymLink));
+    assert!(same(namei(&root,"a/lb/",false),&b));
+    assert_eq!(namei(&root,"loop",true).err(),Some(-ELOOP));
+    assert_eq!(namei(&root,"loop",false).map(|v| v.get_type()),Ok(InodeType::SymLink));
+
+    drop((f,b,a,root));
+    dcache_shrink_sb(&sb);
+    info_sync!("namei test OK!");
+}
@@ -1,194 +1,283 @@
-use alloc::collections::LinkedList;
+use alloc::collections::TryReserveError;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::cmp::min;
-use core::default::default;
-use core::sync::atomic::{AtomicU8, Ordering};
-use xmas_elf::symbol_table::Visibility::Default;
-use crate::{SpinLock, Task};
-use crate::task::{add_task, scheduler};
-use crate::task::task::get_running;
-use crate::task::task::TaskStatus::TaskSleeping;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::{info_sync, SpinLock};
+use crate::consts::PAGE_SIZE;
+use crate::fs::poll::PollEvents;
+use crate::syscall::errno::{EAGAIN, EBUSY, ENOMEM, EPERM, EPIPE};
+use crate::task::wait::{Waiter, WaitQueue};
 
+// 不超过PIPE_BUF的写入是原子的，不会与其他writer的数据交错
+pub const PIPE_BUF:usize = PAGE_SIZE;
+// 新建pipe的缓冲区大小，F_SETPIPE_SZ可以修改
+const PIPE_DEF_SIZE:usize = PAGE_SIZE;
+// 非特权进程能设置的最大缓冲区，内核堆较小，比linux的1M小
+const PIPE_MAX_SIZE:usize = 16*PAGE_SIZE;
+
+// 匿名pipe和命名FIFO共用，读写端的数量由打开的DFile维护
 pub struct Pipe {
     buffer: SpinLock<PipeRingBuffer>,
-    write_cnt:AtomicU8,
-    wait_write:SpinLock<LinkedList<Arc<SpinLock<Task>>>>,
-    wait_read:SpinLock<LinkedList<Arc<SpinLock<Task>>>>
+    read_cnt:AtomicUsize,
+    write_cnt:AtomicUsize,
+    wait_write:WaitQueue,
+    wait_read:WaitQueue,
+    // 阻塞打开FIFO时等待另一端
+    wait_open:WaitQueue,
 }
 
 impl Pipe {
     pub fn new()->Self{
         Self{
             buffer: SpinLock::new(PipeRingBuffer::new()),
-            write_cnt: AtomicU8::new(0),
-            wait_write: SpinLock::new(default()),
-            wait_read: SpinLock::new(default())
+            read_cnt: AtomicUsize::new(0),
+            write_cnt: AtomicUsize::new(0),
+            wait_write: WaitQueue::new(),
+            wait_read: WaitQueue::new(),
+            wait_open: WaitQueue::new()
         }
     }
     fn __have_writer(&self)->bool{
         self.write_cnt.load(Ordering::SeqCst) != 0
     }
+    fn __have_reader(&self)->bool{
+        self.read_cnt.load(Ordering::SeqCst) != 0
+    }
+    pub fn inc_read(&self){
+        self.read_cnt.fetch_add(1,Ordering::SeqCst);
+        self.wait_open.wake_all();
+    }
+    pub fn dec_read(&self){
+        if self.read_cnt.fetch_sub(1,Ordering::SeqCst)==1 {
+            // reader全部释放，等待的writer醒来后返回EPIPE
+            self.wait_write.wake_all();
+        }
+    }
     pub fn inc_write(&self){
         self.write_cnt.fetch_add(1,Ordering::SeqCst);
+        self.wait_open.wake_all();
     }
     pub fn dec_write(&self){
         if self.write_cnt.fetch_sub(1,Ordering::SeqCst)==1 {
             // writer全部释放 注意此处需要唤醒所有等待的reader，不然会导致无法醒来
-            self.wake_up_read();
+            self.wait_read.wake_all();
         }
     }
-    fn wake_up_write(&self){
-        let len = self.wait_write.lock().unwrap().len();
-        if len == 0{
-            return;
-        }
-        for i in 0..len {
-            add_task(self.wait_write.lock_irq().unwrap().pop_front().unwrap());
-        }
+    // 打开FIFO的一端后等待另一端被打开
+    pub fn wait_for_reader(&self){
+        self.wait_open.wait_until(||self.__have_reader());
     }
-    fn wake_up_read(&self){
-        let len = self.wait_read.lock().unwrap().len();
-        if len == 0{
-            return;
-        }
-        for i in 0..len {
-            add_task(self.wait_read.lock_irq().unwrap().pop_front().unwrap());
-        }
-    }
-    fn sleep_wait_write(&self){
-        get_running().lock_irq().unwrap().set_status(TaskSleeping);
-        scheduler(Some(&self.wait_write))
+    pub fn wait_for_writer(&self){
+        self.wait_open.wait_until(||self.__have_writer());
     }
-    fn sleep_wait_read(&self){
-        get_running().lock_irq().unwrap().set_status(TaskSleeping);
-        scheduler(Some(&self.wait_read))
+    pub fn have_reader(&self)->bool{
+        self.__have_reader()
     }
-    pub fn read_exact(&self,buf:&mut [u8])->Result<usize,()> {
-        let mut buf_pos = 0usize;
-        let need_read = buf.len();
-        while  buf_pos<need_read {
-            let mut ring_buffer = self.buffer.lock_irq().unwrap();
-            let read_once = ring_buffer.read(buf);
-            if read_once == 0{
-                if self.__have_writer(){
-                    // sleep之前需要释放锁
-                    drop(ring_buffer);
-                    self.sleep_wait_read();
-                    // re lock
-                    ring_buffer = self.buffer.lock_irq().unwrap();
-                } else {
-                    // read at all writer close
-                    return Ok(buf_pos);
+    // 有数据时立即返回读到的部分，没有writer时返回0
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.wait_read.add(&waiter);
+            let n = self.buffer.lock_irq().unwrap().read(buf);
+            if n == 0 && self.__have_writer() {
+                if nonblock {
+                    return Err(-EAGAIN);
                 }
+                waiter.sleep();
+                continue;
             }
-            buf_pos+=read_once;
+            if n != 0 {
+                self.wait_write.wake_all();
+            }
+            return Ok(n);
         }
-        assert_eq!(buf_pos,need_read);
-        self.wake_up_write();
-        Ok(buf_pos)
     }
-    pub fn write_exact(&self,buf:&[u8])->Result<usize,()> {
+    // 不超过PIPE_BUF时等到有足够空间再一次写入
+    // 没有reader时返回EPIPE，调用者负责发送SIGPIPE
+    pub fn write(&self,buf:&[u8],nonblock:bool)->Result<usize,isize> {
+        let atomic = buf.len() <= PIPE_BUF;
         let mut buf_pos = 0usize;
-        let need_write = buf.len();
-        while  buf_pos< need_write {
+        let waiter = Waiter::new();
+        while buf_pos < buf.len() {
+            waiter.prepare();
+            self.wait_write.add(&waiter);
+            if !self.__have_reader() {
+                return if buf_pos == 0 { Err(-EPIPE) } else { Ok(buf_pos) };
+            }
             let mut ring_buffer = self.buffer.lock_irq().unwrap();
-            let write_once = ring_buffer.write(buf);
-            if write_once == 0{
+            let space = ring_buffer.available_write();
+            let can_write = if atomic && space < buf.len() { 0 } else { space };
+            if can_write != 0 {
+                buf_pos += ring_buffer.write(&buf[buf_pos..]);
                 drop(ring_buffer);
-                self.sleep_wait_write();
-                // re lock
-                ring_buffer = self.buffer.lock_irq().unwrap();
+                self.wait_read.wake_all();
+                continue;
             }
-            buf_pos+= write_once;
+            drop(ring_buffer);
+            if nonblock {
+                return if buf_pos == 0 { Err(-EAGAIN) } else { Ok(buf_pos) };
+            }
+            waiter.sleep();
         }
-        assert_eq!(buf_pos, need_write);
-        self.wake_up_read();
         Ok(buf_pos)
     }
+    // 读端有数据或者没有writer时可读，写端能原子写入PIPE_BUF时可写
+    // waiter注册到对应方向的等待队列
+    pub fn poll(&self,read:bool,write:bool,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        let mut events = PollEvents::empty();
+        if let Some(w) = waiter {
+            if read {
+                self.wait_read.add(w);
+            }
+            if write {
+                self.wait_write.add(w);
+            }
+        }
+        let ring_buffer = self.buffer.lock_irq().unwrap();
+        if read {
+            if ring_buffer.available_read() != 0 {
+                events |= PollEvents::POLLIN | PollEvents::POLLRDNORM;
+            }
+            if !self.__have_writer() {
+                events |= PollEvents::POLLHUP;
+            }
+        }
+        if write {
+            if ring_buffer.available_write() >= PIPE_BUF {
+                events |= PollEvents::POLLOUT | PollEvents::POLLWRNORM;
+            }
+            if !self.__have_reader() {
+                events |= PollEvents::POLLERR;
+            }
+        }
+        events
+    }
+    pub fn get_size(&self)->usize{
+        self.buffer.lock_irq().unwrap().capacity()
+    }
+    // 大小向上取整到2的幂个页，返回实际大小
+    // 缓冲区中已有的数据放不下时返回EBUSY
+    pub fn set_size(&self,size:usize,capable:bool)->Result<usize,isize>{
+        let size = match size.max(PAGE_SIZE).checked_next_power_of_two() {
+            Some(v) => v,
+            None => {
+                return Err(-EPERM);
+            }
+        };
+        if size > PIPE_MAX_SIZE && !capable {
+            return Err(-EPERM);
+        }
+        let mut ring_buffer = self.buffer.lock_irq().unwrap();
+        if ring_buffer.available_read() > size {
+            return Err(-EBUSY);
+        }
+        ring_buffer.resize(size).map_err(|_| -ENOMEM)?;
+        drop(ring_buffer);
+        self.wait_write.wake_all();
+        Ok(size)
+    }
 }
 
-const RING_BUFFER_SIZE: usize = 1024;
-
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    FULL,
-    EMPTY,
-    NORMAL,
-}
-
+// 环形缓冲区，head是第一个未读字节
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Vec<u8>,
     head: usize,
-    tail: usize,
-    status: RingBufferStatus,
     count:usize,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; PIPE_DEF_SIZE],
             head: 0,
-            tail: 0,
-            status: RingBufferStatus::EMPTY,
             count:0,
         }
     }
-    pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::NORMAL;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::FULL;
-        }
-    }
-    pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::NORMAL;
-        let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::EMPTY;
-        }
-        c
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
     }
     pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::EMPTY {
-            0
-        } else {
-            if self.tail > self.head {
-                self.tail - self.head
-            } else {
-                self.tail + RING_BUFFER_SIZE - self.head
-            }
-        }
+        self.count
     }
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::FULL {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read()
-        }
+        self.arr.len() - self.count
     }
     fn read(&mut self, buf :&mut [u8]) ->usize {
-        let mut can_read = self.available_read();
-        if can_read == 0 {
-            return 0;
-        }
-        can_read = min(can_read,buf.len());
+        let can_read = min(self.count,buf.len());
         for i in 0..can_read {
-            buf[i] = self.read_byte();
+            buf[i] = self.arr[(self.head + i) % self.arr.len()];
         }
-        return can_read;
+        self.head = (self.head + can_read) % self.arr.len();
+        self.count -= can_read;
+        can_read
     }
     fn write(&mut self, buf :&[u8]) ->usize {
-        let mut can_write = self.available_write();
-        if can_write == 0 {
-            return 0;
-        }
-        can_write = min(can_write,buf.len());
+        let can_write = min(self.available_write(),buf.len());
+        let tail = self.head + self.count;
         for i in 0..can_write {
-            self.write_byte(buf[i]);
+            let pos = (tail + i) % self.arr.len();
+            self.arr[pos] = buf[i];
         }
-        return can_write;
+        self.count += can_write;
+        can_write
     }
+    // 调用者保证size不小于已有数据
+    fn resize(&mut self, size:usize) -> Result<(),TryReserveError> {
+        let mut arr = Vec::new();
+        arr.try_reserve_exact(size)?;
+        arr.resize(size,0);
+        let count = self.count;
+        self.read(&mut arr[..count]);
+        self.arr = arr;
+        self.head = 0;
+        self.count = count;
+        Ok(())
+    }
+}
+
+// 只使用非阻塞读写，不需要其他task
+pub fn pipe_test(){
+    let pipe = Pipe::new();
+    pipe.inc_read();
+    pipe.inc_write();
+    let data:Vec<u8> = (0..2*PIPE_BUF).map(|i| i as u8).collect();
+    assert_eq!(pipe.write(&data[..PIPE_DEF_SIZE-10],true),Ok(PIPE_DEF_SIZE-10));
+    // 不超过PIPE_BUF的写入在空间不足时不写入任何数据
+    assert_eq!(pipe.write(&data[..20],true),Err(-EAGAIN));
+    assert!(!pipe.poll(false,true,None).contains(PollEvents::POLLOUT));
+    // 超过PIPE_BUF时可以部分写入
+    assert_eq!(pipe.write(&data[..PIPE_BUF+1],true),Ok(10));
+
+    // 读取跨过环形缓冲区的末尾
+    let mut buf = vec![0u8;2*PIPE_BUF];
+    assert_eq!(pipe.read(&mut buf[..100],true),Ok(100));
+    assert_eq!(pipe.write(&data[..50],true),Ok(50));
+    assert_eq!(pipe.read(&mut buf,true),Ok(PIPE_DEF_SIZE-50));
+    assert_eq!(&buf[..PIPE_DEF_SIZE-110],&data[100..PIPE_DEF_SIZE-10]);
+    assert_eq!(&buf[PIPE_DEF_SIZE-110..PIPE_DEF_SIZE-100],&data[..10]);
+    assert_eq!(&buf[PIPE_DEF_SIZE-100..PIPE_DEF_SIZE-50],&data[..50]);
+    assert_eq!(pipe.read(&mut buf,true),Err(-EAGAIN));
+    assert!(pipe.poll(false,true,None).contains(PollEvents::POLLOUT));
+
+    // 大小取整到2的幂个页，已有数据放不下时不能缩小
+    assert_eq!(pipe.set_size(3*PAGE_SIZE,false),Ok(4*PAGE_SIZE));
+    assert_eq!(pipe.set_size(2*PIPE_MAX_SIZE,false),Err(-EPERM));
+    assert_eq!(pipe.write(&data[..2*PIPE_BUF],true),Ok(2*PIPE_BUF));
+    assert_eq!(pipe.set_size(PAGE_SIZE,false),Err(-EBUSY));
+    assert_eq!(pipe.read(&mut buf,true),Ok(2*PIPE_BUF));
+    assert_eq!(buf,data);
+
+    // 没有writer时读到EOF，没有reader时写返回EPIPE
+    pipe.dec_write();
+    assert_eq!(pipe.read(&mut buf,true),Ok(0));
+    assert!(pipe.poll(true,false,None).contains(PollEvents::POLLHUP));
+    pipe.dec_read();
+    assert_eq!(pipe.write(&data[..1],true),Err(-EPIPE));
+    info_sync!("pipe test OK!");
 }
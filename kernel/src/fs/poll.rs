@@ -1,8 +1,49 @@
 
-#[derive(Clone,Debug)]
+// poll和epoll共用的事件位，poll中只用低16位
+bitflags! {
+    pub struct PollEvents: u32 {
+        const POLLIN = 0x001;
+        const POLLPRI = 0x002;
+        const POLLOUT = 0x004;
+        const POLLERR = 0x008;
+        const POLLHUP = 0x010;
+        const POLLNVAL = 0x020;
+        const POLLRDNORM = 0x040;
+        const POLLRDBAND = 0x080;
+        const POLLWRNORM = 0x100;
+        const POLLWRBAND = 0x200;
+        const POLLRDHUP = 0x2000;
+    }
+}
+
+impl PollEvents {
+    // 不需要请求也总会返回的事件
+    pub fn always()->Self{
+        Self::POLLERR | Self::POLLHUP | Self::POLLNVAL
+    }
+}
+
+// 不支持poll的文件总是可读写
+pub const DEFAULT_POLLMASK:PollEvents = PollEvents::from_bits_truncate(
+    PollEvents::POLLIN.bits | PollEvents::POLLOUT.bits | PollEvents::POLLRDNORM.bits | PollEvents::POLLWRNORM.bits);
+
+#[derive(Clone,Default,Debug)]
 #[repr(C)]
 pub struct PollFd{
-    fd:i32,
-    events:u16,
-    revents:u16,
-}
\ No newline at end of file
+    pub fd:i32,
+    pub events:u16,
+    pub revents:u16,
+}
+
+impl PollFd {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8,core::mem::size_of::<Self>()) }
+    }
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8,core::mem::size_of::<Self>()) }
+    }
+}
+
+// select的fd_set是按位的u64数组
+pub const FD_SETSIZE:usize = 1024;
+pub const NFDBITS:usize = 64;
@@ -0,0 +1,265 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+use core::fmt::Write;
+use crate::consts::CPUS;
+use crate::fs::mount::mounts_text;
+use crate::fs::superblock::SuperBlockOps;
+use crate::fs::vfs::{DirEntryInfo, FileOps, InodeAttr, InodeOps, InodeType};
+use crate::mm::stat::meminfo_text;
+use crate::syscall::errno::{EACCES, EINVAL, ENOENT, ENOTDIR};
+use crate::task::{all_tasks, find_task, task_stat_text, task_status_text};
+use crate::task::info::{S_IRGRP, S_IROTH, S_IRUSR, S_IRWXG, S_IRWXO, S_IRWXU, S_IXGRP, S_IXOTH, S_IXUSR};
+use crate::task::task::{get_running, Task};
+use crate::SpinLock;
+
+// proc文件系统，文件内容在读时生成
+pub struct ProcSuperBlock;
+
+impl SuperBlockOps for ProcSuperBlock {
+    fn fs_type(&self) -> &'static str {
+        "proc"
+    }
+    // task目录随task创建和退出
+    fn cache_negative(&self) -> bool {
+        false
+    }
+    fn root_inode(&self) -> Box<dyn InodeOps> {
+        Box::new(ProcInode::Root)
+    }
+}
+
+const PROC_ROOT_ENTRIES:[(&str,InodeType);4] = [
+    ("mounts",InodeType::File),
+    ("meminfo",InodeType::File),
+    ("cpuinfo",InodeType::File),
+    ("self",InodeType::SymLink),
+];
+
+const PROC_PID_ENTRIES:[(&str,InodeType);7] = [
+    ("status",InodeType::File),
+    ("stat",InodeType::File),
+    ("maps",InodeType::File),
+    ("cmdline",InodeType::File),
+    ("exe",InodeType::SymLink),
+    ("cwd",InodeType::SymLink),
+    ("fd",InodeType::Dir),
+];
+
+// 进程相关的节点只保存tid，每次访问时重新查找task
+// task退出后节点仍可能被缓存，此时按ENOENT处理
+pub enum ProcInode{
+    Root,
+    Mounts,
+    Meminfo,
+    Cpuinfo,
+    SelfLink,
+    PidDir(usize),
+    PidStatus(usize),
+    PidStat(usize),
+    PidMaps(usize),
+    PidCmdline(usize),
+    PidExe(usize),
+    PidCwd(usize),
+    FdDir(usize),
+    Fd(usize,usize),
+}
+
+fn cpuinfo_text()->String{
+    let mut s = String::new();
+    for hart in 0..CPUS {
+        writeln!(s,"processor\t: {}",hart).unwrap();
+        writeln!(s,"hart\t\t: {}",hart).unwrap();
+        writeln!(s,"isa\t\t: rv64imafdc").unwrap();
+        writeln!(s,"mmu\t\t: sv39").unwrap();
+        s.push('\n');
+    }
+    s
+}
+
+// argv以'\0'分隔
+fn cmdline_text(task:&Arc<SpinLock<Task>>)->String{
+    let mut s = String::new();
+    for arg in task.lock_irq().unwrap().get_cmdline() {
+        s.push_str(arg);
+        s.push('\0');
+    }
+    s
+}
+
+fn maps_text(task:&Arc<SpinLock<Task>>)->String{
+    let mm = task.lock_irq().unwrap().mm.clone();
+    mm.map_or(String::new(),|mm|{mm.lock_irq().unwrap().maps_text()})
+}
+
+impl ProcInode {
+    fn tid(&self)->Option<usize>{
+        match self {
+            ProcInode::PidDir(tid)|ProcInode::PidStatus(tid)|ProcInode::PidStat(tid)|
+            ProcInode::PidMaps(tid)|ProcInode::PidCmdline(tid)|ProcInode::PidExe(tid)|
+            ProcInode::PidCwd(tid)|ProcInode::FdDir(tid)|ProcInode::Fd(tid,_) => Some(*tid),
+            _ => None
+        }
+    }
+    fn task(&self)->Result<Option<Arc<SpinLock<Task>>>,isize>{
+        match self.tid() {
+            Some(tid) => find_task(tid).map(|t| Some(t)).ok_or(-ENOENT),
+            None => Ok(None)
+        }
+    }
+    fn content(&self)->String{
+        let task = match self.task() {
+            Ok(t) => t,
+            Err(_) => return String::new()
+        };
+        match self {
+            ProcInode::Mounts => mounts_text(),
+            ProcInode::Meminfo => meminfo_text(),
+            ProcInode::Cpuinfo => cpuinfo_text(),
+            ProcInode::PidStatus(_) => task_status_text(task.as_ref().unwrap()),
+            ProcInode::PidStat(_) => task_stat_text(task.as_ref().unwrap()),
+            ProcInode::PidMaps(_) => maps_text(task.as_ref().unwrap()),
+            ProcInode::PidCmdline(_) => cmdline_text(task.as_ref().unwrap()),
+            _ => String::new()
+        }
+    }
+}
+
+impl InodeOps for ProcInode {
+    fn get_type(&self) -> InodeType {
+        match self {
+            ProcInode::Root|ProcInode::PidDir(_)|ProcInode::FdDir(_) => InodeType::Dir,
+            ProcInode::SelfLink|ProcInode::PidExe(_)|ProcInode::PidCwd(_)|ProcInode::Fd(..) => InodeType::SymLink,
+            _ => InodeType::File
+        }
+    }
+    fn getattr(&self) -> InodeAttr {
+        let itype = self.get_type();
+        let mut attr = InodeAttr::new(itype,self.content().len());
+        attr.mode = match itype {
+            InodeType::Dir => S_IRUSR | S_IXUSR | S_IRGRP | S_IXGRP | S_IROTH | S_IXOTH,
+            InodeType::SymLink => S_IRWXU | S_IRWXG | S_IRWXO,
+            _ => S_IRUSR | S_IRGRP | S_IROTH
+        };
+        attr
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn lookup(&self, name: &str) -> Result<Box<dyn InodeOps>, isize> {
+        let task = self.task()?;
+        let node = match self {
+            ProcInode::Root => {
+                match name {
+                    "mounts" => ProcInode::Mounts,
+                    "meminfo" => ProcInode::Meminfo,
+                    "cpuinfo" => ProcInode::Cpuinfo,
+                    "self" => ProcInode::SelfLink,
+                    _ => {
+                        let tid = name.parse::<usize>().map_err(|_| -ENOENT)?;
+                        find_task(tid).ok_or(-ENOENT)?;
+                        ProcInode::PidDir(tid)
+                    }
+                }
+            }
+            ProcInode::PidDir(tid) => {
+                let tid = *tid;
+                match name {
+                    "status" => ProcInode::PidStatus(tid),
+                    "stat" => ProcInode::PidStat(tid),
+                    "maps" => ProcInode::PidMaps(tid),
+                    "cmdline" => ProcInode::PidCmdline(tid),
+                    "exe" => ProcInode::PidExe(tid),
+                    "cwd" => ProcInode::PidCwd(tid),
+                    "fd" => ProcInode::FdDir(tid),
+                    _ => return Err(-ENOENT)
+                }
+            }
+            ProcInode::FdDir(tid) => {
+                let fd = name.parse::<usize>().map_err(|_| -ENOENT)?;
+                task.unwrap().lock_irq().unwrap().get_opened(fd).ok_or(-ENOENT)?;
+                ProcInode::Fd(*tid,fd)
+            }
+            _ => return Err(-ENOTDIR)
+        };
+        Ok(Box::new(node))
+    }
+    fn readdir(&self) -> Result<Vec<DirEntryInfo>, isize> {
+        let task = self.task()?;
+        match self {
+            ProcInode::Root => {
+                let mut ret:Vec<DirEntryInfo> = PROC_ROOT_ENTRIES.iter().map(|(name,itype)| DirEntryInfo{
+                    name: name.to_string(),
+                    itype: *itype
+                }).collect();
+                for t in all_tasks() {
+                    ret.push(DirEntryInfo{
+                        name: t.lock_irq().unwrap().get_tid().to_string(),
+                        itype: InodeType::Dir
+                    });
+                }
+                Ok(ret)
+            }
+            ProcInode::PidDir(_) => {
+                Ok(PROC_PID_ENTRIES.iter().map(|(name,itype)| DirEntryInfo{
+                    name: name.to_string(),
+                    itype: *itype
+                }).collect())
+            }
+            ProcInode::FdDir(_) => {
+                let fds = task.unwrap().lock_irq().unwrap().opened_fds();
+                Ok(fds.iter().map(|fd| DirEntryInfo{
+                    name: fd.to_string(),
+                    itype: InodeType::SymLink
+                }).collect())
+            }
+            _ => Err(-ENOTDIR)
+        }
+    }
+    // /proc/self指向当前task的目录，其余链接指向task持有的文件
+    fn readlink(&self) -> Result<String, isize> {
+        let task = self.task()?;
+        match self {
+            ProcInode::SelfLink => Ok(get_running().lock_irq().unwrap().get_tid().to_string()),
+            ProcInode::PidExe(_) => {
+                let exe = task.unwrap().lock_irq().unwrap().get_exe();
+                exe.map(|e| e.get_path()).ok_or(-ENOENT)
+            }
+            ProcInode::PidCwd(_) => {
+                let cwd = task.unwrap().lock_irq().unwrap().get_cwd();
+                Ok(cwd.get_path())
+            }
+            ProcInode::Fd(_,fd) => {
+                let file = task.unwrap().lock_irq().unwrap().get_opened(*fd);
+                file.map(|f| f.get_path()).ok_or(-ENOENT)
+            }
+            _ => Err(-EINVAL)
+        }
+    }
+    fn file_ops(&self) -> Option<&dyn FileOps> {
+        match self.get_type() {
+            InodeType::File => Some(self),
+            _ => None
+        }
+    }
+}
+
+impl FileOps for ProcInode {
+    fn read_at(&self, off: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        self.task()?;
+        let content = self.content();
+        let bytes = content.as_bytes();
+        if off >= bytes.len() {
+            return Ok(0);
+        }
+        let len = min(buf.len(),bytes.len()-off);
+        buf[..len].copy_from_slice(&bytes[off..off+len]);
+        Ok(len)
+    }
+    fn write_at(&self, off: usize, buf: &[u8]) -> Result<usize, isize> {
+        Err(-EACCES)
+    }
+}
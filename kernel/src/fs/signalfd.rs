@@ -0,0 +1,80 @@
+use alloc::sync::Arc;
+use crate::SpinLock;
+use crate::fs::poll::PollEvents;
+use crate::syscall::errno::{EAGAIN, EINVAL};
+use crate::task::signal::{dequeue_signal, SigSet};
+use crate::task::task::get_running;
+use crate::task::wait::Waiter;
+
+// signalfd4 flags，与O_NONBLOCK和O_CLOEXEC相同
+pub const SFD_NONBLOCK:u32 = 1 << 11;
+pub const SFD_CLOEXEC:u32 = 0o2000000;
+
+// struct signalfd_siginfo的大小
+pub const SIGNALFD_SIGINFO_SIZE:usize = 128;
+
+// 读取调用者自己的待处理信号，信号需要先被屏蔽，否则返回用户态时已经按默认动作处理
+pub struct SignalFd {
+    mask:SpinLock<SigSet>,
+}
+
+impl SignalFd {
+    pub fn new(mask:SigSet)->Self{
+        Self{
+            mask: SpinLock::new(mask.without_unblockable())
+        }
+    }
+    // 对已有的signalfd再次调用signalfd4时替换mask
+    pub fn set_mask(&self,mask:SigSet){
+        *self.mask.lock_irq().unwrap() = mask.without_unblockable();
+    }
+    fn mask(&self)->SigSet{
+        *self.mask.lock_irq().unwrap()
+    }
+    // 每个信号填充一个signalfd_siginfo，不记录发送者，只有ssi_signo有效
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
+        if buf.len() < SIGNALFD_SIGINFO_SIZE {
+            return Err(-EINVAL);
+        }
+        let mask = self.mask();
+        let wq = get_running().lock_irq().unwrap().sig_wait.clone();
+        let waiter = Waiter::new();
+        let mut len = 0;
+        loop {
+            waiter.prepare();
+            wq.add(&waiter);
+            while len + SIGNALFD_SIGINFO_SIZE <= buf.len() {
+                let sig = match dequeue_signal(mask) {
+                    Some(s) => s,
+                    None => {
+                        break;
+                    }
+                };
+                let info = &mut buf[len..len+SIGNALFD_SIGINFO_SIZE];
+                info.fill(0);
+                info[0..4].copy_from_slice(&(sig as u32).to_ne_bytes());
+                len += SIGNALFD_SIGINFO_SIZE;
+            }
+            if len != 0 {
+                return Ok(len);
+            }
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            waiter.sleep();
+        }
+    }
+    // 调用者有mask中的待处理信号时可读
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        let running = get_running();
+        let tsk = running.lock_irq().unwrap();
+        if let Some(w) = waiter {
+            tsk.sig_wait.add(w);
+        }
+        if tsk.pending.intersect(self.mask()).is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::POLLIN | PollEvents::POLLRDNORM
+        }
+    }
+}
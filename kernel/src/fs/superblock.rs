@@ -1,5 +1,90 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::fs::inode::Inode;
+use crate::fs::vfs::InodeOps;
 
-//todo! 实现superblock和相关trait
+// 具体文件系统实现的superblock操作
+pub trait SuperBlockOps: Send + Sync {
+    fn fs_type(&self)->&'static str;
+    fn root_inode(&self)->Box<dyn InodeOps>;
+    // 把缓存的数据写回设备
+    fn sync(&self)->Result<(),isize>{
+        Ok(())
+    }
+    // 目录内容不经过vfs变化的文件系统不能缓存不存在的名字
+    fn cache_negative(&self)->bool{
+        true
+    }
+}
+
+// 一个已经挂载的文件系统实例，同一文件系统的inode共享
 pub struct Superblock{
+    ops:Box<dyn SuperBlockOps>,
+    // 打开的文件、工作目录和挂载在其中的子挂载的数量，不为0时不能卸载
+    // 目录项缓存和inode之间的引用不计入
+    users:AtomicUsize,
+}
+
+impl Superblock {
+    pub fn new(ops:Box<dyn SuperBlockOps>)->Arc<Self>{
+        Arc::new(Self{
+            ops,
+            users: AtomicUsize::new(0)
+        })
+    }
+    pub fn get_user(&self){
+        self.users.fetch_add(1,Ordering::SeqCst);
+    }
+    pub fn put_user(&self){
+        self.users.fetch_sub(1,Ordering::SeqCst);
+    }
+    pub fn users(&self)->usize{
+        self.users.load(Ordering::SeqCst)
+    }
+    pub fn fs_type(&self)->&'static str{
+        self.ops.fs_type()
+    }
+    pub fn root_inode(&self)->Box<dyn InodeOps>{
+        self.ops.root_inode()
+    }
+    pub fn sync(&self)->Result<(),isize>{
+        self.ops.sync()
+    }
+    pub fn cache_negative(&self)->bool{
+        self.ops.cache_negative()
+    }
+}
+
+// 持有inode并让它所在的文件系统保持忙，用于工作目录和挂载点
+pub struct PinnedInode(Arc<Inode>);
+
+impl PinnedInode {
+    pub fn new(node:Arc<Inode>)->Self{
+        node.get_sb().get_user();
+        Self(node)
+    }
+    pub fn get(&self)->Arc<Inode>{
+        self.0.clone()
+    }
+}
+
+impl Clone for PinnedInode {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl Deref for PinnedInode {
+    type Target = Arc<Inode>;
+    fn deref(&self) -> &Arc<Inode> {
+        &self.0
+    }
+}
 
-}
\ No newline at end of file
+impl Drop for PinnedInode {
+    fn drop(&mut self) {
+        self.0.get_sb().put_user();
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use crate::SpinLock;
+use crate::fs::poll::PollEvents;
+use crate::syscall::errno::{EAGAIN, EINVAL};
+use crate::task::wait::{Waiter, WaitQueue};
+use crate::trap::timer::{add_timer, del_timer, get_realtime_ns, get_time_ns, TimerId};
+
+// timerfd_create flags，与O_NONBLOCK和O_CLOEXEC相同
+pub const TFD_NONBLOCK:u32 = 1 << 11;
+pub const TFD_CLOEXEC:u32 = 0o2000000;
+// timerfd_settime flags
+pub const TFD_TIMER_ABSTIME:u32 = 1 << 0;
+pub const TFD_TIMER_CANCEL_ON_SET:u32 = 1 << 1;
+
+struct TimerFdInner {
+    // 上次read之后到期的次数
+    ticks:u64,
+    // 开机以来的纳秒，0表示没有启动
+    deadline:u64,
+    interval:u64,
+    timer:Option<TimerId>,
+}
+
+// 由内核定时器驱动，到期回调在时钟中断中执行
+pub struct TimerFd {
+    // CLOCK_REALTIME的绝对时间在设置时换算为开机以来的时间，之后修改墙上时间不影响
+    realtime:bool,
+    inner:SpinLock<TimerFdInner>,
+    wq:WaitQueue,
+}
+
+impl TimerFd {
+    pub fn new(realtime:bool)->Arc<Self>{
+        Arc::new(Self{
+            realtime,
+            inner: SpinLock::new(TimerFdInner{
+                ticks: 0,
+                deadline: 0,
+                interval: 0,
+                timer: None
+            }),
+            wq: WaitQueue::new()
+        })
+    }
+    // 定时器只持有弱引用，文件关闭后回调什么也不做
+    fn arm(self:&Arc<Self>,deadline:u64)->TimerId{
+        let t = Arc::downgrade(self);
+        add_timer(deadline,Box::new(move ||{
+            if let Some(t) = t.upgrade() {
+                t.expire();
+            }
+        }))
+    }
+    // 周期定时器错过的周期也计入到期次数
+    fn expire(self:&Arc<Self>){
+        let mut inner = self.inner.lock_irq().unwrap();
+        inner.timer = None;
+        if inner.deadline == 0 {
+            return;
+        }
+        if inner.interval == 0 {
+            inner.ticks += 1;
+            inner.deadline = 0;
+        } else {
+            let missed = get_time_ns().saturating_sub(inner.deadline) / inner.interval + 1;
+            inner.ticks += missed;
+            inner.deadline += missed * inner.interval;
+            inner.timer = Some(self.arm(inner.deadline));
+        }
+        drop(inner);
+        self.wq.wake_all();
+    }
+    // (interval,距离下次到期的时间)
+    fn __gettime(inner:&TimerFdInner)->(u64,u64){
+        if inner.deadline == 0 {
+            (inner.interval,0)
+        } else {
+            (inner.interval,inner.deadline.saturating_sub(get_time_ns()).max(1))
+        }
+    }
+    pub fn gettime(&self)->(u64,u64){
+        Self::__gettime(&self.inner.lock_irq().unwrap())
+    }
+    // value为0时停止定时器，返回原来的设置
+    pub fn settime(self:&Arc<Self>,abstime:bool,interval:u64,value:u64)->(u64,u64){
+        let mut inner = self.inner.lock_irq().unwrap();
+        let old = Self::__gettime(&inner);
+        if let Some(id) = inner.timer.take() {
+            del_timer(id);
+        }
+        inner.ticks = 0;
+        inner.interval = interval;
+        inner.deadline = 0;
+        if value != 0 {
+            let deadline = if !abstime {
+                get_time_ns().saturating_add(value)
+            } else if self.realtime {
+                (value as i64 - (get_realtime_ns() as i64 - get_time_ns() as i64)).max(0) as u64
+            } else {
+                value
+            };
+            inner.deadline = deadline.max(1);
+            inner.timer = Some(self.arm(inner.deadline));
+        }
+        old
+    }
+    // 读出到期次数并清零，没有到期时等待
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
+        if buf.len() < 8 {
+            return Err(-EINVAL);
+        }
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.wq.add(&waiter);
+            let mut inner = self.inner.lock_irq().unwrap();
+            if inner.ticks != 0 {
+                buf[..8].copy_from_slice(&inner.ticks.to_ne_bytes());
+                inner.ticks = 0;
+                return Ok(8);
+            }
+            drop(inner);
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            waiter.sleep();
+        }
+    }
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        if let Some(w) = waiter {
+            self.wq.add(w);
+        }
+        if self.inner.lock_irq().unwrap().ticks != 0 {
+            PollEvents::POLLIN | PollEvents::POLLRDNORM
+        } else {
+            PollEvents::empty()
+        }
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        if let Some(id) = self.inner.lock_irq().unwrap().timer.take() {
+            del_timer(id);
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+use core::ptr::slice_from_raw_parts_mut;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use crate::consts::PAGE_SIZE;
+use crate::fs::superblock::SuperBlockOps;
+use crate::fs::vfs::{DirEntryInfo, FileOps, InodeAttr, InodeOps, InodeType, SetAttr};
+use crate::mm::{alloc_one_page, get_total_pages};
+use crate::mm::page::Page;
+use crate::mm::stat::{mem_stat_add, mem_stat_sub, MemStatItem};
+use crate::syscall::errno::{EEXIST, EFBIG, EINVAL, EISDIR, ENOENT, ENOMEM, ENOSPC, ENOTDIR, ENOTEMPTY, EPERM, EXDEV};
+use crate::task::cred::current_cred;
+use crate::task::info::{S_IRWXG, S_IRWXO, S_IRWXU, S_ISGID};
+use crate::trap::timer::get_realtime_sec;
+use crate::fs::fcntl::RENAME_EXCHANGE;
+use crate::{info_sync, SpinLock};
+
+// 权限位和特殊位
+const MODE_MASK:u32 = 0o7777;
+// 单个文件的最大长度，同linux的MAX_LFS_FILESIZE
+const MAX_FILE_SIZE:usize = i64::MAX as usize;
+
+// 内存文件系统，文件内容保存在伙伴系统分配的页中
+// 一个挂载实例内共享页数限制和inode号
+pub struct TmpfsSuperBlock{
+    info:Arc<TmpfsInfo>,
+    root:Arc<TmpNode>,
+}
+
+struct TmpfsInfo{
+    max_pages:usize,
+    used_pages:AtomicUsize,
+    next_ino:AtomicU64,
+    // rename要读写两个目录，同一实例内的rename串行执行
+    rename_lock:SpinLock<()>,
+}
+
+impl TmpfsInfo {
+    // 超过限制时返回ENOSPC
+    fn charge(&self, nr:usize)->Result<(),isize>{
+        let mut used = self.used_pages.load(Ordering::Relaxed);
+        loop {
+            if used+nr > self.max_pages {
+                return Err(-ENOSPC);
+            }
+            match self.used_pages.compare_exchange(used,used+nr,Ordering::Relaxed,Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(v) => used = v
+            }
+        }
+        mem_stat_add(MemStatItem::Shmem,nr);
+        Ok(())
+    }
+    fn uncharge(&self, nr:usize){
+        self.used_pages.fetch_sub(nr,Ordering::Relaxed);
+        mem_stat_sub(MemStatItem::Shmem,nr);
+    }
+}
+
+impl TmpfsSuperBlock {
+    // data是挂载选项，支持size=<bytes>[k|m|g]和mode=<octal>
+    // 默认大小为内存的一半
+    pub fn new(data:&str)->Result<Self,isize>{
+        let mut max_pages = get_total_pages()/2;
+        let mut mode = S_IRWXU | S_IRWXG | S_IRWXO;
+        for opt in data.split(',').filter(|x| !x.is_empty()) {
+            match opt.split_once('=') {
+                Some(("size",v)) => {
+                    max_pages = (parse_size(v)?+PAGE_SIZE-1)/PAGE_SIZE;
+                }
+                Some(("mode",v)) => {
+                    mode = u32::from_str_radix(v,8).map_err(|_| -EINVAL)? & MODE_MASK;
+                }
+                _ => {
+                    return Err(-EINVAL);
+                }
+            }
+        }
+        let info = Arc::new(TmpfsInfo{
+            max_pages,
+            used_pages: AtomicUsize::new(0),
+            next_ino: AtomicU64::new(1),
+            rename_lock: SpinLock::new(())
+        });
+        let root = TmpNode::new(&info,TmpData::Dir(BTreeMap::new()),mode);
+        Ok(Self{
+            info,
+            root
+        })
+    }
+}
+
+fn parse_size(s:&str)->Result<usize,isize>{
+    let (num,shift) = match s.as_bytes().last() {
+        Some(b'k') | Some(b'K') => (&s[..s.len()-1],10),
+        Some(b'm') | Some(b'M') => (&s[..s.len()-1],20),
+        Some(b'g') | Some(b'G') => (&s[..s.len()-1],30),
+        _ => (s,0)
+    };
+    num.parse::<usize>().map(|v| v<<shift).map_err(|_| -EINVAL)
+}
+
+impl SuperBlockOps for TmpfsSuperBlock {
+    fn fs_type(&self) -> &'static str {
+        "tmpfs"
+    }
+    fn root_inode(&self) -> Box<dyn InodeOps> {
+        Box::new(TmpInode(self.root.clone()))
+    }
+}
+
+enum TmpData{
+    // 按页保存，None为空洞
+    File(Vec<Option<Arc<Page>>>),
+    Dir(BTreeMap<String,Arc<TmpNode>>),
+    SymLink(String),
+    // FIFO的pipe由vfs在打开时创建
+    Fifo,
+}
+
+struct TmpNodeInner{
+    attr:InodeAttr,
+    data:TmpData,
+}
+
+// 一个tmpfs inode，所有硬链接共享
+struct TmpNode{
+    info:Arc<TmpfsInfo>,
+    inner:SpinLock<TmpNodeInner>,
+}
+
+impl TmpNode {
+    fn new(info:&Arc<TmpfsInfo>, data:TmpData, mode:u32)->Arc<Self>{
+        let (itype,nlink,size) = match &data {
+            TmpData::File(_) => (InodeType::File,1,0),
+            TmpData::Dir(_) => (InodeType::Dir,2,0),
+            TmpData::SymLink(s) => (InodeType::SymLink,1,s.len()),
+            TmpData::Fifo => (InodeType::Fifo,1,0),
+        };
+        let now = get_realtime_sec();
+        let mut attr = InodeAttr::new(itype,size);
+        attr.ino = info.next_ino.fetch_add(1,Ordering::Relaxed);
+        attr.mode = mode & MODE_MASK;
+        attr.nlink = nlink;
+        attr.atime = now;
+        attr.mtime = now;
+        attr.ctime = now;
+        Arc::new(Self{
+            info: info.clone(),
+            inner: SpinLock::new(TmpNodeInner{
+                attr,
+                data
+            })
+        })
+    }
+}
+
+impl TmpNode {
+    fn is_dir(&self)->bool{
+        matches!(self.inner.lock_irq().unwrap().data,TmpData::Dir(_))
+    }
+}
+
+// 在目录节点的锁内操作子节点表和属性
+fn with_dir<R>(node:&TmpNode, f:impl FnOnce(&mut BTreeMap<String,Arc<TmpNode>>,&mut InodeAttr)->R)->Result<R,isize>{
+    let mut inner = node.inner.lock_irq().unwrap();
+    let TmpNodeInner{attr,data} = &mut *inner;
+    match data {
+        TmpData::Dir(children) => Ok(f(children,attr)),
+        _ => Err(-ENOTDIR)
+    }
+}
+
+// 目录内容改变，nlink_delta是子目录数的变化
+fn touch_dir(attr:&mut InodeAttr, nlink_delta:isize, now:u64){
+    attr.nlink = (attr.nlink as isize + nlink_delta) as usize;
+    attr.mtime = now;
+    attr.ctime = now;
+}
+
+impl Drop for TmpNode {
+    fn drop(&mut self) {
+        if let TmpData::File(pages) = &self.inner.lock_irq().unwrap().data {
+            let nr = pages.iter().filter(|p| p.is_some()).count();
+            self.info.uncharge(nr);
+        }
+    }
+}
+
+fn page_buf(page:&Arc<Page>)->&mut [u8]{
+    unsafe { &mut *slice_from_raw_parts_mut(page.get_vaddr().get_inner() as *mut u8,PAGE_SIZE) }
+}
+
+// 删除len之后的页，并把最后一页len之后的部分清零
+fn truncate_pages(info:&TmpfsInfo, pages:&mut Vec<Option<Arc<Page>>>, len:usize){
+    let nr = (len+PAGE_SIZE-1)/PAGE_SIZE;
+    if nr < pages.len() {
+        let freed = pages.drain(nr..).filter(|p| p.is_some()).count();
+        info.uncharge(freed);
+    }
+    if len%PAGE_SIZE != 0 {
+        if let Some(Some(page)) = pages.get(len/PAGE_SIZE) {
+            page_buf(page)[len%PAGE_SIZE..].fill(0);
+        }
+    }
+}
+
+pub struct TmpInode(Arc<TmpNode>);
+
+impl TmpInode {
+    // 在目录中加入新节点，属主是当前进程
+    // 父目录有setgid位时新节点继承它的组，新目录也继承setgid位
+    fn add_entry(&self, name:&str, data:TmpData, mut mode:u32)->Result<Box<dyn InodeOps>,isize>{
+        let cred = current_cred();
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let is_dir = matches!(data,TmpData::Dir(_));
+        let mut gid = cred.fsgid;
+        if inner.attr.mode & S_ISGID != 0 {
+            gid = inner.attr.gid;
+            if is_dir {
+                mode |= S_ISGID;
+            }
+        }
+        let node = match &mut inner.data {
+            TmpData::Dir(children) => {
+                if children.contains_key(name) {
+                    return Err(-EEXIST);
+                }
+                let node = TmpNode::new(&self.0.info,data,mode);
+                {
+                    let mut child = node.inner.lock_irq().unwrap();
+                    child.attr.uid = cred.fsuid;
+                    child.attr.gid = gid;
+                }
+                children.insert(name.to_string(),node.clone());
+                node
+            }
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        // 子目录的..指向自身
+        if is_dir {
+            inner.attr.nlink += 1;
+        }
+        let now = get_realtime_sec();
+        inner.attr.mtime = now;
+        inner.attr.ctime = now;
+        Ok(Box::new(TmpInode(node)))
+    }
+    // 从目录中删除name，check检查被删除的节点
+    fn remove_entry(&self, name:&str, check:impl Fn(&TmpNodeInner)->Result<(),isize>)->Result<(),isize>{
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let children = match &mut inner.data {
+            TmpData::Dir(c) => c,
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        let node = children.get(name).ok_or(-ENOENT)?.clone();
+        let now = get_realtime_sec();
+        let is_dir = {
+            let mut child = node.inner.lock_irq().unwrap();
+            check(&child)?;
+            child.attr.nlink -= 1;
+            child.attr.ctime = now;
+            matches!(child.data,TmpData::Dir(_))
+        };
+        children.remove(name);
+        if is_dir {
+            inner.attr.nlink -= 1;
+        }
+        inner.attr.mtime = now;
+        inner.attr.ctime = now;
+        Ok(())
+    }
+}
+
+impl InodeOps for TmpInode {
+    fn get_type(&self) -> InodeType {
+        self.0.inner.lock_irq().unwrap().attr.itype
+    }
+    fn getattr(&self) -> InodeAttr {
+        self.0.inner.lock_irq().unwrap().attr
+    }
+    fn setattr(&self, attr: &SetAttr) -> Result<(), isize> {
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        if let Some(mode) = attr.mode {
+            inner.attr.mode = mode & MODE_MASK;
+        }
+        if let Some(uid) = attr.uid {
+            inner.attr.uid = uid;
+        }
+        if let Some(gid) = attr.gid {
+            inner.attr.gid = gid;
+        }
+        if let Some(atime) = attr.atime {
+            inner.attr.atime = atime;
+        }
+        if let Some(mtime) = attr.mtime {
+            inner.attr.mtime = mtime;
+        }
+        inner.attr.ctime = get_realtime_sec();
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn lookup(&self, name: &str) -> Result<Box<dyn InodeOps>, isize> {
+        match &self.0.inner.lock_irq().unwrap().data {
+            TmpData::Dir(children) => {
+                children.get(name).map(|node|{
+                    let ops:Box<dyn InodeOps> = Box::new(TmpInode(node.clone()));
+                    ops
+                }).ok_or(-ENOENT)
+            }
+            _ => Err(-ENOTDIR)
+        }
+    }
+    fn readdir(&self) -> Result<Vec<DirEntryInfo>, isize> {
+        // 先复制子节点，避免同时持有父子节点的锁
+        let children:Vec<(String,Arc<TmpNode>)> = match &self.0.inner.lock_irq().unwrap().data {
+            TmpData::Dir(children) => {
+                children.iter().map(|(k,v)| (k.clone(),v.clone())).collect()
+            }
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        Ok(children.into_iter().map(|(name,node)|{
+            DirEntryInfo{
+                name,
+                itype: node.inner.lock_irq().unwrap().attr.itype
+            }
+        }).collect())
+    }
+    fn create(&self, name: &str, mode: u32) -> Result<Box<dyn InodeOps>, isize> {
+        self.add_entry(name,TmpData::File(Vec::new()),mode)
+    }
+    fn mkdir(&self, name: &str, mode: u32) -> Result<Box<dyn InodeOps>, isize> {
+        self.add_entry(name,TmpData::Dir(BTreeMap::new()),mode)
+    }
+    fn symlink(&self, name: &str, target: &str) -> Result<Box<dyn InodeOps>, isize> {
+        self.add_entry(name,TmpData::SymLink(target.to_string()),S_IRWXU | S_IRWXG | S_IRWXO)
+    }
+    fn mknod(&self, name: &str, itype: InodeType, mode: u32) -> Result<Box<dyn InodeOps>, isize> {
+        match itype {
+            InodeType::Fifo => self.add_entry(name,TmpData::Fifo,mode),
+            _ => Err(-EPERM)
+        }
+    }
+    fn link(&self, name: &str, target: &dyn InodeOps) -> Result<(), isize> {
+        let target = match target.as_any().downcast_ref::<TmpInode>() {
+            Some(t) if Arc::ptr_eq(&t.0.info,&self.0.info) => t.0.clone(),
+            _ => {
+                return Err(-EXDEV);
+            }
+        };
+        if Arc::ptr_eq(&target,&self.0) {
+            return Err(-EPERM);
+        }
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let children = match &mut inner.data {
+            TmpData::Dir(c) => c,
+            _ => {
+                return Err(-ENOTDIR);
+            }
+        };
+        if children.contains_key(name) {
+            return Err(-EEXIST);
+        }
+        let now = get_realtime_sec();
+        {
+            let mut t = target.inner.lock_irq().unwrap();
+            // 不能为目录建立硬链接
+            if t.attr.itype == InodeType::Dir {
+                return Err(-EPERM);
+            }
+            t.attr.nlink += 1;
+            t.attr.ctime = now;
+        }
+        children.insert(name.to_string(),target);
+        inner.attr.mtime = now;
+        inner.attr.ctime = now;
+        Ok(())
+    }
+    fn unlink(&self, name: &str) -> Result<(), isize> {
+        self.remove_entry(name,|child|{
+            match child.data {
+                TmpData::Dir(_) => Err(-EISDIR),
+                _ => Ok(())
+            }
+        })
+    }
+    fn rmdir(&self, name: &str) -> Result<(), isize> {
+        self.remove_entry(name,|child|{
+            match &child.data {
+                TmpData::Dir(c) if c.is_empty() => Ok(()),
+                TmpData::Dir(_) => Err(-ENOTEMPTY),
+                _ => Err(-ENOTDIR)
+            }
+        })
+    }
+    // 每次只持有一个节点的锁
+    fn rename(&self, old_name: &str, new_dir: &dyn InodeOps, new_name: &str, flags: u32) -> Result<(), isize> {
+        let new_dir = match new_dir.as_any().downcast_ref::<TmpInode>() {
+            Some(t) if Arc::ptr_eq(&t.0.info,&self.0.info) => t.0.clone(),
+            _ => {
+                return Err(-EXDEV);
+            }
+        };
+        let old_dir = &self.0;
+        let _guard = self.0.info.rename_lock.lock_irq().unwrap();
+        let node = with_dir(old_dir,|c,_| c.get(old_name).cloned())?.ok_or(-ENOENT)?;
+        let target = with_dir(&new_dir,|c,_| c.get(new_name).cloned())?;
+        if let Some(t) = &target {
+            // 同一个inode的两个硬链接
+            if Arc::ptr_eq(t,&node) {
+                return Ok(());
+            }
+        }
+        let now = get_realtime_sec();
+        let node_is_dir = node.is_dir();
+        let target_is_dir = target.as_ref().map_or(false,|t| t.is_dir());
+        let same_dir = Arc::ptr_eq(old_dir,&new_dir);
+        // 子目录移动到另一个目录时，两个父目录的nlink随之变化
+        let moved:isize = if node_is_dir && !same_dir { 1 } else { 0 };
+        if flags & RENAME_EXCHANGE != 0 {
+            let target = target.ok_or(-ENOENT)?;
+            let back:isize = if target_is_dir && !same_dir { 1 } else { 0 };
+            with_dir(old_dir,|c,attr|{
+                c.insert(old_name.to_string(),target.clone());
+                touch_dir(attr,back-moved,now);
+            })?;
+            with_dir(&new_dir,|c,attr|{
+                c.insert(new_name.to_string(),node.clone());
+                touch_dir(attr,moved-back,now);
+            })?;
+            target.inner.lock_irq().unwrap().attr.ctime = now;
+            node.inner.lock_irq().unwrap().attr.ctime = now;
+            return Ok(());
+        }
+        if let Some(t) = &target {
+            let mut t = t.inner.lock_irq().unwrap();
+            if let TmpData::Dir(c) = &t.data {
+                if !c.is_empty() {
+                    return Err(-ENOTEMPTY);
+                }
+            }
+            // 被替换的目录不再有任何链接
+            t.attr.nlink = if target_is_dir { 0 } else { t.attr.nlink - 1 };
+            t.attr.ctime = now;
+        }
+        let replaced:isize = if target_is_dir { 1 } else { 0 };
+        with_dir(old_dir,|c,attr|{
+            c.remove(old_name);
+            touch_dir(attr,-moved,now);
+        })?;
+        with_dir(&new_dir,|c,attr|{
+            c.insert(new_name.to_string(),node.clone());
+            touch_dir(attr,moved-replaced,now);
+        })?;
+        node.inner.lock_irq().unwrap().attr.ctime = now;
+        Ok(())
+    }
+    fn readlink(&self) -> Result<String, isize> {
+        match &self.0.inner.lock_irq().unwrap().data {
+            TmpData::SymLink(s) => Ok(s.clone()),
+            _ => Err(-EINVAL)
+        }
+    }
+    fn file_ops(&self) -> Option<&dyn FileOps> {
+        match self.get_type() {
+            InodeType::File => Some(self),
+            _ => None
+        }
+    }
+}
+
+impl FileOps for TmpInode {
+    fn read_at(&self, off: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let size = inner.attr.size;
+        if off >= size {
+            return Ok(0);
+        }
+        let len = min(buf.len(),size-off);
+        let pages = match &inner.data {
+            TmpData::File(p) => p,
+            _ => {
+                return Err(-EISDIR);
+            }
+        };
+        let mut pos = 0;
+        while pos < len {
+            let cur = off+pos;
+            let pg_off = cur%PAGE_SIZE;
+            let n = min(len-pos,PAGE_SIZE-pg_off);
+            // truncate变长后size之内可能没有对应的页
+            match pages.get(cur/PAGE_SIZE).and_then(|p| p.as_ref()) {
+                Some(page) => {
+                    buf[pos..pos+n].copy_from_slice(&page_buf(page)[pg_off..pg_off+n]);
+                }
+                None => {
+                    buf[pos..pos+n].fill(0);
+                }
+            }
+            pos += n;
+        }
+        inner.attr.atime = get_realtime_sec();
+        Ok(len)
+    }
+    fn write_at(&self, off: usize, buf: &[u8]) -> Result<usize, isize> {
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let info = &self.0.info;
+        let TmpNodeInner{attr,data} = &mut *inner;
+        let pages = match data {
+            TmpData::File(p) => p,
+            _ => {
+                return Err(-EISDIR);
+            }
+        };
+        let end = off.checked_add(buf.len()).ok_or(-EFBIG)?;
+        if end > MAX_FILE_SIZE {
+            return Err(-EFBIG);
+        }
+        let nr = (end+PAGE_SIZE-1)/PAGE_SIZE;
+        // 页表本身也按偏移增长，超过实例大小的写入不可能成功
+        if nr > info.max_pages {
+            return Err(-ENOSPC);
+        }
+        if pages.len() < nr {
+            pages.resize(nr,None);
+        }
+        let mut pos = 0;
+        while pos < buf.len() {
+            let cur = off+pos;
+            let pg_off = cur%PAGE_SIZE;
+            let n = min(buf.len()-pos,PAGE_SIZE-pg_off);
+            let slot = &mut pages[cur/PAGE_SIZE];
+            if slot.is_none() {
+                // 空间不足时返回已经写入的部分
+                let page = info.charge(1).and_then(|_|{
+                    alloc_one_page().ok_or_else(||{
+                        info.uncharge(1);
+                        -ENOMEM
+                    })
+                });
+                match page {
+                    Ok(page) => {
+                        page_buf(&page).fill(0);
+                        *slot = Some(page);
+                    }
+                    Err(e) => {
+                        if pos == 0 {
+                            return Err(e);
+                        }
+                        break;
+                    }
+                }
+            }
+            page_buf(slot.as_ref().unwrap())[pg_off..pg_off+n].copy_from_slice(&buf[pos..pos+n]);
+            pos += n;
+        }
+        if off+pos > attr.size {
+            attr.size = off+pos;
+        }
+        let now = get_realtime_sec();
+        attr.mtime = now;
+        attr.ctime = now;
+        Ok(pos)
+    }
+    fn truncate(&self, len: usize) -> Result<(), isize> {
+        let mut inner = self.0.inner.lock_irq().unwrap();
+        let TmpNodeInner{attr,data} = &mut *inner;
+        match data {
+            TmpData::File(pages) => {
+                if len < attr.size {
+                    truncate_pages(&self.0.info,pages,len);
+                }
+                // 变长的部分是空洞
+                attr.size = len;
+                let now = get_realtime_sec();
+                attr.mtime = now;
+                attr.ctime = now;
+                Ok(())
+            }
+            _ => Err(-EISDIR)
+        }
+    }
+}
+
+// 创建文件需要当前task的身份，要在内核task中运行
+pub fn tmpfs_test(){
+    assert_eq!(parse_size("16k"),Ok(16<<10));
+    assert_eq!(parse_size("2M"),Ok(2<<20));
+    assert_eq!(parse_size("x"),Err(-EINVAL));
+    assert!(TmpfsSuperBlock::new("size=16k,bad").is_err());
+
+    let sb = TmpfsSuperBlock::new("size=16k,mode=755").unwrap();
+    let info = sb.info.clone();
+    let used = || info.used_pages.load(Ordering::Relaxed);
+    let root = sb.root_inode();
+    assert_eq!(root.getattr().mode,0o755);
+    let f = root.create("f",0o644).unwrap();
+    let ops = f.file_ops().unwrap();
+    assert_eq!(ops.write_at(0,b"hello"),Ok(5));
+    // 空洞读出0，不占用页
+    assert_eq!(ops.write_at(2*PAGE_SIZE+1,b"world"),Ok(5));
+    assert_eq!(used(),2);
+    assert_eq!(f.getattr().size,2*PAGE_SIZE+6);
+    let mut buf = [0xffu8;8];
+    assert_eq!(ops.read_at(PAGE_SIZE,&mut buf),Ok(8));
+    assert_eq!(buf,[0u8;8]);
+    assert_eq!(ops.read_at(2*PAGE_SIZE,&mut buf),Ok(6));
+    assert_eq!(&buf[..6],b"\0world");
+
+    // 超过实例大小或者偏移溢出时不分配页表
+    assert_eq!(ops.write_at(4*PAGE_SIZE,b"x"),Err(-ENOSPC));
+    assert_eq!(ops.write_at(usize::MAX-1,b"xyz"),Err(-EFBIG));
+    assert_eq!(ops.write_at(MAX_FILE_SIZE,b"x"),Err(-EFBIG));
+    // 页用完时返回已经写入的部分
+    let g = root.create("g",0o644).unwrap();
+    let data = vec![1u8;3*PAGE_SIZE];
+    assert_eq!(g.file_ops().unwrap().write_at(0,&data),Ok(2*PAGE_SIZE));
+    assert_eq!(used(),4);
+    assert_eq!(g.file_ops().unwrap().write_at(2*PAGE_SIZE,b"x"),Err(-ENOSPC));
+
+    // truncate和删除最后一个链接时释放页
+    g.file_ops().unwrap().truncate(0).unwrap();
+    assert_eq!(used(),2);
+    ops.truncate(3).unwrap();
+    assert_eq!(used(),1);
+    assert_eq!(ops.read_at(0,&mut buf),Ok(3));
+    assert_eq!(&buf[..3],b"hel");
+    root.unlink("f").unwrap();
+    assert_eq!(used(),1);
+    drop(f);
+    assert_eq!(used(),0);
+    assert_eq!(root.unlink("f"),Err(-ENOENT));
+    info_sync!("tmpfs test OK!");
+}
@@ -0,0 +1,189 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use crate::fs::poll::{DEFAULT_POLLMASK, PollEvents};
+use crate::io::device::DevId;
+use crate::syscall::errno::{EINVAL, ENOTDIR, ENOTTY, EPERM};
+use crate::task::wait::Waiter;
+use crate::task::info::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG, S_IRWXG, S_IRWXO, S_IRWXU};
+
+// VFS层的文件系统接口
+// 具体文件系统实现SuperBlockOps，InodeOps和FileOps
+// DFile和syscall只通过fs::inode::Inode访问文件系统
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InodeType{
+    File,
+    Dir,
+    SymLink,
+    CharDev,
+    BlockDev,
+    Fifo,
+}
+
+impl InodeType {
+    // linux_dirent64中的d_type
+    pub fn dirent_type(&self)->u8{
+        match self {
+            InodeType::File => 8,
+            InodeType::Dir => 4,
+            InodeType::SymLink => 10,
+            InodeType::CharDev => 2,
+            InodeType::BlockDev => 6,
+            InodeType::Fifo => 1,
+        }
+    }
+}
+
+// 时间单位为秒
+#[derive(Copy, Clone)]
+pub struct InodeAttr{
+    pub itype:InodeType,
+    // 文件系统内唯一的inode号，为0时由VFS生成
+    pub ino:u64,
+    // 权限位，不包括文件类型
+    pub mode:u32,
+    pub nlink:usize,
+    pub uid:u32,
+    pub gid:u32,
+    pub size:usize,
+    pub atime:u64,
+    pub mtime:u64,
+    pub ctime:u64,
+}
+
+impl InodeAttr {
+    pub fn new(itype:InodeType,size:usize)->Self{
+        Self{
+            itype,
+            ino: 0,
+            mode: S_IRWXU | S_IRWXG | S_IRWXO,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            size,
+            atime: 0,
+            mtime: 0,
+            ctime: 0
+        }
+    }
+    // stat中的st_mode
+    pub fn st_mode(&self)->u32{
+        let ifmt = match self.itype {
+            InodeType::File => S_IFREG,
+            InodeType::Dir => S_IFDIR,
+            InodeType::SymLink => S_IFLNK,
+            InodeType::CharDev => S_IFCHR,
+            InodeType::BlockDev => S_IFBLK,
+            InodeType::Fifo => S_IFIFO,
+        };
+        ifmt | self.mode
+    }
+}
+
+// setattr要修改的项，None表示不修改
+#[derive(Copy, Clone, Default)]
+pub struct SetAttr{
+    pub mode:Option<u32>,
+    pub uid:Option<u32>,
+    pub gid:Option<u32>,
+    pub atime:Option<u64>,
+    pub mtime:Option<u64>,
+}
+
+pub struct DirEntryInfo{
+    pub name:String,
+    pub itype:InodeType,
+}
+
+// 文件内容的读写，偏移由调用者维护
+pub trait FileOps: Send + Sync {
+    fn read_at(&self, off:usize, buf:&mut [u8])->Result<usize,isize>;
+    fn write_at(&self, off:usize, buf:&[u8])->Result<usize,isize>;
+    fn flush(&self)->Result<(),isize>{
+        Ok(())
+    }
+    // 修改文件长度，变长的部分读出为0
+    fn truncate(&self, len:usize)->Result<(),isize>{
+        Err(-EPERM)
+    }
+    fn ioctl(&self, cmd:usize, arg:usize)->Result<usize,isize>{
+        Err(-ENOTTY)
+    }
+    // 普通文件总是可读写
+    fn poll(&self, waiter:Option<&Arc<Waiter>>)->PollEvents{
+        DEFAULT_POLLMASK
+    }
+}
+
+// 具体文件系统中inode的操作
+// 错误返回负的errno
+// 目录操作在不支持的文件系统上返回EPERM
+pub trait InodeOps: Send + Sync {
+    fn get_type(&self)->InodeType;
+    fn getattr(&self)->InodeAttr;
+    fn setattr(&self, attr:&SetAttr)->Result<(),isize>{
+        Err(-EPERM)
+    }
+    // 用于link时判断是否属于同一文件系统
+    fn as_any(&self)->&dyn Any;
+    // 查找目录中名为name的项，不处理"."和".."
+    fn lookup(&self, name:&str)->Result<Box<dyn InodeOps>,isize>{
+        Err(-ENOTDIR)
+    }
+    // 不包括"."和".."
+    fn readdir(&self)->Result<Vec<DirEntryInfo>,isize>{
+        Err(-ENOTDIR)
+    }
+    // 在目录中创建普通文件
+    fn create(&self, name:&str, mode:u32)->Result<Box<dyn InodeOps>,isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    fn mkdir(&self, name:&str, mode:u32)->Result<Box<dyn InodeOps>,isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    fn symlink(&self, name:&str, target:&str)->Result<Box<dyn InodeOps>,isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 创建特殊文件，目前只有FIFO
+    fn mknod(&self, name:&str, itype:InodeType, mode:u32)->Result<Box<dyn InodeOps>,isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 为target建立名为name的硬链接
+    fn link(&self, name:&str, target:&dyn InodeOps)->Result<(),isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 删除非目录项
+    fn unlink(&self, name:&str)->Result<(),isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 删除空目录
+    fn rmdir(&self, name:&str)->Result<(),isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 把old_name移动到new_dir中的new_name，new_dir属于同一文件系统
+    // 类型和RENAME_NOREPLACE已经由vfs检查，已存在的new_name被替换
+    fn rename(&self, old_name:&str, new_dir:&dyn InodeOps, new_name:&str, flags:u32)->Result<(),isize>{
+        Err(dir_op_unsupported(self.get_type()))
+    }
+    // 设备文件的设备号
+    fn dev_id(&self)->Option<DevId>{
+        None
+    }
+    fn readlink(&self)->Result<String,isize>{
+        Err(-EINVAL)
+    }
+    // 普通文件和设备返回自身的FileOps，其他类型返回None
+    fn file_ops(&self)->Option<&dyn FileOps>{
+        None
+    }
+}
+
+fn dir_op_unsupported(itype:InodeType)->isize{
+    match itype {
+        InodeType::Dir => -EPERM,
+        _ => -ENOTDIR
+    }
+}
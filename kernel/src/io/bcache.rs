@@ -0,0 +1,408 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::slice;
+use crate::io::{BlockRead, BlockReadWrite, BlockWrite, MemDisk};
+use crate::asm::{disable_irq, enable_irq};
+use crate::consts::PAGE_SIZE;
+use crate::io::device::SECTOR_SIZE;
+use crate::mm::{alloc_one_page, get_total_pages};
+use crate::mm::page::Page;
+use crate::syscall::errno::{EIO, ENOMEM};
+use crate::task::{others_runnable, yield_self};
+use crate::task::task::Task;
+use crate::task::wait::{WaitQueue, Waiter};
+use crate::trap::timer::{get_time_ms, get_time_ns};
+use crate::{info_sync, SpinLock, warn_sync};
+use crate::mm::swap::NoReclaimGuard;
+use crate::sync::SpinLockGuard;
+
+// 块缓存
+// 文件系统和块设备文件都经过这里读写扇区，写入只把块标记为脏
+// 脏块由写回线程定期写回，sync和淘汰时立即写回
+// 读写设备时不持有缓存的锁，连续的块合并成一个分散/聚集请求
+// 块的内存按页从页分配器取得，heap很小(k210上只有160KB)，不能放在heap中
+const BCACHE_MAX:usize = 4096;
+// 缓存最多占用内存的1/BCACHE_MEM_RATIO
+const BCACHE_MEM_RATIO:usize = 8;
+const SECTORS_PER_PAGE:usize = PAGE_SIZE/SECTOR_SIZE;
+// 缺失时最多预读的块数
+const READAHEAD_BLKS:usize = 32;
+// 一次写回请求最多的块数
+const WRITEBACK_BLKS:usize = 64;
+// 脏块在内存中最多停留的时间
+const DIRTY_EXPIRE_MS:usize = 3000;
+const WRITEBACK_INTERVAL_MS:usize = 1000;
+
+// (设备地址,块号)，按这个顺序写回时对同一设备是顺序写
+type BufKey = (usize,usize);
+
+// 扇区的地址，内存属于SectorPool
+#[derive(Copy, Clone)]
+struct Sector(usize);
+
+impl Sector {
+    fn as_slice(&self)->&[u8]{
+        unsafe { slice::from_raw_parts(self.0 as *const u8,SECTOR_SIZE) }
+    }
+    fn as_mut_slice(&mut self)->&mut [u8]{
+        unsafe { slice::from_raw_parts_mut(self.0 as *mut u8,SECTOR_SIZE) }
+    }
+}
+
+// 一页分成多个扇区，页不归还，总量受缓存块数的上限限制
+struct SectorPool{
+    pages:Vec<Arc<Page>>,
+    free:Vec<Sector>,
+}
+
+impl SectorPool {
+    fn alloc(&mut self)->Option<Sector>{
+        if self.free.is_empty() {
+            let pg = alloc_one_page()?;
+            let base = pg.get_vaddr().get_inner();
+            self.free.extend((0..SECTORS_PER_PAGE).rev().map(|i| Sector(base+i*SECTOR_SIZE)));
+            self.pages.push(pg);
+        }
+        self.free.pop()
+    }
+    fn free(&mut self,sector:Sector){
+        self.free.push(sector);
+    }
+}
+
+struct Buffer{
+    dev:Arc<dyn BlockReadWrite>,
+    data:Sector,
+    // 变脏的时间
+    dirty:Option<usize>,
+    // 写入过，淘汰时设备上的数据可能比其他task正在读的新
+    modified:bool,
+    // 正在写回，设备直接访问data，不能淘汰
+    busy:usize,
+    // 正在淘汰，写回期间仍留在缓存中，被再次访问时取消淘汰
+    evicting:bool,
+}
+
+struct BCache{
+    seq:u64,
+    // 序号最小的最久未使用
+    lru:BTreeMap<u64,BufKey>,
+    bufs:BTreeMap<BufKey,(u64,Buffer)>,
+    // 淘汰写入过的块的次数，不持锁读设备期间变化时读到的数据可能已经过时
+    evict_modified:u64,
+    pool:SectorPool,
+    // 缓存块数的上限，由内存大小决定
+    max:usize,
+}
+
+impl BCache {
+    // 调用者保证块已经在缓存中
+    fn get(&mut self,dev:&Arc<dyn BlockReadWrite>,blk:usize)->&mut Buffer{
+        let key = (dev_key(dev),blk);
+        self.seq += 1;
+        let seq = self.seq;
+        let old = self.bufs.get(&key).unwrap().0;
+        self.lru.remove(&old);
+        self.bufs.get_mut(&key).unwrap().0 = seq;
+        self.lru.insert(seq,key);
+        let b = &mut self.bufs.get_mut(&key).unwrap().1;
+        b.evicting = false;
+        b
+    }
+    fn insert(&mut self,dev:&Arc<dyn BlockReadWrite>,blk:usize,data:Sector){
+        self.seq += 1;
+        let key = (dev_key(dev),blk);
+        self.bufs.insert(key,(self.seq,Buffer{
+            dev: dev.clone(),
+            data,
+            dirty: None,
+            modified: false,
+            busy: 0,
+            evicting: false
+        }));
+        self.lru.insert(self.seq,key);
+    }
+    fn remove(&mut self,key:&BufKey){
+        let (seq,buf) = self.bufs.remove(key).unwrap();
+        self.lru.remove(&seq);
+        if buf.modified {
+            self.evict_modified += 1;
+        }
+        self.pool.free(buf.data);
+    }
+    // 从blk开始连续不在缓存中的块数，不超过设备末尾
+    fn missing_run(&self,dev:&Arc<dyn BlockReadWrite>,blk:usize)->usize{
+        let key = dev_key(dev);
+        let end = dev.num_blocks().unwrap_or(blk+1).max(blk+1);
+        let mut n = 1;
+        while n < READAHEAD_BLKS && blk+n < end && !self.bufs.contains_key(&(key,blk+n)) {
+            n += 1;
+        }
+        n
+    }
+}
+
+lazy_static!{
+    static ref BCACHE:SpinLock<BCache> = SpinLock::new(BCache{
+        seq: 0,
+        lru: BTreeMap::new(),
+        bufs: BTreeMap::new(),
+        evict_modified: 0,
+        pool: SectorPool{
+            pages: Vec::new(),
+            free: Vec::new()
+        },
+        max: (get_total_pages()/BCACHE_MEM_RATIO*SECTORS_PER_PAGE).clamp(SECTORS_PER_PAGE,BCACHE_MAX)
+    });
+    static ref WRITEBACK_WQ:WaitQueue = WaitQueue::new();
+}
+
+fn dev_key(dev:&Arc<dyn BlockReadWrite>)->usize{
+    Arc::as_ptr(dev) as *const () as usize
+}
+
+type BCacheGuard = SpinLockGuard<'static,BCache>;
+
+// 淘汰最久未使用的一块，没有可淘汰的块时返回false
+// 脏块标记为正在淘汰后释放锁写回，期间被访问或再次变脏时保留
+fn evict_one(mut cache:BCacheGuard)->(BCacheGuard,bool){
+    let victim = cache.lru.values()
+        .find(|key| {
+            let b = &cache.bufs[*key].1;
+            b.busy == 0 && !b.evicting
+        })
+        .copied();
+    let key = match victim {
+        Some(k) => k,
+        None => {
+            return (cache,false);
+        }
+    };
+    let buf = &mut cache.bufs.get_mut(&key).unwrap().1;
+    if buf.dirty.is_none() {
+        cache.remove(&key);
+        return (cache,true);
+    }
+    buf.dirty = None;
+    buf.busy += 1;
+    buf.evicting = true;
+    let (dev,data) = (buf.dev.clone(),buf.data);
+    drop(cache);
+    // 淘汰脏块说明写回跟不上，唤醒写回线程
+    WRITEBACK_WQ.wake_all();
+    // 写回失败时与linux一样丢弃这个块，只记录错误
+    if let Err(e) = dev.write_block(key.1,data.as_slice()) {
+        warn_sync!("bcache: write back block {} fail: {}",key.1,e);
+    }
+    let mut cache = BCACHE.lock_irq().unwrap();
+    let buf = &mut cache.bufs.get_mut(&key).unwrap().1;
+    buf.busy -= 1;
+    if buf.evicting && buf.dirty.is_none() && buf.busy == 0 {
+        cache.remove(&key);
+    } else {
+        buf.evicting = false;
+    }
+    (cache,true)
+}
+
+// 取得最多n个空闲扇区，缓存满或者没有内存时淘汰旧的块，至少返回一个
+// 所有块都在写回中并且没有内存时返回ENOMEM
+fn alloc_sectors(mut cache:BCacheGuard,n:usize)->Result<(BCacheGuard,Vec<Sector>),isize>{
+    let mut sectors = Vec::new();
+    while sectors.len() < n {
+        if cache.bufs.len()+sectors.len() < cache.max {
+            if let Some(s) = cache.pool.alloc() {
+                sectors.push(s);
+                continue;
+            }
+        }
+        let (c,evicted) = evict_one(cache);
+        cache = c;
+        if !evicted {
+            break;
+        }
+    }
+    if sectors.is_empty() {
+        return Err(-ENOMEM);
+    }
+    Ok((cache,sectors))
+}
+
+// 返回时blk在缓存中，fill为false时调用者会覆盖整块，不需要从设备读
+// 缺失时预读之后连续缺失的块，读设备期间不持锁，其他task先放入的块优先
+// 读设备失败时返回错误，预读的块不放入缓存
+fn lock_block(dev:&Arc<dyn BlockReadWrite>,blk:usize,fill:bool)->Result<BCacheGuard,isize>{
+    let key = dev_key(dev);
+    // 读设备时要分配页，不能回收到swap文件再进入块缓存
+    let _noio = NoReclaimGuard::new();
+    let mut cache = BCACHE.lock_irq().unwrap();
+    while !cache.bufs.contains_key(&(key,blk)) {
+        let n = if fill { cache.missing_run(dev,blk) } else { 1 };
+        let (c,mut sectors) = alloc_sectors(cache,n)?;
+        cache = c;
+        // 淘汰时释放过锁
+        if cache.bufs.contains_key(&(key,blk)) {
+            sectors.into_iter().for_each(|s| cache.pool.free(s));
+            break;
+        }
+        if !fill {
+            let mut s = sectors.pop().unwrap();
+            sectors.into_iter().for_each(|s| cache.pool.free(s));
+            s.as_mut_slice().fill(0);
+            cache.insert(dev,blk,s);
+            break;
+        }
+        let evict_modified = cache.evict_modified;
+        drop(cache);
+        let ret = {
+            let mut bufs:Vec<&mut [u8]> = sectors.iter_mut().map(|s| s.as_mut_slice()).collect();
+            dev.read_blocks(blk,&mut bufs)
+        };
+        cache = BCACHE.lock_irq().unwrap();
+        if let Err(e) = ret {
+            sectors.into_iter().for_each(|s| cache.pool.free(s));
+            return Err(e);
+        }
+        if cache.evict_modified != evict_modified {
+            sectors.into_iter().for_each(|s| cache.pool.free(s));
+            continue;
+        }
+        for (i,s) in sectors.into_iter().enumerate() {
+            if cache.bufs.contains_key(&(key,blk+i)) {
+                cache.pool.free(s);
+            } else {
+                cache.insert(dev,blk+i,s);
+            }
+        }
+    }
+    Ok(cache)
+}
+
+// 读写块内[off,off+buf.len())的部分
+pub fn bcache_read(dev:&Arc<dyn BlockReadWrite>,blk:usize,off:usize,buf:&mut [u8])->Result<(),isize>{
+    let mut cache = lock_block(dev,blk,true)?;
+    let b = cache.get(dev,blk);
+    buf.copy_from_slice(&b.data.as_slice()[off..off+buf.len()]);
+    Ok(())
+}
+
+pub fn bcache_write(dev:&Arc<dyn BlockReadWrite>,blk:usize,off:usize,buf:&[u8])->Result<(),isize>{
+    let mut cache = lock_block(dev,blk,buf.len() != SECTOR_SIZE)?;
+    let b = cache.get(dev,blk);
+    b.data.as_mut_slice()[off..off+buf.len()].copy_from_slice(buf);
+    b.modified = true;
+    if b.dirty.is_none() {
+        b.dirty = Some(get_time_ms());
+    }
+    Ok(())
+}
+
+// 写回f选中的脏块，同一设备上连续的块合并成一个请求，请求的每一段直接指向缓存的块
+// 写回期间块被再次修改时重新变脏，之后再写一次
+// 写失败的块不再是脏块，返回第一个错误
+fn bcache_sync<F:Fn(&BufKey,&Buffer)->bool>(f:F)->Result<(),isize>{
+    let mut runs:Vec<(Arc<dyn BlockReadWrite>,usize,Vec<Sector>)> = Vec::new();
+    let _noio = NoReclaimGuard::new();
+    let mut cache = BCACHE.lock_irq().unwrap();
+    for (key,(_,buf)) in cache.bufs.iter_mut() {
+        if buf.dirty.is_none() || !f(key,buf) {
+            continue;
+        }
+        buf.dirty = None;
+        buf.busy += 1;
+        let data = buf.data;
+        match runs.last_mut() {
+            Some((dev,start,datas)) if dev_key(dev) == key.0 && *start+datas.len() == key.1 && datas.len() < WRITEBACK_BLKS => {
+                datas.push(data);
+            }
+            _ => {
+                runs.push((buf.dev.clone(),key.1,vec![data]));
+            }
+        }
+    }
+    drop(cache);
+    let mut ret = Ok(());
+    for (dev,start,datas) in runs.iter() {
+        let bufs:Vec<&[u8]> = datas.iter().map(|d| d.as_slice()).collect();
+        if let Err(e) = dev.write_blocks(*start,&bufs) {
+            warn_sync!("bcache: write back blocks {}+{} fail: {}",start,datas.len(),e);
+            ret = ret.and(Err(e));
+        }
+    }
+    let mut cache = BCACHE.lock_irq().unwrap();
+    for (dev,start,datas) in runs.iter() {
+        for i in 0..datas.len() {
+            cache.bufs.get_mut(&(dev_key(dev),start+i)).unwrap().1.busy -= 1;
+        }
+    }
+    ret
+}
+
+pub fn bcache_sync_dev(dev:&Arc<dyn BlockReadWrite>)->Result<(),isize>{
+    let key = dev_key(dev);
+    bcache_sync(|k,_| k.0 == key)
+}
+
+pub fn bcache_sync_all()->Result<(),isize>{
+    bcache_sync(|_,_| true)
+}
+
+// 写回线程定时写回超过期限的脏块，刚写入的块可能很快被再次修改
+// 淘汰脏块时被提前唤醒，这时写回所有脏块
+fn writeback_thread(){
+    let waiter = Waiter::new();
+    let mut all = false;
+    loop {
+        waiter.prepare();
+        WRITEBACK_WQ.add(&waiter);
+        let now = get_time_ms();
+        let _ = bcache_sync(|_,b|{
+            b.dirty.map_or(false,|t| all || now.saturating_sub(t) >= DIRTY_EXPIRE_MS)
+        });
+        // 没有idle task，只剩自己可以运行时不能sleep，检查和sleep之间关中断
+        let irq = disable_irq();
+        if others_runnable() {
+            let deadline = get_time_ns()+(WRITEBACK_INTERVAL_MS as u64)*1_000_000;
+            all = !waiter.sleep_until(Some(deadline));
+        } else {
+            yield_self();
+            all = waiter.is_woken();
+        }
+        enable_irq(irq);
+    }
+}
+
+pub fn bcache_init(){
+    Task::create_kern_task_and_run(writeback_thread);
+}
+
+// 写入只修改缓存，sync之后才到达设备
+pub fn bcache_test(){
+    let disk = Arc::new(MemDisk::new(64));
+    let dev:Arc<dyn BlockReadWrite> = disk.clone();
+    let mut sector = [7u8;SECTOR_SIZE];
+    dev.write_block(3,&sector).unwrap();
+    let mut buf = [0u8;16];
+    bcache_read(&dev,3,100,&mut buf).unwrap();
+    assert_eq!(buf,[7u8;16]);
+
+    // 部分写入保留块中的其他数据，整块写入不读设备
+    bcache_write(&dev,3,100,&[1u8;16]).unwrap();
+    bcache_write(&dev,40,0,&[2u8;SECTOR_SIZE]).unwrap();
+    bcache_read(&dev,3,96,&mut buf).unwrap();
+    assert_eq!(buf,[7,7,7,7,1,1,1,1,1,1,1,1,1,1,1,1]);
+    disk.read_block(3,&mut sector).unwrap();
+    assert_eq!(sector[100],7);
+    bcache_sync_dev(&dev).unwrap();
+    disk.read_block(3,&mut sector).unwrap();
+    assert_eq!((sector[99],sector[100],sector[115],sector[116]),(7,1,1,7));
+    disk.read_block(40,&mut sector).unwrap();
+    assert_eq!(sector,[2u8;SECTOR_SIZE]);
+
+    // 超出设备的块返回设备的错误，不放入缓存
+    assert_eq!(bcache_read(&dev,64,0,&mut buf),Err(-EIO));
+    assert_eq!(bcache_read(&dev,64,0,&mut buf),Err(-EIO));
+    info_sync!("bcache test OK!");
+}
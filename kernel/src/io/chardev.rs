@@ -0,0 +1,97 @@
+use alloc::sync::Arc;
+use core::mem::size_of;
+use riscv::register::time;
+use crate::io::device::{CharDevice, DevId, MEM_MAJOR, register_chrdev, TTY_MAJOR};
+use crate::io::pty::{PTMX_DEV, PtmxDev};
+use crate::io::tty::console_tty;
+use crate::syscall::errno::ENOSPC;
+use crate::SpinLock;
+
+// 内存类字符设备 /dev/null /dev/zero /dev/full /dev/random /dev/urandom
+pub struct NullDev;
+pub struct ZeroDev;
+pub struct FullDev;
+
+impl CharDevice for NullDev {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        Ok(0)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        Ok(buf.len())
+    }
+}
+
+impl CharDevice for ZeroDev {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        Ok(buf.len())
+    }
+}
+
+impl CharDevice for FullDev {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        Err(-ENOSPC)
+    }
+}
+
+// xorshift64*，不是密码学安全的随机数
+// random和urandom共用，写入的数据混入状态
+pub struct RandomDev{
+    state:SpinLock<u64>,
+}
+
+impl RandomDev {
+    pub fn new()->Self{
+        Self{
+            state: SpinLock::new(time::read() as u64 | 1)
+        }
+    }
+}
+
+impl CharDevice for RandomDev {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        let mut state = self.state.lock_irq().unwrap();
+        for chunk in buf.chunks_mut(size_of::<u64>()) {
+            *state ^= *state >> 12;
+            *state ^= *state << 25;
+            *state ^= *state >> 27;
+            let v = state.wrapping_mul(0x2545F4914F6CDD1D).to_le_bytes();
+            chunk.copy_from_slice(&v[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        let mut state = self.state.lock_irq().unwrap();
+        for b in buf {
+            *state = (*state).rotate_left(8) ^ (*b as u64);
+        }
+        if *state == 0 {
+            *state = 1;
+        }
+        Ok(buf.len())
+    }
+}
+
+// /dev/tty是打开者的控制终端
+pub const CTTY_DEV:DevId = DevId::new(TTY_MAJOR,0);
+pub const CONSOLE_DEV:DevId = DevId::new(TTY_MAJOR,1);
+
+pub fn chardev_init(){
+    let random:Arc<dyn CharDevice> = Arc::new(RandomDev::new());
+    let console:Arc<dyn CharDevice> = console_tty();
+    register_chrdev("null",DevId::new(MEM_MAJOR,3),Arc::new(NullDev)).unwrap();
+    register_chrdev("zero",DevId::new(MEM_MAJOR,5),Arc::new(ZeroDev)).unwrap();
+    register_chrdev("full",DevId::new(MEM_MAJOR,7),Arc::new(FullDev)).unwrap();
+    register_chrdev("random",DevId::new(MEM_MAJOR,8),random.clone()).unwrap();
+    register_chrdev("urandom",DevId::new(MEM_MAJOR,9),random).unwrap();
+    register_chrdev("tty",CTTY_DEV,console.clone()).unwrap();
+    register_chrdev("console",CONSOLE_DEV,console).unwrap();
+    register_chrdev("ptmx",PTMX_DEV,Arc::new(PtmxDev)).unwrap();
+}
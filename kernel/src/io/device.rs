@@ -0,0 +1,187 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use crate::fs::poll::{DEFAULT_POLLMASK, PollEvents};
+use crate::io::BlockReadWrite;
+use crate::io::bcache::{bcache_read, bcache_write};
+use crate::io::chardev::chardev_init;
+use crate::io::partition::register_partitions;
+use crate::io::rtc::rtc_init;
+use crate::syscall::errno::{EEXIST, ENOTTY};
+use crate::task::wait::Waiter;
+use crate::{info_sync, SpinLock};
+
+// 主设备号，与linux一致
+pub const MEM_MAJOR:u32 = 1;
+pub const TTY_MAJOR:u32 = 5;
+pub const MMC_MAJOR:u32 = 179;
+pub const VIRTBLK_MAJOR:u32 = 254;
+// 每个磁盘预留给分区的次设备号
+pub const DISK_MINORS:u32 = 16;
+
+pub const SECTOR_SIZE:usize = 512;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct DevId{
+    pub major:u32,
+    pub minor:u32,
+}
+
+impl DevId {
+    pub const fn new(major:u32,minor:u32)->Self{
+        Self{
+            major,
+            minor
+        }
+    }
+    // linux的dev_t编码
+    pub fn encode(&self)->u64{
+        let major = self.major as u64;
+        let minor = self.minor as u64;
+        (minor & 0xff) | ((major & 0xfff) << 8) | ((minor & !0xff) << 12) | ((major & !0xfff) << 32)
+    }
+}
+
+// 字符设备驱动，没有文件偏移
+pub trait CharDevice: Send + Sync {
+    fn read(&self, buf:&mut [u8])->Result<usize,isize>;
+    fn write(&self, buf:&[u8])->Result<usize,isize>;
+    // arg一般是用户空间指针
+    fn ioctl(&self, cmd:usize, arg:usize)->Result<usize,isize>{
+        Err(-ENOTTY)
+    }
+    // 默认总是可读写
+    fn poll(&self, waiter:Option<&Arc<Waiter>>)->PollEvents{
+        DEFAULT_POLLMASK
+    }
+}
+
+#[derive(Clone)]
+pub enum Device{
+    Char(Arc<dyn CharDevice>),
+    Block(Arc<dyn BlockReadWrite>),
+}
+
+#[derive(Clone)]
+pub struct DeviceEntry{
+    pub name:String,
+    pub id:DevId,
+    pub dev:Device,
+}
+
+lazy_static!{
+    // 按注册顺序排列，devfs按这个顺序列出设备
+    static ref DEVICES:SpinLock<Vec<DeviceEntry>> = SpinLock::new(Vec::new());
+}
+
+fn register_device(name:&str,id:DevId,dev:Device)->Result<(),isize>{
+    let mut devices = DEVICES.lock_irq().unwrap();
+    if devices.iter().any(|d| d.name.eq(name) || d.id == id) {
+        return Err(-EEXIST);
+    }
+    info_sync!("register device {} {}:{}",name,id.major,id.minor);
+    devices.push(DeviceEntry{
+        name: name.to_string(),
+        id,
+        dev
+    });
+    Ok(())
+}
+
+pub fn register_chrdev(name:&str,id:DevId,dev:Arc<dyn CharDevice>)->Result<(),isize>{
+    register_device(name,id,Device::Char(dev))
+}
+
+pub fn register_blkdev(name:&str,id:DevId,dev:Arc<dyn BlockReadWrite>)->Result<(),isize>{
+    register_device(name,id,Device::Block(dev))
+}
+
+pub fn find_device(name:&str)->Option<DeviceEntry>{
+    DEVICES.lock_irq().unwrap().iter().find(|d| d.name.eq(name)).map(|d| d.clone())
+}
+
+pub fn get_chrdev(id:DevId)->Option<Arc<dyn CharDevice>>{
+    DEVICES.lock_irq().unwrap().iter().find_map(|d|{
+        match &d.dev {
+            Device::Char(c) if d.id == id => Some(c.clone()),
+            _ => None
+        }
+    })
+}
+
+pub fn get_blkdev(id:DevId)->Option<Arc<dyn BlockReadWrite>>{
+    DEVICES.lock_irq().unwrap().iter().find_map(|d|{
+        match &d.dev {
+            Device::Block(b) if d.id == id => Some(b.clone()),
+            _ => None
+        }
+    })
+}
+
+pub fn list_devices()->Vec<DeviceEntry>{
+    DEVICES.lock_irq().unwrap().clone()
+}
+
+// 初始化rtc和中断控制器，注册字符设备、探测到的块设备和其中的分区
+pub fn device_init(){
+    rtc_init();
+    chardev_init();
+    #[cfg(feature = "qemu")]
+    {
+        crate::trap::plic::plic_init_hart();
+        crate::io::uart::uart_init();
+        let mut disk = 0;
+        for slot in 0..8 {
+            if let Some(blk) = crate::io::virtio::get_virtio_blk(slot) {
+                let name = alloc::format!("vd{}",(b'a'+disk as u8) as char);
+                let id = DevId::new(VIRTBLK_MAJOR,disk*DISK_MINORS);
+                register_blkdev(&name,id,blk.clone()).unwrap();
+                register_partitions(&name,id,&blk);
+                disk += 1;
+            }
+        }
+    }
+    #[cfg(feature = "k210")]
+    {
+        let blk = crate::io::sdcard::get_sdcard();
+        register_blkdev("mmcblk0",DevId::new(MMC_MAJOR,0),blk.clone()).unwrap();
+        register_partitions("mmcblk0",DevId::new(MMC_MAJOR,0),&blk);
+    }
+}
+
+// 设备末尾之后的部分不读写
+fn blk_clamp(dev:&Arc<dyn BlockReadWrite>, off:usize, len:usize)->usize{
+    match dev.num_blocks() {
+        Some(n) => min(len,(n*SECTOR_SIZE).saturating_sub(off)),
+        None => len
+    }
+}
+
+// 按字节偏移读写块设备，经过块缓存
+// 出错时已经读写的部分不算，与linux对块设备的处理一样返回错误
+pub fn blk_read_at(dev:&Arc<dyn BlockReadWrite>, off:usize, buf:&mut [u8])->Result<usize,isize>{
+    let buf = &mut buf[..blk_clamp(dev,off,buf.len())];
+    let mut pos = 0;
+    while pos < buf.len() {
+        let cur = off+pos;
+        let sec_off = cur%SECTOR_SIZE;
+        let n = min(buf.len()-pos,SECTOR_SIZE-sec_off);
+        bcache_read(dev,cur/SECTOR_SIZE,sec_off,&mut buf[pos..pos+n])?;
+        pos += n;
+    }
+    Ok(pos)
+}
+
+pub fn blk_write_at(dev:&Arc<dyn BlockReadWrite>, off:usize, buf:&[u8])->Result<usize,isize>{
+    let buf = &buf[..blk_clamp(dev,off,buf.len())];
+    let mut pos = 0;
+    while pos < buf.len() {
+        let cur = off+pos;
+        let sec_off = cur%SECTOR_SIZE;
+        let n = min(buf.len()-pos,SECTOR_SIZE-sec_off);
+        bcache_write(dev,cur/SECTOR_SIZE,sec_off,&buf[pos..pos+n])?;
+        pos += n;
+    }
+    Ok(pos)
+}
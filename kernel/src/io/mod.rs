@@ -1,14 +1,29 @@
+pub mod bcache;
 pub mod virtio;
 pub mod sdcard;
+pub mod device;
+pub mod chardev;
+pub mod rtc;
+pub mod tty;
+pub mod pty;
+pub mod uart;
+pub mod partition;
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::any::Any;
 use fatfs::IntoStorage;
 use virtio::VirtioDev;
 use crate::fs::fat::BlkStorage;
 use crate::io::virtio::virtio_test;
+use crate::io::partition::partition_test;
+use crate::io::tty::tty_test;
+use crate::io::bcache::bcache_test;
+use crate::io::device::{Device, find_device, SECTOR_SIZE};
+use crate::syscall::errno::EIO;
+use crate::SpinLock;
 
 pub struct IOBytes<T>{
     inner:T
@@ -113,18 +128,78 @@ pub trait Read {
     // }
 }
 
+// 读写失败时返回负的errno
 pub trait BlockRead{
-    fn read_block(&self,blk_no:usize,buf:&mut [u8]);
+    fn read_block(&self,blk_no:usize,buf:&mut [u8])->Result<(),isize>;
+    // 设备的扇区数，不知道时为None
+    fn num_blocks(&self)->Option<usize>{
+        None
+    }
+    // 从blk_no开始连续读入多个缓冲区，每个缓冲区是整数个扇区
+    // 支持分散/聚集的设备用一个请求完成
+    fn read_blocks(&self,blk_no:usize,bufs:&mut [&mut [u8]])->Result<(),isize>{
+        let mut blk = blk_no;
+        for buf in bufs.iter_mut() {
+            for sec in buf.chunks_mut(SECTOR_SIZE) {
+                self.read_block(blk,sec)?;
+                blk += 1;
+            }
+        }
+        Ok(())
+    }
 }
 
 pub trait BlockWrite{
-    fn write_block(&self,blk_no:usize,buf:&[u8]);
+    fn write_block(&self,blk_no:usize,buf:&[u8])->Result<(),isize>;
+    fn write_blocks(&self,blk_no:usize,bufs:&[&[u8]])->Result<(),isize>{
+        let mut blk = blk_no;
+        for buf in bufs.iter() {
+            for sec in buf.chunks(SECTOR_SIZE) {
+                self.write_block(blk,sec)?;
+                blk += 1;
+            }
+        }
+        Ok(())
+    }
 }
 
 pub trait BlockReadWrite:BlockRead+BlockWrite+Send+Sync+Any{
 
 }
 
+// 块设备以Arc<dyn BlockReadWrite>的形式在文件系统间共享
+impl<T:BlockRead+?Sized> BlockRead for Arc<T> {
+    fn read_block(&self, blk_no: usize, buf: &mut [u8]) -> Result<(), isize> {
+        (**self).read_block(blk_no,buf)
+    }
+    fn num_blocks(&self) -> Option<usize> {
+        (**self).num_blocks()
+    }
+    fn read_blocks(&self, blk_no: usize, bufs: &mut [&mut [u8]]) -> Result<(), isize> {
+        (**self).read_blocks(blk_no,bufs)
+    }
+}
+
+impl<T:BlockWrite+?Sized> BlockWrite for Arc<T> {
+    fn write_block(&self, blk_no: usize, buf: &[u8]) -> Result<(), isize> {
+        (**self).write_block(blk_no,buf)
+    }
+    fn write_blocks(&self, blk_no: usize, bufs: &[&[u8]]) -> Result<(), isize> {
+        (**self).write_blocks(blk_no,bufs)
+    }
+}
+
+impl BlockReadWrite for Arc<dyn BlockReadWrite>{}
+
+// 根据设备名获取注册的块设备，名字可以带/dev/前缀
+pub fn get_blk_dev(name:&str)->Option<Arc<dyn BlockReadWrite>>{
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    match find_device(name)?.dev {
+        Device::Block(b) => Some(b),
+        Device::Char(_) => None
+    }
+}
+
 pub trait BlockReadBuf:BlockRead{
 
 }
@@ -133,6 +208,51 @@ pub trait BlockWriteBuf:BlockWrite{
 
 }
 
+// 内存中的块设备，测试时代替磁盘
+pub struct MemDisk {
+    data:SpinLock<Vec<u8>>,
+}
+
+impl MemDisk {
+    pub fn new(nr_blocks:usize)->Self{
+        Self{
+            data: SpinLock::new(vec![0u8;nr_blocks*SECTOR_SIZE])
+        }
+    }
+}
+
+impl BlockRead for MemDisk {
+    fn read_block(&self, blk_no: usize, buf: &mut [u8]) -> Result<(), isize> {
+        let data = self.data.lock_irq().unwrap();
+        let off = blk_no*SECTOR_SIZE;
+        if off+buf.len() > data.len() {
+            return Err(-EIO);
+        }
+        buf.copy_from_slice(&data[off..off+buf.len()]);
+        Ok(())
+    }
+    fn num_blocks(&self) -> Option<usize> {
+        Some(self.data.lock_irq().unwrap().len()/SECTOR_SIZE)
+    }
+}
+
+impl BlockWrite for MemDisk {
+    fn write_block(&self, blk_no: usize, buf: &[u8]) -> Result<(), isize> {
+        let mut data = self.data.lock_irq().unwrap();
+        let off = blk_no*SECTOR_SIZE;
+        if off+buf.len() > data.len() {
+            return Err(-EIO);
+        }
+        data[off..off+buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl BlockReadWrite for MemDisk{}
+
 pub fn io_test(){
     virtio_test();
+    partition_test();
+    tty_test();
+    bcache_test();
 }
\ No newline at end of file
@@ -0,0 +1,339 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use crate::info_sync;
+use crate::io::{BlockRead, BlockReadWrite, BlockWrite, MemDisk};
+use crate::io::device::{register_blkdev, DevId, DISK_MINORS, SECTOR_SIZE};
+use crate::syscall::errno::EIO;
+
+// 磁盘上的一个分区，块号相对于分区开头
+pub struct Partition {
+    disk:Arc<dyn BlockReadWrite>,
+    start:usize,
+    len:usize,
+}
+
+impl Partition {
+    // 越过分区末尾的访问与越过磁盘末尾一样返回EIO
+    fn check(&self,blk_no:usize,cnt:usize)->Result<(),isize>{
+        if blk_no.checked_add(cnt).map_or(true,|end| end > self.len) {
+            return Err(-EIO);
+        }
+        Ok(())
+    }
+}
+
+impl BlockRead for Partition {
+    fn read_block(&self, blk_no: usize, buf: &mut [u8]) -> Result<(), isize> {
+        self.check(blk_no,1)?;
+        self.disk.read_block(self.start+blk_no,buf)
+    }
+    fn num_blocks(&self) -> Option<usize> {
+        Some(self.len)
+    }
+    fn read_blocks(&self, blk_no: usize, bufs: &mut [&mut [u8]]) -> Result<(), isize> {
+        self.check(blk_no,bufs.iter().map(|b| b.len()/SECTOR_SIZE).sum())?;
+        self.disk.read_blocks(self.start+blk_no,bufs)
+    }
+}
+
+impl BlockWrite for Partition {
+    fn write_block(&self, blk_no: usize, buf: &[u8]) -> Result<(), isize> {
+        self.check(blk_no,1)?;
+        self.disk.write_block(self.start+blk_no,buf)
+    }
+    fn write_blocks(&self, blk_no: usize, bufs: &[&[u8]]) -> Result<(), isize> {
+        self.check(blk_no,bufs.iter().map(|b| b.len()/SECTOR_SIZE).sum())?;
+        self.disk.write_blocks(self.start+blk_no,bufs)
+    }
+}
+
+impl BlockReadWrite for Partition{}
+
+// MBR
+const MBR_SIGNATURE:[u8;2] = [0x55,0xaa];
+const MBR_SIGNATURE_OFF:usize = 510;
+const MBR_ENTRY_OFF:usize = 446;
+const MBR_ENTRY_SIZE:usize = 16;
+const MBR_TYPE_EMPTY:u8 = 0x00;
+const MBR_TYPE_EXTENDED:[u8;3] = [0x05,0x0f,0x85];
+// GPT的保护MBR
+const MBR_TYPE_GPT:u8 = 0xee;
+// 扩展分区链表的长度限制，防止构造的环
+const MAX_LOGICAL:usize = 64;
+
+// GPT
+const GPT_HEADER_LBA:usize = 1;
+const GPT_SIGNATURE:&[u8;8] = b"EFI PART";
+const GPT_MAX_ENTRIES:usize = 128;
+
+// 分区在磁盘上的起始扇区和扇区数，编号从1开始，逻辑分区从5开始
+pub struct PartInfo {
+    pub index:usize,
+    pub start:usize,
+    pub len:usize,
+}
+
+fn le_u32(buf:&[u8],off:usize)->u32{
+    u32::from_le_bytes(buf[off..off+4].try_into().unwrap())
+}
+
+fn le_u64(buf:&[u8],off:usize)->u64{
+    u64::from_le_bytes(buf[off..off+8].try_into().unwrap())
+}
+
+// 读失败时当作没有分区表
+fn read_sector(disk:&Arc<dyn BlockReadWrite>,blk:usize)->Option<Vec<u8>>{
+    let mut buf = vec![0u8;SECTOR_SIZE];
+    disk.read_block(blk,&mut buf).ok()?;
+    Some(buf)
+}
+
+struct MbrEntry {
+    boot:u8,
+    ptype:u8,
+    start:usize,
+    len:usize,
+}
+
+// 没有0x55aa签名时返回None
+fn mbr_entries(sector:&[u8])->Option<Vec<MbrEntry>>{
+    if sector[MBR_SIGNATURE_OFF..MBR_SIGNATURE_OFF+2] != MBR_SIGNATURE {
+        return None;
+    }
+    Some((0..4).map(|i|{
+        let e = &sector[MBR_ENTRY_OFF+i*MBR_ENTRY_SIZE..MBR_ENTRY_OFF+(i+1)*MBR_ENTRY_SIZE];
+        MbrEntry{
+            boot: e[0],
+            ptype: e[4],
+            start: le_u32(e,8) as usize,
+            len: le_u32(e,12) as usize
+        }
+    }).collect())
+}
+
+// 没有分区表的FAT也以0x55aa结尾，与linux一样用引导标志区分，引导代码一般不是0或0x80
+fn parse_mbr(disk:&Arc<dyn BlockReadWrite>,sector:&[u8],disk_len:usize,parts:&mut Vec<PartInfo>)->bool{
+    let entries = match mbr_entries(sector) {
+        Some(e) => e,
+        None => {
+            return false;
+        }
+    };
+    if entries.iter().any(|e| e.boot != 0 && e.boot != 0x80) {
+        return false;
+    }
+    if entries.iter().any(|e| e.ptype == MBR_TYPE_GPT) {
+        return parse_gpt(disk,disk_len,parts);
+    }
+    for (i,e) in entries.iter().enumerate() {
+        if e.ptype == MBR_TYPE_EMPTY || e.len == 0 {
+            continue;
+        }
+        if MBR_TYPE_EXTENDED.contains(&e.ptype) {
+            parse_extended(disk,e.start,disk_len,parts);
+        } else {
+            parts.push(PartInfo{
+                index: i+1,
+                start: e.start,
+                len: e.len
+            });
+        }
+    }
+    true
+}
+
+// 扩展分区中每个EBR的第一项是逻辑分区(相对这个EBR)，第二项指向下一个EBR(相对扩展分区开头)
+fn parse_extended(disk:&Arc<dyn BlockReadWrite>,ext_start:usize,disk_len:usize,parts:&mut Vec<PartInfo>){
+    let mut ebr = ext_start;
+    let mut index = 5;
+    for _ in 0..MAX_LOGICAL {
+        if ebr >= disk_len {
+            return;
+        }
+        let entries = match read_sector(disk,ebr).and_then(|s| mbr_entries(&s)) {
+            Some(e) => e,
+            None => {
+                return;
+            }
+        };
+        let (part,next) = (&entries[0],&entries[1]);
+        if part.ptype != MBR_TYPE_EMPTY && part.len != 0 {
+            parts.push(PartInfo{
+                index,
+                start: ebr+part.start,
+                len: part.len
+            });
+            index += 1;
+        }
+        if !MBR_TYPE_EXTENDED.contains(&next.ptype) || next.start == 0 {
+            return;
+        }
+        ebr = ext_start+next.start;
+    }
+}
+
+// 不校验crc，只检查签名和分区项的范围
+fn parse_gpt(disk:&Arc<dyn BlockReadWrite>,disk_len:usize,parts:&mut Vec<PartInfo>)->bool{
+    let header = match read_sector(disk,GPT_HEADER_LBA) {
+        Some(h) => h,
+        None => {
+            return false;
+        }
+    };
+    if &header[0..8] != GPT_SIGNATURE {
+        return false;
+    }
+    let entry_lba = le_u64(&header,72) as usize;
+    let nr_entries = (le_u32(&header,80) as usize).min(GPT_MAX_ENTRIES);
+    let entry_size = le_u32(&header,84) as usize;
+    if entry_size < 128 || entry_size > SECTOR_SIZE || SECTOR_SIZE%entry_size != 0 {
+        return false;
+    }
+    let per_sector = SECTOR_SIZE/entry_size;
+    let mut sector = Vec::new();
+    for i in 0..nr_entries {
+        if i%per_sector == 0 {
+            let lba = entry_lba+i/per_sector;
+            if lba >= disk_len {
+                break;
+            }
+            sector = match read_sector(disk,lba) {
+                Some(s) => s,
+                None => {
+                    break;
+                }
+            };
+        }
+        let e = &sector[(i%per_sector)*entry_size..(i%per_sector+1)*entry_size];
+        // 类型GUID全0的项未使用
+        if e[0..16].iter().all(|b| *b == 0) {
+            continue;
+        }
+        let (first,last) = (le_u64(e,32) as usize,le_u64(e,40) as usize);
+        if last < first {
+            continue;
+        }
+        parts.push(PartInfo{
+            index: i+1,
+            start: first,
+            len: last-first+1
+        });
+    }
+    true
+}
+
+// 读取磁盘的分区表，没有分区表时返回空
+pub fn read_partitions(disk:&Arc<dyn BlockReadWrite>)->Vec<PartInfo>{
+    let disk_len = disk.num_blocks().unwrap_or(usize::MAX);
+    let mut parts = Vec::new();
+    if let Some(sector) = read_sector(disk,0) {
+        parse_mbr(disk,&sector,disk_len,&mut parts);
+    }
+    // 超出磁盘的分区丢弃，编号用完的也不注册
+    parts.retain(|p| p.len != 0 && p.start.checked_add(p.len).map_or(false,|end| end <= disk_len) && p.index < DISK_MINORS as usize);
+    parts
+}
+
+// 与linux一致，磁盘名以数字结尾时中间加p，如vda1、mmcblk0p1
+pub fn partition_name(disk:&str,index:usize)->String{
+    if disk.ends_with(|c:char| c.is_ascii_digit()) {
+        format!("{}p{}",disk,index)
+    } else {
+        format!("{}{}",disk,index)
+    }
+}
+
+// 注册磁盘的各个分区，次设备号是磁盘的次设备号加分区编号
+pub fn register_partitions(name:&str,id:DevId,disk:&Arc<dyn BlockReadWrite>){
+    for p in read_partitions(disk) {
+        let pname = partition_name(name,p.index);
+        info_sync!("{}: start {} sectors {}",pname,p.start,p.len);
+        let part:Arc<dyn BlockReadWrite> = Arc::new(Partition{
+            disk: disk.clone(),
+            start: p.start,
+            len: p.len
+        });
+        if let Err(e) = register_blkdev(&pname,DevId::new(id.major,id.minor+p.index as u32),part) {
+            info_sync!("register {} fail: {}",pname,e);
+        }
+    }
+}
+
+fn put_mbr_entry(sector:&mut [u8],i:usize,ptype:u8,start:u32,len:u32){
+    let e = &mut sector[MBR_ENTRY_OFF+i*MBR_ENTRY_SIZE..MBR_ENTRY_OFF+(i+1)*MBR_ENTRY_SIZE];
+    e[4] = ptype;
+    e[8..12].copy_from_slice(&start.to_le_bytes());
+    e[12..16].copy_from_slice(&len.to_le_bytes());
+}
+
+fn mbr_sector()->Vec<u8>{
+    let mut sector = vec![0u8;SECTOR_SIZE];
+    sector[MBR_SIGNATURE_OFF..MBR_SIGNATURE_OFF+2].copy_from_slice(&MBR_SIGNATURE);
+    sector
+}
+
+fn part_list(disk:&Arc<dyn BlockReadWrite>)->Vec<(usize,usize,usize)>{
+    read_partitions(disk).iter().map(|p| (p.index,p.start,p.len)).collect()
+}
+
+// 在内存磁盘上构造MBR、扩展分区链和GPT
+pub fn partition_test(){
+    let disk:Arc<dyn BlockReadWrite> = Arc::new(MemDisk::new(256));
+    assert!(part_list(&disk).is_empty());
+
+    // 第三个主分区超出磁盘
+    let mut mbr = mbr_sector();
+    put_mbr_entry(&mut mbr,0,0x83,2,10);
+    put_mbr_entry(&mut mbr,1,0x05,20,40);
+    put_mbr_entry(&mut mbr,2,0x83,100,500);
+    disk.write_block(0,&mbr).unwrap();
+    let mut ebr = mbr_sector();
+    put_mbr_entry(&mut ebr,0,0x83,1,5);
+    put_mbr_entry(&mut ebr,1,0x05,10,10);
+    disk.write_block(20,&ebr).unwrap();
+    let mut ebr = mbr_sector();
+    put_mbr_entry(&mut ebr,0,0x83,2,3);
+    disk.write_block(30,&ebr).unwrap();
+    assert_eq!(part_list(&disk),vec![(1,2,10),(5,21,5),(6,32,3)]);
+
+    // 引导标志不是0或0x80时是没有分区表的FAT
+    mbr[MBR_ENTRY_OFF] = 0xeb;
+    disk.write_block(0,&mbr).unwrap();
+    assert!(part_list(&disk).is_empty());
+
+    // 第二项未使用，第四项结束早于开始
+    let mut mbr = mbr_sector();
+    put_mbr_entry(&mut mbr,0,MBR_TYPE_GPT,1,255);
+    disk.write_block(0,&mbr).unwrap();
+    let mut header = vec![0u8;SECTOR_SIZE];
+    header[0..8].copy_from_slice(GPT_SIGNATURE);
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&4u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    disk.write_block(GPT_HEADER_LBA,&header).unwrap();
+    let mut entries = vec![0u8;SECTOR_SIZE];
+    for &(i,first,last) in [(0usize,34u64,99u64),(2,100,149),(3,200,150)].iter() {
+        let e = &mut entries[i*128..(i+1)*128];
+        e[0] = 1;
+        e[32..40].copy_from_slice(&first.to_le_bytes());
+        e[40..48].copy_from_slice(&last.to_le_bytes());
+    }
+    disk.write_block(2,&entries).unwrap();
+    assert_eq!(part_list(&disk),vec![(1,34,66),(3,100,50)]);
+
+    // 分区内的块号越界返回EIO
+    let part = Partition{ disk: disk.clone(), start: 2, len: 10 };
+    let mut buf = vec![0u8;2*SECTOR_SIZE];
+    assert_eq!(part.read_block(9,&mut buf[..SECTOR_SIZE]),Ok(()));
+    assert_eq!(part.read_block(10,&mut buf[..SECTOR_SIZE]),Err(-EIO));
+    assert_eq!(part.read_blocks(8,&mut [&mut buf[..]]),Ok(()));
+    assert_eq!(part.write_blocks(9,&[&buf[..]]),Err(-EIO));
+
+    assert_eq!(partition_name("vda",1),"vda1");
+    assert_eq!(partition_name("mmcblk0",2),"mmcblk0p2");
+    info_sync!("partition test OK!");
+}
@@ -0,0 +1,288 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::size_of;
+use fatfs::{Read, Write};
+use crate::SpinLock;
+use crate::fs::poll::PollEvents;
+use crate::io::device::{CharDevice, DevId, TTY_MAJOR};
+use crate::io::tty::{console_tty, Tty, TtyDriver};
+use crate::mm::addr::Vaddr;
+use crate::mm::mm::user_access_ok;
+use crate::syscall::errno::{EAGAIN, EINTR, EIO, ENOSPC};
+use crate::task::cred::current_cred;
+use crate::task::signal::signal_pending;
+use crate::task::task::get_running;
+use crate::task::wait::{Waiter, WaitQueue};
+
+// /dev/ptmx和devpts中slave的设备号，与linux一致
+pub const PTMX_DEV:DevId = DevId::new(TTY_MAJOR,2);
+pub const PTS_MAJOR:u32 = 136;
+
+// master的ioctl，其余的ioctl由slave的终端处理
+pub const TIOCGPTN:usize = 0x80045430;
+pub const TIOCSPTLCK:usize = 0x40045431;
+pub const TIOCGPTLCK:usize = 0x80045439;
+
+// 最多同时存在的伪终端数
+const NR_PTYS:usize = 256;
+// slave输出等待master读取的缓冲区大小
+const PTY_BUF_SIZE:usize = 8192;
+
+// slave的输出，master从这里读取
+struct PtyOutput {
+    buf:SpinLock<VecDeque<u8>>,
+    // master读和slave写共用
+    wq:WaitQueue,
+}
+
+struct PtyDriver(Arc<PtyOutput>);
+
+impl TtyDriver for PtyDriver {
+    // 回显可能略微超过缓冲区大小
+    fn output(&self, buf: &[u8]) {
+        self.0.buf.lock_irq().unwrap().extend(buf.iter());
+        self.0.wq.wake_all();
+    }
+    fn write_room(&self) -> usize {
+        PTY_BUF_SIZE.saturating_sub(self.0.buf.lock_irq().unwrap().len())
+    }
+    fn write_wait(&self) -> Option<&WaitQueue> {
+        Some(&self.0.wq)
+    }
+}
+
+struct PtyInner {
+    // unlockpt之前不能打开slave
+    locked:bool,
+    slave_opens:usize,
+    // 打开过的slave全部关闭后master读返回EIO
+    slave_closed:bool,
+}
+
+// 一对伪终端，slave一侧是带行规程的终端
+pub struct Pty {
+    index:usize,
+    tty:Arc<Tty>,
+    out:Arc<PtyOutput>,
+    // slave设备文件属于打开ptmx的用户
+    uid:u32,
+    gid:u32,
+    inner:SpinLock<PtyInner>,
+}
+
+lazy_static!{
+    // master打开期间存在的伪终端，按编号排列
+    static ref PTYS:SpinLock<BTreeMap<usize,Arc<Pty>>> = SpinLock::new(BTreeMap::new());
+}
+
+pub fn find_pty(index:usize)->Option<Arc<Pty>>{
+    PTYS.lock_irq().unwrap().get(&index).cloned()
+}
+
+pub fn list_ptys()->Vec<Arc<Pty>>{
+    PTYS.lock_irq().unwrap().values().cloned().collect()
+}
+
+// 以slave为控制终端的会话
+pub fn session_pty(sid:usize)->Option<Arc<Pty>>{
+    list_ptys().into_iter().find(|p| p.tty.get_sid() == sid)
+}
+
+// 会话的控制终端，没有时返回None
+pub fn session_tty(sid:usize)->Option<Arc<Tty>>{
+    let console = console_tty();
+    if console.get_sid() == sid {
+        return Some(console);
+    }
+    session_pty(sid).map(|p| p.tty.clone())
+}
+
+impl Pty {
+    pub fn get_index(&self)->usize{
+        self.index
+    }
+    pub fn get_tty(&self)->Arc<Tty>{
+        self.tty.clone()
+    }
+    pub fn get_owner(&self)->(u32,u32){
+        (self.uid,self.gid)
+    }
+}
+
+// 打开/dev/ptmx得到的master，最后一个引用释放时挂断slave
+pub struct PtyMaster {
+    pty:Arc<Pty>,
+}
+
+impl PtyMaster {
+    // 分配最小的空闲编号，新的伪终端处于锁定状态
+    pub fn new()->Result<Self,isize>{
+        let cred = current_cred();
+        let mut ptys = PTYS.lock_irq().unwrap();
+        let index = (0..NR_PTYS).find(|i| !ptys.contains_key(i)).ok_or(-ENOSPC)?;
+        let out = Arc::new(PtyOutput{
+            buf: SpinLock::new(VecDeque::new()),
+            wq: WaitQueue::new()
+        });
+        let pty = Arc::new(Pty{
+            index,
+            tty: Arc::new(Tty::new(Box::new(PtyDriver(out.clone())))),
+            out,
+            uid: cred.fsuid,
+            gid: cred.fsgid,
+            inner: SpinLock::new(PtyInner{
+                locked: true,
+                slave_opens: 0,
+                slave_closed: false
+            })
+        });
+        ptys.insert(index,pty.clone());
+        Ok(Self{
+            pty
+        })
+    }
+    pub fn get_index(&self)->usize{
+        self.pty.index
+    }
+    // 读取slave的输出
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let out = &self.pty.out;
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            out.wq.add(&waiter);
+            let mut data = out.buf.lock_irq().unwrap();
+            if !data.is_empty() {
+                let n = min(data.len(),buf.len());
+                for (i,c) in data.drain(..n).enumerate() {
+                    buf[i] = c;
+                }
+                drop(data);
+                out.wq.wake_all();
+                return Ok(n);
+            }
+            drop(data);
+            if self.pty.inner.lock_irq().unwrap().slave_closed {
+                return Err(-EIO);
+            }
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            if signal_pending() {
+                return Err(-EINTR);
+            }
+            waiter.sleep();
+        }
+    }
+    // 写入的数据是slave的输入
+    pub fn write(&self,buf:&[u8])->Result<usize,isize>{
+        self.pty.tty.receive(buf);
+        Ok(buf.len())
+    }
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        if let Some(w) = waiter {
+            self.pty.out.wq.add(w);
+        }
+        let mut events = PollEvents::POLLOUT | PollEvents::POLLWRNORM;
+        if !self.pty.out.buf.lock_irq().unwrap().is_empty() {
+            events |= PollEvents::POLLIN | PollEvents::POLLRDNORM;
+        }
+        if self.pty.inner.lock_irq().unwrap().slave_closed {
+            events |= PollEvents::POLLHUP;
+        }
+        events
+    }
+    // termios和窗口大小等ioctl作用于slave的终端
+    pub fn ioctl(&self,cmd:usize,arg:usize)->Result<usize,isize>{
+        match cmd {
+            TIOCGPTN => {
+                user_access_ok(arg,size_of::<u32>(),true)?;
+                Vaddr(arg).write(&(self.pty.index as u32).to_ne_bytes()).unwrap();
+                Ok(0)
+            }
+            TIOCSPTLCK => {
+                user_access_ok(arg,size_of::<i32>(),false)?;
+                let mut bytes = [0u8;4];
+                Vaddr(arg).read(&mut bytes).unwrap();
+                self.pty.inner.lock_irq().unwrap().locked = i32::from_ne_bytes(bytes) != 0;
+                Ok(0)
+            }
+            TIOCGPTLCK => {
+                let locked = self.pty.inner.lock_irq().unwrap().locked as i32;
+                user_access_ok(arg,size_of::<i32>(),true)?;
+                Vaddr(arg).write(&locked.to_ne_bytes()).unwrap();
+                Ok(0)
+            }
+            _ => self.pty.tty.ioctl(cmd,arg)
+        }
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        PTYS.lock_irq().unwrap().remove(&self.pty.index);
+        self.pty.tty.hangup();
+    }
+}
+
+// 打开的slave，最后一个引用释放时计数减一
+pub struct PtySlave {
+    pty:Arc<Pty>,
+}
+
+impl PtySlave {
+    // 不是O_NOCTTY时，没有控制终端的会话leader以它为控制终端
+    pub fn open(pty:Arc<Pty>,noctty:bool)->Result<Self,isize>{
+        let mut inner = pty.inner.lock_irq().unwrap();
+        if inner.locked {
+            return Err(-EIO);
+        }
+        inner.slave_opens += 1;
+        inner.slave_closed = false;
+        drop(inner);
+        let (tgid,pgid,sid) = {
+            let running = get_running();
+            let t = running.lock_irq().unwrap();
+            (t.get_tgid(),t.get_pgid(),t.get_sid())
+        };
+        if !noctty && sid == tgid && session_tty(sid).is_none() {
+            pty.tty.set_session(sid,pgid);
+        }
+        Ok(Self{
+            pty
+        })
+    }
+    pub fn get_tty(&self)->Arc<Tty>{
+        self.pty.tty.clone()
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        let mut inner = self.pty.inner.lock_irq().unwrap();
+        inner.slave_opens -= 1;
+        if inner.slave_opens == 0 {
+            inner.slave_closed = true;
+            drop(inner);
+            self.pty.out.wq.wake_all();
+        }
+    }
+}
+
+// /dev/ptmx本身不能读写，打开时由DFile换成新的master
+pub struct PtmxDev;
+
+impl CharDevice for PtmxDev {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        Err(-EIO)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        Err(-EIO)
+    }
+}
@@ -0,0 +1,30 @@
+use crate::consts::DEV_REMAP_START;
+use crate::fdt::get_fdt_info;
+use crate::info_sync;
+use crate::trap::timer::{NSEC_PER_SEC, set_realtime_ns};
+
+// goldfish rtc，qemu virt平台提供
+// 读TIME_LOW时硬件锁存TIME_HIGH，值为unix纪元以来的纳秒
+const RTC_TIME_LOW:usize = 0x00;
+const RTC_TIME_HIGH:usize = 0x04;
+
+pub fn rtc_read_ns()->Option<u64>{
+    let base = get_fdt_info().rtc_base;
+    if base == 0 {
+        return None;
+    }
+    let va = base+DEV_REMAP_START;
+    unsafe {
+        let low = ((va+RTC_TIME_LOW) as *const u32).read_volatile() as u64;
+        let high = ((va+RTC_TIME_HIGH) as *const u32).read_volatile() as u64;
+        Some((high<<32)|low)
+    }
+}
+
+// 没有rtc时墙上时间从1970年开始
+pub fn rtc_init(){
+    if let Some(ns) = rtc_read_ns() {
+        set_realtime_ns(ns);
+        info_sync!("rtc: {} seconds since epoch",ns/NSEC_PER_SEC);
+    }
+}
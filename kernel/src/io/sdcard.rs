@@ -16,7 +16,9 @@ use k210_soc::{
 
 use lazy_static::*;
 use crate::{info_sync, println, SpinLock};
+use crate::syscall::errno::EIO;
 
+use alloc::sync::Arc;
 use core::convert::TryInto;
 use crate::{info, trace};
 use crate::io::{BlockRead, BlockReadWrite, BlockWrite};
@@ -761,14 +763,14 @@ pub struct SDCardDev{
 }
 
 impl BlockRead for SDCardDev {
-    fn read_block(&self, blk_no: usize, buf: &mut [u8]) {
-        self.inner.lock().unwrap().read_sector(buf,blk_no as u32).unwrap();
+    fn read_block(&self, blk_no: usize, buf: &mut [u8]) -> Result<(), isize> {
+        self.inner.lock().unwrap().read_sector(buf,blk_no as u32).map_err(|_| -EIO)
     }
 }
 
 impl BlockWrite for SDCardDev {
-    fn write_block(&self, blk_no: usize, buf: &[u8]) {
-        self.inner.lock().unwrap().write_sector(buf,blk_no as u32).unwrap();
+    fn write_block(&self, blk_no: usize, buf: &[u8]) -> Result<(), isize> {
+        self.inner.lock().unwrap().write_sector(buf,blk_no as u32).map_err(|_| -EIO)
     }
 }
 
@@ -782,6 +784,15 @@ impl SDCardDev {
     }
 }
 
+lazy_static!{
+    static ref SDCARD:Arc<SDCardDev> = Arc::new(SDCardDev::new());
+}
+
+// sd卡只初始化一次
+pub fn get_sdcard()->Arc<dyn BlockReadWrite>{
+    SDCARD.clone()
+}
+
 // pub struct SDCardWrapper(Mutex<SDCard<SPIImpl<SPI0>>>);
 //
 // impl SDCardWrapper {
@@ -0,0 +1,818 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::size_of;
+use core::mem::take;
+use core::sync::atomic::{AtomicBool, Ordering};
+use fatfs::{Read, Write};
+use crate::{info_sync, SpinLock};
+use crate::fs::poll::PollEvents;
+use crate::io::device::CharDevice;
+use crate::mm::addr::Vaddr;
+use crate::mm::mm::user_access_ok;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::syscall::errno::{EAGAIN, EINTR, EINVAL, EIO, ENOTTY, EPERM};
+use crate::task::find_task;
+use crate::task::cred::current_cred;
+use crate::task::signal::{pgrp_tasks, send_signal, send_signal_pgrp, signal_pending, SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGWINCH};
+use crate::task::task::get_running;
+use crate::task::wait::{Waiter, WaitQueue};
+use crate::trap::timer::{get_time_ns, NSEC_PER_SEC};
+
+// ioctl
+pub const TCGETS:usize = 0x5401;
+pub const TCSETS:usize = 0x5402;
+pub const TCSETSW:usize = 0x5403;
+pub const TCSETSF:usize = 0x5404;
+pub const TCFLSH:usize = 0x540B;
+pub const TIOCSCTTY:usize = 0x540E;
+pub const TIOCGPGRP:usize = 0x540F;
+pub const TIOCSPGRP:usize = 0x5410;
+pub const TIOCGWINSZ:usize = 0x5413;
+pub const TIOCSWINSZ:usize = 0x5414;
+pub const FIONREAD:usize = 0x541B;
+pub const TIOCNOTTY:usize = 0x5422;
+pub const TIOCGSID:usize = 0x5429;
+
+// TCFLSH的参数
+const TCIFLUSH:usize = 0;
+const TCOFLUSH:usize = 1;
+const TCIOFLUSH:usize = 2;
+
+// c_iflag
+const INLCR:u32 = 0o100;
+const IGNCR:u32 = 0o200;
+const ICRNL:u32 = 0o400;
+const IXON:u32 = 0o2000;
+// c_oflag
+const OPOST:u32 = 0o1;
+const ONLCR:u32 = 0o4;
+// c_cflag
+const B38400:u32 = 0o17;
+const CS8:u32 = 0o60;
+const CREAD:u32 = 0o200;
+// c_lflag
+const ISIG:u32 = 0o1;
+const ICANON:u32 = 0o2;
+const ECHO:u32 = 0o10;
+const ECHOE:u32 = 0o20;
+const ECHOK:u32 = 0o40;
+const ECHONL:u32 = 0o100;
+const NOFLSH:u32 = 0o200;
+const ECHOCTL:u32 = 0o1000;
+const ECHOKE:u32 = 0o4000;
+const IEXTEN:u32 = 0o100000;
+
+// c_cc的下标
+const VINTR:usize = 0;
+const VQUIT:usize = 1;
+const VERASE:usize = 2;
+const VKILL:usize = 3;
+const VEOF:usize = 4;
+const VTIME:usize = 5;
+const VMIN:usize = 6;
+const VSUSP:usize = 10;
+const VEOL:usize = 11;
+const VREPRINT:usize = 12;
+const VWERASE:usize = 14;
+const VLNEXT:usize = 15;
+const VEOL2:usize = 16;
+const NCCS:usize = 19;
+
+// 规范模式一行和非规范模式输入的最大长度
+const N_TTY_BUF_SIZE:usize = 4096;
+
+// asm-generic的struct termios
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Termios{
+    pub c_iflag:u32,
+    pub c_oflag:u32,
+    pub c_cflag:u32,
+    pub c_lflag:u32,
+    pub c_line:u8,
+    pub c_cc:[u8;NCCS],
+}
+
+impl Default for Termios {
+    // 与linux新建终端的默认值相同
+    fn default() -> Self {
+        Self{
+            c_iflag: ICRNL | IXON,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: B38400 | CS8 | CREAD,
+            c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            c_line: 0,
+            c_cc: *b"\x03\x1c\x7f\x15\x04\x00\x01\x00\x11\x13\x1a\x00\x12\x0f\x17\x16\x00\x00\x00"
+        }
+    }
+}
+
+impl Termios {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8,core::mem::size_of::<Self>()) }
+    }
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8,core::mem::size_of::<Self>()) }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct WinSize{
+    pub ws_row:u16,
+    pub ws_col:u16,
+    pub ws_xpixel:u16,
+    pub ws_ypixel:u16,
+}
+
+impl WinSize {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8,core::mem::size_of::<Self>()) }
+    }
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8,core::mem::size_of::<Self>()) }
+    }
+}
+
+// 终端的输出端，串口控制台或者伪终端的master
+pub trait TtyDriver: Send + Sync {
+    // 在时钟中断等上下文中也可能调用，不能sleep
+    fn output(&self, buf:&[u8]);
+    // 还能接收的输出字节数，为0时写者在write_wait上等待
+    fn write_room(&self)->usize{
+        usize::MAX
+    }
+    fn write_wait(&self)->Option<&WaitQueue>{
+        None
+    }
+}
+
+struct TtyInner {
+    termios:Termios,
+    winsize:WinSize,
+    // 控制这个终端的会话和其中的前台进程组，0表示没有
+    sid:usize,
+    pgrp:usize,
+    // 规范模式下正在编辑的行
+    line:Vec<u8>,
+    // 规范模式下已经完成的行，空行表示EOF
+    lines:VecDeque<Vec<u8>>,
+    // 非规范模式下的输入
+    raw:VecDeque<u8>,
+    // 下一个字符按字面输入
+    lnext:bool,
+    // 对端已经关闭，读返回EOF，写返回EIO
+    hung_up:bool,
+}
+
+impl TtyInner {
+    fn new()->Self{
+        Self{
+            termios: Termios::default(),
+            winsize: WinSize{ ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 },
+            sid: 0,
+            pgrp: 0,
+            line: Vec::new(),
+            lines: VecDeque::new(),
+            raw: VecDeque::new(),
+            lnext: false,
+            hung_up: false
+        }
+    }
+    fn lflag(&self,flag:u32)->bool{
+        self.termios.c_lflag & flag != 0
+    }
+    // c_cc中为0的控制字符被禁用
+    fn is_cc(&self,c:u8,idx:usize)->bool{
+        let v = self.termios.c_cc[idx];
+        v != 0 && v == c
+    }
+    fn flush_input(&mut self){
+        self.line.clear();
+        self.lines.clear();
+        self.raw.clear();
+        self.lnext = false;
+    }
+    fn available(&self)->usize{
+        if self.lflag(ICANON) {
+            self.lines.iter().map(|l| l.len()).sum()
+        } else {
+            self.raw.len()
+        }
+    }
+    // ECHOCTL时控制字符回显为^X
+    fn echo_char(&self,c:u8,echo:&mut Vec<u8>){
+        if !self.lflag(ECHO) {
+            return;
+        }
+        if self.lflag(ECHOCTL) && (c < 0x20 || c == 0x7f) && c != b'\t' && c != b'\n' {
+            echo.push(b'^');
+            echo.push(c ^ 0x40);
+        } else {
+            echo.push(c);
+        }
+    }
+    // 从编辑行删除一个字符，ECHOE时在屏幕上擦除
+    fn erase_char(&mut self,echo:&mut Vec<u8>)->bool{
+        let c = match self.line.pop() {
+            Some(c) => c,
+            None => {
+                return false;
+            }
+        };
+        if self.lflag(ECHO) && self.lflag(ECHOE) {
+            let width = if self.lflag(ECHOCTL) && (c < 0x20 || c == 0x7f) && c != b'\t' { 2 } else { 1 };
+            for _ in 0..width {
+                echo.extend_from_slice(b"\x08 \x08");
+            }
+        }
+        true
+    }
+    // 处理一个输入字符，产生的回显放入echo，需要发给前台进程组的信号放入sigs
+    fn receive_char(&mut self,mut c:u8,echo:&mut Vec<u8>,sigs:&mut Vec<usize>){
+        let canon = self.lflag(ICANON);
+        if self.lnext {
+            self.lnext = false;
+            if canon {
+                if self.line.len() < N_TTY_BUF_SIZE - 1 {
+                    self.line.push(c);
+                }
+            } else if self.raw.len() < N_TTY_BUF_SIZE {
+                self.raw.push_back(c);
+            }
+            self.echo_char(c,echo);
+            return;
+        }
+        let iflag = self.termios.c_iflag;
+        if c == b'\r' {
+            if iflag & IGNCR != 0 {
+                return;
+            }
+            if iflag & ICRNL != 0 {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag & INLCR != 0 {
+            c = b'\r';
+        }
+        if self.lflag(ISIG) {
+            let sig = if self.is_cc(c,VINTR) {
+                Some(SIGINT)
+            } else if self.is_cc(c,VQUIT) {
+                Some(SIGQUIT)
+            } else if self.is_cc(c,VSUSP) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(sig) = sig {
+                if !self.lflag(NOFLSH) {
+                    self.flush_input();
+                }
+                self.echo_char(c,echo);
+                sigs.push(sig);
+                return;
+            }
+        }
+        if self.lflag(IEXTEN) && self.is_cc(c,VLNEXT) {
+            self.lnext = true;
+            if self.lflag(ECHO) && self.lflag(ECHOCTL) {
+                echo.extend_from_slice(b"^\x08");
+            }
+            return;
+        }
+        if !canon {
+            if self.raw.len() < N_TTY_BUF_SIZE {
+                self.raw.push_back(c);
+                self.echo_char(c,echo);
+            }
+            return;
+        }
+        if self.is_cc(c,VERASE) {
+            self.erase_char(echo);
+            return;
+        }
+        if self.lflag(IEXTEN) && self.is_cc(c,VWERASE) {
+            while self.line.last().map_or(false,|c| *c == b' ' || *c == b'\t') {
+                self.erase_char(echo);
+            }
+            while self.line.last().map_or(false,|c| *c != b' ' && *c != b'\t') {
+                self.erase_char(echo);
+            }
+            return;
+        }
+        if self.is_cc(c,VKILL) {
+            if self.lflag(ECHOKE) || !self.lflag(ECHOK) {
+                while self.erase_char(echo) {}
+            } else {
+                self.line.clear();
+                self.echo_char(c,echo);
+                if self.lflag(ECHO) {
+                    echo.push(b'\n');
+                }
+            }
+            return;
+        }
+        if self.lflag(IEXTEN) && self.is_cc(c,VREPRINT) {
+            self.echo_char(c,echo);
+            if self.lflag(ECHO) {
+                echo.push(b'\n');
+                echo.extend_from_slice(&self.line);
+            }
+            return;
+        }
+        // EOF字符本身不放入行中，空行的EOF使read返回0
+        if self.is_cc(c,VEOF) {
+            let line = take(&mut self.line);
+            self.lines.push_back(line);
+            return;
+        }
+        if c == b'\n' || self.is_cc(c,VEOL) || self.is_cc(c,VEOL2) {
+            self.line.push(c);
+            let line = take(&mut self.line);
+            self.lines.push_back(line);
+            if c == b'\n' && self.lflag(ECHONL) && !self.lflag(ECHO) {
+                echo.push(b'\n');
+            } else {
+                self.echo_char(c,echo);
+            }
+            return;
+        }
+        if self.line.len() < N_TTY_BUF_SIZE - 1 {
+            self.line.push(c);
+            self.echo_char(c,echo);
+        }
+    }
+    // 规范模式每次最多读一行
+    // 非规范模式按VMIN和VTIME，VTIME以0.1秒为单位，deadline在第一次需要计时的时候设置
+    fn take_input(&mut self,buf:&mut [u8],deadline:&mut Option<u64>,timed_out:bool)->Option<usize>{
+        if self.lflag(ICANON) {
+            let mut line = self.lines.pop_front()?;
+            let n = min(line.len(),buf.len());
+            buf[..n].copy_from_slice(&line[..n]);
+            if n < line.len() {
+                line.drain(..n);
+                self.lines.push_front(line);
+            }
+            return Some(n);
+        }
+        let vmin = self.termios.c_cc[VMIN] as usize;
+        let vtime = self.termios.c_cc[VTIME] as u64 * NSEC_PER_SEC / 10;
+        let avail = self.raw.len();
+        let done = if vtime == 0 {
+            avail >= min(vmin,buf.len())
+        } else if vmin == 0 {
+            if deadline.is_none() {
+                *deadline = Some(get_time_ns() + vtime);
+            }
+            avail > 0 || timed_out
+        } else {
+            if avail > 0 && deadline.is_none() {
+                *deadline = Some(get_time_ns() + vtime);
+            }
+            avail >= min(vmin,buf.len()) || (avail > 0 && timed_out)
+        };
+        if !done {
+            return None;
+        }
+        let n = min(avail,buf.len());
+        for (i,c) in self.raw.drain(..n).enumerate() {
+            buf[i] = c;
+        }
+        Some(n)
+    }
+    // 关闭规范模式时未完成的行也可以读取，打开时已有的输入作为编辑行的开头
+    fn set_termios(&mut self,t:Termios){
+        let was_canon = self.lflag(ICANON);
+        self.termios = t;
+        let canon = self.lflag(ICANON);
+        if was_canon && !canon {
+            for l in take(&mut self.lines) {
+                self.raw.extend(l);
+            }
+            let line = take(&mut self.line);
+            self.raw.extend(line);
+        } else if !was_canon && canon {
+            self.line = self.raw.drain(..).take(N_TTY_BUF_SIZE - 1).collect();
+        }
+    }
+}
+
+// 终端和行规程
+pub struct Tty {
+    driver:Box<dyn TtyDriver>,
+    inner:SpinLock<TtyInner>,
+    // 有新输入时唤醒reader
+    wq_read:WaitQueue,
+}
+
+impl Tty {
+    pub fn new(driver:Box<dyn TtyDriver>)->Self{
+        Self{
+            driver,
+            inner: SpinLock::new(TtyInner::new()),
+            wq_read: WaitQueue::new()
+        }
+    }
+    // 没有控制会话时成为sid的控制终端
+    pub fn set_session(&self,sid:usize,pgrp:usize){
+        let mut inner = self.inner.lock_irq().unwrap();
+        if inner.sid == 0 {
+            inner.sid = sid;
+            inner.pgrp = pgrp;
+        }
+    }
+    pub fn get_sid(&self)->usize{
+        self.inner.lock_irq().unwrap().sid
+    }
+    // 对端关闭，向前台进程组和会话leader发送SIGHUP，之后终端不再属于会话
+    pub fn hangup(&self){
+        let mut inner = self.inner.lock_irq().unwrap();
+        inner.hung_up = true;
+        let (sid,pgrp) = (inner.sid,inner.pgrp);
+        inner.sid = 0;
+        inner.pgrp = 0;
+        drop(inner);
+        send_signal_pgrp(pgrp,SIGHUP);
+        if sid != pgrp {
+            if let Some(t) = find_task(sid) {
+                send_signal(&t,SIGHUP);
+            }
+        }
+        self.wq_read.wake_all();
+        if let Some(wq) = self.driver.write_wait() {
+            wq.wake_all();
+        }
+    }
+    // 设备一侧收到的输入，可能在中断中调用
+    pub fn receive(&self,data:&[u8]){
+        let mut echo = Vec::new();
+        let mut sigs = Vec::new();
+        let mut inner = self.inner.lock_irq().unwrap();
+        for c in data {
+            inner.receive_char(*c,&mut echo,&mut sigs);
+        }
+        let pgrp = inner.pgrp;
+        drop(inner);
+        if !echo.is_empty() {
+            self.output(&echo);
+        }
+        for sig in sigs {
+            send_signal_pgrp(pgrp,sig);
+        }
+        self.wq_read.wake_all();
+    }
+    // OPOST|ONLCR时把\n转换为\r\n
+    fn output(&self,buf:&[u8]){
+        let oflag = self.inner.lock_irq().unwrap().termios.c_oflag;
+        if oflag & OPOST == 0 || oflag & ONLCR == 0 {
+            self.driver.output(buf);
+            return;
+        }
+        for chunk in buf.split_inclusive(|c| *c == b'\n') {
+            match chunk.split_last() {
+                Some((b'\n',rest)) => {
+                    self.driver.output(rest);
+                    self.driver.output(b"\r\n");
+                }
+                _ => self.driver.output(chunk)
+            }
+        }
+    }
+    // 还不支持后台进程组读终端时的SIGTTIN
+    // 收到未屏蔽的信号时返回EINTR
+    pub fn read(&self,buf:&mut [u8],nonblock:bool)->Result<usize,isize>{
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let sig_wq = get_running().lock_irq().unwrap().sig_wait.clone();
+        let waiter = Waiter::new();
+        let mut deadline = None;
+        let mut timed_out = false;
+        loop {
+            waiter.prepare();
+            self.wq_read.add(&waiter);
+            sig_wq.add(&waiter);
+            let mut inner = self.inner.lock_irq().unwrap();
+            if let Some(n) = inner.take_input(buf,&mut deadline,timed_out) {
+                return Ok(n);
+            }
+            if inner.hung_up {
+                return Ok(0);
+            }
+            drop(inner);
+            if nonblock {
+                return Err(-EAGAIN);
+            }
+            if signal_pending() {
+                return Err(-EINTR);
+            }
+            timed_out = waiter.sleep_until(deadline);
+        }
+    }
+    // 输出端满时等待，已经写出一部分时不返回错误
+    pub fn write(&self,buf:&[u8],nonblock:bool)->Result<usize,isize>{
+        let waiter = Waiter::new();
+        let mut written = 0;
+        while written < buf.len() {
+            waiter.prepare();
+            if let Some(wq) = self.driver.write_wait() {
+                wq.add(&waiter);
+            }
+            let err = if self.inner.lock_irq().unwrap().hung_up {
+                -EIO
+            } else {
+                let room = self.driver.write_room();
+                if room != 0 {
+                    let n = min(room,buf.len()-written);
+                    self.output(&buf[written..written+n]);
+                    written += n;
+                    continue;
+                }
+                if nonblock {
+                    -EAGAIN
+                } else if signal_pending() {
+                    -EINTR
+                } else {
+                    waiter.sleep();
+                    continue;
+                }
+            };
+            return if written != 0 { Ok(written) } else { Err(err) };
+        }
+        Ok(written)
+    }
+    pub fn poll(&self,waiter:Option<&Arc<Waiter>>)->PollEvents{
+        if let Some(w) = waiter {
+            self.wq_read.add(w);
+            if let Some(wq) = self.driver.write_wait() {
+                wq.add(w);
+            }
+        }
+        let mut events = PollEvents::empty();
+        if self.driver.write_room() != 0 {
+            events |= PollEvents::POLLOUT | PollEvents::POLLWRNORM;
+        }
+        let inner = self.inner.lock_irq().unwrap();
+        let readable = if inner.lflag(ICANON) { !inner.lines.is_empty() } else { !inner.raw.is_empty() };
+        if readable {
+            events |= PollEvents::POLLIN | PollEvents::POLLRDNORM;
+        }
+        if inner.hung_up {
+            events |= PollEvents::POLLHUP;
+        }
+        events
+    }
+    // 不在终端所属会话中的进程不能查询和设置前台进程组
+    pub fn ioctl(&self,cmd:usize,arg:usize)->Result<usize,isize>{
+        let (pid,pgid,sid) = {
+            let running = get_running();
+            let t = running.lock_irq().unwrap();
+            (t.get_tgid(),t.get_pgid(),t.get_sid())
+        };
+        match cmd {
+            TCGETS => {
+                user_access_ok(arg,size_of::<Termios>(),true)?;
+                let t = self.inner.lock_irq().unwrap().termios;
+                Vaddr(arg).write(t.as_bytes()).unwrap();
+                Ok(0)
+            }
+            TCSETS|TCSETSW|TCSETSF => {
+                user_access_ok(arg,size_of::<Termios>(),false)?;
+                let mut t = Termios::default();
+                Vaddr(arg).read(t.as_bytes_mut()).unwrap();
+                let mut inner = self.inner.lock_irq().unwrap();
+                if cmd == TCSETSF {
+                    inner.flush_input();
+                }
+                inner.set_termios(t);
+                drop(inner);
+                self.wq_read.wake_all();
+                Ok(0)
+            }
+            TIOCGWINSZ => {
+                user_access_ok(arg,size_of::<WinSize>(),true)?;
+                let ws = self.inner.lock_irq().unwrap().winsize;
+                Vaddr(arg).write(ws.as_bytes()).unwrap();
+                Ok(0)
+            }
+            // 大小改变时通知前台进程组
+            TIOCSWINSZ => {
+                user_access_ok(arg,size_of::<WinSize>(),false)?;
+                let mut ws = WinSize{ ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+                Vaddr(arg).read(ws.as_bytes_mut()).unwrap();
+                let mut inner = self.inner.lock_irq().unwrap();
+                if inner.winsize != ws {
+                    inner.winsize = ws;
+                    let pgrp = inner.pgrp;
+                    drop(inner);
+                    send_signal_pgrp(pgrp,SIGWINCH);
+                }
+                Ok(0)
+            }
+            TIOCGPGRP|TIOCGSID => {
+                let inner = self.inner.lock_irq().unwrap();
+                if inner.sid == 0 || inner.sid != sid {
+                    return Err(-ENOTTY);
+                }
+                let v = (if cmd == TIOCGPGRP { inner.pgrp } else { inner.sid }) as i32;
+                drop(inner);
+                user_access_ok(arg,size_of::<i32>(),true)?;
+                Vaddr(arg).write(&v.to_ne_bytes()).unwrap();
+                Ok(0)
+            }
+            // 新的前台进程组必须在同一会话中
+            TIOCSPGRP => {
+                user_access_ok(arg,size_of::<i32>(),false)?;
+                let mut bytes = [0u8;4];
+                Vaddr(arg).read(&mut bytes).unwrap();
+                let pgrp = i32::from_ne_bytes(bytes);
+                if pgrp < 0 {
+                    return Err(-EINVAL);
+                }
+                if self.inner.lock_irq().unwrap().sid != sid || sid == 0 {
+                    return Err(-ENOTTY);
+                }
+                let same_session = pgrp_tasks(pgrp as usize).iter().any(|t| t.lock_irq().unwrap().get_sid() == sid);
+                if !same_session {
+                    return Err(-EPERM);
+                }
+                self.inner.lock_irq().unwrap().pgrp = pgrp as usize;
+                Ok(0)
+            }
+            // 会话leader获得控制终端，arg为1时root可以从其他会话夺取
+            TIOCSCTTY => {
+                let mut inner = self.inner.lock_irq().unwrap();
+                if inner.sid == sid && sid != 0 {
+                    return Ok(0);
+                }
+                if sid != pid || (inner.sid != 0 && !(arg == 1 && current_cred().euid == 0)) {
+                    return Err(-EPERM);
+                }
+                inner.sid = sid;
+                inner.pgrp = pgid;
+                Ok(0)
+            }
+            // 会话leader放弃控制终端
+            TIOCNOTTY => {
+                let mut inner = self.inner.lock_irq().unwrap();
+                if inner.sid != sid || sid == 0 {
+                    return Err(-ENOTTY);
+                }
+                if sid == pid {
+                    inner.sid = 0;
+                    inner.pgrp = 0;
+                }
+                Ok(0)
+            }
+            FIONREAD => {
+                let n = self.inner.lock_irq().unwrap().available() as i32;
+                user_access_ok(arg,size_of::<i32>(),true)?;
+                Vaddr(arg).write(&n.to_ne_bytes()).unwrap();
+                Ok(0)
+            }
+            // 输出不缓存，只需要清空输入
+            TCFLSH => {
+                match arg {
+                    TCIFLUSH|TCIOFLUSH => {
+                        self.inner.lock_irq().unwrap().flush_input();
+                        Ok(0)
+                    }
+                    TCOFLUSH => Ok(0),
+                    _ => Err(-EINVAL)
+                }
+            }
+            _ => Err(-ENOTTY)
+        }
+    }
+}
+
+// 通过设备文件访问时不支持O_NONBLOCK
+impl CharDevice for Tty {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        Tty::read(self,buf,false)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        Tty::write(self,buf,false)
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, isize> {
+        Tty::ioctl(self,cmd,arg)
+    }
+    fn poll(&self, waiter: Option<&Arc<Waiter>>) -> PollEvents {
+        Tty::poll(self,waiter)
+    }
+}
+
+// 通过sbi输出的控制台
+struct SbiConsole;
+
+impl TtyDriver for SbiConsole {
+    fn output(&self, buf: &[u8]) {
+        for c in buf {
+            console_putchar(*c as usize);
+        }
+    }
+}
+
+lazy_static!{
+    static ref CONSOLE_TTY:Arc<Tty> = Arc::new(Tty::new(Box::new(SbiConsole)));
+}
+
+pub fn console_tty()->Arc<Tty>{
+    CONSOLE_TTY.clone()
+}
+
+// 串口接收中断可用之前，控制台输入在时钟中断中通过sbi读取
+static CONSOLE_POLLING:AtomicBool = AtomicBool::new(true);
+
+pub fn console_stop_polling(){
+    CONSOLE_POLLING.store(false,Ordering::Release);
+}
+
+pub fn console_poll(){
+    if !CONSOLE_POLLING.load(Ordering::Acquire) {
+        return;
+    }
+    let mut buf = [0u8;64];
+    let mut len = 0;
+    while len < buf.len() {
+        let c = console_getchar();
+        if c == usize::MAX {
+            break;
+        }
+        buf[len] = c as u8;
+        len += 1;
+    }
+    if len != 0 {
+        CONSOLE_TTY.receive(&buf[..len]);
+    }
+}
+
+// 向行规程输入data，返回回显和信号
+fn feed(inner:&mut TtyInner,data:&[u8])->(Vec<u8>,Vec<usize>){
+    let mut echo = Vec::new();
+    let mut sigs = Vec::new();
+    for c in data {
+        inner.receive_char(*c,&mut echo,&mut sigs);
+    }
+    (echo,sigs)
+}
+
+fn read_input(inner:&mut TtyInner,len:usize)->Option<Vec<u8>>{
+    let mut buf = vec![0u8;len];
+    let n = inner.take_input(&mut buf,&mut None,false)?;
+    buf.truncate(n);
+    Some(buf)
+}
+
+// 规范模式的行编辑、信号字符和非规范模式的VMIN
+pub fn tty_test(){
+    let mut inner = TtyInner::new();
+    let (echo,sigs) = feed(&mut inner,b"ab\x7fc\r");
+    assert_eq!(echo,b"ab\x08 \x08c\n".to_vec());
+    assert!(sigs.is_empty());
+    assert_eq!(read_input(&mut inner,64),Some(b"ac\n".to_vec()));
+    assert_eq!(read_input(&mut inner,64),None);
+
+    // 每次最多读一行，读不完的部分留给下一次
+    feed(&mut inner,b"hello\nworld\n");
+    assert_eq!(inner.available(),12);
+    assert_eq!(read_input(&mut inner,3),Some(b"hel".to_vec()));
+    assert_eq!(read_input(&mut inner,64),Some(b"lo\n".to_vec()));
+    assert_eq!(read_input(&mut inner,64),Some(b"world\n".to_vec()));
+
+    // VKILL、VWERASE，空行的VEOF读到0字节
+    feed(&mut inner,b"xy\x15z\n");
+    assert_eq!(read_input(&mut inner,64),Some(b"z\n".to_vec()));
+    feed(&mut inner,b"foo bar\x17baz\n");
+    assert_eq!(read_input(&mut inner,64),Some(b"foo baz\n".to_vec()));
+    feed(&mut inner,b"\x04");
+    assert_eq!(read_input(&mut inner,64),Some(Vec::new()));
+
+    // VINTR丢弃编辑中的行，VLNEXT之后按字面输入
+    let (echo,sigs) = feed(&mut inner,b"ab\x03");
+    assert_eq!(sigs,vec![SIGINT]);
+    assert_eq!(echo,b"ab^C".to_vec());
+    assert!(inner.line.is_empty());
+    let (_,sigs) = feed(&mut inner,b"\x16\x03\n");
+    assert!(sigs.is_empty());
+    assert_eq!(read_input(&mut inner,64),Some(b"\x03\n".to_vec()));
+
+    // 关闭规范模式时编辑中的行变为可读
+    feed(&mut inner,b"pq");
+    let mut t = inner.termios;
+    t.c_lflag &= !ICANON;
+    t.c_cc[VMIN] = 4;
+    t.c_cc[VTIME] = 0;
+    inner.set_termios(t);
+    assert_eq!(read_input(&mut inner,64),None);
+    feed(&mut inner,b"r\r");
+    assert_eq!(read_input(&mut inner,64),Some(b"pqr\n".to_vec()));
+    // 缓冲区小于VMIN时读满即可返回
+    feed(&mut inner,b"s");
+    assert_eq!(read_input(&mut inner,1),Some(b"s".to_vec()));
+    info_sync!("tty test OK!");
+}
@@ -0,0 +1,110 @@
+use alloc::sync::Arc;
+use crate::consts::DEV_REMAP_START;
+use crate::fdt::get_fdt_info;
+use crate::info_sync;
+use crate::io::tty::{console_stop_polling, console_tty};
+use crate::trap::plic::register_irq;
+
+// ns16550兼容串口，qemu virt平台提供，寄存器间隔1字节
+const UART_RBR:usize = 0;
+const UART_THR:usize = 0;
+const UART_IER:usize = 1;
+const UART_FCR:usize = 2;
+const UART_LCR:usize = 3;
+const UART_MCR:usize = 4;
+const UART_LSR:usize = 5;
+
+// IER
+const IER_RDI:u8 = 1 << 0;
+// FCR
+const FCR_ENABLE:u8 = 1 << 0;
+const FCR_CLEAR_RCVR:u8 = 1 << 1;
+const FCR_CLEAR_XMIT:u8 = 1 << 2;
+// LCR，8位数据1位停止位无校验
+const LCR_WLEN8:u8 = 0x03;
+// MCR，OUT2在pc上用于打开中断输出
+const MCR_DTR:u8 = 1 << 0;
+const MCR_RTS:u8 = 1 << 1;
+const MCR_OUT2:u8 = 1 << 3;
+// LSR
+const LSR_DR:u8 = 1 << 0;
+const LSR_THRE:u8 = 1 << 5;
+
+pub struct Uart {
+    base:usize,
+}
+
+impl Uart {
+    fn read_reg(&self,off:usize)->u8{
+        unsafe { ((self.base+off) as *const u8).read_volatile() }
+    }
+    fn write_reg(&self,off:usize,v:u8){
+        unsafe { ((self.base+off) as *mut u8).write_volatile(v) }
+    }
+    // 波特率已经由sbi设置，不修改除数
+    fn init(&self){
+        self.write_reg(UART_IER,0);
+        self.write_reg(UART_LCR,LCR_WLEN8);
+        self.write_reg(UART_FCR,FCR_ENABLE | FCR_CLEAR_RCVR | FCR_CLEAR_XMIT);
+        self.write_reg(UART_MCR,MCR_DTR | MCR_RTS | MCR_OUT2);
+        self.write_reg(UART_IER,IER_RDI);
+    }
+    pub fn getc(&self)->Option<u8>{
+        if self.read_reg(UART_LSR) & LSR_DR != 0 {
+            Some(self.read_reg(UART_RBR))
+        } else {
+            None
+        }
+    }
+    pub fn putc(&self,c:u8){
+        while self.read_reg(UART_LSR) & LSR_THRE == 0 {}
+        self.write_reg(UART_THR,c);
+    }
+    // 读空接收FIFO，交给控制台终端
+    fn handle_irq(&self){
+        let mut buf = [0u8;64];
+        loop {
+            let mut len = 0;
+            while len < buf.len() {
+                match self.getc() {
+                    Some(c) => {
+                        buf[len] = c;
+                        len += 1;
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+            if len != 0 {
+                console_tty().receive(&buf[..len]);
+            }
+            if len < buf.len() {
+                break;
+            }
+        }
+    }
+}
+
+// 注册接收中断成功后控制台不再通过sbi轮询输入，输出仍然经过sbi
+pub fn uart_init(){
+    let info = get_fdt_info();
+    if info.uart_base == 0 || info.uart_irq == 0 {
+        return;
+    }
+    let uart = Arc::new(Uart{
+        base: info.uart_base+DEV_REMAP_START
+    });
+    uart.init();
+    let u = uart.clone();
+    match register_irq(info.uart_irq,Arc::new(move || u.handle_irq())) {
+        Ok(()) => {
+            console_stop_polling();
+            info_sync!("uart {:#X} irq {}",info.uart_base,info.uart_irq);
+        }
+        Err(e) => {
+            uart.write_reg(UART_IER,0);
+            info_sync!("uart irq {} register fail: {}",info.uart_irq,e);
+        }
+    }
+}
@@ -1,125 +1,508 @@
-use alloc::collections::LinkedList;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
-use crate::{info, trace};
-use virtio_drivers::{VirtIOBlk, VirtIOHeader};
-use crate::consts::{DEV_REMAP_START, PAGE_SIZE, PHY_MEM_OFFSET};
+use core::cmp::min;
+use core::mem::size_of;
+use core::ptr::slice_from_raw_parts_mut;
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+use crate::asm::{r_sstatus, SSTATUS_SIE};
+use crate::consts::{DEV_REMAP_START, DIRECT_MAP_END, DIRECT_MAP_START, PAGE_SIZE};
 use crate::io::{BlockRead, BlockReadWrite, BlockWrite};
-use crate::mm::addr::{OldAddr, Paddr, PFN, Vaddr};
+use crate::io::device::SECTOR_SIZE;
+use crate::mm::addr::{Paddr, Vaddr};
 use crate::mm::alloc_pages;
 use crate::mm::page::Page;
-use crate::{info_sync, println, SpinLock, trace_sync};
+use crate::{info_sync, println, SpinLock, warn_sync};
 use crate::pre::InnerAccess;
+use crate::syscall::errno::{EIO, EROFS};
+use crate::task::others_runnable;
+use crate::task::wait::WaitQueue;
+use crate::trap::plic::register_irq;
 use crate::utils::{order2pages, pages2order};
 
 #[allow(unused)]
 const VIRTIO0: usize = 0x10001000+DEV_REMAP_START;
+// qemu virt的virtio mmio设备数和间隔
+const VIRTIO_SLOTS: usize = 8;
+const VIRTIO_STRIDE: usize = 0x1000;
+// qemu virt上第slot个设备的中断号是slot+1
+const VIRTIO_IRQ0: usize = 1;
 
-pub struct VirtioDev {
-    inner:SpinLock<VirtIOBlk<'static>>
+// virtio mmio寄存器，支持legacy(version 1)和version 2两种接口
+const MMIO_MAGIC:usize = 0x000;
+const MMIO_VERSION:usize = 0x004;
+const MMIO_DEVICE_ID:usize = 0x008;
+const MMIO_DEVICE_FEATURES:usize = 0x010;
+const MMIO_DEVICE_FEATURES_SEL:usize = 0x014;
+const MMIO_DRIVER_FEATURES:usize = 0x020;
+const MMIO_DRIVER_FEATURES_SEL:usize = 0x024;
+const MMIO_GUEST_PAGE_SIZE:usize = 0x028;
+const MMIO_QUEUE_SEL:usize = 0x030;
+const MMIO_QUEUE_NUM_MAX:usize = 0x034;
+const MMIO_QUEUE_NUM:usize = 0x038;
+const MMIO_QUEUE_ALIGN:usize = 0x03c;
+const MMIO_QUEUE_PFN:usize = 0x040;
+const MMIO_QUEUE_READY:usize = 0x044;
+const MMIO_QUEUE_NOTIFY:usize = 0x050;
+const MMIO_INTERRUPT_STATUS:usize = 0x060;
+const MMIO_INTERRUPT_ACK:usize = 0x064;
+const MMIO_STATUS:usize = 0x070;
+const MMIO_QUEUE_DESC_LOW:usize = 0x080;
+const MMIO_QUEUE_DESC_HIGH:usize = 0x084;
+const MMIO_QUEUE_AVAIL_LOW:usize = 0x090;
+const MMIO_QUEUE_AVAIL_HIGH:usize = 0x094;
+const MMIO_QUEUE_USED_LOW:usize = 0x0a0;
+const MMIO_QUEUE_USED_HIGH:usize = 0x0a4;
+// 块设备配置空间开头是以扇区为单位的容量
+const MMIO_CONFIG:usize = 0x100;
+
+const VIRTIO_MAGIC:u32 = 0x74726976;
+const VIRTIO_ID_BLOCK:u32 = 2;
+
+const STATUS_ACKNOWLEDGE:u32 = 1;
+const STATUS_DRIVER:u32 = 2;
+const STATUS_DRIVER_OK:u32 = 4;
+const STATUS_FEATURES_OK:u32 = 8;
+const STATUS_FAILED:u32 = 128;
+
+// version 2必须协商的特性，位于第二组特性
+const VIRTIO_F_VERSION_1:u32 = 1 << 0;
+const VIRTIO_BLK_F_RO:u32 = 1 << 5;
+
+const VIRTQ_DESC_F_NEXT:u16 = 1;
+const VIRTQ_DESC_F_WRITE:u16 = 2;
+
+const VIRTIO_BLK_T_IN:u32 = 0;
+const VIRTIO_BLK_T_OUT:u32 = 1;
+const VIRTIO_BLK_S_OK:u8 = 0;
+
+// 只使用一个请求队列，实际长度不超过设备允许的最大值
+const QUEUE_SIZE:usize = 128;
+// legacy设备的used ring按这个对齐
+const QUEUE_ALIGN:usize = PAGE_SIZE;
+// 一个请求最多的数据段数，另外两个描述符是请求头和状态
+const MAX_SEGS:usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqDesc {
+    addr:u64,
+    len:u32,
+    flags:u16,
+    next:u16,
 }
 
-impl BlockRead for VirtioDev {
-    fn read_block(&self, blk_no: usize, buf: &mut [u8]) {
-        self.inner.lock_irq().unwrap().read_block(blk_no,buf).expect("read block fail")
-    }
+#[repr(C)]
+struct BlkReqHeader {
+    type_:u32,
+    reserved:u32,
+    sector:u64,
 }
 
-impl BlockWrite for VirtioDev {
-    fn write_block(&self, blk_no: usize, buf: &[u8]) {
-        self.inner.lock_irq().unwrap().write_block(blk_no,buf).expect("write block fail")
-    }
+// 设备通过DMA读请求头、写状态，请求完成前不能释放
+#[repr(C)]
+struct BlkReqBuf {
+    hdr:BlkReqHeader,
+    status:u8,
 }
 
-impl BlockReadWrite for VirtioDev{}
+// 设备直接访问的内存必须在direct map中(内核镜像、heap和页分配器的页)，虚拟地址连续时物理地址也连续
+// 用户地址和kmap等其他映射不能这样转换
+fn virt_to_phys(vaddr:usize)->u64{
+    assert!(vaddr >= DIRECT_MAP_START && vaddr < DIRECT_MAP_END,"virtio: dma address {:#X} not in direct map",vaddr);
+    let p:Paddr = Vaddr(vaddr).into();
+    p.get_inner() as u64
+}
 
-impl VirtioDev {
-    pub fn new()->Self{
-        VirtioDev{
-            inner: SpinLock::new(
-                match VirtIOBlk::new(
-                    unsafe { &mut *(VIRTIO0 as *mut VirtIOHeader) }
-                ) {
-                    Ok(v) => {v}
-                    Err(e) => {
-                        panic!("{:?}",e);
-                    }
-                }
-            )
+// split virtqueue，描述符表、avail ring和used ring放在一块连续的物理页中
+struct VirtQueue {
+    pages:Arc<Page>,
+    size:usize,
+    desc:usize,
+    avail:usize,
+    used:usize,
+    free:Vec<u16>,
+    avail_idx:u16,
+    last_used:u16,
+}
+
+impl VirtQueue {
+    fn new(size:usize)->Option<Self>{
+        let avail_off = size_of::<VirtqDesc>()*size;
+        let used_off = (avail_off+6+2*size+QUEUE_ALIGN-1)/QUEUE_ALIGN*QUEUE_ALIGN;
+        let total = used_off+6+8*size;
+        let pages = alloc_pages(pages2order((total+PAGE_SIZE-1)/PAGE_SIZE))?;
+        let base = pages.get_vaddr().get_inner();
+        unsafe { (base as *mut u8).write_bytes(0,order2pages(pages.get_order())*PAGE_SIZE); }
+        Some(Self{
+            pages,
+            size,
+            desc: base,
+            avail: base+avail_off,
+            used: base+used_off,
+            free: (0..size as u16).rev().collect(),
+            avail_idx: 0,
+            last_used: 0
+        })
+    }
+    fn desc_ptr(&self,i:u16)->*mut VirtqDesc{
+        (self.desc+size_of::<VirtqDesc>()*i as usize) as *mut VirtqDesc
+    }
+    fn set_desc(&self,i:u16,vaddr:usize,len:usize,flags:u16,next:u16){
+        assert!(vaddr+len <= DIRECT_MAP_END,"virtio: dma buffer {:#X}+{:#X} not in direct map",vaddr,len);
+        unsafe {
+            self.desc_ptr(i).write_volatile(VirtqDesc{
+                addr: virt_to_phys(vaddr),
+                len: len as u32,
+                flags,
+                next
+            });
+        }
+    }
+    // 描述符链放入avail ring，更新idx之前保证描述符已经写入
+    fn push_avail(&mut self,head:u16){
+        let slot = self.avail_idx as usize%self.size;
+        unsafe { ((self.avail+4+2*slot) as *mut u16).write_volatile(head); }
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { ((self.avail+2) as *mut u16).write_volatile(self.avail_idx); }
+        fence(Ordering::SeqCst);
+    }
+    // 取出一个已完成的描述符链的头
+    fn pop_used(&mut self)->Option<u16>{
+        let idx = unsafe { ((self.used+2) as *const u16).read_volatile() };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = self.last_used as usize%self.size;
+        let id = unsafe { ((self.used+4+8*slot) as *const u32).read_volatile() };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some(id as u16)
+    }
+    fn free_chain(&mut self,head:u16){
+        let mut i = head;
+        loop {
+            let d = unsafe { self.desc_ptr(i).read_volatile() };
+            self.free.push(i);
+            if d.flags & VIRTQ_DESC_F_NEXT == 0 {
+                break;
+            }
+            i = d.next;
         }
     }
 }
 
-type PhysAddr = usize;
-type VirtAddr = usize;
+// 请求完成时由中断处理或轮询的一方设置
+struct BlkCompletion {
+    done:AtomicBool,
+    ok:AtomicBool,
+    wq:WaitQueue,
+}
 
-lazy_static!{
-    static ref DMA_FMS:SpinLock<LinkedList<Arc<Page>>> = SpinLock::new(LinkedList::new());
+struct Inflight {
+    buf:Box<BlkReqBuf>,
+    comp:Arc<BlkCompletion>,
 }
 
-fn dma_fms_insert(pg:Arc<Page>){
-    DMA_FMS.lock().unwrap().push_back(pg);
+struct VirtioBlkInner {
+    queue:VirtQueue,
+    // 按描述符链的头索引
+    inflight:BTreeMap<u16,Inflight>,
 }
 
-fn dma_fms_remove(vaddr:Vaddr)->Arc<Page>{
-    let mut fms = DMA_FMS.lock().unwrap();
-    let mut index: usize = 0;
-    let mut cursor = fms.cursor_front_mut();
-    while cursor.current().is_some() {
-        if cursor.current().unwrap().get_vaddr() == vaddr {
-            return cursor.remove_current().unwrap();
-        }
-        cursor.move_next();
+// 中断驱动的virtio块设备，请求异步提交，调用者sleep等待完成期间其他task可以运行
+// 没有注册中断、启动阶段或者关中断时轮询used ring
+pub struct VirtioDev {
+    base:usize,
+    slot:usize,
+    // 以扇区为单位
+    capacity:usize,
+    read_only:bool,
+    irq:AtomicBool,
+    inner:SpinLock<VirtioBlkInner>,
+    // 等待空闲描述符
+    desc_wq:WaitQueue,
+}
+
+impl BlockRead for VirtioDev {
+    fn read_block(&self, blk_no: usize, buf: &mut [u8]) -> Result<(), isize> {
+        self.read_blocks(blk_no,&mut [buf])
+    }
+    fn num_blocks(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+    fn read_blocks(&self, blk_no: usize, bufs: &mut [&mut [u8]]) -> Result<(), isize> {
+        let segs:Vec<(usize,usize)> = bufs.iter_mut().map(|b| (b.as_mut_ptr() as usize,b.len())).collect();
+        self.rw_segs(false,blk_no,&segs)
     }
-    panic!("remove dma fail");
 }
 
-#[no_mangle]
-pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr{
-    let pg = alloc_pages(pages2order(pages)).unwrap();
-    dma_fms_insert(pg.clone());
-    let paddr: Paddr = pg.get_vaddr().into();
-    let ret = paddr.get_inner();
-    trace_sync!("virtio dma alloc paddr {:#X}, pages {:#X}\n",ret,pages);
-    return ret;
+impl BlockWrite for VirtioDev {
+    fn write_block(&self, blk_no: usize, buf: &[u8]) -> Result<(), isize> {
+        self.write_blocks(blk_no,&[buf])
+    }
+    fn write_blocks(&self, blk_no: usize, bufs: &[&[u8]]) -> Result<(), isize> {
+        if self.read_only {
+            return Err(-EROFS);
+        }
+        let segs:Vec<(usize,usize)> = bufs.iter().map(|b| (b.as_ptr() as usize,b.len())).collect();
+        self.rw_segs(true,blk_no,&segs)
+    }
 }
 
-#[no_mangle]
-pub extern "C" fn virtio_dma_dealloc(paddr: PhysAddr, pages: usize) -> i32{
-    let pg = dma_fms_remove(Paddr(paddr).into());
-    trace_sync!("virtio dma dealloc paddr:{:#X} ,pgs:{:#X}",paddr,pages);
-    // safe checker
-    assert_eq!(pg.get_block_page_cnt(),pages);
-    0
+impl BlockReadWrite for VirtioDev{}
+
+impl VirtioDev {
+    pub fn new()->Self{
+        match Self::new_at(0) {
+            Some(v) => {v}
+            None => {
+                panic!("virtio blk 0 not found");
+            }
+        }
+    }
+    fn read_reg(&self,off:usize)->u32{
+        unsafe { ((self.base+off) as *const u32).read_volatile() }
+    }
+    fn write_reg(&self,off:usize,v:u32){
+        unsafe { ((self.base+off) as *mut u32).write_volatile(v) }
+    }
+    // 探测第slot个virtio mmio设备，不是块设备时返回None
+    pub fn new_at(slot:usize)->Option<Self>{
+        if slot>=VIRTIO_SLOTS {
+            return None;
+        }
+        let base = VIRTIO0+slot*VIRTIO_STRIDE;
+        let reg = |off:usize| unsafe { ((base+off) as *const u32).read_volatile() };
+        let version = reg(MMIO_VERSION);
+        if reg(MMIO_MAGIC) != VIRTIO_MAGIC || (version != 1 && version != 2) || reg(MMIO_DEVICE_ID) != VIRTIO_ID_BLOCK {
+            return None;
+        }
+        let num_max = reg(MMIO_QUEUE_NUM_MAX) as usize;
+        // 至少要放下请求头、一个数据段和状态
+        if num_max < 3 {
+            info_sync!("virtio blk {} init fail: queue size {}",slot,num_max);
+            return None;
+        }
+        let queue = VirtQueue::new(min(num_max,QUEUE_SIZE))?;
+        let mut dev = VirtioDev{
+            base,
+            slot,
+            capacity: 0,
+            read_only: false,
+            irq: AtomicBool::new(false),
+            inner: SpinLock::new(VirtioBlkInner{
+                queue,
+                inflight: BTreeMap::new()
+            }),
+            desc_wq: WaitQueue::new()
+        };
+        if let Err(e) = dev.init(version) {
+            dev.write_reg(MMIO_STATUS,STATUS_FAILED);
+            info_sync!("virtio blk {} init fail: {}",slot,e);
+            return None;
+        }
+        Some(dev)
+    }
+    // 按virtio规范的顺序初始化，不协商块设备的可选特性
+    fn init(&mut self,version:u32)->Result<(),&'static str>{
+        self.write_reg(MMIO_STATUS,0);
+        let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+        self.write_reg(MMIO_STATUS,status);
+        self.write_reg(MMIO_DEVICE_FEATURES_SEL,0);
+        self.read_only = self.read_reg(MMIO_DEVICE_FEATURES) & VIRTIO_BLK_F_RO != 0;
+        self.write_reg(MMIO_DRIVER_FEATURES_SEL,0);
+        self.write_reg(MMIO_DRIVER_FEATURES,self.read_reg(MMIO_DEVICE_FEATURES) & VIRTIO_BLK_F_RO);
+        if version == 2 {
+            self.write_reg(MMIO_DEVICE_FEATURES_SEL,1);
+            if self.read_reg(MMIO_DEVICE_FEATURES) & VIRTIO_F_VERSION_1 == 0 {
+                return Err("no VIRTIO_F_VERSION_1");
+            }
+            self.write_reg(MMIO_DRIVER_FEATURES_SEL,1);
+            self.write_reg(MMIO_DRIVER_FEATURES,VIRTIO_F_VERSION_1);
+            status |= STATUS_FEATURES_OK;
+            self.write_reg(MMIO_STATUS,status);
+            if self.read_reg(MMIO_STATUS) & STATUS_FEATURES_OK == 0 {
+                return Err("features not accepted");
+            }
+        } else {
+            self.write_reg(MMIO_GUEST_PAGE_SIZE,PAGE_SIZE as u32);
+        }
+        let inner = self.inner.lock_irq().unwrap();
+        let q = &inner.queue;
+        self.write_reg(MMIO_QUEUE_SEL,0);
+        self.write_reg(MMIO_QUEUE_NUM,q.size as u32);
+        if version == 2 {
+            let (desc,avail,used) = (virt_to_phys(q.desc),virt_to_phys(q.avail),virt_to_phys(q.used));
+            self.write_reg(MMIO_QUEUE_DESC_LOW,desc as u32);
+            self.write_reg(MMIO_QUEUE_DESC_HIGH,(desc >> 32) as u32);
+            self.write_reg(MMIO_QUEUE_AVAIL_LOW,avail as u32);
+            self.write_reg(MMIO_QUEUE_AVAIL_HIGH,(avail >> 32) as u32);
+            self.write_reg(MMIO_QUEUE_USED_LOW,used as u32);
+            self.write_reg(MMIO_QUEUE_USED_HIGH,(used >> 32) as u32);
+            self.write_reg(MMIO_QUEUE_READY,1);
+        } else {
+            self.write_reg(MMIO_QUEUE_ALIGN,QUEUE_ALIGN as u32);
+            self.write_reg(MMIO_QUEUE_PFN,(virt_to_phys(q.desc)/PAGE_SIZE as u64) as u32);
+        }
+        drop(inner);
+        self.capacity = (self.read_reg(MMIO_CONFIG) as usize) | ((self.read_reg(MMIO_CONFIG+4) as usize) << 32);
+        status |= STATUS_DRIVER_OK;
+        self.write_reg(MMIO_STATUS,status);
+        Ok(())
+    }
+    // 注册成功后请求完成由中断通知
+    fn enable_irq(self:&Arc<Self>){
+        let irq = VIRTIO_IRQ0+self.slot;
+        let dev = self.clone();
+        match register_irq(irq,Arc::new(move || dev.handle_irq())) {
+            Ok(()) => {
+                self.irq.store(true,Ordering::SeqCst);
+                info_sync!("virtio blk {} irq {}, {} sectors",self.slot,irq,self.capacity);
+            }
+            Err(e) => {
+                info_sync!("virtio blk {} irq {} register fail: {}, use polling",self.slot,irq,e);
+            }
+        }
+    }
+    fn handle_irq(&self){
+        let status = self.read_reg(MMIO_INTERRUPT_STATUS);
+        self.write_reg(MMIO_INTERRUPT_ACK,status);
+        self.reap();
+    }
+    // 回收已完成的请求，唤醒等待者
+    fn reap(&self){
+        let mut done = Vec::new();
+        let mut inner = self.inner.lock_irq().unwrap();
+        while let Some(head) = inner.queue.pop_used() {
+            inner.queue.free_chain(head);
+            if let Some(r) = inner.inflight.remove(&head) {
+                let status = unsafe { (&r.buf.status as *const u8).read_volatile() };
+                r.comp.ok.store(status == VIRTIO_BLK_S_OK,Ordering::SeqCst);
+                r.comp.done.store(true,Ordering::SeqCst);
+                done.push(r.comp);
+            }
+        }
+        drop(inner);
+        if done.is_empty() {
+            return;
+        }
+        self.desc_wq.wake_all();
+        for c in done {
+            c.wq.wake_all();
+        }
+    }
+    // 持有lock_irq的锁时中断是关闭的，不能sleep
+    fn can_sleep(&self)->bool{
+        self.irq.load(Ordering::SeqCst) && r_sstatus() & SSTATUS_SIE != 0 && others_runnable()
+    }
+    fn wait_event(&self,wq:&WaitQueue,cond:impl Fn()->bool){
+        if self.can_sleep() {
+            wq.wait_until(cond);
+            return;
+        }
+        while !cond() {
+            self.reap();
+        }
+    }
+    // 提交一个请求，segs是(虚拟地址,长度)，描述符不够时等待其他请求完成
+    fn submit(&self,write:bool,sector:usize,segs:&[(usize,usize)])->Arc<BlkCompletion>{
+        let need = segs.len()+2;
+        let buf = Box::new(BlkReqBuf{
+            hdr: BlkReqHeader{
+                type_: if write { VIRTIO_BLK_T_OUT } else { VIRTIO_BLK_T_IN },
+                reserved: 0,
+                sector: sector as u64
+            },
+            status: 0xff
+        });
+        let comp = Arc::new(BlkCompletion{
+            done: AtomicBool::new(false),
+            ok: AtomicBool::new(false),
+            wq: WaitQueue::new()
+        });
+        loop {
+            let mut inner = self.inner.lock_irq().unwrap();
+            if inner.queue.free.len() >= need {
+                let q = &mut inner.queue;
+                let descs:Vec<u16> = (0..need).map(|_| q.free.pop().unwrap()).collect();
+                q.set_desc(descs[0],&buf.hdr as *const _ as usize,size_of::<BlkReqHeader>(),VIRTQ_DESC_F_NEXT,descs[1]);
+                let data_flags = if write { VIRTQ_DESC_F_NEXT } else { VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE };
+                for (i,(addr,len)) in segs.iter().enumerate() {
+                    q.set_desc(descs[i+1],*addr,*len,data_flags,descs[i+2]);
+                }
+                q.set_desc(descs[need-1],&buf.status as *const _ as usize,1,VIRTQ_DESC_F_WRITE,0);
+                q.push_avail(descs[0]);
+                inner.inflight.insert(descs[0],Inflight{
+                    buf,
+                    comp: comp.clone()
+                });
+                drop(inner);
+                self.write_reg(MMIO_QUEUE_NOTIFY,0);
+                return comp;
+            }
+            drop(inner);
+            self.wait_event(&self.desc_wq,|| self.inner.lock_irq().unwrap().queue.free.len() >= need);
+        }
+    }
+    // 连续扇区的读写，超过段数限制时拆成多个请求，全部提交后再等待
+    // 请求都完成后才能返回，失败的请求只记录第一个
+    fn rw_segs(&self,write:bool,sector:usize,segs:&[(usize,usize)])->Result<(),isize>{
+        if sector.checked_add(segs.iter().map(|(_,len)| len/SECTOR_SIZE).sum()).map_or(true,|end| end > self.capacity) {
+            return Err(-EIO);
+        }
+        let max = min(MAX_SEGS,self.inner.lock_irq().unwrap().queue.size-2);
+        let mut pending = Vec::new();
+        let mut sec = sector;
+        for chunk in segs.chunks(max) {
+            pending.push((sec,self.submit(write,sec,chunk)));
+            sec += chunk.iter().map(|(_,len)| len/SECTOR_SIZE).sum::<usize>();
+        }
+        let mut ret = Ok(());
+        for (sec,c) in pending {
+            self.wait_event(&c.wq,|| c.done.load(Ordering::SeqCst));
+            if !c.ok.load(Ordering::SeqCst) && ret.is_ok() {
+                warn_sync!("virtio blk {} {} fail at sector {}",self.slot,if write { "write" } else { "read" },sec);
+                ret = Err(-EIO);
+            }
+        }
+        ret
+    }
 }
 
-#[no_mangle]
-pub extern "C" fn virtio_phys_to_virt(paddr: PhysAddr) -> VirtAddr {
-    let p:Vaddr = Paddr(paddr).into();
-    // trace_sync!("virtio phy=>virt: {:#X}=>{:#X}",paddr,p.get_inner());
-    p.get_inner()
+lazy_static!{
+    static ref VIRTIO_BLKS:SpinLock<Vec<Option<Arc<dyn BlockReadWrite>>>> = SpinLock::new(vec![None;VIRTIO_SLOTS]);
 }
 
-#[no_mangle]
-pub extern "C" fn virtio_virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
-    let v:Paddr = Vaddr(vaddr).into();
-    // trace_sync!("virtio virt=>phy: {:#X}=>{:#X}",vaddr,v.get_inner());
-    v.get_inner()
+// 每个slot的设备只初始化一次，之后共享
+pub fn get_virtio_blk(slot:usize)->Option<Arc<dyn BlockReadWrite>>{
+    if slot>=VIRTIO_SLOTS {
+        return None;
+    }
+    let mut blks = VIRTIO_BLKS.lock_irq().unwrap();
+    if blks[slot].is_none() {
+        blks[slot] = VirtioDev::new_at(slot).map(|v|{
+            let v = Arc::new(v);
+            v.enable_irq();
+            let dev:Arc<dyn BlockReadWrite> = v;
+            dev
+        });
+    }
+    blks[slot].clone()
 }
 
 pub fn virtio_test(){
     info_sync!("read start");
     let v = VirtioDev::new();
     let pages = alloc_pages(pages2order(5)).unwrap();
-    // println!("order:{},len: {},vaddr: {:#X}=>{:#X}",pages.get_order(),file_len,pages.get_vaddr().0,(pages.get_vaddr()+order2pages(pages.get_order())*PAGE_SIZE).0);
     let ptr = pages.get_vaddr().get_inner() as *mut u8;
     let pgs = order2pages(5);
     let read_buf = slice_from_raw_parts_mut(ptr,0x400000);
-    // let read_buf = &mut unsafe { *ptr };
-    // let mut buf = [0u8;512];
     for i in 0..(pgs*PAGE_SIZE)/512{
-        unsafe { v.read_block(i, &mut (*read_buf)[i * 512..(i + 1) * 512]); }
+        unsafe { v.read_block(i, &mut (*read_buf)[i * 512..(i + 1) * 512]).unwrap(); }
     }
     println!("read ok");
-}
\ No newline at end of file
+}
@@ -31,7 +31,9 @@ use riscv::register::mie::read;
 use riscv::register::mtvec::TrapMode;
 use riscv::register::sstatus::Sstatus;
 use crate::asm::r_sstatus;
-use crate::fs::fat::fat_init;
+use crate::fdt::fdt_init;
+use crate::fs::init_fs;
+use crate::io::device::device_init;
 
 use crate::logger::early_logger_init;
 use crate::mm::buddy::buddy_test;
@@ -62,6 +64,7 @@ mod fs;
 mod test;
 mod io;
 mod pre;
+mod fdt;
 
 global_asm!(include_str!("entry.asm"));
 
@@ -83,11 +86,14 @@ fn start_kernel(cpu:usize,dev_tree:usize) {
     let grd2 = lock.lock().unwrap();
     early_logger_init();
     trap_init();
+    fdt_init(dev_tree);
     mm_init();
+    // 启动task的cwd是根目录，根文件系统需要先注册块设备
+    device_init();
     task_cpu_init();
     // task_test();
     timer_startup();
-    fat_init();
+    init_fs();
     unsafe {
         do_test();
     }
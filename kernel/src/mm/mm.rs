@@ -2,21 +2,26 @@ use alloc::collections::{BTreeMap, LinkedList};
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use alloc::string::String;
+use core::fmt::Write;
 use core::cmp::{max, min};
 use core::fmt::{Debug, Formatter};
 use core::ops::Bound::{Excluded, Included};
 use xmas_elf::ElfFile;
 use xmas_elf::program::Type::Load;
 
-use crate::consts::{MMAP_TOP, PAGE_OFFSET, PAGE_SIZE, PHY_MEM_OFFSET, KMAP_END, KMAP_START, USER_HEAP_VMA_INIT_NR_PAGES, USER_SPACE_END, USER_SPACE_START, USER_STACK_MAX_ADDR, USER_STACK_SIZE_NR_PAGES};
+use crate::consts::{HUGE_PAGE_ORDER, HUGE_PAGE_SIZE, MMAP_TOP, PAGE_OFFSET, PAGE_SIZE, PHY_MEM_OFFSET, KMAP_END, KMAP_START, USER_HEAP_VMA_INIT_NR_PAGES, USER_SPACE_END, USER_SPACE_START, USER_STACK_MAX_ADDR, USER_STACK_SIZE_NR_PAGES};
 use crate::fs::inode::Inode;
 use crate::mm::addr::{Addr, PageAlign, PFN, Vaddr};
 use crate::mm::{alloc_one_page, alloc_pages, get_kernel_pagetable};
 use crate::mm::aux::{AT_BASE, AT_CLKTCK, AT_EGID, AT_ENTRY, AT_EUID, AT_FLAGS, AT_GID, AT_HWCAP, AT_NOTELF, AT_NULL, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_PLATFORM, AT_SECURE, AT_UID, AuxHeader, make_auxv};
 use crate::utils::order2pages;
 use crate::mm::page::Page;
+use crate::mm::swap::{NoReclaimGuard, swap_in_page};
 use crate::mm::pagetable::{PageTable, PTEFlags, WalkRet};
-use crate::mm::vma::{_vma_flags_2_pte_flags, MmapFlags, MmapProt, VMA, VmFlags};
+use crate::mm::vma::{_vma_flags_2_pte_flags, MADV_DONTNEED, MADV_FREE, MADV_HUGEPAGE, MADV_NOHUGEPAGE, MADV_NORMAL, MADV_RANDOM, MADV_SEQUENTIAL, MADV_WILLNEED, MmapFlags, MmapProt, PageSource, VMA, VmFlags};
+use crate::syscall::errno::{EFAULT, EINVAL, EIO, ENOMEM};
+use crate::task::task::get_running_mm;
 use crate::pre::{ReadWriteOffUnsafe, ReadWriteSingleNoOff, ShowRdWrEx};
 use crate::{println, SpinLock, warn_sync};
 use crate::sbi::shutdown;
@@ -30,7 +35,10 @@ pub struct MmStruct{
     vmas: BTreeMap<Vaddr,VMA>,
     start_brk:Vaddr,
     brk:Vaddr,
-    pub cow_target:Option<Arc<SpinLock<MmStruct>>>
+    pub cow_target:Option<Arc<SpinLock<MmStruct>>>,
+    // rss和vsz的峰值，单位为页，只在减少之前更新
+    hiwater_rss:usize,
+    hiwater_vm:usize
 }
 
 impl Debug for MmStruct {
@@ -49,7 +57,7 @@ pub struct VmaCache {
 }
 
 #[cfg(not(feature = "copy_on_write"))]
-pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->MmStruct{
+pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->Result<MmStruct,isize>{
     let mut old_locked = old.lock_irq().unwrap();
     let new_pagetable = PageTable::new_user();
     let mut new = MmStruct::new_empty_user_mm_by_pagetable(new_pagetable);
@@ -60,29 +68,60 @@ pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->MmStruct{
         let mut new_vma = VMA{
             start_vaddr: vma.start_vaddr,
             end_vaddr: vma.end_vaddr,
-            vm_flags: vma.vm_flags,
+            // mlock不会被子进程继承
+            vm_flags: vma.vm_flags&!VmFlags::VM_LOCKED,
             pages_tree: BTreeMap::new(),
             pagetable: Some(new.pagetable.clone()),
             file: vma.file.as_ref().map(|x|{x.clone()}),
             file_off: vma.file_off,
             file_in_vma_off: vma.file_in_vma_off,
             file_len: vma.file_len,
-            phy_pgs_cnt: vma.phy_pgs_cnt,
-            cow_write_reserve_pgs: None
+            phy_pgs_cnt: 0,
+            cow_write_reserve_pgs: None,
+            swap_pgs_cnt: 0,
+            swap_hand: vma.start_vaddr
         };
         let vma_pgt = vma.pagetable.as_ref().unwrap();
         for (vv, pg) in vma.pages_tree.iter() {
+            if pg.get_order() == HUGE_PAGE_ORDER {
+                // 大页优先复制为大页，分配失败时退化为4K页
+                match new_vma.__alloc_huge_page(vv.clone()) {
+                    Some(new_pg) => unsafe {
+                        new_pg.copy_pages_block_data_from(pg);
+                    }
+                    None => {
+                        for (i,sub) in vv.page_addr_iter(HUGE_PAGE_SIZE).enumerate() {
+                            let new_pg = new_vma.__fast_alloc_one_page_and_get(sub);
+                            unsafe {
+                                core::ptr::copy_nonoverlapping((pg.get_vaddr()+i*PAGE_SIZE).0 as *const u8,
+                                                               new_pg.get_vaddr().0 as *mut u8, PAGE_SIZE);
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
             // change map flags
             let new_pg = new_vma.__fast_alloc_one_page_and_get(vv.clone());
             unsafe { new_pg.copy_one_page_data_from(pg.clone()); }
         }
+        // 换出的页直接从swap读到新进程的物理页中
+        if vma.swap_pgs_cnt>0 {
+            for vv in vma.start_vaddr.page_addr_iter((vma.end_vaddr-vma.start_vaddr.0).0) {
+                if let Some(entry) = vma_pgt.get_swap_entry(vv) {
+                    let new_pg = new_vma.__fast_alloc_one_page_and_get(vv);
+                    // 读取失败时fork失败，已经复制的部分随new释放
+                    swap_in_page(entry,&new_pg).map_err(|_| -EIO)?;
+                }
+            }
+        }
         assert!(new.vmas.insert(vaddr.clone(),new_vma).is_none());
     }
-    new
+    Ok(new)
 }
 
 #[cfg(feature = "copy_on_write")]
-pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->MmStruct{
+pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->Result<MmStruct,isize>{
     let mut old_locked = old.lock_irq().unwrap();
     if old_locked.cow_target.is_some(){
         // 正在cow其他mm
@@ -109,7 +148,7 @@ pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->MmStruct{
         let new_vma = VMA{
             start_vaddr: vma.start_vaddr,
             end_vaddr: vma.end_vaddr,
-            vm_flags: vma.vm_flags,
+            vm_flags: vma.vm_flags&!VmFlags::VM_LOCKED,
             // 空tree map 不关联任何物理页
             pages_tree: BTreeMap::new(),
             pagetable: Some(new.pagetable.clone()),
@@ -117,12 +156,67 @@ pub fn new_mm_by_old(old:Arc<SpinLock<MmStruct>>) ->MmStruct{
             file_off: vma.file_off,
             file_in_vma_off: vma.file_in_vma_off,
             file_len: vma.file_len,
-            phy_pgs_cnt: vma.phy_pgs_cnt,
-            cow_write_reserve_pgs: None
+            phy_pgs_cnt: 0,
+            cow_write_reserve_pgs: None,
+            swap_pgs_cnt: 0,
+            swap_hand: vma.start_vaddr
         };
         assert!(new.vmas.insert(vaddr.clone(),new_vma).is_none());
     }
-    new
+    Ok(new)
+}
+
+// 为[start,start+len)分配物理页并读入数据，用于mlock和MADV_WILLNEED
+// 读入swap和文件需要块设备I/O，不能持有mm锁，每页在锁外读入后再加锁映射
+// willneed时没有后备存储的匿名页不分配
+pub fn populate_range(mm:&Arc<SpinLock<MmStruct>>,start:Vaddr,len:usize,willneed:bool)->Result<(),()>{
+    for v in start.page_addr_iter(Vaddr(start.0+len).ceil().0-start.0) {
+        let src = {
+            let mut mm_locked = mm.lock_irq().unwrap();
+            let vma = mm_locked.find_vma(v).ok_or(())?;
+            if vma._vaddr_have_map(v) {
+                continue;
+            }
+            match vma._page_source(v)? {
+                PageSource::Zero => {
+                    if !willneed {
+                        // 持有mm锁，分配时不能换出
+                        let _noio = NoReclaimGuard::new();
+                        vma._do_alloc_one_page(v)?;
+                    }
+                    continue;
+                }
+                src => src
+            }
+        };
+        let pg = alloc_one_page().ok_or(())?;
+        src.fill(&pg)?;
+        if let Some(vma) = mm.lock_irq().unwrap().find_vma(v) {
+            vma._install_page(v,pg,&src)?;
+        }
+    }
+    Ok(())
+}
+
+// 检查syscall传入的用户指针，[addr,addr+len)需要位于当前task有对应权限的vma中
+// 否则访问时缺页处理找不到vma会panic
+pub fn user_access_ok(addr:usize,len:usize,write:bool)->Result<(),isize>{
+    if len == 0 {
+        return Ok(());
+    }
+    let end = match addr.checked_add(len) {
+        Some(e) if e<=USER_SPACE_END => e,
+        _ => {
+            return Err(-EFAULT);
+        }
+    };
+    let mm = get_running_mm().ok_or(-EFAULT)?;
+    let mm_locked = mm.lock_irq().unwrap();
+    if mm_locked._range_accessible(Vaddr(addr),Vaddr(end),write) {
+        Ok(())
+    } else {
+        Err(-EFAULT)
+    }
 }
 
 impl MmStruct {
@@ -148,7 +242,9 @@ impl MmStruct {
             vmas: Default::default(),
             start_brk: Default::default(),
             brk: Default::default(),
-            cow_target:None
+            cow_target:None,
+            hiwater_rss: 0,
+            hiwater_vm: 0
         }
     }
     pub fn new_empty_user_mm_by_pagetable(pagetable:PageTable)->Self{
@@ -159,7 +255,9 @@ impl MmStruct {
             vmas: Default::default(),
             start_brk: Default::default(),
             brk: Default::default(),
-            cow_target: None
+            cow_target: None,
+            hiwater_rss: 0,
+            hiwater_vm: 0
         }
     }
     pub fn new_empty_user_mm() ->Self{
@@ -170,7 +268,9 @@ impl MmStruct {
             vmas: Default::default(),
             start_brk: Default::default(),
             brk: Default::default(),
-            cow_target: None
+            cow_target: None,
+            hiwater_rss: 0,
+            hiwater_vm: 0
         }
     }
     pub fn get_brk(&self)->Vaddr{
@@ -184,6 +284,97 @@ impl MmStruct {
         self.brk = new;
         ret
     }
+    fn __user_vmas(&self)->impl Iterator<Item=&VMA>{
+        self.vmas.range(&Vaddr(USER_SPACE_START)..&Vaddr(USER_SPACE_END)).map(|(_,v)|{v})
+    }
+    // 以下统计单位均为页
+    pub fn get_rss(&self)->usize{
+        self.__user_vmas().map(|v|{v.phy_pgs_cnt}).sum()
+    }
+    pub fn get_rss_anon(&self)->usize{
+        self.__user_vmas().filter(|v|{v.is_anon()}).map(|v|{v.phy_pgs_cnt}).sum()
+    }
+    pub fn get_vsz(&self)->usize{
+        self.__user_vmas().map(|v|{
+            (v.get_end_vaddr().ceil()-v.get_start_vaddr().0).0/PAGE_SIZE
+        }).sum()
+    }
+    pub fn get_swap_pgs(&self)->usize{
+        self.__user_vmas().map(|v|{v.swap_pgs_cnt}).sum()
+    }
+    pub fn get_locked_pgs(&self)->usize{
+        self.__user_vmas().filter(|v|{v.is_locked()}).map(|v|{
+            (v.get_end_vaddr().ceil()-v.get_start_vaddr().0).0/PAGE_SIZE
+        }).sum()
+    }
+    // rss和vsz减少之前调用
+    pub fn update_hiwater(&mut self){
+        self.hiwater_rss = max(self.hiwater_rss,self.get_rss());
+        self.hiwater_vm = max(self.hiwater_vm,self.get_vsz());
+    }
+    pub fn get_hiwater_rss(&mut self)->usize{
+        self.update_hiwater();
+        self.hiwater_rss
+    }
+    pub fn get_hiwater_vm(&mut self)->usize{
+        self.update_hiwater();
+        self.hiwater_vm
+    }
+    // /proc/<pid>/status中内存相关的部分，单位为kB
+    pub fn status_text(&mut self)->String{
+        let kb = PAGE_SIZE/1024;
+        let rss = self.get_rss();
+        let rss_anon = self.get_rss_anon();
+        let items = [
+            ("VmPeak:",self.get_hiwater_vm()),
+            ("VmSize:",self.get_vsz()),
+            ("VmLck:",self.get_locked_pgs()),
+            ("VmHWM:",self.get_hiwater_rss()),
+            ("VmRSS:",rss),
+            ("RssAnon:",rss_anon),
+            ("RssFile:",rss-rss_anon),
+            ("VmSwap:",self.get_swap_pgs()),
+        ];
+        let mut s = String::new();
+        for (name,v) in items.iter() {
+            writeln!(s,"{:<16}{:>8} kB",name,v*kb).unwrap();
+        }
+        s
+    }
+    // /proc/<pid>/maps，每个vma一行
+    pub fn maps_text(&self)->String{
+        let mut s = String::new();
+        for v in self.__user_vmas() {
+            let perms = [
+                if v.readable() {'r'} else {'-'},
+                if v.writeable() {'w'} else {'-'},
+                if v.execable() {'x'} else {'-'},
+                if v.vm_flags.contains(VmFlags::VM_SHARD) {'s'} else {'p'},
+            ];
+            let (off,ino,path) = match &v.file {
+                Some(f) => (v.file_off,f.getattr().ino,f.get_path()),
+                None => {
+                    let name = if v.get_start_vaddr() == self.start_brk {
+                        "[heap]"
+                    } else if v.get_end_vaddr().0 == USER_STACK_MAX_ADDR {
+                        "[stack]"
+                    } else {
+                        ""
+                    };
+                    (0,0,String::from(name))
+                }
+            };
+            write!(s,"{:08x}-{:08x} {}{}{}{} {:08x} 00:00 {}",
+                   v.get_start_vaddr().0,v.get_end_vaddr().0,
+                   perms[0],perms[1],perms[2],perms[3],off,ino).unwrap();
+            if path.is_empty() {
+                s.push('\n');
+            } else {
+                writeln!(s,"        {}",path).unwrap();
+            }
+        }
+        s
+    }
     pub fn _expand_brk(&mut self,new_brk:Vaddr)->Result<(),()> {
         let m = self.vmas.range(self.start_brk..Vaddr(MMAP_TOP)).skip(1).next();
         match m {
@@ -203,14 +394,179 @@ impl MmStruct {
         Ok(())
     }
     pub fn _shrink_brk(&mut self,new_brk:Vaddr)->Result<(),()>{
-        match self.find_vma(self.start_brk).unwrap().split(new_brk){
-            None => {
-                Err(())
+        self.update_hiwater();
+        // heap vma至少保留一页
+        let new_end = max(new_brk.ceil(),self.start_brk+PAGE_SIZE);
+        let vma = self.find_vma(self.start_brk).ok_or(())?;
+        if new_end<vma.get_end_vaddr() {
+            // 拆分出的部分drop时unmap
+            vma.split(new_end).ok_or(())?;
+        }
+        self.set_brk(new_brk);
+        Ok(())
+    }
+    // 把[start,end)范围和vma的交集拆分成独立的vma，返回这些vma的起始地址
+    fn _isolate_range(&mut self,start:Vaddr,end:Vaddr)->Result<Vec<Vaddr>,()>{
+        let keys:Vec<Vaddr> = self.vmas.range(..end).filter(|(_,v)|{v.get_end_vaddr()>start}).map(|(k,_)|{*k}).collect();
+        let mut ret = Vec::new();
+        for k in keys {
+            let mut vma = self.vmas.remove(&k).unwrap();
+            if vma.get_start_vaddr()<start {
+                let high = vma.split(start);
+                self.vmas.insert(vma.get_start_vaddr(),vma);
+                vma = high.ok_or(())?;
+            }
+            if vma.get_end_vaddr()>end {
+                let high = vma.split(end).ok_or(())?;
+                self.vmas.insert(high.get_start_vaddr(),high);
             }
-            Some(_) => {
-                Ok(())
+            ret.push(vma.get_start_vaddr());
+            self.vmas.insert(vma.get_start_vaddr(),vma);
+        }
+        Ok(ret)
+    }
+    // [start,end)中不能有未映射的地址
+    fn _range_covered(&self,start:Vaddr,end:Vaddr)->bool{
+        let mut probe = start;
+        for (_,v) in self.vmas.range(..end).filter(|(_,v)|{v.get_end_vaddr()>start}) {
+            if v.get_start_vaddr()>probe {
+                return false;
             }
+            probe = v.get_end_vaddr();
         }
+        probe>=end
+    }
+    // [start,end)全部位于有对应权限的vma中
+    fn _range_accessible(&self,start:Vaddr,end:Vaddr,write:bool)->bool{
+        if !self._range_covered(start,end) {
+            return false;
+        }
+        self.vmas.range(..end).filter(|(_,v)|{v.get_end_vaddr()>start}).all(|(_,v)|{
+            v.readable() && (!write || v.writeable())
+        })
+    }
+    // 检查用户地址范围，返回对齐后的结束地址
+    fn __check_user_range(&self,start:Vaddr,len:usize)->Result<Vaddr,isize>{
+        if !start.is_align() {
+            return Err(-EINVAL);
+        }
+        // 先检查溢出，Vaddr的加法溢出时会panic
+        let end = match start.0.checked_add(len) {
+            Some(e) if e<=USER_SPACE_END => Vaddr(e).ceil(),
+            _ => {
+                return Err(-ENOMEM);
+            }
+        };
+        if !self._range_covered(start,end) {
+            return Err(-ENOMEM);
+        }
+        Ok(end)
+    }
+    // MADV_WILLNEED只检查范围，读入需要I/O，由调用者释放mm锁后调用populate_range
+    pub fn madvise(&mut self,start:Vaddr,len:usize,advice:usize)->Result<(),isize>{
+        let end = self.__check_user_range(start,len)?;
+        match advice {
+            MADV_NORMAL|MADV_RANDOM|MADV_SEQUENTIAL|MADV_WILLNEED => {}
+            MADV_DONTNEED|MADV_FREE => {
+                // 锁定的页不能释放，MADV_FREE只用于匿名页
+                for (_,vma) in self.vmas.range(..end).filter(|(_,v)|{v.get_end_vaddr()>start}) {
+                    if vma.is_locked() || (advice==MADV_FREE && !vma.is_anon()) {
+                        return Err(-EINVAL);
+                    }
+                }
+                // MADV_FREE的页随时可以被回收，这里直接按照DONTNEED释放
+                self.update_hiwater();
+                for (_,vma) in self.vmas.range_mut(..end).filter(|(_,v)|{v.get_end_vaddr()>start}) {
+                    let s = max(start,vma.get_start_vaddr());
+                    let e = min(end,vma.get_end_vaddr());
+                    vma._zap_range(s,e).map_err(|_|{-ENOMEM})?;
+                }
+            }
+            MADV_HUGEPAGE|MADV_NOHUGEPAGE => {
+                for k in self._isolate_range(start,end).map_err(|_|{-ENOMEM})? {
+                    self.vmas.get_mut(&k).unwrap()._set_hugepage_advice(advice==MADV_HUGEPAGE);
+                }
+            }
+            _ => {
+                return Err(-EINVAL);
+            }
+        }
+        Ok(())
+    }
+    // 锁定的vma中的页不会被换出，返回这次新锁定的vma的起始地址
+    // 页的分配需要I/O，由调用者释放mm锁后调用populate_range，失败时用_undo_mlock恢复
+    pub fn mlock(&mut self,start:Vaddr,len:usize)->Result<Vec<Vaddr>,isize>{
+        let end = self.__check_user_range(start,len)?;
+        let mut locked = Vec::new();
+        for k in self._isolate_range(start,end).map_err(|_|{-ENOMEM})? {
+            let vma = self.vmas.get_mut(&k).unwrap();
+            if !vma.is_locked() {
+                vma._set_locked(true);
+                locked.push(k);
+            }
+        }
+        Ok(locked)
+    }
+    // 期间被munmap的vma直接跳过
+    pub fn _undo_mlock(&mut self,locked:&[Vaddr]){
+        for k in locked {
+            if let Some(vma) = self.vmas.get_mut(k) {
+                vma._set_locked(false);
+            }
+        }
+    }
+    pub fn munlock(&mut self,start:Vaddr,len:usize)->Result<(),isize>{
+        let end = self.__check_user_range(start,len)?;
+        for k in self._isolate_range(start,end).map_err(|_|{-ENOMEM})? {
+            self.vmas.get_mut(&k).unwrap()._set_locked(false);
+        }
+        Ok(())
+    }
+    // 检查mincore的范围，返回对齐后的结束地址
+    pub fn mincore_range(&self,start:Vaddr,len:usize)->Result<Vaddr,isize>{
+        self.__check_user_range(start,len)
+    }
+    // 从start开始每页一个字节，最低位表示是否在内存中
+    pub fn mincore(&self,start:Vaddr,out:&mut [u8]){
+        for (i,b) in out.iter_mut().enumerate() {
+            *b = self.pagetable.is_mapped(start+i*PAGE_SIZE) as u8;
+        }
+    }
+    // 范围内的大页会被拆分
+    pub fn munmap(&mut self,start:Vaddr,len:usize)->Result<(),()>{
+        if !start.is_align() || len==0 {
+            return Err(());
+        }
+        // len接近usize::MAX时start+len会溢出
+        let end = match start.0.checked_add(len) {
+            Some(e) if e<=USER_SPACE_END => Vaddr(e).ceil(),
+            _ => {
+                return Err(());
+            }
+        };
+        self.update_hiwater();
+        for k in self._isolate_range(start,end)? {
+            self.vmas.remove(&k);
+        }
+        Ok(())
+    }
+    pub fn mprotect(&mut self,start:Vaddr,len:usize,prot:MmapProt)->Result<(),isize>{
+        if !start.is_align() {
+            return Err(-EINVAL);
+        }
+        let end = match start.0.checked_add(len) {
+            Some(e) if e<=USER_SPACE_END => Vaddr(e).ceil(),
+            _ => {
+                return Err(-EINVAL);
+            }
+        };
+        if !self._range_covered(start,end) {
+            return Err(-ENOMEM);
+        }
+        for k in self._isolate_range(start,end).map_err(|_|{-ENOMEM})? {
+            self.vmas.get_mut(&k).unwrap()._set_prot(prot).map_err(|_|{-ENOMEM})?;
+        }
+        Ok(())
     }
     // 这个函数调试使用，未分配物理页的地址会panic
     pub unsafe fn __read_single_by_vaddr<T:Copy+Sized>(&self, vaddr:Vaddr) ->T{
@@ -357,7 +713,8 @@ impl MmStruct {
         self.__alloc_unmapped_core(None, len, to_high, Vaddr(KMAP_START), Vaddr(KMAP_END)).map(
             |mut vma| {
                 vma.pagetable = Some(self.pagetable.clone());
-                vma.vm_flags = VmFlags::VM_READ|VmFlags::VM_WRITE|VmFlags::VM_EXEC|VmFlags::VM_ANON;
+                // kmap窗口不可执行
+                vma.vm_flags = VmFlags::VM_READ|VmFlags::VM_WRITE|VmFlags::VM_ANON;
                 vma
             }
         )
@@ -372,11 +729,36 @@ impl MmStruct {
                 vma.file = Some(file);
                 vma.file_len = file_len;
                 vma.file_in_vma_off = 0;
-                vma.vm_flags = VmFlags::VM_READ|VmFlags::VM_WRITE|VmFlags::VM_EXEC;
+                vma.vm_flags = VmFlags::VM_READ|VmFlags::VM_WRITE;
                 vma
             }
         )
     }
+    // 换出用户空间的匿名页，返回换出的页数
+    // cow共享的页不能换出
+    pub fn reclaim_anon_pages(&mut self,nr:usize)->usize{
+        if self.is_kern || self.cow_target.is_some() {
+            return 0;
+        }
+        self.update_hiwater();
+        let mut done = 0;
+        for (_,vma) in self.vmas.range_mut(&Vaddr(USER_SPACE_START)..&Vaddr(USER_SPACE_END)){
+            if done>=nr {
+                break;
+            }
+            if !vma.is_anon() || vma.cow_write_reserve_pgs.is_some() || vma.is_locked() {
+                continue;
+            }
+            done += vma._swap_out_pages(nr-done);
+        }
+        done
+    }
+    pub fn swap_in_area(&mut self,swap_type:usize)->Result<(),()>{
+        for (_,vma) in self.vmas.iter_mut(){
+            vma._swap_in_area(swap_type)?;
+        }
+        Ok(())
+    }
     pub fn drop_vma(&mut self,vaddr:Vaddr)->Option<VMA>{
         self.vmas.remove(&vaddr)
     }
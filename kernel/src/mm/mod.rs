@@ -16,12 +16,17 @@ use pagetable::create_kernel_pagetable;
 use pagetable::PageTable;
 
 use crate::{consts, info_sync, println, SpinLock, trace_sync};
-use crate::consts::{DEV_REMAP_START, DIRECT_MAP_START, MAX_ORDER, PAGE_OFFSET, PAGE_SIZE, PHY_MEM_OFFSET, PHY_MEM_START};
+use core::cmp::min;
+use crate::fdt::get_fdt_info;
+use crate::mm::swap::reclaim_pages;
+use crate::utils::order2pages;
+use crate::consts::{DEV_REMAP_START, DIRECT_MAP_START, HUGE_PAGE_ORDER, HUGE_PAGE_SIZE, KERNEL_STACK_SIZE, MAX_ORDER, PAGE_OFFSET, PAGE_SIZE, PHY_MEM_OFFSET, PHY_MEM_START};
 use crate::mm::addr::{addr_test, OldAddr, PageAlign, PFN, Vaddr};
 use crate::mm::bitmap::bitmap_test;
+use crate::mm::swap::swap_test;
 use crate::mm::mm::MmStruct;
 use crate::mm::page::Page;
-use crate::mm::pagetable::{create_kernel_mm, PTE, PTEFlags};
+use crate::mm::pagetable::{create_kernel_mm, KERNEL_RODATA_FLAGS, KERNEL_RW_FLAGS, KERNEL_TEXT_FLAGS, PTE, PTEFlags};
 use crate::pre::{InnerAccess, ReadWriteSingleNoOff};
 use crate::sbi::shutdown;
 use crate::sync::SpinLockGuard;
@@ -36,9 +41,13 @@ pub(crate) mod vma;
 pub(crate) mod mm;
 pub(crate) mod aux;
 pub(crate) mod kmap;
+pub(crate) mod swap;
+pub(crate) mod stat;
 
 const k210_mem_mb:u32 = 6;
 const qemu_mem_mb:u32 = 128;
+// boot pagetable只映射了1GB的物理内存
+const BOOT_MAP_MEM_MAX:usize = 0x40000000;
 
 const BitmapBits:usize = 4096;
 const BitmapOneMax:usize = 1024;
@@ -59,6 +68,9 @@ pub fn alloc_error_handler(layout: core::alloc::Layout)->!{
 extern "C" {
     fn ekernel();
     fn skernel();
+    fn stext();
+    fn srodata();
+    fn erodata();
 }
 
 lazy_static!{
@@ -73,6 +85,20 @@ pub fn trace_global_buddy(){
     trace_sync!("{:?}",b);
 }
 
+// buddy管理的物理页数
+pub fn get_total_pages()->usize{
+    PAGES_MANAGER.lock().unwrap().cap()
+}
+
+pub fn get_free_pages()->usize{
+    BUDDY_ALLOCATOR.lock().unwrap().get_free_pages_cnt()
+}
+
+// kernel heap已经分配的字节数
+pub fn get_heap_used()->usize{
+    HEAP_ALLOCATOR.lock().stats_alloc_actual()
+}
+
 pub fn get_kernel_pagetable()->Arc<PageTable>{
     KERNEL_MM.lock_irq().unwrap().pagetable.clone()
 }
@@ -90,20 +116,34 @@ fn buddy_init(start_addr: Vaddr, end_addr: Vaddr){
 }
 
 fn k210_remap(pgt:Arc<PageTable>){
-    pgt._force_map_one(0x38000000,0x38000000,0xcf);
-    pgt._force_map_one(0x38001000,0x38001000,0xcf);
+    pgt._force_map_one(0x38000000,0x38000000,KERNEL_RW_FLAGS);
+    pgt._force_map_one(0x38001000,0x38001000,KERNEL_RW_FLAGS);
 }
 
 fn hardware_remapping(){
     let pgt = get_kernel_pagetable();
-    let flags = PTEFlags::V.bits()| PTEFlags::R.bits()| PTEFlags::W.bits()| PTEFlags::X.bits();
+    // 设备寄存器不可执行
+    let flags = KERNEL_RW_FLAGS;
     #[cfg(feature = "qemu")]
     {
         // 清空0x40000000映射部分 这部分是k210专用
         let v:usize =unsafe{(pgt._get_root_page_vaddr()+8).read_single().unwrap()};
         unsafe{(pgt._get_root_page_vaddr()+8).write_single(0).unwrap();}
         for i in 0x10001..0x10300{
-           pgt._force_map_one(0+PAGE_SIZE*i+DEV_REMAP_START, 0+PAGE_SIZE*i, 0xcf);
+           pgt._force_map_one(0+PAGE_SIZE*i+DEV_REMAP_START, 0+PAGE_SIZE*i, flags);
+        }
+        let info = get_fdt_info();
+        let rtc = info.rtc_base & !(PAGE_SIZE-1);
+        if rtc != 0 {
+            pgt._force_map_one(rtc+DEV_REMAP_START, rtc, flags);
+        }
+        let uart = info.uart_base & !(PAGE_SIZE-1);
+        if uart != 0 {
+            pgt._force_map_one(uart+DEV_REMAP_START, uart, flags);
+        }
+        let plic = info.plic_base & !(PAGE_SIZE-1);
+        for i in 0..(info.plic_size+PAGE_SIZE-1)/PAGE_SIZE {
+            pgt._force_map_one(plic+PAGE_SIZE*i+DEV_REMAP_START, plic+PAGE_SIZE*i, flags);
         }
     }
     #[cfg(feature = "k210")]
@@ -114,6 +154,71 @@ fn hardware_remapping(){
     info_sync!("remap hardware ok");
 }
 
+// 用2MB大页映射PagesManager的页描述数组
+#[cfg(feature = "qemu")]
+fn vmemmap_init(start_addr: Vaddr, end_addr: Vaddr){
+    let pgs = (end_addr-start_addr.0).0/PAGE_SIZE;
+    let size = pgs*core::mem::size_of::<Option<alloc::sync::Weak<Page>>>();
+    get_kernel_pagetable().vmemmap_populate(size,&mut ||{
+        let area = BUDDY_ALLOCATOR.lock().unwrap().alloc_area(HUGE_PAGE_ORDER).ok()?;
+        unsafe { core::ptr::write_bytes(area.get_inner() as *mut u8, 0, HUGE_PAGE_SIZE); }
+        Some(area)
+    }).unwrap();
+    info_sync!("vmemmap init ok, size:{:#X}",size);
+}
+
+// boot pagetable使用1GB大页映射物理内存，拆分成2MB大页并去掉不存在的部分
+fn direct_map_init(mem_end: Vaddr){
+    let pgt = get_kernel_pagetable();
+    pgt.split_huge_leaf(Vaddr(PHY_MEM_START)).unwrap();
+    #[cfg(feature = "qemu")]
+    {
+        let mut v = (mem_end.get_inner()+HUGE_PAGE_SIZE-1)/HUGE_PAGE_SIZE*HUGE_PAGE_SIZE;
+        while v < PHY_MEM_START+BOOT_MAP_MEM_MAX {
+            pgt.unmap_huge_page(Vaddr(v)).unwrap();
+            v += HUGE_PAGE_SIZE;
+        }
+    }
+    info_sync!("direct map use 2MB pages, end:{:#X}",mem_end.get_inner());
+}
+
+fn kernel_section_flags(vaddr:usize)->u8{
+    if vaddr >= stext as usize && vaddr < srodata as usize {
+        KERNEL_TEXT_FLAGS
+    } else if vaddr >= srodata as usize && vaddr < erodata as usize {
+        KERNEL_RODATA_FLAGS
+    } else {
+        KERNEL_RW_FLAGS
+    }
+}
+
+// boot pagetable的映射都是RWX
+// 内核镜像按段设置权限: .text R-X .rodata R-- .data/.bss RW-，direct map的其余部分RW-
+fn kernel_map_init(){
+    let pgt = get_kernel_pagetable();
+    let s = skernel as usize/HUGE_PAGE_SIZE*HUGE_PAGE_SIZE;
+    let e = (ekernel as usize+HUGE_PAGE_SIZE-1)/HUGE_PAGE_SIZE*HUGE_PAGE_SIZE;
+    // 内核镜像所在的2MB页拆分成4K页，拆分后权限不变，可以拆分正在执行的代码所在的页
+    for v in (s..e).step_by(HUGE_PAGE_SIZE) {
+        pgt.split_huge_leaf(Vaddr(v)).unwrap();
+    }
+    for v in (s..e).step_by(PAGE_SIZE) {
+        pgt.change_map_flags(Vaddr(v),kernel_section_flags(v)).unwrap();
+    }
+    // 超出物理内存的部分已经unmap，修改权限会失败
+    for v in (PHY_MEM_START..PHY_MEM_START+BOOT_MAP_MEM_MAX).step_by(HUGE_PAGE_SIZE) {
+        if v < s || v >= e {
+            pgt.change_map_flags(Vaddr(v),KERNEL_RW_FLAGS).ok();
+        }
+    }
+    // 清除跳转到高地址时使用的0x80000000恒等映射
+    unsafe {
+        (pgt._get_root_page_vaddr()+addr_get_ppn2(0x80000000)*8).write_single(0usize).unwrap();
+        sfence_vma_all();
+    }
+    info_sync!("kernel map init ok, text:{:#X}-{:#X}",stext as usize,srodata as usize);
+}
+
 pub fn _insert_area_for_page_drop(vaddr:Vaddr, order:usize) ->Result<(),isize>{
     BUDDY_ALLOCATOR.lock().unwrap().free_area(vaddr, order)
 }
@@ -121,24 +226,42 @@ pub fn _insert_area_for_page_drop(vaddr:Vaddr, order:usize) ->Result<(),isize>{
 pub fn mm_init(){
     let sk = skernel as usize;
     let ek = ekernel as usize;
-    let new_ek = ek+PAGE_SIZE*HeapPages;
+    // qemu上heap结束地址2MB对齐，这样buddy中order>=9的块可以作为大页映射
+    #[cfg(feature = "qemu")]
+    let new_ek = (ek+PAGE_SIZE*HeapPages+HUGE_PAGE_SIZE-1)/HUGE_PAGE_SIZE*HUGE_PAGE_SIZE;
+    // buddy中的块相对起始地址对齐，k210上heap结束地址按内核栈大小对齐，trap入口的栈溢出检查依赖这一点
+    #[cfg(feature = "k210")]
+    let new_ek = (ek+PAGE_SIZE*HeapPages+KERNEL_STACK_SIZE-1)/KERNEL_STACK_SIZE*KERNEL_STACK_SIZE;
     unsafe {
-        HEAP_ALLOCATOR.lock().init(ek,PAGE_SIZE*HeapPages);
+        HEAP_ALLOCATOR.lock().init(ek,new_ek-ek);
     }
     info_sync!("Heap Allocator Init OK!");
     // init PAGE FRAME ALLOCATOR
     #[cfg(feature = "qemu")]
-    let mbs = qemu_mem_mb;
+    let mem_size = {
+        // 优先使用设备树中的内存大小，qemu可以通过-m修改
+        let fdt_mem_size = get_fdt_info().mem_size;
+        if fdt_mem_size != 0 {
+            min(fdt_mem_size,BOOT_MAP_MEM_MAX)
+        } else {
+            (qemu_mem_mb as usize)*1024*1024
+        }
+    };
     #[cfg(feature = "k210")]
-    let mbs = k210_mem_mb;
+    let mem_size = (k210_mem_mb as usize)*1024*1024;
 
-    let emem = (mbs as usize)*1024*1024+PHY_MEM_START;
+    let emem = mem_size+PHY_MEM_START;
     let mut s_addr = Vaddr(new_ek);
     let mut e_addr = Vaddr(emem);
     s_addr = s_addr.ceil();
     e_addr = e_addr.floor();
+    assert_eq!(s_addr.0%KERNEL_STACK_SIZE,0);
     buddy_init(s_addr,e_addr);
+    #[cfg(feature = "qemu")]
+    vmemmap_init(s_addr,e_addr);
     page_init(s_addr,e_addr);
+    direct_map_init(e_addr);
+    kernel_map_init();
     hardware_remapping();
 }
 
@@ -146,7 +269,11 @@ pub fn alloc_pages(order:usize)->Option<Arc<Page>>{
     if order>=MAX_ORDER {
         return None;
     }
-    let area = BUDDY_ALLOCATOR.lock().unwrap().alloc_area(order);
+    let mut area = BUDDY_ALLOCATOR.lock().unwrap().alloc_area(order);
+    if area.is_err() && reclaim_pages(order2pages(order)) != 0 {
+        // 换出匿名页后重试
+        area = BUDDY_ALLOCATOR.lock().unwrap().alloc_area(order);
+    }
     return match area {
         Ok(vaddr) => {
             let pgs = PAGES_MANAGER.lock().unwrap().new_pages_block_in_memory(vaddr, order);
@@ -163,6 +290,11 @@ pub fn alloc_one_page()->Option<Arc<Page>>{
     alloc_pages(0)
 }
 
+// 拆分pages block为独立的页，用于拆分大页
+pub fn split_pages(page:Arc<Page>)->Vec<Arc<Page>>{
+    PAGES_MANAGER.lock().unwrap().split_pages_block(page)
+}
+
 // free a pages block..
 // the arg 'page' `s ownership will move to this func and drop.
 // do same things with 'Drop(page)'
@@ -173,5 +305,6 @@ pub fn free_pages(page:Arc<Page>){
 pub fn mm_test(){
     bitmap_test();
     addr_test();
+    swap_test();
     shutdown();
 }
@@ -17,6 +17,8 @@ use log::set_max_level;
 
 use crate::{println, SpinLock, trace_sync};
 use crate::consts::PAGE_SIZE;
+#[cfg(feature = "qemu")]
+use crate::consts::VMEMMAP_START;
 use crate::mm::{_insert_area_for_page_drop, trace_global_buddy};
 use crate::mm::addr::{Addr, OldAddr, Paddr, PageAlign, PFN, Vaddr};
 use crate::pre::{ReadWriteSingleNoOff, InnerAccess, IOReadeWriteSeek, ReadWriteSingleOff};
@@ -25,14 +27,15 @@ use crate::sync::SpinLockGuard;
 
 pub struct PagesManager{
     start_vaddr: Vaddr,
-    pages: Vec<Option<Weak<Page>>>,
+    // qemu上位于vmemmap，k210内存太小，使用heap
+    pages: &'static mut [Option<Weak<Page>>],
 }
 
 impl Default for PagesManager {
     fn default() -> Self {
         PagesManager{
             start_vaddr: Vaddr(0),
-            pages: vec![],
+            pages: &mut [],
         }
     }
 }
@@ -42,7 +45,17 @@ impl PagesManager {
         let ns = start_addr.ceil();
         let ne = end_addr.floor();
         let pgs = (ne-ns.0).0/PAGE_SIZE;
-        self.pages.resize_with(pgs, || {None});
+        #[cfg(feature = "qemu")]
+        {
+            // vmemmap已经映射并清零，全0即为None
+            self.pages = unsafe { core::slice::from_raw_parts_mut(VMEMMAP_START as *mut Option<Weak<Page>>, pgs) };
+        }
+        #[cfg(feature = "k210")]
+        {
+            let mut pages = Vec::new();
+            pages.resize_with(pgs, || {None});
+            self.pages = pages.leak();
+        }
         self.start_vaddr = ns;
     }
     pub fn new(start_addr: Vaddr, end_addr: Vaddr) ->Self{
@@ -70,7 +83,7 @@ impl PagesManager {
     }
     pub fn get_in_memory_page_cnt(&self) ->usize{
         let mut cnt = 0;
-        for i in &self.pages {
+        for i in self.pages.iter() {
             match i {
                 Some(inner) =>{
                     match inner.upgrade() {
@@ -127,6 +140,22 @@ impl PagesManager {
         // trace_global_buddy();
         ret
     }
+    // 把一个pages block拆分成order为0的独立页，原leader成为第一页
+    // 调用者需要保证block没有被其他地方引用
+    pub fn split_pages_block(&mut self, pg:Arc<Page>) ->Vec<Arc<Page>>{
+        debug_assert!(pg.is_leader());
+        let friends = core::mem::take(&mut pg.get_inner_guard().friends);
+        pg.set_order(0);
+        let mut ret = vec![pg];
+        for f in friends {
+            // friend不是leader，drop时不会释放内存
+            let new_pg = Page::new(f.get_vaddr(), true);
+            let index = self.__get_index_no_check(f.get_vaddr());
+            self.pages[index] = Some(Arc::downgrade(&new_pg));
+            ret.push(new_pg);
+        }
+        ret
+    }
     pub fn get_in_memory_page(&self, vaddr:Vaddr) ->Option<Arc<Page>>{
         match self.__get_index_check(vaddr){
             None => {
@@ -278,6 +307,12 @@ impl Page {
     fn flush(&mut self) -> Result<(), ()> {
         (self.vaddr+self.inner.lock().unwrap().pos).flush()
     }
+    // 复制整个pages block，两个block的大小需要相同
+    pub unsafe fn copy_pages_block_data_from(&self,pg:&Arc<Page>){
+        let len = self.get_block_page_cnt()*PAGE_SIZE;
+        debug_assert_eq!(len, pg.get_block_page_cnt()*PAGE_SIZE);
+        core::ptr::copy_nonoverlapping(pg.get_vaddr().get_inner() as *const u8, self.get_vaddr().get_inner() as *mut u8, len);
+    }
     pub unsafe fn copy_one_page_data_from(&self,pg:Arc<Page>){
         let dest = self.get_vaddr();
         let src = pg.get_vaddr();
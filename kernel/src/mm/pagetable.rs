@@ -11,7 +11,8 @@ use log::error;
 use riscv::asm::sfence_vma_all;
 use riscv::register::satp::Satp;
 
-use crate::consts::{PAGE_SIZE, PHY_MEM_OFFSET};
+use core::ptr::addr_of;
+use crate::consts::{HUGE_PAGE_SIZE, PAGE_SIZE, PHY_MEM_OFFSET, VMEMMAP_START};
 use crate::{debug_sync, error_sync, info_sync, println, SpinLock, trace_sync};
 use crate::asm::w_satp;
 use crate::mm::{alloc_one_page, alloc_pages, get_kernel_pagetable, skernel};
@@ -19,6 +20,8 @@ use crate::mm::addr::{OldAddr, Paddr, PageAlign, PFN, Vaddr};
 use crate::mm::mm::{MmStruct, VmaCache};
 use crate::utils::order2pages;
 use crate::mm::page::Page;
+use crate::mm::swap::SwapEntry;
+use crate::mm::stat::{mem_stat_add, mem_stat_sub, MemStatItem};
 use crate::pre::{InnerAccess, ReadWriteSingleNoOff};
 use crate::utils::{addr_get_ppn0, addr_get_ppn1, addr_get_ppn2, get_usize_by_addr, set_usize_by_addr};
 
@@ -38,6 +41,18 @@ const WalkRetLevelRoot:usize = 0;
 const WalkRetLevelMiddle:usize = 1;
 const WalkRetLevelLeaf:usize = 2;
 
+// 每一级leaf pte映射的大小
+fn level_page_size(level:usize)->usize{
+    PAGE_SIZE<<(9*(WalkRetLevelLeaf-level))
+}
+
+#[repr(C, align(4096))]
+struct RawTable([usize;512]);
+
+// vmemmap在PAGES_MANAGER初始化之前建立，二级页表不能从PAGES_MANAGER分配
+// 使用内核镜像中的静态页表，最多映射1GB
+static mut VMEMMAP_PMD:RawTable = RawTable([0;512]);
+
 pub struct WalkRet{
     level:usize,
     pub(crate) pte_addr:usize,
@@ -79,8 +94,8 @@ impl PageTable {
         #[cfg(feature = "k210")]
         {
             // k210需要单独映射一部分
-            p._force_map_one(0x38000000,0x38000000,0xcf);
-            p._force_map_one(0x38001000,0x38001000,0xcf);
+            p._force_map_one(0x38000000,0x38000000,KERNEL_RW_FLAGS);
+            p._force_map_one(0x38001000,0x38001000,KERNEL_RW_FLAGS);
         }
         p
     }
@@ -97,6 +112,10 @@ impl PageTable {
     }
     // alloc时默认不使用大页
     fn _walk_common(&self, vaddr:usize, alloc:bool)->Option<WalkRet>{
+        self._walk_level(vaddr,WalkRetLevelLeaf,alloc)
+    }
+    // 最多walk到max_level，遇到大页leaf时提前返回
+    fn _walk_level(&self, vaddr:usize, max_level:usize, alloc:bool)->Option<WalkRet>{
         // walk will access pagetable,use lock..
         let mut pg_vaddr = self._get_root_page_vaddr().get_inner();
         let mut lock = self.private_pgs.lock_irq().unwrap();
@@ -115,9 +134,9 @@ impl PageTable {
                     pte_addr: entry_addr
                 });
             }
-            if i == 2 {
+            if i == max_level {
                 return Some(WalkRet{
-                    level: 2,
+                    level: i,
                     pte_addr: entry_addr
                 })
             }
@@ -140,6 +159,7 @@ impl PageTable {
                     // 防止deadlock
                     // self._insert_new_pages(pg_arc);
                     lock.push(pg_arc);
+                    mem_stat_add(MemStatItem::PageTable,1);
                     pte.set_ppn_by_paddr(allocated_page_vaddr -PHY_MEM_OFFSET);
                     //set RWX
                     //此时一定是非叶子页表
@@ -167,17 +187,14 @@ impl PageTable {
         return None;
     }
     pub fn get_kvaddr_by_uvaddr(&self,vaddr:Vaddr)->Option<Vaddr> {
-        let off = vaddr.0 % PAGE_SIZE;
         let mut vaddr_ = vaddr.clone();
         vaddr_.align();
         let r = self.walk(vaddr_.get_inner());
 
         r.map(|x|{
-            if x.level == WalkRetLevelLeaf {
-                Paddr(x.get_pte().get_point_paddr()+off).into()
-            } else {
-                todo!()
-            }
+            // 大页需要加上在大页内的偏移
+            let off = vaddr.0 % level_page_size(x.level);
+            Paddr(x.get_pte().get_point_paddr()+off).into()
         })
     }
     //强制映射 可能会破坏大页
@@ -204,6 +221,7 @@ impl PageTable {
                 // 防止deadlock
                 // self._insert_new_pages(pg_arc);
                 lock.push(pg_arc);
+                mem_stat_add(MemStatItem::PageTable,1);
                 pte.set_ppn_by_paddr(allocated_page_vaddr - PHY_MEM_OFFSET);
                 let pp = pte.get_point_paddr();
                 //clear RWX
@@ -291,6 +309,17 @@ impl PageTable {
         }
     }
 
+    // 检查vaddr是否映射到物理页，支持大页
+    pub fn is_mapped(&self, vaddr: Vaddr)->bool{
+        match self.walk(vaddr.0) {
+            None => false,
+            Some(r) => {
+                let pte = r.get_pte();
+                pte.vaild() && pte.is_leaf()
+            }
+        }
+    }
+
     pub fn is_not_mapped_order(&self, vaddr: Vaddr,order:usize)->bool {
         for i in vaddr.page_addr_iter(order2pages(order)*PAGE_SIZE) {
             if !self.is_not_mapped(i) {
@@ -307,22 +336,15 @@ impl PageTable {
         }
         let lock = self.private_pgs.lock_irq().unwrap();
         let r = r.unwrap();
-        match r.level {
-            WalkRetLevelLeaf => {
-                let pte_val = unsafe { get_usize_by_addr(r.pte_addr) };
-                let mut pte = PTE::from(pte_val);
-                if !pte.vaild() {
-                    return Err(());
-                }
-                pte.clear_all_flags();
-                pte.set_flags(new_flags);
-                let new_pte_val = pte.into();
-                unsafe { set_usize_by_addr(r.pte_addr, new_pte_val) };
-            },
-            _ => {
-                return Err(());
-            }
+        let mut pte = r.get_pte();
+        // 大页的leaf同样可以修改
+        if !pte.vaild() || (r.level != WalkRetLevelLeaf && !pte.is_leaf()) {
+            return Err(());
         }
+        pte.clear_all_flags();
+        pte.set_flags(new_flags);
+        let new_pte_val = pte.into();
+        unsafe { set_usize_by_addr(r.pte_addr, new_pte_val) };
         // clear tlb entry
         unsafe { sfence_vma_all(); }
         Ok(())
@@ -421,6 +443,187 @@ impl PageTable {
         }
         Ok(ret_option.unwrap())
     }
+    // 映射一个2MB大页，vaddr和paddr都需要2MB对齐
+    // 如果这个范围内已经存在一个空的三级页表，回收后再映射
+    pub fn map_huge_page(&self, vaddr: Vaddr, paddr: Paddr, flags:u8)->Result<(),isize>{
+        debug_assert!(vaddr.is_align_n(HUGE_PAGE_SIZE));
+        debug_assert!(paddr.is_align_n(HUGE_PAGE_SIZE));
+        let r = self._walk_level(vaddr.0, WalkRetLevelMiddle, true).unwrap();
+        if r.level != WalkRetLevelMiddle {
+            return Err(-1);
+        }
+        let mut lock = self.private_pgs.lock_irq().unwrap();
+        let pte = r.get_pte();
+        let mut old_table:Option<Vaddr> = None;
+        if pte.vaild() {
+            if pte.is_leaf() {
+                return Err(-1);
+            }
+            // 换出的页同样占用三级页表中的pte
+            let table:Vaddr = Paddr(pte.get_point_paddr()).into();
+            for i in 0..512 {
+                let v = unsafe { get_usize_by_addr(table.0+i*8) };
+                if PTE::from(v).vaild() || SwapEntry::from_pte(v).is_some() {
+                    return Err(-1);
+                }
+            }
+            old_table = Some(table);
+        }
+        let mut new_pte = PTE::default();
+        new_pte.set_ppn_by_paddr(paddr.get_inner());
+        new_pte.set_flags(flags);
+        unsafe { set_usize_by_addr(r.pte_addr, new_pte.into()) };
+        if let Some(table) = old_table {
+            lock.retain(|pg| pg.get_vaddr()!=table);
+            mem_stat_sub(MemStatItem::PageTable,1);
+        }
+        unsafe { sfence_vma_all(); }
+        Ok(())
+    }
+
+    // 返回被unmap的大页物理地址
+    pub fn unmap_huge_page(&self, vaddr: Vaddr)->Result<Paddr,isize>{
+        let r = self._walk_level(vaddr.0, WalkRetLevelMiddle, false).ok_or(-1)?;
+        let lock = self.private_pgs.lock_irq().unwrap();
+        let pte = r.get_pte();
+        if r.level != WalkRetLevelMiddle || !pte.vaild() || !pte.is_leaf() {
+            return Err(-1);
+        }
+        unsafe {
+            set_usize_by_addr(r.pte_addr, 0);
+            sfence_vma_vaddr(vaddr.get_inner());
+        }
+        Ok(Paddr(pte.get_point_paddr()))
+    }
+
+    // 把覆盖vaddr的大页拆分成下一级页表，映射和权限保持不变
+    // 1GB的页拆分成2MB的页，2MB的页拆分成4K的页
+    pub fn split_huge_leaf(&self, vaddr: Vaddr)->Result<(),()>{
+        let r = self.walk(vaddr.0).ok_or(())?;
+        if r.level == WalkRetLevelLeaf {
+            return Ok(());
+        }
+        let pte = r.get_pte();
+        if !pte.vaild() {
+            return Err(());
+        }
+        let table = alloc_pages(0).ok_or(())?;
+        let child_size = level_page_size(r.level+1);
+        let base = pte.get_point_paddr();
+        for i in 0..512 {
+            let mut child = PTE::default();
+            child.set_ppn_by_paddr(base+i*child_size);
+            child.set_flags(pte.flags);
+            unsafe { set_usize_by_addr(table.get_vaddr().0+i*8, child.into()) };
+        }
+        let mut new_pte = PTE::default();
+        new_pte.set_ppn_by_paddr(table.get_paddr().get_inner());
+        new_pte.set_flags(PTEFlags::V.bits);
+        let mut lock = self.private_pgs.lock_irq().unwrap();
+        lock.push(table);
+        mem_stat_add(MemStatItem::PageTable,1);
+        // 新页表已经完整建立，替换pte对正在使用这段映射的代码是原子的
+        unsafe {
+            set_usize_by_addr(r.pte_addr, new_pte.into());
+            sfence_vma_all();
+        }
+        Ok(())
+    }
+
+    // 在direct map中取消guard page的映射，覆盖它的大页会先被拆分
+    pub fn unmap_kernel_guard(&self, vaddr: Vaddr)->Result<(),()>{
+        self.split_huge_leaf(vaddr)?;
+        self._unmap_one_page(vaddr).map(|_|()).map_err(|_|())
+    }
+
+    // 恢复guard page在direct map中的映射
+    pub fn remap_kernel_guard(&self, vaddr: Vaddr)->Result<(),()>{
+        self.map_one_page(vaddr, vaddr.into(), KERNEL_RW_FLAGS).map_err(|_|())
+    }
+
+    // 使用2MB大页映射vmemmap，area_alloc返回2MB对齐的内存
+    // 只能在PAGES_MANAGER初始化之前对kernel pagetable使用
+    pub fn vmemmap_populate(&self, size:usize, area_alloc:&mut dyn FnMut()->Option<Vaddr>)->Result<(),()>{
+        let nr = (size+HUGE_PAGE_SIZE-1)/HUGE_PAGE_SIZE;
+        if nr > 512 {
+            return Err(());
+        }
+        let pmd = unsafe { addr_of!(VMEMMAP_PMD) as usize };
+        let root_entry = self._get_root_page_vaddr().get_inner()+addr_get_ppn2(VMEMMAP_START)*8;
+        let lock = self.private_pgs.lock_irq().unwrap();
+        for i in 0..nr {
+            let area = area_alloc().ok_or(())?;
+            let mut pte = PTE::default();
+            pte.set_ppn_by_paddr(area.get_inner()-PHY_MEM_OFFSET);
+            pte.set_flags(PTEFlags::V.bits|PTEFlags::R.bits|PTEFlags::W.bits|PTEFlags::A.bits|PTEFlags::D.bits);
+            unsafe { set_usize_by_addr(pmd+i*8, pte.into()) };
+        }
+        let mut pte = PTE::default();
+        pte.set_ppn_by_paddr(pmd-PHY_MEM_OFFSET);
+        pte.set_flags(PTEFlags::V.bits);
+        unsafe {
+            set_usize_by_addr(root_entry, pte.into());
+            sfence_vma_all();
+        }
+        Ok(())
+    }
+
+    // 换出的页在leaf pte中保存swap entry，V位为0
+    // 如果存在有效映射 那么返回Err
+    pub fn set_swap_entry(&self, vaddr: Vaddr, entry:SwapEntry)->Result<(),()>{
+        let r = self.walk_alloc(vaddr.0);
+        let lock = self.private_pgs.lock_irq().unwrap();
+        if r.level != WalkRetLevelLeaf || r.get_pte().vaild() {
+            return Err(());
+        }
+        unsafe { set_usize_by_addr(r.pte_addr, entry.to_pte()) };
+        Ok(())
+    }
+
+    pub fn get_swap_entry(&self, vaddr: Vaddr)->Option<SwapEntry>{
+        let r = self.walk(vaddr.0)?;
+        let lock = self.private_pgs.lock_irq().unwrap();
+        if r.level != WalkRetLevelLeaf {
+            return None;
+        }
+        SwapEntry::from_pte(unsafe { get_usize_by_addr(r.pte_addr) })
+    }
+
+    // 清除并返回swap entry
+    pub fn clear_swap_entry(&self, vaddr: Vaddr)->Option<SwapEntry>{
+        let r = self.walk(vaddr.0)?;
+        let lock = self.private_pgs.lock_irq().unwrap();
+        if r.level != WalkRetLevelLeaf {
+            return None;
+        }
+        let ret = SwapEntry::from_pte(unsafe { get_usize_by_addr(r.pte_addr) });
+        if ret.is_some(){
+            unsafe { set_usize_by_addr(r.pte_addr, 0) };
+        }
+        ret
+    }
+
+    // 返回accessed位并清除，用于页面回收
+    pub fn test_and_clear_accessed(&self, vaddr: Vaddr)->bool{
+        let r = match self.walk(vaddr.0) {
+            None => {
+                return false;
+            }
+            Some(r) => r
+        };
+        let lock = self.private_pgs.lock_irq().unwrap();
+        let mut pte = r.get_pte();
+        if !pte.vaild() || !pte._get_bits(PTEFlags::A.bits) {
+            return false;
+        }
+        pte.clear_flags(PTEFlags::A.bits);
+        unsafe {
+            set_usize_by_addr(r.pte_addr, pte.into());
+            sfence_vma_vaddr(vaddr.get_inner());
+        }
+        true
+    }
+
     // todo map的pages需要添加到mm空间的表中
     pub unsafe fn flush_self(&self){
         sfence_vma_all();
@@ -432,6 +635,7 @@ impl PageTable {
 
 impl Default for PageTable {
     fn default() -> Self {
+        mem_stat_add(MemStatItem::PageTable,1);
         PageTable{
             // alloc one pages for root page table
             private_pgs:SpinLock::new(vec![alloc_pages(0).unwrap()])
@@ -441,7 +645,8 @@ impl Default for PageTable {
 
 impl Drop for PageTable {
     fn drop(&mut self) {
-        // todo!()
+        // 页表页随private_pgs释放
+        mem_stat_sub(MemStatItem::PageTable,self.private_pgs.lock_irq().unwrap().len());
     }
 }
 
@@ -458,6 +663,11 @@ bitflags! {
     }
 }
 
+// 内核映射使用的权限，A/D位预先置位
+pub const KERNEL_TEXT_FLAGS:u8 = PTEFlags::V.bits|PTEFlags::R.bits|PTEFlags::X.bits|PTEFlags::A.bits|PTEFlags::D.bits;
+pub const KERNEL_RODATA_FLAGS:u8 = PTEFlags::V.bits|PTEFlags::R.bits|PTEFlags::A.bits|PTEFlags::D.bits;
+pub const KERNEL_RW_FLAGS:u8 = PTEFlags::V.bits|PTEFlags::R.bits|PTEFlags::W.bits|PTEFlags::A.bits|PTEFlags::D.bits;
+
 pub struct PTE{
     pub flags:u8,
     // rsw:u8, // 只能使用两位
@@ -0,0 +1,97 @@
+use alloc::string::String;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::consts::PAGE_SIZE;
+use crate::mm::{get_free_pages, get_heap_used, get_total_pages};
+use crate::mm::swap::get_swap_info;
+
+// 全局内存统计项，单位为页
+#[derive(Copy, Clone)]
+pub enum MemStatItem {
+    // 用户空间的文件映射页
+    File = 0,
+    // 用户空间的匿名页
+    Anon = 1,
+    KernelStack = 2,
+    PageTable = 3,
+    // tmpfs中的文件页
+    Shmem = 4,
+}
+
+const NR_MEM_STAT_ITEMS:usize = 5;
+
+static MEM_STAT:[AtomicUsize;NR_MEM_STAT_ITEMS] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+pub fn mem_stat_add(item:MemStatItem, nr:usize){
+    MEM_STAT[item as usize].fetch_add(nr,Ordering::Relaxed);
+}
+
+pub fn mem_stat_sub(item:MemStatItem, nr:usize){
+    MEM_STAT[item as usize].fetch_sub(nr,Ordering::Relaxed);
+}
+
+pub fn mem_stat_get(item:MemStatItem)->usize{
+    MEM_STAT[item as usize].load(Ordering::Relaxed)
+}
+
+// 单位为字节
+#[derive(Copy, Clone)]
+pub struct MemInfo{
+    pub total:usize,
+    pub free:usize,
+    pub file:usize,
+    pub anon:usize,
+    pub kernel_stack:usize,
+    pub page_table:usize,
+    pub shmem:usize,
+    // kernel heap中已分配的部分
+    pub slab:usize,
+    pub swap_total:usize,
+    pub swap_free:usize,
+}
+
+pub fn get_meminfo()->MemInfo{
+    let (swap_total,swap_free) = get_swap_info();
+    MemInfo{
+        total: get_total_pages()*PAGE_SIZE,
+        free: get_free_pages()*PAGE_SIZE,
+        file: mem_stat_get(MemStatItem::File)*PAGE_SIZE,
+        anon: mem_stat_get(MemStatItem::Anon)*PAGE_SIZE,
+        kernel_stack: mem_stat_get(MemStatItem::KernelStack)*PAGE_SIZE,
+        page_table: mem_stat_get(MemStatItem::PageTable)*PAGE_SIZE,
+        shmem: mem_stat_get(MemStatItem::Shmem)*PAGE_SIZE,
+        slab: get_heap_used(),
+        swap_total,
+        swap_free
+    }
+}
+
+// /proc/meminfo格式，单位为kB
+pub fn meminfo_text()->String{
+    let info = get_meminfo();
+    let mut s = String::new();
+    let items = [
+        ("MemTotal:",info.total),
+        ("MemFree:",info.free),
+        ("MemAvailable:",info.free),
+        ("Cached:",info.file),
+        ("AnonPages:",info.anon),
+        ("Mapped:",info.file),
+        ("Shmem:",info.shmem),
+        ("Slab:",info.slab),
+        ("KernelStack:",info.kernel_stack),
+        ("PageTables:",info.page_table),
+        ("SwapTotal:",info.swap_total),
+        ("SwapFree:",info.swap_free),
+    ];
+    for (name,v) in items.iter() {
+        writeln!(s,"{:<16}{:>8} kB",name,v/1024).unwrap();
+    }
+    s
+}
@@ -0,0 +1,542 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::consts::PAGE_SIZE;
+use crate::fs::inode::Inode;
+use crate::io::{BlockReadWrite, MemDisk};
+use crate::mm::bitmap::Bitmap;
+use crate::mm::page::Page;
+use crate::pre::InnerAccess;
+use crate::syscall::errno::{EBUSY, EINVAL, EIO, ENOMEM, EPERM};
+use crate::task::all_tasks;
+use crate::task::task::{PF_MEMALLOC_NOIO, running_flags};
+use crate::{info_sync, SpinLock, warn_sync};
+
+// V=0时硬件不会检查pte的其他位，使用RSW的第一位标记swap entry
+const SWAP_PTE_MARK:usize = 1<<8;
+const SWAP_TYPE_SHIFT:usize = 10;
+const SWAP_TYPE_BITS:usize = 5;
+const SWAP_OFFSET_SHIFT:usize = SWAP_TYPE_SHIFT+SWAP_TYPE_BITS;
+pub const MAX_SWAP_FILES:usize = 1<<SWAP_TYPE_BITS;
+
+pub const SWAP_FLAG_PREFER:usize = 0x8000;
+pub const SWAP_FLAG_PRIO_MASK:usize = 0x7fff;
+
+const SECTOR_SIZE:usize = 512;
+const SECTORS_PER_PAGE:usize = PAGE_SIZE/SECTOR_SIZE;
+
+// mkswap写在第0页的header，签名在页末尾
+const SWAP_SIGNATURE:&[u8;10] = b"SWAPSPACE2";
+const SWAP_HEADER_VERSION:usize = 1024;
+const SWAP_HEADER_LAST_PAGE:usize = 1028;
+const SWAP_HEADER_NR_BADPAGES:usize = 1032;
+const SWAP_HEADER_BADPAGES:usize = 1536;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SwapEntry{
+    swap_type:usize,
+    offset:usize
+}
+
+impl SwapEntry {
+    pub fn new(swap_type:usize,offset:usize)->Self{
+        debug_assert!(swap_type<MAX_SWAP_FILES);
+        Self{
+            swap_type,
+            offset
+        }
+    }
+    pub fn get_type(&self)->usize{
+        self.swap_type
+    }
+    pub fn get_offset(&self)->usize{
+        self.offset
+    }
+    pub fn to_pte(&self)->usize{
+        SWAP_PTE_MARK|(self.swap_type<<SWAP_TYPE_SHIFT)|(self.offset<<SWAP_OFFSET_SHIFT)
+    }
+    // valid的pte或者没有swap标记的pte返回None
+    pub fn from_pte(pte:usize)->Option<Self>{
+        if pte&1 != 0 || pte&SWAP_PTE_MARK == 0 {
+            return None;
+        }
+        Some(Self{
+            swap_type: (pte>>SWAP_TYPE_SHIFT)&(MAX_SWAP_FILES-1),
+            offset: pte>>SWAP_OFFSET_SHIFT
+        })
+    }
+}
+
+#[derive(Clone)]
+enum SwapBackend{
+    // 块设备以及起始扇区
+    Block(Arc<dyn BlockReadWrite>,usize),
+    File(Arc<Inode>)
+}
+
+impl SwapBackend {
+    fn same(&self,other:&SwapBackend)->bool{
+        match (self,other) {
+            (SwapBackend::File(a),SwapBackend::File(b)) => Arc::ptr_eq(a,b),
+            (SwapBackend::Block(a,_),SwapBackend::Block(b,_)) => Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const (),
+            _ => false
+        }
+    }
+    fn read_page(&self,slot:usize,buf:&mut [u8])->Result<(),()>{
+        debug_assert_eq!(buf.len(),PAGE_SIZE);
+        match self {
+            SwapBackend::Block(dev,start_blk) => {
+                let blk = start_blk+slot*SECTORS_PER_PAGE;
+                for i in 0..SECTORS_PER_PAGE{
+                    dev.read_block(blk+i,&mut buf[i*SECTOR_SIZE..(i+1)*SECTOR_SIZE]).map_err(|_| ())?;
+                }
+                Ok(())
+            }
+            SwapBackend::File(inode) => {
+                match inode.read_off_exact(buf,slot*PAGE_SIZE) {
+                    Ok(PAGE_SIZE) => Ok(()),
+                    _ => Err(())
+                }
+            }
+        }
+    }
+    fn write_page(&self,slot:usize,buf:&[u8])->Result<(),()>{
+        debug_assert_eq!(buf.len(),PAGE_SIZE);
+        match self {
+            SwapBackend::Block(dev,start_blk) => {
+                let blk = start_blk+slot*SECTORS_PER_PAGE;
+                for i in 0..SECTORS_PER_PAGE{
+                    dev.write_block(blk+i,&buf[i*SECTOR_SIZE..(i+1)*SECTOR_SIZE]).map_err(|_| ())?;
+                }
+                Ok(())
+            }
+            SwapBackend::File(inode) => {
+                match inode.write_off_exact(buf,slot*PAGE_SIZE) {
+                    Ok(PAGE_SIZE) => Ok(()),
+                    _ => Err(())
+                }
+            }
+        }
+    }
+}
+
+pub struct SwapArea{
+    name:String,
+    backend:SwapBackend,
+    slots:Bitmap,
+    nr_slots:usize,
+    nr_used:usize,
+    // header中记录的坏页，在bitmap中标记为已使用
+    nr_bad:usize,
+    next_slot:usize,
+    prio:isize,
+    // swapoff过程中不再分配slot
+    enabled:bool
+}
+
+impl SwapArea {
+    fn new(name:&str,backend:SwapBackend,nr_slots:usize,bad:&[usize],prio:isize)->Self{
+        let mut slots = Bitmap::new(nr_slots);
+        for page in bad.iter() {
+            slots.set(*page);
+        }
+        Self{
+            name: String::from(name),
+            backend,
+            slots,
+            nr_slots,
+            nr_used: 0,
+            nr_bad: bad.len(),
+            next_slot: 1,
+            prio,
+            enabled: true
+        }
+    }
+    // slot 0保留给swap header
+    fn alloc_slot(&mut self)->Option<usize>{
+        if !self.enabled || self.nr_used+self.nr_bad+1>=self.nr_slots {
+            return None;
+        }
+        for i in 0..self.nr_slots{
+            let slot = (self.next_slot+i)%self.nr_slots;
+            if slot == 0{
+                continue;
+            }
+            if !self.slots.get(slot){
+                self.slots.set(slot);
+                self.nr_used+=1;
+                self.next_slot = slot+1;
+                return Some(slot);
+            }
+        }
+        None
+    }
+    fn free_slot(&mut self,slot:usize){
+        debug_assert!(self.slots.get(slot));
+        self.slots.clear(slot);
+        self.nr_used-=1;
+    }
+    pub fn get_name(&self)->&str{
+        &self.name
+    }
+    pub fn is_file(&self)->bool{
+        match self.backend {
+            SwapBackend::File(_) => true,
+            _ => false
+        }
+    }
+    pub fn get_size(&self)->usize{
+        (self.nr_slots-1-self.nr_bad)*PAGE_SIZE
+    }
+    pub fn get_used(&self)->usize{
+        self.nr_used*PAGE_SIZE
+    }
+    pub fn get_prio(&self)->isize{
+        self.prio
+    }
+}
+
+lazy_static!{
+    static ref SWAP_AREAS:SpinLock<Vec<Option<SwapArea>>> = SpinLock::new(Vec::new());
+    static ref RECLAIMING:AtomicBool = AtomicBool::new(false);
+}
+
+// 持有文件系统、块缓存的锁时分配页不能回收，换出到swap文件会重入这些锁
+// 在当前task上设置PF_MEMALLOC_NOIO，只影响持有者自己，可以嵌套
+pub struct NoReclaimGuard{
+    flags:Option<Arc<AtomicUsize>>,
+    was_set:bool
+}
+
+impl NoReclaimGuard {
+    pub fn new()->Self{
+        let flags = running_flags();
+        let was_set = flags.as_ref().map_or(false,|f|{
+            f.fetch_or(PF_MEMALLOC_NOIO,Ordering::SeqCst)&PF_MEMALLOC_NOIO != 0
+        });
+        NoReclaimGuard{ flags, was_set }
+    }
+}
+
+impl Drop for NoReclaimGuard {
+    fn drop(&mut self) {
+        if let Some(f) = &self.flags {
+            if !self.was_set {
+                f.fetch_and(!PF_MEMALLOC_NOIO,Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+// 当前task是否禁止换出I/O
+fn __noio()->bool{
+    running_flags().map_or(false,|f|{ f.load(Ordering::SeqCst)&PF_MEMALLOC_NOIO != 0 })
+}
+
+fn header_u32(header:&[u8],off:usize)->usize{
+    u32::from_ne_bytes([header[off],header[off+1],header[off+2],header[off+3]]) as usize
+}
+
+// 检查mkswap的header，返回slot数和坏页，slot数不超过max_slots
+fn read_swap_header(backend:&SwapBackend,max_slots:usize)->Result<(usize,Vec<usize>),isize>{
+    if max_slots<2 {
+        return Err(-EINVAL);
+    }
+    let mut header = vec![0u8;PAGE_SIZE];
+    backend.read_page(0,&mut header).map_err(|_| -EIO)?;
+    if &header[PAGE_SIZE-SWAP_SIGNATURE.len()..] != SWAP_SIGNATURE || header_u32(&header,SWAP_HEADER_VERSION) != 1 {
+        return Err(-EINVAL);
+    }
+    let last_page = header_u32(&header,SWAP_HEADER_LAST_PAGE);
+    if last_page == 0 || last_page >= max_slots {
+        return Err(-EINVAL);
+    }
+    let nr_slots = last_page+1;
+    let nr_bad = header_u32(&header,SWAP_HEADER_NR_BADPAGES);
+    if SWAP_HEADER_BADPAGES+nr_bad*4 > PAGE_SIZE-SWAP_SIGNATURE.len() {
+        return Err(-EINVAL);
+    }
+    let mut bad = Vec::new();
+    for i in 0..nr_bad {
+        let page = header_u32(&header,SWAP_HEADER_BADPAGES+i*4);
+        if page == 0 || page >= nr_slots {
+            return Err(-EINVAL);
+        }
+        if !bad.contains(&page) {
+            bad.push(page);
+        }
+    }
+    if bad.len()+1 >= nr_slots {
+        return Err(-EINVAL);
+    }
+    Ok((nr_slots,bad))
+}
+
+// header的读取不持有SWAP_AREAS的锁
+fn _swapon(name:&str,backend:SwapBackend,max_slots:usize,prio:isize)->Result<(),isize>{
+    let (nr_slots,bad) = read_swap_header(&backend,max_slots)?;
+    let mut areas = SWAP_AREAS.lock_irq().unwrap();
+    let mut free_type = None;
+    let mut min_prio = 0isize;
+    for (i,a) in areas.iter().enumerate(){
+        match a {
+            None => {
+                if free_type.is_none(){
+                    free_type = Some(i);
+                }
+            }
+            Some(area) => {
+                if area.backend.same(&backend) {
+                    return Err(-EBUSY);
+                }
+                min_prio = min_prio.min(area.prio);
+            }
+        }
+    }
+    let swap_type = match free_type {
+        Some(t) => t,
+        None => {
+            if areas.len()>=MAX_SWAP_FILES{
+                return Err(-EPERM);
+            }
+            areas.push(None);
+            areas.len()-1
+        }
+    };
+    // 未指定优先级时依次递减
+    let prio = if prio<0 { min_prio-1 } else { prio };
+    areas[swap_type] = Some(SwapArea::new(name,backend,nr_slots,&bad,prio));
+    info_sync!("swapon {}: {} pages, {} bad, prio {}",name,nr_slots-1,bad.len(),prio);
+    Ok(())
+}
+
+// swap文件需要预先分配好大小并经过mkswap，header中的大小不能超过文件
+pub fn swapon_file(name:&str,inode:Arc<Inode>,prio:isize)->Result<(),isize>{
+    if !inode.is_file(){
+        return Err(-EINVAL);
+    }
+    let max_slots = inode.get_size()/PAGE_SIZE;
+    _swapon(name,SwapBackend::File(inode),max_slots,prio)
+}
+
+// 整个块设备或分区作为swap area
+pub fn swapon_blk(name:&str,dev:Arc<dyn BlockReadWrite>,prio:isize)->Result<(),isize>{
+    let max_slots = dev.num_blocks().map_or(usize::MAX,|n| n/SECTORS_PER_PAGE);
+    _swapon(name,SwapBackend::Block(dev,0),max_slots,prio)
+}
+
+fn _find_swap_type(backend:&SwapBackend)->Option<usize>{
+    let areas = SWAP_AREAS.lock_irq().unwrap();
+    areas.iter().position(|a| a.as_ref().map_or(false,|area| area.backend.same(backend)))
+}
+
+fn _set_swap_enabled(swap_type:usize,enabled:bool){
+    SWAP_AREAS.lock_irq().unwrap()[swap_type].as_mut().unwrap().enabled = enabled;
+}
+
+// 把所有进程在该swap area上的页换入，然后移除swap area
+// 调用者不能持有任何task或mm的锁
+fn swapoff(backend:SwapBackend)->Result<(),isize>{
+    let swap_type = match _find_swap_type(&backend) {
+        None => {
+            return Err(-EINVAL);
+        }
+        Some(t) => t
+    };
+    _set_swap_enabled(swap_type,false);
+    for tsk in all_tasks(){
+        let mm = match tsk.lock_irq().unwrap().mm.as_ref() {
+            None => {
+                continue;
+            }
+            Some(mm) => mm.clone()
+        };
+        if mm.lock_irq().unwrap().swap_in_area(swap_type).is_err(){
+            // 内存不足 无法完成swapoff
+            _set_swap_enabled(swap_type,true);
+            return Err(-ENOMEM);
+        }
+    }
+    let mut areas = SWAP_AREAS.lock_irq().unwrap();
+    let area = areas[swap_type].take().unwrap();
+    if area.nr_used != 0 {
+        warn_sync!("swapoff {}: {} slots leaked",area.name,area.nr_used);
+    }
+    info_sync!("swapoff {}",area.name);
+    Ok(())
+}
+
+pub fn swapoff_file(inode:Arc<Inode>)->Result<(),isize>{
+    swapoff(SwapBackend::File(inode))
+}
+
+pub fn swapoff_blk(dev:Arc<dyn BlockReadWrite>)->Result<(),isize>{
+    swapoff(SwapBackend::Block(dev,0))
+}
+
+pub fn swap_enabled()->bool{
+    SWAP_AREAS.lock_irq().unwrap().iter().any(|a|{
+        a.as_ref().map_or(false,|area| area.enabled)
+    })
+}
+
+// (total,free) 单位字节
+pub fn get_swap_info()->(usize,usize){
+    let mut total = 0;
+    let mut used = 0;
+    for a in SWAP_AREAS.lock_irq().unwrap().iter(){
+        if let Some(area) = a {
+            total += area.get_size();
+            used += area.get_used();
+        }
+    }
+    (total,total-used)
+}
+
+pub fn for_each_swap_area(mut f:impl FnMut(&SwapArea)){
+    for a in SWAP_AREAS.lock_irq().unwrap().iter(){
+        if let Some(area) = a {
+            f(area);
+        }
+    }
+}
+
+fn swap_alloc_slot()->Option<(SwapEntry,SwapBackend)>{
+    let mut areas = SWAP_AREAS.lock_irq().unwrap();
+    let mut best:Option<usize> = None;
+    for (i,a) in areas.iter().enumerate(){
+        if let Some(area) = a {
+            if area.enabled && area.nr_used+area.nr_bad+1<area.nr_slots
+                && best.map_or(true,|b| areas[b].as_ref().unwrap().prio<area.prio) {
+                best = Some(i);
+            }
+        }
+    }
+    let swap_type = best?;
+    let area = areas[swap_type].as_mut().unwrap();
+    area.alloc_slot().map(|slot|{
+        (SwapEntry::new(swap_type,slot),area.backend.clone())
+    })
+}
+
+fn _get_backend(entry:SwapEntry)->SwapBackend{
+    SWAP_AREAS.lock_irq().unwrap()[entry.get_type()].as_ref().unwrap().backend.clone()
+}
+
+pub fn swap_free_slot(entry:SwapEntry){
+    SWAP_AREAS.lock_irq().unwrap()[entry.get_type()].as_mut().unwrap().free_slot(entry.get_offset());
+}
+
+// 写入swap时不能持有SWAP_AREAS的锁，块设备读写期间可能再次分配物理页
+pub fn swap_out_page(pg:&Arc<Page>)->Option<SwapEntry>{
+    debug_assert_eq!(pg.get_order(),0);
+    let (entry,backend) = swap_alloc_slot()?;
+    let buf = unsafe { &*slice_from_raw_parts(pg.get_vaddr().get_inner() as *const u8,PAGE_SIZE) };
+    match backend.write_page(entry.get_offset(),buf) {
+        Ok(_) => Some(entry),
+        Err(_) => {
+            swap_free_slot(entry);
+            None
+        }
+    }
+}
+
+// 只读取数据，不释放slot
+pub fn swap_in_page(entry:SwapEntry,pg:&Arc<Page>)->Result<(),()>{
+    let buf = unsafe { &mut *slice_from_raw_parts_mut(pg.get_vaddr().get_inner() as *mut u8,PAGE_SIZE) };
+    _get_backend(entry).read_page(entry.get_offset(),buf)
+}
+
+// 物理页不足时调用，尝试换出nr个匿名页，返回实际换出的页数
+// 持有锁的task或mm会被跳过，所以可以在缺页处理等持锁的路径中调用
+pub fn reclaim_pages(nr:usize)->usize{
+    if nr == 0 || !swap_enabled() || __noio() {
+        return 0;
+    }
+    if RECLAIMING.swap(true,Ordering::SeqCst){
+        // swap读写过程中再次触发回收
+        return 0;
+    }
+    let mut done = 0;
+    // 第一轮扫描会清除accessed位，最近访问过的页要到第二轮才会被换出
+    for _ in 0..2 {
+        for tsk in all_tasks(){
+            if done>=nr{
+                break;
+            }
+            let mm = match tsk.lock_irq_nowait() {
+                None => {
+                    continue;
+                }
+                Some(t) => {
+                    match t.mm.as_ref() {
+                        None => {
+                            continue;
+                        }
+                        Some(mm) => mm.clone()
+                    }
+                }
+            };
+            if let Some(mut mm_locked) = mm.lock_irq_nowait(){
+                done += mm_locked.reclaim_anon_pages(nr-done);
+            }
+        }
+        if done>=nr{
+            break;
+        }
+    }
+    RECLAIMING.store(false,Ordering::SeqCst);
+    done
+}
+
+fn put_u32(buf:&mut [u8],off:usize,v:u32){
+    buf[off..off+4].copy_from_slice(&v.to_ne_bytes());
+}
+
+// 在内存磁盘上构造mkswap的header，检查pte编码、header解析和slot分配
+pub fn swap_test(){
+    let entry = SwapEntry::new(3,0x1234);
+    assert_eq!(SwapEntry::from_pte(entry.to_pte()),Some(entry));
+    assert_eq!(SwapEntry::from_pte(entry.to_pte()|1),None);
+    assert_eq!(SwapEntry::from_pte(0),None);
+
+    let disk:Arc<dyn BlockReadWrite> = Arc::new(MemDisk::new(8*SECTORS_PER_PAGE));
+    let backend = SwapBackend::Block(disk,0);
+    assert_eq!(read_swap_header(&backend,8).err(),Some(-EINVAL));
+    let mut header = vec![0u8;PAGE_SIZE];
+    header[PAGE_SIZE-SWAP_SIGNATURE.len()..].copy_from_slice(SWAP_SIGNATURE);
+    put_u32(&mut header,SWAP_HEADER_VERSION,1);
+    put_u32(&mut header,SWAP_HEADER_LAST_PAGE,7);
+    put_u32(&mut header,SWAP_HEADER_NR_BADPAGES,1);
+    put_u32(&mut header,SWAP_HEADER_BADPAGES,3);
+    backend.write_page(0,&header).unwrap();
+    // header中的大小超过设备
+    assert_eq!(read_swap_header(&backend,7).err(),Some(-EINVAL));
+    let (nr_slots,bad) = read_swap_header(&backend,8).unwrap();
+    assert_eq!(nr_slots,8);
+    assert_eq!(bad,vec![3]);
+
+    let data = vec![0x5au8;PAGE_SIZE];
+    let mut buf = vec![0u8;PAGE_SIZE];
+    backend.write_page(2,&data).unwrap();
+    backend.read_page(2,&mut buf).unwrap();
+    assert_eq!(buf,data);
+    assert!(backend.read_page(8,&mut buf).is_err());
+
+    // 跳过header和坏页
+    let mut area = SwapArea::new("test",backend,nr_slots,&bad,0);
+    let got:Vec<usize> = (0..6).map(|_| area.alloc_slot().unwrap()).collect();
+    assert_eq!(got,vec![1,2,4,5,6,7]);
+    assert_eq!(area.alloc_slot(),None);
+    assert_eq!(area.get_size(),6*PAGE_SIZE);
+    assert_eq!(area.get_used(),6*PAGE_SIZE);
+    area.free_slot(5);
+    assert_eq!(area.alloc_slot(),Some(5));
+    area.free_slot(2);
+    area.enabled = false;
+    assert_eq!(area.alloc_slot(),None);
+    info_sync!("swap test OK!");
+}
@@ -4,21 +4,24 @@ use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::riscv64::fence_i;
 use core::cell::RefCell;
-use core::cmp::Ordering;
+use core::cmp::{min, Ordering};
 use core::default::Default;
 use core::fmt::{Debug, Formatter};
 use core::mem::size_of;
 use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
+use core::sync::atomic;
 use fatfs::{Read, Seek, SeekFrom, Write};
 use log::set_max_level;
-use crate::consts::PAGE_SIZE;
+use crate::consts::{HUGE_PAGE_ORDER, HUGE_PAGE_SIZE, PAGE_SIZE};
 
 use crate::mm::addr::{OldAddr, Paddr, PageAlign, Vaddr};
-use crate::mm::{alloc_one_page, get_kernel_pagetable};
+use crate::mm::{alloc_one_page, alloc_pages, get_kernel_pagetable, split_pages};
 use crate::utils::order2pages;
 use crate::mm::mm::MmStruct;
 use crate::mm::page::Page;
 use crate::mm::pagetable::{PageTable, PTEFlags};
+use crate::mm::swap::{swap_free_slot, swap_in_page, swap_out_page, SwapEntry};
+use crate::mm::stat::{mem_stat_add, mem_stat_sub, MemStatItem};
 use crate::pre::{InnerAccess, ReadWriteOffUnsafe, ReadWriteSingleNoOff, ReadWriteSingleOff, ShowRdWrEx};
 use crate::{error_sync, info_sync, println, SpinLock};
 use crate::fs::inode::Inode;
@@ -34,9 +37,15 @@ bitflags! {
         const VM_SHARD = 1 << 4;
         const VM_ANON = 1 << 5;
         const VM_DIRTY = 1<< 6;
+        const VM_HUGEPAGE = 1 << 7;
+        const VM_LOCKED = 1 << 8;
+        const VM_NOHUGEPAGE = 1 << 9;
     }
 }
 
+// 透明大页：匿名vma中对齐的2MB区域缺页时直接使用大页
+pub static THP_ENABLED:atomic::AtomicBool = atomic::AtomicBool::new(true);
+
 impl VmFlags {
     pub fn from_mmap(mmap_flags:MmapFlags,prot_flags:MmapProt)->Self{
         let mut ret = Self::VM_NONE;
@@ -52,6 +61,9 @@ impl VmFlags {
         if mmap_flags.contains(MmapFlags::MAP_ANONYMOUS){
             ret|=Self::VM_ANON;
         }
+        if mmap_flags.contains(MmapFlags::MAP_HUGETLB){
+            ret|=Self::VM_HUGEPAGE;
+        }
         ret|=Self::VM_USER;
         ret
     }
@@ -64,6 +76,7 @@ bitflags! {
         const MAP_PRIVATE = 0x02;
         const MAP_FIXED = 0x10;
         const MAP_ANONYMOUS = 0x20;
+        const MAP_HUGETLB = 0x40000;
     }
 }
 
@@ -79,6 +92,52 @@ bitflags! {
     }
 }
 
+// madvise
+pub const MADV_NORMAL:usize = 0;
+pub const MADV_RANDOM:usize = 1;
+pub const MADV_SEQUENTIAL:usize = 2;
+pub const MADV_WILLNEED:usize = 3;
+pub const MADV_DONTNEED:usize = 4;
+pub const MADV_FREE:usize = 8;
+pub const MADV_HUGEPAGE:usize = 14;
+pub const MADV_NOHUGEPAGE:usize = 15;
+
+// 缺页时页中数据的来源，mlock和MADV_WILLNEED在mm锁外读入数据
+pub enum PageSource{
+    // 不需要读入数据
+    Zero,
+    Swap(SwapEntry),
+    // 页内[left,right)从文件的off处读取
+    File(Arc<Inode>,usize,usize,usize)
+}
+
+impl PageSource {
+    // 读入数据到pg，可能进行块设备I/O，调用时不能持有mm锁
+    pub fn fill(&self,pg:&Arc<Page>)->Result<(),()>{
+        match self {
+            PageSource::Zero => Ok(()),
+            PageSource::Swap(entry) => swap_in_page(*entry,pg),
+            PageSource::File(f,left,right,off) => {
+                let buf = unsafe { &mut *slice_from_raw_parts_mut(pg.get_vaddr().get_inner() as *mut u8,PAGE_SIZE) };
+                match f.read_off_exact(&mut buf[*left..*right],*off) {
+                    Ok(n) if n==right-left => Ok(()),
+                    _ => Err(())
+                }
+            }
+        }
+    }
+    fn same(&self,other:&Self)->bool{
+        match (self,other) {
+            (PageSource::Zero,PageSource::Zero) => true,
+            (PageSource::Swap(a),PageSource::Swap(b)) => a==b,
+            (PageSource::File(f,l,r,o),PageSource::File(g,l2,r2,o2)) => {
+                Arc::ptr_eq(f,g) && (l,r,o)==(l2,r2,o2)
+            }
+            _ => false
+        }
+    }
+}
+
 // pub enum VmaType{
 //     VmaNone,
 //     VmaAnon,
@@ -96,7 +155,11 @@ pub struct VMA{
     pub file_in_vma_off:usize,
     pub file_len:usize,
     pub phy_pgs_cnt:usize,
-    pub cow_write_reserve_pgs:Option<BTreeMap<Vaddr,Arc<Page>>>
+    pub cow_write_reserve_pgs:Option<BTreeMap<Vaddr,Arc<Page>>>,
+    // 换出到swap的页数，页的swap entry保存在pte中
+    pub swap_pgs_cnt:usize,
+    // 回收扫描的起始位置
+    pub swap_hand:Vaddr
 }
 
 impl ShowRdWrEx for VMA{
@@ -164,7 +227,9 @@ impl VMA {
             file_in_vma_off: 0,
             file_len: 0,
             phy_pgs_cnt: 0,
-            cow_write_reserve_pgs: None
+            cow_write_reserve_pgs: None,
+            swap_pgs_cnt: 0,
+            swap_hand: start_vaddr
         }
     }
     pub fn new(start_vaddr: Vaddr, end_vaddr: Vaddr,
@@ -185,7 +250,9 @@ impl VMA {
             file_in_vma_off,
             file_len,
             phy_pgs_cnt: 0,
-            cow_write_reserve_pgs: None
+            cow_write_reserve_pgs: None,
+            swap_pgs_cnt: 0,
+            swap_hand: start_vaddr
         }
     }
     pub fn new_anon(start_vaddr: Vaddr, end_vaddr: Vaddr,vm_flags:VmFlags,
@@ -231,8 +298,45 @@ impl VMA {
     pub fn get_flags(&self)->VmFlags{
         self.vm_flags
     }
+    // 用户空间的页计入全局统计
+    fn __mem_stat_item(&self)->Option<MemStatItem>{
+        if !self.vm_flags.contains(VmFlags::VM_USER) {
+            return None;
+        }
+        Some(if self.is_anon() { MemStatItem::Anon } else { MemStatItem::File })
+    }
+    fn __account_pages(&mut self, nr:usize, add:bool){
+        if add {
+            self.phy_pgs_cnt += nr;
+        } else {
+            self.phy_pgs_cnt = self.phy_pgs_cnt.saturating_sub(nr);
+        }
+        if let Some(item) = self.__mem_stat_item() {
+            if add {
+                mem_stat_add(item,nr);
+            } else {
+                mem_stat_sub(item,nr);
+            }
+        }
+    }
+    // pages_tree的修改都需要经过这两个函数，保证phy_pgs_cnt正确
+    fn __insert_page(&mut self, vaddr:Vaddr, pg:Arc<Page>)->Option<Arc<Page>>{
+        self.__account_pages(pg.get_block_page_cnt(),true);
+        let old = self.pages_tree.insert(vaddr,pg);
+        if let Some(o) = &old {
+            self.__account_pages(o.get_block_page_cnt(),false);
+        }
+        old
+    }
+    fn __remove_page(&mut self, vaddr:Vaddr)->Option<Arc<Page>>{
+        let ret = self.pages_tree.remove(&vaddr);
+        if let Some(pg) = &ret {
+            self.__account_pages(pg.get_block_page_cnt(),false);
+        }
+        ret
+    }
     pub fn _vaddr_have_map(&self, vaddr:Vaddr) ->bool{
-        self.pages_tree.contains_key(&vaddr)
+        self._find_page(vaddr).is_some()
     }
     // 为什么要返回错误值？ 可能出现映射区域超出vma范围情况
     // 输入参数要求，vaddr align && vaddr in vma
@@ -262,8 +366,7 @@ impl VMA {
                 return Err(());
             }
         }
-        self.phy_pgs_cnt += pgs_cnt;
-        if self.pages_tree.insert(vaddr, page).is_some(){
+        if self.__insert_page(vaddr, page).is_some(){
             return Err(());
         }
         Ok(())
@@ -274,13 +377,18 @@ impl VMA {
         debug_assert!(self.is_anon());
         debug_assert!(vaddr.is_align());
         debug_assert!(self.in_vma(vaddr));
-        match self.pages_tree.remove(&vaddr){
+        match self.__remove_page(vaddr){
             None => {
                 None
             }
             Some(pg) => {
                 let order = pg.get_order();
-                debug_assert!(self.pagetable.as_mut().unwrap().unmap_pages(vaddr,order).is_ok());
+                let ret = if order == HUGE_PAGE_ORDER {
+                    self.pagetable.as_mut().unwrap().unmap_huge_page(vaddr)
+                } else {
+                    self.pagetable.as_mut().unwrap().unmap_pages(vaddr,order)
+                };
+                debug_assert!(ret.is_ok());
                 Some(pg)
             }
         }
@@ -292,44 +400,208 @@ impl VMA {
             }
         )
     }
+    // 在vaddr处拆分，self保留[start,vaddr)，返回[vaddr,end)
     pub fn split(&mut self,vaddr:Vaddr)->Option<Self>{
-        if self.in_vma(vaddr) {
+        if !self.in_vma(vaddr) || vaddr==self.start_vaddr || !vaddr.is_align() {
             return None;
         }
+        self.__split_huge_cross(vaddr).ok()?;
+        let mut new = Self::empty(vaddr,self.end_vaddr);
         self.end_vaddr = vaddr;
-        if self.is_anon(){
-            let new_anon = self.pages_tree.split_off(&vaddr);
-            let mut new= Self::new_anon(
-                vaddr,
-                self.end_vaddr,
-                self.vm_flags,
-                self.get_pagetable(),
-            );
-            new.pages_tree = new_anon;
-            Some(new)
-        } else {
-            // let new = Self::new_file(
-            //     vaddr,
-            //     self.end_vaddr,
-            //     self.vm_flags,
-            //     self.get_pagetable(),
-            //     self.get_file_inode().unwrap(),
-            //     self.file_off+((vaddr-self.start_vaddr.0).0)
-            // );
-            // Some(new)
-            todo!()
+        new.vm_flags = self.vm_flags;
+        new.pagetable = self.pagetable.clone();
+        new.file = self.get_file_inode();
+        if self.is_file(){
+            // 文件在vma中的范围为[start+file_in_vma_off,start+file_in_vma_off+file_len)
+            let file_map_start_vaddr = self.start_vaddr+self.file_in_vma_off;
+            if vaddr<=file_map_start_vaddr {
+                new.file_off = self.file_off;
+                new.file_in_vma_off = (file_map_start_vaddr-vaddr.0).0;
+                new.file_len = self.file_len;
+                self.file_len = 0;
+            } else {
+                let consumed = (vaddr-file_map_start_vaddr.0).0;
+                new.file_off = self.file_off+consumed;
+                new.file_in_vma_off = 0;
+                new.file_len = self.file_len.saturating_sub(consumed);
+                self.file_len = min(self.file_len,consumed);
+            }
+        }
+        new.pages_tree = self.pages_tree.split_off(&vaddr);
+        new.cow_write_reserve_pgs = self.cow_write_reserve_pgs.as_mut().map(|x|{x.split_off(&vaddr)});
+        let moved:usize = new.pages_tree.values().map(|pg|{pg.get_block_page_cnt()}).sum();
+        new.phy_pgs_cnt = moved;
+        self.phy_pgs_cnt = self.phy_pgs_cnt.saturating_sub(moved);
+        // swap entry在pte中，按拆分后的范围统计
+        if self.swap_pgs_cnt>0 {
+            let pgt = self.get_pagetable();
+            let moved = vaddr.page_addr_iter((new.end_vaddr-vaddr.0).0).filter(|v|{pgt.get_swap_entry(*v).is_some()}).count();
+            new.swap_pgs_cnt = moved;
+            self.swap_pgs_cnt = self.swap_pgs_cnt.saturating_sub(moved);
         }
+        if self.swap_hand>=vaddr {
+            self.swap_hand = self.start_vaddr;
+        }
+        Some(new)
     }
+    // 大页只在起始地址记录，大页中的地址返回整个大页
     pub fn _find_page(&self, vaddr:Vaddr) ->Option<Arc<Page>> {
-        self.pages_tree.get(&vaddr).map(|x| {
-            x.clone()
+        self.pages_tree.range(..=vaddr).next_back().and_then(|(k,pg)| {
+            if *k==vaddr || (pg.get_order()!=0 && vaddr<*k+pg.get_block_page_cnt()*PAGE_SIZE) {
+                Some(pg.clone())
+            } else {
+                None
+            }
         })
     }
+    fn __huge_page_allowed(&self)->bool{
+        // cow的页表按4K页共享，不使用大页
+        if cfg!(feature = "copy_on_write") || !self.is_anon() || !self.vm_flags.contains(VmFlags::VM_USER)
+            || self.vm_flags.contains(VmFlags::VM_NOHUGEPAGE) {
+            return false;
+        }
+        self.vm_flags.contains(VmFlags::VM_HUGEPAGE) || THP_ENABLED.load(atomic::Ordering::Relaxed)
+    }
+    // 分配2MB页并映射到base，不检查vma是否允许大页
+    pub fn __alloc_huge_page(&mut self, base:Vaddr)->Option<Arc<Page>>{
+        debug_assert!(base.is_align_n(HUGE_PAGE_SIZE));
+        let pg = alloc_pages(HUGE_PAGE_ORDER)?;
+        // 物理地址没有2MB对齐时无法作为大页映射
+        if !pg.get_paddr().is_align_n(HUGE_PAGE_SIZE) {
+            return None;
+        }
+        let flags = _vma_flags_2_pte_flags(self.get_flags());
+        self.get_pagetable().map_huge_page(base, pg.get_paddr(), flags).ok()?;
+        self.__insert_page(base, pg.clone());
+        Some(pg)
+    }
+    // 缺页时尝试使用大页，vaddr所在的2MB区域需要完整位于vma中并且没有任何映射
+    fn _try_alloc_huge_page(&mut self, vaddr:Vaddr)->Option<Arc<Page>>{
+        if !self.__huge_page_allowed() {
+            return None;
+        }
+        let base = Vaddr(vaddr.get_inner()/HUGE_PAGE_SIZE*HUGE_PAGE_SIZE);
+        if base<self.start_vaddr || base+HUGE_PAGE_SIZE>self.end_vaddr {
+            return None;
+        }
+        if self.pages_tree.range(base..base+HUGE_PAGE_SIZE).next().is_some() {
+            return None;
+        }
+        if self.swap_pgs_cnt>0 {
+            let pgt = self.get_pagetable();
+            if base.page_addr_iter(HUGE_PAGE_SIZE).any(|v|{pgt.get_swap_entry(v).is_some()}) {
+                return None;
+            }
+        }
+        self.__alloc_huge_page(base)
+    }
+    // 把base处的大页拆分成4K页，用于mprotect或者munmap大页的一部分
+    pub fn _split_huge_page(&mut self, base:Vaddr)->Result<(),()>{
+        let pg = match self.pages_tree.get(&base) {
+            Some(pg) if pg.get_order()==HUGE_PAGE_ORDER => pg.clone(),
+            _ => {
+                return Ok(());
+            }
+        };
+        self.get_pagetable().split_huge_leaf(base)?;
+        self.__remove_page(base);
+        // 只有pages_tree引用大页时才能拆分
+        debug_assert_eq!(Arc::strong_count(&pg),1);
+        for (i,p) in split_pages(pg).into_iter().enumerate() {
+            self.__insert_page(base+i*PAGE_SIZE,p);
+        }
+        Ok(())
+    }
+    // 跨过vaddr的大页需要先拆成4K页
+    fn __split_huge_cross(&mut self, vaddr:Vaddr)->Result<(),()>{
+        let cross = self.pages_tree.range(..vaddr).next_back().map(|(k,pg)|{
+            (*k,pg.get_order()!=0 && (*k+pg.get_block_page_cnt()*PAGE_SIZE)>vaddr)
+        });
+        match cross {
+            Some((base,true)) => self._split_huge_page(base),
+            _ => Ok(())
+        }
+    }
+    pub fn is_locked(&self)->bool{
+        self.vm_flags.contains(VmFlags::VM_LOCKED)
+    }
+    pub fn _set_locked(&mut self, locked:bool){
+        self.vm_flags.set(VmFlags::VM_LOCKED,locked);
+    }
+    pub fn _set_hugepage_advice(&mut self, huge:bool){
+        self.vm_flags.set(VmFlags::VM_HUGEPAGE,huge);
+        self.vm_flags.set(VmFlags::VM_NOHUGEPAGE,!huge);
+    }
+    // 释放[start,end)中的物理页和swap slot，之后访问时重新缺页
+    // 匿名页重新缺页得到0页，文件页重新从文件读取
+    pub fn _zap_range(&mut self, start:Vaddr, end:Vaddr)->Result<(),()>{
+        debug_assert!(start>=self.start_vaddr && end<=self.end_vaddr);
+        self.__split_huge_cross(start)?;
+        self.__split_huge_cross(end)?;
+        let keys:Vec<Vaddr> = self.pages_tree.range(start..end).map(|(k,_)|{*k}).collect();
+        let pgt = self.get_pagetable();
+        for k in keys {
+            if self.is_anon() {
+                self._anon_unmap_pages(k);
+            } else {
+                self.__remove_page(k);
+                pgt._unmap_one_page(k).ok();
+            }
+        }
+        if self.swap_pgs_cnt>0 {
+            for v in start.page_addr_iter((end-start.0).0) {
+                if let Some(entry) = pgt.clear_swap_entry(v) {
+                    swap_free_slot(entry);
+                    self.swap_pgs_cnt-=1;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn __no_access(&self)->bool{
+        !self.vm_flags.intersects(VmFlags::VM_READ|VmFlags::VM_WRITE|VmFlags::VM_EXEC)
+    }
+    // 修改vma权限并更新已经映射的页
+    pub fn _set_prot(&mut self, prot:MmapProt)->Result<(),()>{
+        let mut flags = self.vm_flags&!(VmFlags::VM_READ|VmFlags::VM_WRITE|VmFlags::VM_EXEC);
+        if prot.contains(MmapProt::PROT_READ){
+            flags|=VmFlags::VM_READ;
+        }
+        if prot.contains(MmapProt::PROT_WRITE){
+            flags|=VmFlags::VM_WRITE;
+        }
+        if prot.contains(MmapProt::PROT_EXEC){
+            flags|=VmFlags::VM_EXEC;
+        }
+        self.vm_flags = flags;
+        let no_access = self.__no_access();
+        let pte_flags = _vma_flags_2_pte_flags(flags);
+        let pgt = self.get_pagetable();
+        for (va,pg) in self.pages_tree.iter() {
+            let huge = pg.get_order()==HUGE_PAGE_ORDER;
+            if no_access {
+                // 没有RWX的pte会被当作非叶子页表，只能unmap，物理页仍然保留在pages_tree中
+                if huge {
+                    pgt.unmap_huge_page(*va).ok();
+                } else {
+                    pgt._unmap_one_page(*va).ok();
+                }
+            } else if pgt.change_map_flags(*va,pte_flags).is_err() {
+                // 之前是PROT_NONE，需要重新映射
+                if huge {
+                    pgt.map_huge_page(*va,pg.get_paddr(),pte_flags).map_err(|_|{()})?;
+                } else {
+                    pgt.map_one_page(*va,pg.get_paddr(),pte_flags).map_err(|_|{()})?;
+                }
+            }
+        }
+        Ok(())
+    }
     // 相对map pages来说在存在映射时可以不分配物理页并且跳过，这样速度更快
     fn __fast_alloc_one_page(&mut self, vaddr:Vaddr){
         debug_assert!(self.in_vma(vaddr));
         debug_assert!(vaddr.is_align());
-        if !self.pages_tree.contains_key(&vaddr) {
+        if !self._vaddr_have_map(vaddr) {
             let pages = alloc_one_page().unwrap();
             let flags = self.get_flags();
             if pages.get_paddr().get_inner()==0x87ff9000{
@@ -348,7 +620,7 @@ impl VMA {
                 error_sync!("fast alloc error pgt:{:#X},vaddr:{:#X}",paddr,vaddr);
                 panic!("err");
             }
-            self.pages_tree.insert(vaddr, pages);
+            self.__insert_page(vaddr, pages);
         }
     }
     // 注意这个分配物理页不一定是连续的
@@ -367,7 +639,7 @@ impl VMA {
             if self.pagetable.as_mut().unwrap().map_one_page(vaddr, pages.get_paddr(), _vma_flags_2_pte_flags(flags)).is_err(){
                 todo!()
             }
-            self.pages_tree.insert(vaddr, pages.clone());
+            self.__insert_page(vaddr, pages.clone());
             return pages;
         } else {
             self._find_page(vaddr).unwrap()
@@ -381,65 +653,81 @@ impl VMA {
         unsafe { new_pg.copy_one_page_data_from(data_pg); }
         let flags = _vma_flags_2_pte_flags(self.vm_flags);
         self.pagetable.as_ref().unwrap().map_one_page(vaddr, new_pg.get_paddr(), flags).unwrap();
-        self.pages_tree.insert(vaddr, new_pg);
+        self.__insert_page(vaddr, new_pg);
+        Ok(())
+    }
+    // 文件在vma中的范围为[start+file_in_vma_off,start+file_in_vma_off+file_len)，范围外的部分不读取
+    fn __file_source(&self,vaddr:Vaddr)->PageSource{
+        let file_map_start_vaddr = self.start_vaddr+self.file_in_vma_off;
+        let file_map_end_vaddr = file_map_start_vaddr+self.file_len;
+        let left = if vaddr<file_map_start_vaddr { (file_map_start_vaddr-vaddr.0).0 } else { 0 };
+        let right = if vaddr<file_map_end_vaddr { min(PAGE_SIZE,(file_map_end_vaddr-vaddr.0).0) } else { 0 };
+        if left>=right {
+            return PageSource::Zero;
+        }
+        let off = self.file_off+((vaddr+left)-file_map_start_vaddr.0).0;
+        PageSource::File(self.file.as_ref().unwrap().clone(),left,right,off)
+    }
+    // vaddr缺页时数据的来源，已经映射时返回Err
+    pub fn _page_source(&self,vaddr:Vaddr)->Result<PageSource,()>{
+        if !vaddr.is_align() || !self.in_vma(vaddr) || self.__no_access() || self._vaddr_have_map(vaddr) {
+            return Err(());
+        }
+        if self.is_anon() {
+            if self.swap_pgs_cnt>0 {
+                if let Some(entry) = self.get_pagetable().get_swap_entry(vaddr) {
+                    return Ok(PageSource::Swap(entry));
+                }
+            }
+            return Ok(PageSource::Zero);
+        }
+        Ok(self.__file_source(vaddr))
+    }
+    // 映射锁外读入的页，期间vaddr已经被映射或者来源发生变化时丢弃pg
+    pub fn _install_page(&mut self,vaddr:Vaddr,pg:Arc<Page>,src:&PageSource)->Result<(),()>{
+        match self._page_source(vaddr) {
+            Ok(now) if now.same(src) => {}
+            _ => {
+                return Ok(());
+            }
+        }
+        // map直接覆盖pte中的swap entry
+        self.get_pagetable().map_one_page(vaddr,pg.get_paddr(),_vma_flags_2_pte_flags(self.get_flags())).map_err(|_|{()})?;
+        if let PageSource::Swap(entry) = src {
+            swap_free_slot(*entry);
+            self.swap_pgs_cnt-=1;
+        }
+        self.__insert_page(vaddr,pg);
+        if self.is_file() && self.execable(){
+            unsafe { fence_i(); }
+        }
+        if self.writeable(){
+            self.__set_dirty();
+        }
         Ok(())
     }
     // for lazy map
     pub fn _do_alloc_one_page(&mut self,vaddr:Vaddr)->Result<Arc<Page>,()>{
-        if !vaddr.is_align() || !self.in_vma(vaddr) {
+        if !vaddr.is_align() || !self.in_vma(vaddr) || self.__no_access() {
             return Err(());
         }
         let mut ret_pg:Option<Arc<Page>> = None;
         if self.is_anon(){
-            // alloc and map but not fill with data
-            ret_pg = Some(self.__fast_alloc_one_page_and_get(vaddr));
-        } else {
-            let file_map_start_vaddr = self.start_vaddr+self.file_in_vma_off;
-            let file_map_end_vaddr = self.start_vaddr+self.file_in_vma_off+self.file_len;
-            if vaddr<file_map_start_vaddr{
-                if (vaddr+PAGE_SIZE)>file_map_start_vaddr{
-                    let pg = self.__fast_alloc_one_page_and_get(vaddr);
-                    ret_pg = Some(pg.clone());
-                    let ptr = pg.get_vaddr().get_inner() as *mut u8;
-                    let buf = slice_from_raw_parts_mut(ptr,PAGE_SIZE);
-                    let mut left_off:usize = 0;
-                    let mut right_off:usize = 0;
-                    if (vaddr+PAGE_SIZE)<=file_map_end_vaddr{
-                        left_off = (file_map_start_vaddr-vaddr.0).0;
-                        right_off = PAGE_SIZE;
-                    } else {
-                        left_off = (file_map_start_vaddr-vaddr.0).0;
-                        right_off = (file_map_end_vaddr-vaddr.0).0;
-                    }
-                    // read from file
-                    let need_read = right_off-left_off;
-                    let real_in_file_off = self.file_off;
-                    let real_read = unsafe { self.file.as_ref().unwrap().read_off_exact(&mut (*buf)[left_off..right_off], real_in_file_off).unwrap() };
-                    assert_eq!(real_read,need_read);
-                }  else {
-                    // map with no data
-                    ret_pg = Some(self.__fast_alloc_one_page_and_get(vaddr));
-                }
-            } else {
-                if vaddr>=file_map_end_vaddr{
-                    ret_pg = Some(self.__fast_alloc_one_page_and_get(vaddr));
-                } else {
-                    let mut right_off:usize = 0;
-                    if (vaddr+PAGE_SIZE)<=file_map_end_vaddr{
-                        right_off = PAGE_SIZE;
-                    } else {
-                        right_off = (file_map_end_vaddr - vaddr.0).0;
-                    }
-                    let real_in_file_off = (vaddr-file_map_start_vaddr.0).0+self.file_off;
-                    let pg = self.__fast_alloc_one_page_and_get(vaddr);
-                    ret_pg = Some(pg.clone());
-                    let ptr = pg.get_vaddr().get_inner() as *mut u8;
-                    let buf = slice_from_raw_parts_mut(ptr,PAGE_SIZE);
-                    let need_read = right_off;
-                    let real_read = unsafe { self.file.as_ref().unwrap().read_off_exact(&mut (*buf)[0..right_off], real_in_file_off).unwrap() };
-                    assert_eq!(real_read,need_read);
+            if self.swap_pgs_cnt>0 {
+                if let Some(entry) = self.get_pagetable().get_swap_entry(vaddr) {
+                    return self._swap_in_one_page(vaddr,entry);
                 }
             }
+            // alloc and map but not fill with data
+            ret_pg = match self._try_alloc_huge_page(vaddr) {
+                Some(pg) => Some(pg),
+                None => Some(self.__fast_alloc_one_page_and_get(vaddr))
+            };
+        } else {
+            let pg = self.__fast_alloc_one_page_and_get(vaddr);
+            ret_pg = Some(pg.clone());
+            let src = self.__file_source(vaddr);
+            assert!(src.fill(&pg).is_ok());
             // 由于修改了page 所以fence.i需要用于清空icache
             // todo check
             if self.execable(){
@@ -453,7 +741,7 @@ impl VMA {
         Ok(ret_pg.unwrap())
     }
     fn __release_one_page(&mut self,vaddr:Vaddr){
-        match self.pages_tree.remove(&vaddr) {
+        match self.__remove_page(vaddr) {
             None => {}
             Some(pg) => {
                 // do unmap pagetable
@@ -475,15 +763,111 @@ impl VMA {
     pub fn _release_all_page(&mut self){
         todo!()
     }
+    // 从swap读回一页并重新映射，释放swap slot
+    pub fn _swap_in_one_page(&mut self,vaddr:Vaddr,entry:SwapEntry)->Result<Arc<Page>,()>{
+        let pg = alloc_one_page().ok_or(())?;
+        swap_in_page(entry,&pg)?;
+        // map直接覆盖pte中的swap entry，失败时entry保留在pte中，slot仍然有效
+        let pgt = self.get_pagetable();
+        if pgt.map_one_page(vaddr,pg.get_paddr(),_vma_flags_2_pte_flags(self.get_flags())).is_err(){
+            return Err(());
+        }
+        swap_free_slot(entry);
+        self.swap_pgs_cnt-=1;
+        self.__insert_page(vaddr,pg.clone());
+        Ok(pg)
+    }
+    // 换入所有位于swap_type上的页，用于swapoff
+    pub fn _swap_in_area(&mut self,swap_type:usize)->Result<(),()>{
+        if self.swap_pgs_cnt == 0 {
+            return Ok(());
+        }
+        let pgt = self.get_pagetable();
+        for vaddr in self.start_vaddr.page_addr_iter((self.end_vaddr-self.start_vaddr.0).0){
+            match pgt.get_swap_entry(vaddr) {
+                Some(entry) if entry.get_type()==swap_type => {
+                    self._swap_in_one_page(vaddr,entry)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    // clock算法换出匿名页：accessed位被置位的页清除后跳过，给第二次机会
+    // 返回换出的页数
+    pub fn _swap_out_pages(&mut self,nr:usize)->usize{
+        debug_assert!(self.is_anon());
+        // PROT_NONE的页没有映射，换入时也无法按vma权限映射
+        if self.__no_access() || self.is_locked() {
+            return 0;
+        }
+        let pgt = self.get_pagetable();
+        let mut candidates = Vec::new();
+        let hand = self.swap_hand;
+        for (va,pg) in self.pages_tree.range(hand..).chain(self.pages_tree.range(..hand)){
+            if candidates.len()>=nr {
+                break;
+            }
+            // 被其他地方引用的页(kmap、cow等)不能换出
+            if Arc::strong_count(pg)>1 || pg.get_order()!=0 {
+                continue;
+            }
+            if pgt.test_and_clear_accessed(*va){
+                continue;
+            }
+            candidates.push(*va);
+        }
+        let flags = _vma_flags_2_pte_flags(self.get_flags());
+        let mut done = 0;
+        for va in candidates {
+            let pg = self.__remove_page(va).unwrap();
+            // 先unmap再写出，避免写出期间页被修改
+            pgt._unmap_one_page(va).unwrap();
+            let entry = swap_out_page(&pg);
+            match entry.map(|e|{ pgt.set_swap_entry(va,e).map_err(|_| e) }) {
+                Some(Ok(())) => {
+                    self.swap_pgs_cnt+=1;
+                    self.swap_hand = va+PAGE_SIZE;
+                    done+=1;
+                }
+                // 写出失败或者pte设置失败，页放回原处，叶子页表刚unmap过，重新映射不会再分配
+                failed => {
+                    if let Some(Err(e)) = failed {
+                        swap_free_slot(e);
+                    }
+                    if pgt.map_one_page(va,pg.get_paddr(),flags).is_err() {
+                        error_sync!("swap out: remap {:#X} fail",va.0);
+                    }
+                    self.__insert_page(va,pg);
+                    break;
+                }
+            }
+        }
+        done
+    }
 }
 
 impl Drop for VMA {
     fn drop(&mut self) {
+        let nr = self.phy_pgs_cnt;
+        self.__account_pages(nr,false);
         for (vaddr,v) in &self.pages_tree{
-            assert_eq!(v.get_order(), 0);
             let p:Paddr = self.pagetable.as_mut().unwrap()._get_root_page_vaddr().into();
             info_sync!("VMA unmap pgt:{:#X},vaddr:{:#X}",p,vaddr);
-            self.pagetable.as_mut().unwrap()._unmap_one_page(*vaddr);
+            if v.get_order() == HUGE_PAGE_ORDER {
+                self.pagetable.as_mut().unwrap().unmap_huge_page(*vaddr);
+            } else {
+                assert_eq!(v.get_order(), 0);
+                self.pagetable.as_mut().unwrap()._unmap_one_page(*vaddr);
+            }
+        }
+        if self.swap_pgs_cnt>0 {
+            let pgt = self.get_pagetable();
+            for vaddr in self.start_vaddr.page_addr_iter((self.end_vaddr-self.start_vaddr.0).0){
+                if let Some(entry) = pgt.clear_swap_entry(vaddr){
+                    swap_free_slot(entry);
+                }
+            }
         }
     }
 }
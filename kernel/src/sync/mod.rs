@@ -61,6 +61,17 @@ impl<T:?Sized> SpinLock<T> {
         Ok(SpinLockGuard::new(self,true,irq_state))
     }
 
+    // 不自旋等待，锁已被持有时直接返回None
+    pub fn lock_irq_nowait(&self)->Option<SpinLockGuard<T>>{
+        let irq_state = disable_irq();
+        if self.inner.compare_exchange(false,true,Ordering::Acquire,Ordering::Relaxed).is_ok(){
+            Some(SpinLockGuard::new(self,true,irq_state))
+        } else {
+            enable_irq(irq_state);
+            None
+        }
+    }
+
     fn _unlock(&self){
         self.inner.store(false,Ordering::Release);
     }
@@ -1,6 +1,12 @@
 mod sys_fs;
 mod sys_proc;
 mod sys_dev;
+mod sys_mm;
+mod sys_time;
+mod sys_cred;
+mod sys_poll;
+mod sys_signal;
+pub mod errno;
 
 use alloc::sync::Arc;
 use core::cmp::min;
@@ -8,28 +14,47 @@ use core::mem::size_of;
 use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
 use fatfs::error;
 use crate::{error_sync, info_sync, println, trace_sync, warn_sync};
-use crate::fs::poll::PollFd;
 use crate::mm::addr::Vaddr;
 use crate::sbi::shutdown;
 use crate::syscall::sys_fs::syscall_fs_entry;
 use crate::syscall::sys_proc::syscall_proc_entry;
-use crate::task::{exit_self, sleep_self_in_sleeping_list};
+use crate::syscall::sys_mm::syscall_mm_entry;
+use crate::syscall::sys_time::syscall_time_entry;
+use crate::syscall::sys_cred::syscall_cred_entry;
+use crate::syscall::sys_poll::syscall_poll_entry;
+use crate::syscall::sys_signal::syscall_signal_entry;
+use crate::task::exit_self;
 use crate::task::task::get_running;
+use crate::task::signal::do_signal;
 use crate::trap::TrapFrame;
 use crate::utils::convert_cstr_from_vaddr;
 
 pub const SYSCALL_GETCWD: usize = 17;
+pub const SYSCALL_EVENTFD2: usize = 19;
+pub const SYSCALL_EPOLL_CREATE1: usize = 20;
+pub const SYSCALL_EPOLL_CTL: usize = 21;
+pub const SYSCALL_EPOLL_PWAIT: usize = 22;
 pub const SYSCALL_DUP: usize = 23;
 pub const SYSCALL_DUP3:usize = 24;
 pub const SYSCALL_FCNTL:usize = 25;
 pub const SYSCALL_IOCTL:usize = 29;
+pub const SYSCALL_MKNODAT: usize = 33;
 pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
+pub const SYSCALL_SYMLINKAT: usize = 36;
 pub const SYSCALL_LINKAT: usize = 37;
 pub const SYSCALL_UMOUNT2: usize = 39;
 pub const SYSCALL_MOUNT: usize = 40;
+pub const SYSCALL_TRUNCATE: usize = 45;
+pub const SYSCALL_FTRUNCATE: usize = 46;
+pub const SYSCALL_FALLOCATE: usize = 47;
 pub const SYSCALL_FACCESSAT: usize = 48;
 pub const SYSCALL_CHDIR: usize = 49;
+pub const SYSCALL_FCHDIR: usize = 50;
+pub const SYSCALL_FCHMOD: usize = 52;
+pub const SYSCALL_FCHMODAT: usize = 53;
+pub const SYSCALL_FCHOWNAT: usize = 54;
+pub const SYSCALL_FCHOWN: usize = 55;
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_PIPE: usize = 59;
@@ -37,14 +62,25 @@ pub const SYSCALL_GETDENTS64: usize = 61;
 pub const SYSCALL_LSEEK: usize = 62;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_READV: usize = 65;
 pub const SYSCALL_WRITEV: usize = 66;
+pub const SYSCALL_PREAD64: usize = 67;
+pub const SYSCALL_PWRITE64: usize = 68;
+pub const SYSCALL_PREADV: usize = 69;
+pub const SYSCALL_PWRITEV: usize = 70;
 pub const SYSCALL_SENDFILE: usize = 71;
 pub const SYSCALL_PSELECT6: usize = 72;
 pub const SYSCALL_PPOLL: usize = 73;
+pub const SYSCALL_SIGNALFD4: usize = 74;
 pub const SYSCALL_READLINKAT: usize = 78;
 pub const SYSCALL_NEW_FSTATAT: usize = 79;
 pub const SYSCALL_FSTAT:usize = 80;
+pub const SYSCALL_SYNC:usize = 81;
 pub const SYSCALL_FSYNC:usize = 82;
+pub const SYSCALL_FDATASYNC:usize = 83;
+pub const SYSCALL_TIMERFD_CREATE:usize = 85;
+pub const SYSCALL_TIMERFD_SETTIME:usize = 86;
+pub const SYSCALL_TIMERFD_GETTIME:usize = 87;
 pub const SYSCALL_UTIMENSAT:usize = 88;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_EXIT_GRUOP: usize = 94;
@@ -52,16 +88,36 @@ pub const SYSCALL_SET_TID_ADDRESS: usize = 96;
 pub const SYSCALL_NANOSLEEP: usize = 101;
 pub const SYSCALL_GETITIMER: usize = 102;
 pub const SYSCALL_SETITIMER: usize = 103;
+pub const SYSCALL_CLOCK_SETTIME: usize = 112;
 pub const SYSCALL_CLOCK_GETTIME: usize = 113;
+pub const SYSCALL_CLOCK_GETRES: usize = 114;
 pub const SYSCALL_YIELD: usize = 124;
 pub const SYSCALL_KILL: usize = 129;
 pub const SYSCALL_SIGACTION: usize = 134;
 pub const SYSCALL_SIGPROCMASK: usize = 135;
 pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETREGID: usize = 143;
+pub const SYSCALL_SETGID: usize = 144;
+pub const SYSCALL_SETREUID: usize = 145;
+pub const SYSCALL_SETUID: usize = 146;
+pub const SYSCALL_SETRESUID: usize = 147;
+pub const SYSCALL_GETRESUID: usize = 148;
+pub const SYSCALL_SETRESGID: usize = 149;
+pub const SYSCALL_GETRESGID: usize = 150;
+pub const SYSCALL_SETFSUID: usize = 151;
+pub const SYSCALL_SETFSGID: usize = 152;
 pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_SETPGID: usize = 154;
+pub const SYSCALL_GETPGID: usize = 155;
+pub const SYSCALL_GETSID: usize = 156;
+pub const SYSCALL_SETSID: usize = 157;
+pub const SYSCALL_GETGROUPS: usize = 158;
+pub const SYSCALL_SETGROUPS: usize = 159;
 pub const SYSCALL_UNAME: usize = 160;
 pub const SYSCALL_GETRUSAGE: usize = 165;
+pub const SYSCALL_UMASK: usize = 166;
 pub const SYSCALL_GET_TIME_OF_DAY: usize = 169;
+pub const SYSCALL_SET_TIME_OF_DAY: usize = 170;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETPPID: usize = 173;
 pub const SYSCALL_GETUID: usize = 174;
@@ -69,16 +125,25 @@ pub const SYSCALL_GETEUID: usize = 175;
 pub const SYSCALL_GETGID: usize = 176;
 pub const SYSCALL_GETEGID: usize = 177;
 pub const SYSCALL_GETTID: usize = 178;
+pub const SYSCALL_SYSINFO: usize = 179;
 pub const SYSCALL_SBRK: usize = 213;
 pub const SYSCALL_BRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_CLONE: usize = 220;
 pub const SYSCALL_EXECVE: usize = 221;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_SWAPON: usize = 224;
+pub const SYSCALL_SWAPOFF: usize = 225;
 pub const SYSCALL_MPROTECT: usize = 226;
+pub const SYSCALL_MLOCK: usize = 228;
+pub const SYSCALL_MUNLOCK: usize = 229;
+pub const SYSCALL_MINCORE: usize = 232;
+pub const SYSCALL_MADVISE: usize = 233;
 pub const SYSCALL_WAIT4: usize = 260;
 pub const SYSCALL_PRLIMIT: usize = 261;
+pub const SYSCALL_SYNCFS: usize = 267;
 pub const SYSCALL_RENAMEAT2: usize = 276;
+pub const SYSCALL_FACCESSAT2: usize = 439;
 
 // Not standard POSIX sys_call
 const SYSCALL_LS: usize = 500;
@@ -89,43 +154,50 @@ pub unsafe fn syscall_entry(trap_frame:&mut TrapFrame){
     let syscall_id = trap_frame.x17;
     warn_sync!("[syscall:{}]",syscall_id);
     match syscall_id {
-        // todo signal
-        SYSCALL_PPOLL =>{
-            let ptr = trap_frame.arg0();
-            let e = (*(ptr as *const PollFd)).clone();
-            println!("{:?},cnt:{}",e,trap_frame.arg1());
-            loop{sleep_self_in_sleeping_list();}
-            // error_sync!("ppoll");
-            // trap_frame.ok()
-        }
-        SYSCALL_SIGACTION => {
-            trap_frame.ok()
-        }
-        SYSCALL_SIGPROCMASK =>{
-            trap_frame.ok()
+        SYSCALL_SIGACTION|SYSCALL_SIGPROCMASK|SYSCALL_SIGNALFD4|SYSCALL_KILL=> {
+            syscall_signal_entry(trap_frame,syscall_id);
         }
         SYSCALL_EXIT_GRUOP =>{
             trap_frame.ok();
         }
-        // todo ioctl
-        SYSCALL_IOCTL=>{
-            info_sync!("syscall ioctl,trap frame:\n{:?}",trap_frame);
-            trap_frame.ok();
-        }
-        SYSCALL_GETUID=>{
-            trap_frame.ret(0);
-        }
         SYSCALL_OPENAT|SYSCALL_SENDFILE|SYSCALL_WRITEV|SYSCALL_WRITE|SYSCALL_READ|
         SYSCALL_DUP|SYSCALL_DUP3|SYSCALL_CLOSE|SYSCALL_NEW_FSTATAT|SYSCALL_FCNTL|
-        SYSCALL_PIPE=> {
+        SYSCALL_PIPE|SYSCALL_MOUNT|SYSCALL_UMOUNT2|SYSCALL_IOCTL|SYSCALL_MKDIRAT|SYSCALL_UNLINKAT|
+        SYSCALL_RENAMEAT2|SYSCALL_LINKAT|SYSCALL_GETDENTS64|SYSCALL_CHDIR|SYSCALL_FCHDIR|
+        SYSCALL_LSEEK|SYSCALL_READV|SYSCALL_PREAD64|SYSCALL_PWRITE64|SYSCALL_PREADV|SYSCALL_PWRITEV|
+        SYSCALL_TRUNCATE|SYSCALL_FTRUNCATE|SYSCALL_FALLOCATE|SYSCALL_SYNC|SYSCALL_FSYNC|SYSCALL_FDATASYNC|
+        SYSCALL_SYNCFS|SYSCALL_UTIMENSAT|SYSCALL_FACCESSAT|SYSCALL_FACCESSAT2|SYSCALL_FCHMOD|SYSCALL_FCHMODAT|
+        SYSCALL_FCHOWN|SYSCALL_FCHOWNAT|SYSCALL_SYMLINKAT|SYSCALL_READLINKAT|
+        SYSCALL_MKNODAT|SYSCALL_EVENTFD2=> {
             syscall_fs_entry(trap_frame,syscall_id);
         }
+        SYSCALL_PPOLL|SYSCALL_PSELECT6|SYSCALL_EPOLL_CREATE1|SYSCALL_EPOLL_CTL|SYSCALL_EPOLL_PWAIT=> {
+            syscall_poll_entry(trap_frame,syscall_id);
+        }
+        SYSCALL_GETUID|SYSCALL_GETEUID|SYSCALL_GETGID|SYSCALL_GETEGID|SYSCALL_SETUID|SYSCALL_SETGID|
+        SYSCALL_SETREUID|SYSCALL_SETREGID|SYSCALL_SETRESUID|SYSCALL_SETRESGID|SYSCALL_GETRESUID|
+        SYSCALL_GETRESGID|SYSCALL_SETFSUID|SYSCALL_SETFSGID|SYSCALL_GETGROUPS|SYSCALL_SETGROUPS|
+        SYSCALL_UMASK=> {
+            syscall_cred_entry(trap_frame,syscall_id);
+        }
+        SYSCALL_CLOCK_GETTIME|SYSCALL_CLOCK_SETTIME|SYSCALL_CLOCK_GETRES|
+        SYSCALL_GET_TIME_OF_DAY|SYSCALL_SET_TIME_OF_DAY|SYSCALL_TIMERFD_CREATE|SYSCALL_TIMERFD_SETTIME|
+        SYSCALL_TIMERFD_GETTIME=> {
+            syscall_time_entry(trap_frame,syscall_id);
+        }
         SYSCALL_BRK|SYSCALL_MMAP|SYSCALL_GETPID|SYSCALL_GETPPID|SYSCALL_UNAME|SYSCALL_GETCWD|
-        SYSCALL_CLONE|SYSCALL_SET_TID_ADDRESS|SYSCALL_WAIT4|SYSCALL_GETTID|SYSCALL_EXIT|SYSCALL_EXECVE=> {
+        SYSCALL_CLONE|SYSCALL_SET_TID_ADDRESS|SYSCALL_WAIT4|SYSCALL_GETTID|SYSCALL_EXIT|SYSCALL_EXECVE|
+        SYSCALL_GETRUSAGE|SYSCALL_SETPGID|SYSCALL_GETPGID|SYSCALL_GETSID|SYSCALL_SETSID=> {
             syscall_proc_entry(trap_frame,syscall_id);
         }
+        SYSCALL_SWAPON|SYSCALL_SWAPOFF|SYSCALL_MUNMAP|SYSCALL_MPROTECT|
+        SYSCALL_MLOCK|SYSCALL_MUNLOCK|SYSCALL_MINCORE|SYSCALL_MADVISE|SYSCALL_SYSINFO=> {
+            syscall_mm_entry(trap_frame,syscall_id);
+        }
         _ => {
             error_sync!("syscall[{}] not register",syscall_id);
         }
     }
-}
\ No newline at end of file
+    // 返回用户态之前处理系统调用中产生的信号
+    do_signal();
+}
@@ -0,0 +1,246 @@
+use alloc::vec::Vec;
+use crate::mm::addr::Vaddr;
+use crate::pre::ReadWriteSingleNoOff;
+use crate::syscall::errno::{EINVAL, EPERM};
+use crate::task::cred::{current_cred, Cred, NGROUPS_MAX};
+use crate::task::task::get_running;
+use crate::trap::TrapFrame;
+use super::*;
+
+// set*id中-1表示不修改
+const ID_UNCHANGED:u32 = u32::MAX;
+
+pub fn syscall_cred_entry(tf:&mut TrapFrame, syscall_id:usize){
+    let ret = match syscall_id {
+        SYSCALL_GETUID => current_cred().uid as isize,
+        SYSCALL_GETEUID => current_cred().euid as isize,
+        SYSCALL_GETGID => current_cred().gid as isize,
+        SYSCALL_GETEGID => current_cred().egid as isize,
+        SYSCALL_SETUID => {
+            let ret = sys_setid(tf.arg0() as u32,uids);
+            info_sync!("setuid:uid:{},ret:{}",tf.arg0() as i32,ret);
+            ret
+        }
+        SYSCALL_SETGID => {
+            let ret = sys_setid(tf.arg0() as u32,gids);
+            info_sync!("setgid:gid:{},ret:{}",tf.arg0() as i32,ret);
+            ret
+        }
+        SYSCALL_SETREUID => {
+            sys_setreid(tf.arg0() as u32,tf.arg1() as u32,uids)
+        }
+        SYSCALL_SETREGID => {
+            sys_setreid(tf.arg0() as u32,tf.arg1() as u32,gids)
+        }
+        SYSCALL_SETRESUID => {
+            sys_setresid(tf.arg0() as u32,tf.arg1() as u32,tf.arg2() as u32,uids)
+        }
+        SYSCALL_SETRESGID => {
+            sys_setresid(tf.arg0() as u32,tf.arg1() as u32,tf.arg2() as u32,gids)
+        }
+        SYSCALL_GETRESUID => {
+            sys_getresid(tf.arg0(),tf.arg1(),tf.arg2(),uids)
+        }
+        SYSCALL_GETRESGID => {
+            sys_getresid(tf.arg0(),tf.arg1(),tf.arg2(),gids)
+        }
+        SYSCALL_SETFSUID => {
+            sys_setfsid(tf.arg0() as u32,uids)
+        }
+        SYSCALL_SETFSGID => {
+            sys_setfsid(tf.arg0() as u32,gids)
+        }
+        SYSCALL_GETGROUPS => {
+            sys_getgroups(tf.arg0() as i32,tf.arg1())
+        }
+        SYSCALL_SETGROUPS => {
+            let ret = sys_setgroups(tf.arg0() as i32,tf.arg1());
+            info_sync!("setgroups:size:{},ret:{}",tf.arg0() as i32,ret);
+            ret
+        }
+        SYSCALL_UMASK => {
+            get_running().lock_irq().unwrap().set_umask(tf.arg0() as u32) as isize
+        }
+        _ => {
+            panic!("cred syscall {} not impl",syscall_id);
+        }
+    };
+    tf.ret(ret as usize);
+}
+
+// 实际、有效、保存和文件系统id，uid和gid的修改规则相同
+struct Ids<'a>{
+    real:&'a mut u32,
+    effective:&'a mut u32,
+    saved:&'a mut u32,
+    fs:&'a mut u32,
+}
+
+impl Ids<'_> {
+    fn is_one_of(&self,id:u32)->bool{
+        id == *self.real || id == *self.effective || id == *self.saved
+    }
+}
+
+fn uids(c:&mut Cred)->Ids{
+    Ids{
+        real: &mut c.uid,
+        effective: &mut c.euid,
+        saved: &mut c.suid,
+        fs: &mut c.fsuid
+    }
+}
+
+fn gids(c:&mut Cred)->Ids{
+    Ids{
+        real: &mut c.gid,
+        effective: &mut c.egid,
+        saved: &mut c.sgid,
+        fs: &mut c.fsgid
+    }
+}
+
+// 在task的锁内修改身份，失败时不修改
+fn update_cred<F:FnOnce(&mut Cred)->Result<(),isize>>(f:F)->isize{
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    let mut cred = tsk.get_cred().clone();
+    match f(&mut cred) {
+        Ok(_) => {
+            tsk.set_cred(cred);
+            0
+        }
+        Err(e) => e
+    }
+}
+
+// 特权进程修改所有id，否则只能把有效id改为实际id或保存的id
+fn sys_setid(id:u32,select:fn(&mut Cred)->Ids)->isize{
+    if id == ID_UNCHANGED {
+        return -EINVAL;
+    }
+    update_cred(|c|{
+        let capable = c.capable();
+        let ids = select(c);
+        if capable {
+            *ids.real = id;
+            *ids.saved = id;
+        } else if id != *ids.real && id != *ids.saved {
+            return Err(-EPERM);
+        }
+        *ids.effective = id;
+        *ids.fs = id;
+        Ok(())
+    })
+}
+
+// 修改了实际id，或有效id被改为不同于原实际id的值时，保存的id等于新的有效id
+fn sys_setreid(real:u32,effective:u32,select:fn(&mut Cred)->Ids)->isize{
+    update_cred(|c|{
+        let capable = c.capable();
+        let ids = select(c);
+        let old_real = *ids.real;
+        if !capable {
+            if real != ID_UNCHANGED && real != *ids.real && real != *ids.effective {
+                return Err(-EPERM);
+            }
+            if effective != ID_UNCHANGED && !ids.is_one_of(effective) {
+                return Err(-EPERM);
+            }
+        }
+        if real != ID_UNCHANGED {
+            *ids.real = real;
+        }
+        if effective != ID_UNCHANGED {
+            *ids.effective = effective;
+        }
+        if real != ID_UNCHANGED || (effective != ID_UNCHANGED && effective != old_real) {
+            *ids.saved = *ids.effective;
+        }
+        *ids.fs = *ids.effective;
+        Ok(())
+    })
+}
+
+// 非特权进程的每个新id必须是当前的实际、有效或保存的id之一
+fn sys_setresid(real:u32,effective:u32,saved:u32,select:fn(&mut Cred)->Ids)->isize{
+    update_cred(|c|{
+        let capable = c.capable();
+        let ids = select(c);
+        let new = [real,effective,saved];
+        if !capable && new.iter().any(|id| *id != ID_UNCHANGED && !ids.is_one_of(*id)) {
+            return Err(-EPERM);
+        }
+        if real != ID_UNCHANGED {
+            *ids.real = real;
+        }
+        if effective != ID_UNCHANGED {
+            *ids.effective = effective;
+        }
+        if saved != ID_UNCHANGED {
+            *ids.saved = saved;
+        }
+        *ids.fs = *ids.effective;
+        Ok(())
+    })
+}
+
+fn sys_getresid(real:usize,effective:usize,saved:usize,select:fn(&mut Cred)->Ids)->isize{
+    let mut cred = current_cred();
+    let ids = select(&mut cred);
+    unsafe {
+        Vaddr(real).write_single(*ids.real).unwrap();
+        Vaddr(effective).write_single(*ids.effective).unwrap();
+        Vaddr(saved).write_single(*ids.saved).unwrap();
+    }
+    0
+}
+
+// 总是返回原来的文件系统id，不满足条件时不修改
+fn sys_setfsid(id:u32,select:fn(&mut Cred)->Ids)->isize{
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    let mut cred = tsk.get_cred().clone();
+    let capable = cred.capable();
+    let ids = select(&mut cred);
+    let old = *ids.fs;
+    if id != ID_UNCHANGED && (capable || id == old || ids.is_one_of(id)) {
+        *ids.fs = id;
+        tsk.set_cred(cred);
+    }
+    old as isize
+}
+
+// size为0时只返回附加组的数量
+fn sys_getgroups(size:i32,list:usize)->isize{
+    if size < 0 {
+        return -EINVAL;
+    }
+    let groups = current_cred().groups;
+    if size == 0 {
+        return groups.len() as isize;
+    }
+    if (size as usize) < groups.len() {
+        return -EINVAL;
+    }
+    for (i,gid) in groups.iter().enumerate() {
+        unsafe { Vaddr(list+i*4).write_single(*gid).unwrap(); }
+    }
+    groups.len() as isize
+}
+
+fn sys_setgroups(size:i32,list:usize)->isize{
+    if size < 0 || size as usize > NGROUPS_MAX {
+        return -EINVAL;
+    }
+    let groups:Vec<u32> = (0..size as usize).map(|i|{
+        unsafe { Vaddr(list+i*4).read_single().unwrap() }
+    }).collect();
+    update_cred(|c|{
+        if !c.capable() {
+            return Err(-EPERM);
+        }
+        c.groups = groups;
+        Ok(())
+    })
+}
@@ -1,15 +1,21 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::ops::Add;
-use fatfs::Write;
+use fatfs::{Read, SeekFrom, Write};
 use crate::error_sync;
-use crate::fs::dfile::{DFile, DirEntryWrapper};
-use crate::fs::fat::get_fatfs;
-use crate::fs::fcntl::{AT_FDCWD, OpenFlags, OpenMode};
-use crate::fs::{get_dentry_from_dir};
+use crate::fs::dfile::DFile;
+use crate::fs::eventfd::{EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE, EventFd};
+use crate::fs::fcntl::{AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW, AT_EACCESS, MAY_WRITE, OpenFlags, OpenMode, R_OK, SEEK_CUR, SEEK_END, SEEK_SET, UTIME_NOW, UTIME_OMIT, W_OK, X_OK};
+use crate::fs::inode::Inode;
+use crate::fs::mount::{do_mount, do_sync, do_umount, UMOUNT_NOFOLLOW};
+use crate::fs::namei::{namei, namei_parent};
+use crate::fs::vfs::{InodeType, SetAttr};
+use crate::syscall::errno::{EBADF, EEXIST, EFAULT, EINVAL, EIO, EISDIR, EMFILE, ENOENT, ENOTDIR, ENOTEMPTY, EPERM};
 use crate::mm::addr::Vaddr;
 use crate::pre::ReadWriteSingleNoOff;
+use crate::task::cred::{current_cred, current_umask};
 use crate::task::info::*;
+use crate::trap::timer::{get_realtime_sec, NSEC_PER_SEC};
 use crate::trap::TrapFrame;
 use crate::utils::convert_cstr_from_vaddr;
 use super::*;
@@ -30,11 +36,16 @@ pub fn syscall_fs_entry(tf:&mut TrapFrame, syscall_id:usize){
                                }
                            }
                        },
-                       OpenMode::from_bits(tf.arg3() as u32).unwrap())
+                       OpenMode::from_bits_truncate(tf.arg3() as u32))
         }
         SYSCALL_PIPE =>{
             sys_pipe(tf.arg0(),tf.arg1())
         }
+        SYSCALL_EVENTFD2 => {
+            let ret = sys_eventfd2(tf.arg0() as u32,tf.arg1() as u32);
+            info_sync!("eventfd2:initval:{},flags:{:#X},ret:{}",tf.arg0() as u32,tf.arg1(),ret);
+            ret
+        }
         SYSCALL_SENDFILE => {
             sys_sendfile(tf.arg0() as isize,tf.arg1() as isize,tf.arg2() as *const usize,tf.arg3())
         }
@@ -65,6 +76,123 @@ pub fn syscall_fs_entry(tf:&mut TrapFrame, syscall_id:usize){
             let ret = sys_fcntl(tf.arg0(),tf.arg1() as u32,tf.arg2());
             ret
         }
+        SYSCALL_MOUNT=>{
+            if tf.arg0()==0 || tf.arg1()==0 || tf.arg2()==0 {
+                -EINVAL
+            } else {
+                let data = if tf.arg4()==0 {
+                    String::new()
+                } else {
+                    convert_cstr_from_vaddr(Vaddr(tf.arg4()))
+                };
+                sys_mount(convert_cstr_from_vaddr(Vaddr(tf.arg0())),
+                          convert_cstr_from_vaddr(Vaddr(tf.arg1())),
+                          convert_cstr_from_vaddr(Vaddr(tf.arg2())),
+                          tf.arg3(),
+                          data)
+            }
+        }
+        SYSCALL_IOCTL=>{
+            sys_ioctl(tf.arg0() as isize,tf.arg1(),tf.arg2())
+        }
+        SYSCALL_UMOUNT2=>{
+            if tf.arg0()==0 {
+                -EINVAL
+            } else {
+                sys_umount2(convert_cstr_from_vaddr(Vaddr(tf.arg0())),tf.arg1())
+            }
+        }
+        SYSCALL_MKNODAT=>{
+            sys_mknodat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32,tf.arg3())
+        }
+        SYSCALL_MKDIRAT=>{
+            sys_mkdirat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32)
+        }
+        SYSCALL_UNLINKAT=>{
+            sys_unlinkat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32)
+        }
+        SYSCALL_RENAMEAT2=>{
+            sys_renameat2(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),
+                          tf.arg2() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg3())),tf.arg4() as u32)
+        }
+        SYSCALL_LINKAT=>{
+            sys_linkat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),
+                       tf.arg2() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg3())),tf.arg4() as u32)
+        }
+        SYSCALL_SYMLINKAT=>{
+            sys_symlinkat(convert_cstr_from_vaddr(Vaddr(tf.arg0())),tf.arg1() as isize,
+                          convert_cstr_from_vaddr(Vaddr(tf.arg2())))
+        }
+        SYSCALL_READLINKAT=>{
+            sys_readlinkat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2(),tf.arg3() as isize)
+        }
+        SYSCALL_GETDENTS64=>{
+            sys_getdents64(tf.arg0() as isize,tf.arg1(),tf.arg2())
+        }
+        SYSCALL_CHDIR=>{
+            sys_chdir(convert_cstr_from_vaddr(Vaddr(tf.arg0())))
+        }
+        SYSCALL_FCHDIR=>{
+            sys_fchdir(tf.arg0() as isize)
+        }
+        SYSCALL_LSEEK=>{
+            sys_lseek(tf.arg0() as isize,tf.arg1() as isize,tf.arg2())
+        }
+        SYSCALL_READV=>{
+            sys_readv(tf.arg0() as isize,tf.arg1(),tf.arg2())
+        }
+        SYSCALL_PREAD64=>{
+            sys_pread64(tf.arg0() as isize,tf.arg1(),tf.arg2(),tf.arg3() as isize)
+        }
+        SYSCALL_PWRITE64=>{
+            sys_pwrite64(tf.arg0() as isize,tf.arg1(),tf.arg2(),tf.arg3() as isize)
+        }
+        SYSCALL_PREADV=>{
+            sys_preadv(tf.arg0() as isize,tf.arg1(),tf.arg2(),tf.arg3() as isize)
+        }
+        SYSCALL_PWRITEV=>{
+            sys_pwritev(tf.arg0() as isize,tf.arg1(),tf.arg2(),tf.arg3() as isize)
+        }
+        SYSCALL_TRUNCATE=>{
+            sys_truncate(convert_cstr_from_vaddr(Vaddr(tf.arg0())),tf.arg1() as isize)
+        }
+        SYSCALL_FTRUNCATE=>{
+            sys_ftruncate(tf.arg0() as isize,tf.arg1() as isize)
+        }
+        SYSCALL_SYNC=>{
+            sys_sync()
+        }
+        SYSCALL_FSYNC|SYSCALL_FDATASYNC=>{
+            sys_fsync(tf.arg0() as isize)
+        }
+        SYSCALL_SYNCFS=>{
+            sys_syncfs(tf.arg0() as isize)
+        }
+        SYSCALL_FALLOCATE=>{
+            sys_fallocate(tf.arg0() as isize,tf.arg1() as u32,tf.arg2() as isize,tf.arg3() as isize)
+        }
+        SYSCALL_FACCESSAT=>{
+            sys_faccessat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32,0)
+        }
+        SYSCALL_FACCESSAT2=>{
+            sys_faccessat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32,tf.arg3() as u32)
+        }
+        SYSCALL_FCHMOD=>{
+            sys_fchmod(tf.arg0() as isize,tf.arg1() as u32)
+        }
+        SYSCALL_FCHMODAT=>{
+            sys_fchmodat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),tf.arg2() as u32)
+        }
+        SYSCALL_FCHOWN=>{
+            sys_fchown(tf.arg0() as isize,tf.arg1() as u32,tf.arg2() as u32)
+        }
+        SYSCALL_FCHOWNAT=>{
+            sys_fchownat(tf.arg0() as isize,convert_cstr_from_vaddr(Vaddr(tf.arg1())),
+                         tf.arg2() as u32,tf.arg3() as u32,tf.arg4() as u32)
+        }
+        SYSCALL_UTIMENSAT=>{
+            sys_utimensat(tf.arg0() as isize,tf.arg1(),tf.arg2(),tf.arg3() as u32)
+        }
         _ => {
             panic!("fs syscall {} not impl",syscall_id);
         }
@@ -72,8 +200,15 @@ pub fn syscall_fs_entry(tf:&mut TrapFrame, syscall_id:usize){
     tf.ret(ret as usize);
 }
 
+// pipe2，flags只能是O_NONBLOCK和O_CLOEXEC
 fn sys_pipe(pipe :usize,flags:usize)->isize{
-    let (read,write) = DFile::new_pipe();
+    let flags = match OpenFlags::from_bits(flags as u32) {
+        Some(f) if (f - (OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC)).is_empty() => f,
+        _ => {
+            return -EINVAL;
+        }
+    };
+    let (read,write) = DFile::new_pipe(flags);
     let read = Arc::new(read);
     let write= Arc::new(write);
     let running = get_running();
@@ -83,14 +218,14 @@ fn sys_pipe(pipe :usize,flags:usize)->isize{
     let mut ret = 0isize;
     match tsk.alloc_opened(read){
         None => {
-            ret = -1;
+            ret = -EMFILE;
         }
         Some(fdr) => {
             readfd = fdr;
             match tsk.alloc_opened(write) {
                 None => {
                     tsk.clear_opened(readfd);
-                    ret = -1;
+                    ret = -EMFILE;
                 }
                 Some(fdw) => {
                     writefd = fdw;
@@ -98,9 +233,12 @@ fn sys_pipe(pipe :usize,flags:usize)->isize{
             }
         }
     }
-    unsafe {Vaddr(pipe).write_single(readfd as u32).unwrap()}
-    unsafe {Vaddr(pipe+size_of::<u32>()).write_single(readfd as u32).unwrap()}
-    info_sync!("sys_pipe: pipe[2]:{:#X},flags:{:b},rfd:{},wfd{},ret:{}",pipe,flags,readfd,writefd,ret);
+    drop(tsk);
+    if ret == 0 {
+        unsafe {Vaddr(pipe).write_single(readfd as u32).unwrap()}
+        unsafe {Vaddr(pipe+size_of::<u32>()).write_single(writefd as u32).unwrap()}
+    }
+    info_sync!("sys_pipe: pipe[2]:{:#X},flags:{:?},rfd:{},wfd{},ret:{}",pipe,flags,readfd,writefd,ret);
     ret
 }
 
@@ -135,6 +273,34 @@ fn sys_fcntl(fd:usize,cmd:u32,arg:usize)->isize{
                     file.set_cloexec_to((arg & 1) == 1);
                     0
                 }
+                F_GETFL=> {
+                    cmd_str = String::from("F_GETFL");
+                    file.get_flags().bits() as isize
+                }
+                F_SETFL=> {
+                    cmd_str = String::from("F_SETFL");
+                    file.set_nonblock(arg as u32 & OpenFlags::O_NONBLOCK.bits() != 0);
+                    0
+                }
+                F_GETPIPE_SZ=> {
+                    cmd_str = String::from("F_GETPIPE_SZ");
+                    match file.get_pipe() {
+                        None => -EBADF,
+                        Some(p) => p.get_size() as isize
+                    }
+                }
+                F_SETPIPE_SZ=> {
+                    cmd_str = String::from("F_SETPIPE_SZ");
+                    match file.get_pipe() {
+                        None => -EBADF,
+                        Some(p) => {
+                            match p.set_size(arg,tsk.get_cred().capable()) {
+                                Ok(v) => v as isize,
+                                Err(e) => e
+                            }
+                        }
+                    }
+                }
                 F_DUPFD_CLOEXEC =>{
                     cmd_str = String::from("F_DUPFD_CLOEXEC");
                     match tsk.alloc_opened_bigger_than(file.clone(),arg) {
@@ -161,36 +327,26 @@ fn sys_fcntl(fd:usize,cmd:u32,arg:usize)->isize{
 fn sys_newfstatat(fd:isize,path_addr:usize,buf:usize,flags:u32)->isize{
     let path = convert_cstr_from_vaddr(Vaddr(path_addr));
     info_sync!("newfstat path:{}",&path);
-    let mut stat = NewStat::empty();
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
-    let dirfile = if fd == AT_FDCWD{
-        tsk.get_pwd_opened()
+    let inode = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        get_dir_inode(fd)
     } else {
-        match tsk.get_opened(fd as usize){
-            None => {
-                return -2;
-            }
-            Some(f) => {
-                f
-            }
+        get_inode_at(fd,&path,flags & AT_SYMLINK_NOFOLLOW == 0)
+    };
+    let inode = match inode {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
         }
     };
-    match dirfile.open_path(&path,OpenFlags::O_RDONLY){
-        None => {
-            return -2;
+    let mut stat = NewStat::empty();
+    // 临时构造的DFile
+    match DFile::from_inode(inode,OpenFlags::O_RDONLY).fill_stat(&mut stat) {
+        Ok(_) => {
+            Vaddr(buf).write(stat.as_bytes()).unwrap();
+            0
         }
-        Some(f) => {
-            // 这个f是临时构造的
-            match f.fill_stat(&mut stat) {
-                Ok(_) => {
-                    Vaddr(buf).write(stat.as_bytes()).unwrap();
-                    return 0;
-                }
-                Err(_) => {
-                    return -1;
-                }
-            }
+        Err(_) => {
+            -EINVAL
         }
     }
 }
@@ -259,37 +415,326 @@ fn do_dup(old_fd:isize,new_fd:Option<isize>,open_flags_bits:Option<usize>)->isiz
     }
 }
 
+// 解析路径时不持有task的锁，procfs等文件系统会访问task
 fn sys_openat(dirfd:isize,filename:String,flags:OpenFlags,mode:OpenMode)->isize{
     info_sync!("openat: dirfd{} filename {}",dirfd,&filename);
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
-    let dir_dfile = if dirfd==AT_FDCWD {
-        tsk.get_pwd_opened()
-    } else {
-        match tsk.get_opened(dirfd as usize){
-            None => {
-                return -1;
-            }
-            Some(f) => {f}
+    let new_file = match get_dir_inode(dirfd).and_then(|dir| DFile::open_at(&dir,&filename,flags,mode)) {
+        Err(e) => {
+            trace_sync!("openat: opened fail {}",e);
+            return e;
         }
+        Ok(f) => f
     };
-    match dir_dfile.open_path(&filename,flags){
+    match get_running().lock_irq().unwrap().alloc_opened(Arc::new(new_file)){
         None => {
             trace_sync!("openat: opened fail");
-            return -1;
+            -EMFILE
         }
-        Some(new_file) => {
-            match tsk.alloc_opened(Arc::new(new_file)){
-                None => {
-                    trace_sync!("openat: opened fail");
-                    return -1;
-                }
-                Some(v) => {
-                    trace_sync!("openat: opened fd {}",v);
-                    return v as isize;
+        Some(v) => {
+            trace_sync!("openat: opened fd {}",v);
+            v as isize
+        }
+    }
+}
+
+fn sys_ioctl(fd:isize,cmd:usize,arg:usize)->isize{
+    if fd < 0 {
+        return -EBADF;
+    }
+    let opened = get_running().lock_irq().unwrap().get_opened(fd as usize);
+    match opened {
+        None => -EBADF,
+        Some(f) => {
+            match f.ioctl(cmd,arg) {
+                Ok(v) => v as isize,
+                Err(e) => e
+            }
+        }
+    }
+}
+
+// dirfd为AT_FDCWD时是工作目录
+fn get_dir_inode(dirfd:isize)->Result<Arc<Inode>,isize>{
+    let running = get_running();
+    let tsk = running.lock_irq().unwrap();
+    if dirfd == AT_FDCWD {
+        return Ok(tsk.get_cwd());
+    }
+    if dirfd < 0 {
+        return Err(-EBADF);
+    }
+    let file = tsk.get_opened(dirfd as usize).ok_or(-EBADF)?;
+    drop(tsk);
+    file.clone_inode().ok_or(-ENOTDIR)
+}
+
+// 绝对路径从根目录开始，否则从dirfd开始
+fn get_inode_at(dirfd:isize,path:&str,follow:bool)->Result<Arc<Inode>,isize>{
+    let start = get_dir_inode(dirfd)?;
+    namei(&start,path,follow)
+}
+
+fn get_inode_by_path(path:&str)->Result<Arc<Inode>,isize>{
+    get_inode_at(AT_FDCWD,path,true)
+}
+
+fn sys_mkdirat(dirfd:isize,path:String,mode:u32)->isize{
+    info_sync!("mkdirat: dirfd {} path {} mode {:o}",dirfd,&path,mode);
+    let cred = current_cred();
+    let umask = current_umask();
+    let ret = get_dir_inode(dirfd).and_then(|start|{
+        let (parent,name) = namei_parent(&start,&path)?;
+        if name.eq(".") || name.eq("..") {
+            return Err(-EEXIST);
+        }
+        parent.may_create(&cred)?;
+        parent.mkdir(&name,mode & 0o7777 & !umask)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 只能创建普通文件和FIFO，设备文件只由devfs提供
+fn sys_mknodat(dirfd:isize,path:String,mode:u32,dev:usize)->isize{
+    info_sync!("mknodat: dirfd {} path {} mode {:o} dev {:#X}",dirfd,&path,mode,dev);
+    let itype = match mode & S_IFMT {
+        0 | S_IFREG => InodeType::File,
+        S_IFIFO => InodeType::Fifo,
+        S_IFCHR | S_IFBLK | S_IFSOCK => {
+            return -EPERM;
+        }
+        _ => {
+            return -EINVAL;
+        }
+    };
+    let cred = current_cred();
+    let umask = current_umask();
+    let ret = get_dir_inode(dirfd).and_then(|start|{
+        let (parent,name) = namei_parent(&start,&path)?;
+        if name.eq(".") || name.eq("..") {
+            return Err(-EEXIST);
+        }
+        parent.may_create(&cred)?;
+        let mode = mode & 0o7777 & !umask;
+        match itype {
+            InodeType::File => parent.create(&name,mode),
+            _ => parent.mknod(&name,itype,mode)
+        }
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// AT_REMOVEDIR时删除空目录，否则删除非目录项
+fn sys_unlinkat(dirfd:isize,path:String,flags:u32)->isize{
+    info_sync!("unlinkat: dirfd {} path {} flags {:#X}",dirfd,&path,flags);
+    if flags & !AT_REMOVEDIR != 0 {
+        return -EINVAL;
+    }
+    let cred = current_cred();
+    let ret = get_dir_inode(dirfd).and_then(|start|{
+        let (parent,name) = namei_parent(&start,&path)?;
+        if flags & AT_REMOVEDIR != 0 {
+            match name.as_str() {
+                "." => Err(-EINVAL),
+                ".." => Err(-ENOTEMPTY),
+                _ => {
+                    parent.may_delete(&parent.lookup(&name)?,&cred)?;
+                    parent.rmdir(&name)
                 }
             }
+        } else {
+            let victim = parent.lookup(&name)?;
+            if path.ends_with('/') || victim.is_dir() {
+                return Err(-EISDIR);
+            }
+            parent.may_delete(&victim,&cred)?;
+            parent.unlink(&name)
+        }
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_renameat2(olddirfd:isize,oldpath:String,newdirfd:isize,newpath:String,flags:u32)->isize{
+    info_sync!("renameat2: {} {} => {} {} flags {:#X}",olddirfd,&oldpath,newdirfd,&newpath,flags);
+    let cred = current_cred();
+    let ret = get_dir_inode(olddirfd).and_then(|old_start|{
+        let new_start = get_dir_inode(newdirfd)?;
+        let (old_dir,old_name) = namei_parent(&old_start,&oldpath)?;
+        let (new_dir,new_name) = namei_parent(&new_start,&newpath)?;
+        // 名字不合法或源不存在时由rename返回对应的错误
+        for (dir,name) in [(&old_dir,&old_name),(&new_dir,&new_name)] {
+            match dir.lookup(name) {
+                Ok(victim) => dir.may_delete(&victim,&cred)?,
+                Err(_) => dir.may_create(&cred)?
+            }
         }
+        old_dir.rename(&old_name,&new_dir,&new_name,flags)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 默认不跟随oldpath的符号链接
+fn sys_linkat(olddirfd:isize,oldpath:String,newdirfd:isize,newpath:String,flags:u32)->isize{
+    info_sync!("linkat: {} {} => {} {} flags {:#X}",olddirfd,&oldpath,newdirfd,&newpath,flags);
+    if flags & !(AT_SYMLINK_FOLLOW|AT_EMPTY_PATH) != 0 {
+        return -EINVAL;
+    }
+    let cred = current_cred();
+    let ret = get_dir_inode(olddirfd).and_then(|old_start|{
+        let target = if oldpath.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            old_start
+        } else {
+            namei(&old_start,&oldpath,flags & AT_SYMLINK_FOLLOW != 0)?
+        };
+        if target.is_dir() {
+            return Err(-EPERM);
+        }
+        let new_start = get_dir_inode(newdirfd)?;
+        let (new_dir,new_name) = namei_parent(&new_start,&newpath)?;
+        if newpath.ends_with('/') {
+            return Err(-ENOENT);
+        }
+        new_dir.may_create(&cred)?;
+        new_dir.link(&new_name,&target)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 目标路径原样保存，不检查是否存在
+fn sys_symlinkat(target:String,newdirfd:isize,linkpath:String)->isize{
+    info_sync!("symlinkat: target {} newdirfd {} linkpath {}",&target,newdirfd,&linkpath);
+    if target.is_empty() {
+        return -ENOENT;
+    }
+    let cred = current_cred();
+    let ret = get_dir_inode(newdirfd).and_then(|start|{
+        let (parent,name) = namei_parent(&start,&linkpath)?;
+        if name.eq(".") || name.eq("..") {
+            return Err(-EEXIST);
+        }
+        parent.may_create(&cred)?;
+        parent.symlink(&name,&target)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 返回的内容不以0结尾，超过bufsiz时截断
+fn sys_readlinkat(dirfd:isize,path:String,buf:usize,bufsiz:isize)->isize{
+    info_sync!("readlinkat: dirfd {} path {} bufsiz {}",dirfd,&path,bufsiz);
+    if bufsiz <= 0 {
+        return -EINVAL;
+    }
+    let ret = get_inode_at(dirfd,&path,false).and_then(|inode|{
+        if inode.get_type() != InodeType::SymLink {
+            return Err(-EINVAL);
+        }
+        let target = inode.readlink()?;
+        let len = target.len().min(bufsiz as usize);
+        Vaddr(buf).write(&target.as_bytes()[..len]).map_err(|_| -EFAULT)?;
+        Ok(len as isize)
+    });
+    match ret {
+        Ok(v) => v,
+        Err(e) => e
+    }
+}
+
+fn sys_getdents64(fd:isize,buf:usize,len:usize)->isize{
+    if fd < 0 {
+        return -EBADF;
+    }
+    let opened = get_running().lock_irq().unwrap().get_opened(fd as usize);
+    let file = match opened {
+        None => {
+            return -EBADF;
+        }
+        Some(f) => f
+    };
+    let buf = unsafe { &mut *slice_from_raw_parts_mut(buf as *mut u8,len) };
+    match file.getdents(buf) {
+        Ok(v) => v as isize,
+        Err(e) => e
+    }
+}
+
+fn sys_chdir(path:String)->isize{
+    info_sync!("chdir: {}",&path);
+    let dir = match get_inode_by_path(&path) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    if !dir.is_dir() {
+        return -ENOTDIR;
+    }
+    get_running().lock_irq().unwrap().set_cwd(dir);
+    0
+}
+
+fn sys_fchdir(fd:isize)->isize{
+    let dir = match get_dir_inode(fd) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    if !dir.is_dir() {
+        return -ENOTDIR;
+    }
+    get_running().lock_irq().unwrap().set_cwd(dir);
+    0
+}
+
+// MS_*标志暂不支持
+fn sys_mount(special:String,dir:String,fstype:String,flags:usize,data:String)->isize{
+    info_sync!("mount: {} on {} type {} flags {:#X} data {}",&special,&dir,&fstype,flags,&data);
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    let target = match get_inode_by_path(&dir) {
+        Err(e) => {
+            return e;
+        }
+        Ok(v) => v
+    };
+    match do_mount(&special,target,&fstype,flags,&data) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_umount2(target:String,flags:usize)->isize{
+    info_sync!("umount2: {} flags {:#X}",&target,flags);
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    let target = match get_inode_at(AT_FDCWD,&target,flags & UMOUNT_NOFOLLOW == 0) {
+        Err(e) => {
+            return e;
+        }
+        Ok(v) => v
+    };
+    match do_umount(target,flags) {
+        Ok(_) => 0,
+        Err(e) => e
     }
 }
 
@@ -322,12 +767,12 @@ fn sys_sendfile(out_fd:isize,in_fd:isize,offset:*const usize,count:usize)->isize
 
 fn sys_write(fd:isize,ptr:usize,len:usize)->isize{
     let buf = slice_from_raw_parts(ptr as *const u8,len);
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
     if fd ==0{
         error_sync!("stdin!");
     }
-    match tsk.get_opened(fd as usize){
+    // 读写期间不能持有task锁，访问用户缓冲区可能缺页，pipe也可能sleep
+    let opened = get_running().lock_irq().unwrap().get_opened(fd as usize);
+    match opened{
         None => {
             return -1;
         }
@@ -337,8 +782,8 @@ fn sys_write(fd:isize,ptr:usize,len:usize)->isize{
                     Ok(l) => {
                         return l as isize;
                     }
-                    Err(_) => {
-                        return -1;
+                    Err(e) => {
+                        return e;
                     }
                 }
             }
@@ -348,9 +793,8 @@ fn sys_write(fd:isize,ptr:usize,len:usize)->isize{
 
 fn sys_read(fd:isize,ptr:usize,len:usize)->isize{
     let buf = slice_from_raw_parts_mut(ptr as *mut u8,len);
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
-    match tsk.get_opened(fd as usize){
+    let opened = get_running().lock_irq().unwrap().get_opened(fd as usize);
+    match opened{
         None => {
             return -1;
         }
@@ -360,8 +804,8 @@ fn sys_read(fd:isize,ptr:usize,len:usize)->isize{
                     Ok(l) => {
                         return l as isize;
                     }
-                    Err(_) => {
-                        return -1;
+                    Err(e) => {
+                        return e;
                     }
                 }
             }
@@ -369,40 +813,404 @@ fn sys_read(fd:isize,ptr:usize,len:usize)->isize{
     }
 }
 
-// todo使用write all
-fn sys_writev(fd:isize,iov_array_base:usize,len:usize)->isize{
-    #[repr(C)]
-    #[derive(Copy,Clone)]
-    struct IOVEC{
-        iov_base:*mut u8,
-        iov_len:usize
+// initval只有32位
+fn sys_eventfd2(initval:u32,flags:u32)->isize{
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    let e = EventFd::new(initval as u64,flags & EFD_SEMAPHORE != 0);
+    let file = Arc::new(DFile::new_eventfd(e,OpenFlags::from_bits_truncate(flags)));
+    match get_running().lock_irq().unwrap().alloc_opened(file) {
+        Some(fd) => fd as isize,
+        None => -EMFILE
     }
+}
 
-    if fd==0{
-        error_sync!("stdin!");
+pub(super) fn get_file(fd:isize)->Result<Arc<DFile>,isize>{
+    if fd < 0 {
+        return Err(-EBADF);
     }
-    let mut len_res = len;
-    let len_need_read = len_res;
-    let mut file = match get_running().lock_irq().unwrap().get_opened(fd as usize){
-        None => {
-            return -1;
+    get_running().lock_irq().unwrap().get_opened(fd as usize).ok_or(-EBADF)
+}
+
+fn sys_lseek(fd:isize,offset:isize,whence:usize)->isize{
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+        SEEK_CUR => SeekFrom::Current(offset as i64),
+        SEEK_END => SeekFrom::End(offset as i64),
+        _ => {
+            return -EINVAL;
+        }
+    };
+    match get_file(fd).and_then(|f| f.seek(pos)) {
+        Ok(v) => v as isize,
+        Err(e) => e
+    }
+}
+
+fn sys_pread64(fd:isize,ptr:usize,len:usize,offset:isize)->isize{
+    if offset < 0 {
+        return -EINVAL;
+    }
+    let buf = unsafe { &mut *slice_from_raw_parts_mut(ptr as *mut u8,len) };
+    match get_file(fd).and_then(|f| f.pread(buf,offset as usize)) {
+        Ok(v) => v as isize,
+        Err(e) => e
+    }
+}
+
+fn sys_pwrite64(fd:isize,ptr:usize,len:usize,offset:isize)->isize{
+    if offset < 0 {
+        return -EINVAL;
+    }
+    let buf = unsafe { &*slice_from_raw_parts(ptr as *const u8,len) };
+    match get_file(fd).and_then(|f| f.pwrite(buf,offset as usize)) {
+        Ok(v) => v as isize,
+        Err(e) => e
+    }
+}
+
+// 用户态的struct iovec
+#[repr(C)]
+#[derive(Copy,Clone)]
+struct IoVec{
+    iov_base:*mut u8,
+    iov_len:usize
+}
+
+const IOV_MAX:usize = 1024;
+
+fn get_iovecs(iov:usize,iovcnt:usize)->Result<Vec<IoVec>,isize>{
+    if iovcnt > IOV_MAX {
+        return Err(-EINVAL);
+    }
+    if iovcnt == 0 {
+        return Ok(Vec::new());
+    }
+    let iovs = unsafe { &*slice_from_raw_parts(iov as *const IoVec,iovcnt) };
+    let mut total:usize = 0;
+    for v in iovs {
+        total = total.checked_add(v.iov_len)
+            .filter(|t| *t <= isize::MAX as usize)
+            .ok_or(-EINVAL)?;
+    }
+    Ok(iovs.to_vec())
+}
+
+// 依次读写每个缓冲区，f的最后一个参数是已经传输的长度
+// 短读写时停止，已经传输过数据时不返回错误
+fn do_iovec<F>(fd:isize,iov:usize,iovcnt:usize,mut f:F)->isize
+    where F:FnMut(&Arc<DFile>,&mut [u8],usize)->Result<usize,isize>{
+    let file = match get_file(fd) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    let iovs = match get_iovecs(iov,iovcnt) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    let mut done = 0;
+    for v in iovs.iter().filter(|v| v.iov_len != 0) {
+        let buf = unsafe { &mut *slice_from_raw_parts_mut(v.iov_base,v.iov_len) };
+        match f(&file,buf,done) {
+            Ok(n) => {
+                done += n;
+                if n < v.iov_len {
+                    break;
+                }
+            }
+            Err(e) => {
+                if done == 0 {
+                    return e;
+                }
+                break;
+            }
+        }
+    }
+    done as isize
+}
+
+fn sys_readv(fd:isize,iov:usize,iovcnt:usize)->isize{
+    do_iovec(fd,iov,iovcnt,|f,buf,_| f.read(buf))
+}
+
+fn sys_writev(fd:isize,iov:usize,iovcnt:usize)->isize{
+    do_iovec(fd,iov,iovcnt,|f,buf,_| f.write(buf))
+}
+
+fn sys_preadv(fd:isize,iov:usize,iovcnt:usize,offset:isize)->isize{
+    if offset < 0 {
+        return -EINVAL;
+    }
+    do_iovec(fd,iov,iovcnt,|f,buf,done| f.pread(buf,offset as usize+done))
+}
+
+fn sys_pwritev(fd:isize,iov:usize,iovcnt:usize,offset:isize)->isize{
+    if offset < 0 {
+        return -EINVAL;
+    }
+    do_iovec(fd,iov,iovcnt,|f,buf,done| f.pwrite(buf,offset as usize+done))
+}
+
+fn sys_sync()->isize{
+    do_sync();
+    0
+}
+
+// 没有单独缓存文件数据以外的元数据，fdatasync与fsync相同
+fn sys_fsync(fd:isize)->isize{
+    match get_file(fd).and_then(|f| f.fsync()) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_syncfs(fd:isize)->isize{
+    let file = match get_file(fd) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
         }
-        Some(f) => {f}
     };
-    for i in 0..usize::MAX {
-        if len_res <=0{
-            break;
-        }
-        let iov = unsafe{*((iov_array_base + i*size_of::<IOVEC>()) as *mut IOVEC)};
-        let real_write_len = min(iov.iov_len, len_res);
-        let write_buf= unsafe{slice_from_raw_parts(iov.iov_base, real_write_len).as_ref().unwrap()};
-        assert_eq!(match file.write(write_buf){
-            Ok(l) => {l}
-            Err(_) => {
-                return -1;
+    let ret = match file.clone_inode() {
+        Some(inode) => inode.get_sb().sync(),
+        None => Ok(())
+    };
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_truncate(path:String,len:isize)->isize{
+    if len < 0 {
+        return -EINVAL;
+    }
+    let ret = get_inode_by_path(&path).and_then(|inode|{
+        if inode.is_dir() {
+            return Err(-EISDIR);
+        }
+        if !inode.is_file() {
+            return Err(-EINVAL);
+        }
+        inode.truncate(len as usize)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_ftruncate(fd:isize,len:isize)->isize{
+    if len < 0 {
+        return -EINVAL;
+    }
+    match get_file(fd).and_then(|f| f.truncate(len as usize)) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_fallocate(fd:isize,mode:u32,offset:isize,len:isize)->isize{
+    if offset < 0 || len <= 0 {
+        return -EINVAL;
+    }
+    match get_file(fd).and_then(|f| f.fallocate(mode,offset as usize,len as usize)) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// UTIME_OMIT为None，UTIME_NOW为当前时间
+fn utime_from_timespec(ts:&TimeSpec)->Result<Option<u64>,isize>{
+    match ts.tv_nsec {
+        UTIME_OMIT => Ok(None),
+        UTIME_NOW => Ok(Some(get_realtime_sec())),
+        nsec if nsec < 0 || nsec >= NSEC_PER_SEC as i64 || ts.tv_sec < 0 => Err(-EINVAL),
+        _ => Ok(Some(ts.tv_sec as u64))
+    }
+}
+
+// path为NULL时修改dirfd本身，即futimens
+// times为NULL时两个时间都设为当前时间
+fn sys_utimensat(dirfd:isize,path_addr:usize,times:usize,flags:u32)->isize{
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return -EINVAL;
+    }
+    let mut attr = SetAttr::default();
+    // 只设为当前时间时有写权限即可，设为指定时间需要是属主
+    let mut now_only = true;
+    if times == 0 {
+        let now = get_realtime_sec();
+        attr.atime = Some(now);
+        attr.mtime = Some(now);
+    } else {
+        let mut ts = [TimeSpec::default();2];
+        for (i,t) in ts.iter_mut().enumerate() {
+            Vaddr(times+i*core::mem::size_of::<TimeSpec>()).read(t.as_bytes_mut()).unwrap();
+        }
+        now_only = ts.iter().all(|t| t.tv_nsec == UTIME_NOW || t.tv_nsec == UTIME_OMIT);
+        let parsed = utime_from_timespec(&ts[0])
+            .and_then(|a| Ok((a,utime_from_timespec(&ts[1])?)));
+        match parsed {
+            Ok((atime,mtime)) => {
+                attr.atime = atime;
+                attr.mtime = mtime;
+            }
+            Err(e) => {
+                return e;
+            }
+        }
+    }
+    let inode = if path_addr == 0 {
+        if dirfd == AT_FDCWD {
+            return -EFAULT;
+        }
+        get_file(dirfd).and_then(|f| f.clone_inode().ok_or(-EINVAL))
+    } else {
+        let path = convert_cstr_from_vaddr(Vaddr(path_addr));
+        info_sync!("utimensat: dirfd {} path {}",dirfd,&path);
+        get_inode_at(dirfd,&path,flags & AT_SYMLINK_NOFOLLOW == 0)
+    };
+    if attr.atime.is_none() && attr.mtime.is_none() {
+        return inode.map_or_else(|e| e,|_| 0);
+    }
+    let cred = current_cred();
+    let ret = inode.and_then(|inode|{
+        if !inode.is_owner(&cred) {
+            if !now_only {
+                return Err(-EPERM);
             }
-        }, real_write_len);
-        len_res -= real_write_len;
+            inode.permission(&cred,MAY_WRITE)?;
+        }
+        inode.setattr(&attr)
+    });
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 默认用实际id检查，AT_EACCESS时用有效id
+fn sys_faccessat(dirfd:isize,path:String,mode:u32,flags:u32)->isize{
+    info_sync!("faccessat: dirfd {} path {} mode {:o} flags {:#X}",dirfd,&path,mode,flags);
+    if mode & !(R_OK|W_OK|X_OK) != 0 || flags & !(AT_EACCESS|AT_SYMLINK_NOFOLLOW|AT_EMPTY_PATH) != 0 {
+        return -EINVAL;
     }
-    len_need_read as isize
-}
\ No newline at end of file
+    let mut cred = current_cred();
+    if flags & AT_EACCESS == 0 {
+        cred.fsuid = cred.uid;
+        cred.fsgid = cred.gid;
+    }
+    let inode = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        get_dir_inode(dirfd)
+    } else {
+        get_inode_at(dirfd,&path,flags & AT_SYMLINK_NOFOLLOW == 0)
+    };
+    match inode.and_then(|inode| inode.permission(&cred,mode)) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 路径为空且有AT_EMPTY_PATH时是dirfd本身
+fn get_inode_for_attr(dirfd:isize,path:&str,flags:u32)->Result<Arc<Inode>,isize>{
+    if flags & !(AT_SYMLINK_NOFOLLOW|AT_EMPTY_PATH) != 0 {
+        return Err(-EINVAL);
+    }
+    if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        get_dir_inode(dirfd)
+    } else {
+        get_inode_at(dirfd,path,flags & AT_SYMLINK_NOFOLLOW == 0)
+    }
+}
+
+// 不在文件所属组中的非特权用户不能设置setgid位
+fn do_chmod(inode:Arc<Inode>,mode:u32)->isize{
+    let cred = current_cred();
+    if !inode.is_owner(&cred) {
+        return -EPERM;
+    }
+    let mut mode = mode & 0o7777;
+    if !cred.fs_capable() && !cred.in_group(inode.getattr().gid) {
+        mode &= !S_ISGID;
+    }
+    let attr = SetAttr{
+        mode: Some(mode),
+        ..SetAttr::default()
+    };
+    match inode.setattr(&attr) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_fchmod(fd:isize,mode:u32)->isize{
+    match get_file(fd).and_then(|f| f.clone_inode().ok_or(-EINVAL)) {
+        Ok(inode) => do_chmod(inode,mode),
+        Err(e) => e
+    }
+}
+
+// linux的fchmodat没有flags参数
+fn sys_fchmodat(dirfd:isize,path:String,mode:u32)->isize{
+    info_sync!("fchmodat: dirfd {} path {} mode {:o}",dirfd,&path,mode);
+    match get_inode_at(dirfd,&path,true) {
+        Ok(inode) => do_chmod(inode,mode),
+        Err(e) => e
+    }
+}
+
+// uid或gid为-1时不修改
+// 非特权用户只能把自己的文件改到自己所在的组
+// 修改普通文件的属主后去掉setuid和有效的setgid位
+fn do_chown(inode:Arc<Inode>,uid:u32,gid:u32)->isize{
+    let cred = current_cred();
+    let cur = inode.getattr();
+    let uid = if uid == u32::MAX { None } else { Some(uid) };
+    let gid = if gid == u32::MAX { None } else { Some(gid) };
+    if !cred.fs_capable() {
+        let uid_ok = uid.map_or(true,|u| u == cur.uid);
+        let gid_ok = gid.map_or(true,|g| g == cur.gid || cred.in_group(g));
+        if cur.uid != cred.fsuid || !uid_ok || !gid_ok {
+            return -EPERM;
+        }
+    }
+    let mut attr = SetAttr{
+        uid,
+        gid,
+        ..SetAttr::default()
+    };
+    if (uid.is_some() || gid.is_some()) && cur.itype != InodeType::Dir {
+        let mut mode = cur.mode & !S_ISUID;
+        if mode & S_IXGRP != 0 {
+            mode &= !S_ISGID;
+        }
+        if mode != cur.mode {
+            attr.mode = Some(mode);
+        }
+    }
+    match inode.setattr(&attr) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+fn sys_fchown(fd:isize,uid:u32,gid:u32)->isize{
+    match get_file(fd).and_then(|f| f.clone_inode().ok_or(-EINVAL)) {
+        Ok(inode) => do_chown(inode,uid,gid),
+        Err(e) => e
+    }
+}
+
+fn sys_fchownat(dirfd:isize,path:String,uid:u32,gid:u32,flags:u32)->isize{
+    info_sync!("fchownat: dirfd {} path {} uid {} gid {} flags {:#X}",dirfd,&path,uid as i32,gid as i32,flags);
+    match get_inode_for_attr(dirfd,&path,flags) {
+        Ok(inode) => do_chown(inode,uid,gid),
+        Err(e) => e
+    }
+}
@@ -0,0 +1,267 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::min;
+use fatfs::Write;
+use crate::fs::namei::namei;
+use crate::mm::addr::Vaddr;
+use alloc::sync::Arc;
+use crate::fs::inode::Inode;
+use crate::fs::vfs::InodeType;
+use crate::io::BlockReadWrite;
+use crate::io::device::get_blkdev;
+use crate::mm::swap::{NoReclaimGuard, SWAP_FLAG_PREFER, SWAP_FLAG_PRIO_MASK, swapoff_blk, swapoff_file, swapon_blk, swapon_file};
+use crate::consts::PAGE_SIZE;
+use crate::mm::mm::{populate_range, user_access_ok};
+use crate::mm::vma::{MADV_WILLNEED, MmapProt};
+use crate::pre::ReadWriteSingleNoOff;
+use crate::mm::stat::get_meminfo;
+use crate::task::all_tasks;
+use crate::task::info::SysInfo;
+use crate::task::task::get_running_mm;
+use crate::trap::timer::get_time_ms;
+use crate::syscall::errno::{EAGAIN, EFAULT, EINVAL, ENOMEM, ENXIO, EPERM};
+use crate::task::cred::current_cred;
+use crate::trap::TrapFrame;
+use crate::utils::convert_cstr_from_vaddr;
+use super::*;
+
+pub fn syscall_mm_entry(tf:&mut TrapFrame, syscall_id:usize){
+    let ret = match syscall_id {
+        SYSCALL_SWAPON => {
+            let ret = sys_swapon(tf.arg0(),tf.arg1());
+            info_sync!("swapon:path_addr:{:#X},flags:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_SWAPOFF => {
+            let ret = sys_swapoff(tf.arg0());
+            info_sync!("swapoff:path_addr:{:#X},ret:{}",tf.arg0(),ret);
+            ret
+        }
+        SYSCALL_MUNMAP => {
+            let ret = sys_munmap(tf.arg0(),tf.arg1());
+            trace_sync!("munmap:vaddr:{:#X},len:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_MPROTECT => {
+            let ret = sys_mprotect(tf.arg0(),tf.arg1(),tf.arg2());
+            trace_sync!("mprotect:vaddr:{:#X},len:{:#X},prot:{:#b},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_MADVISE => {
+            let ret = sys_madvise(tf.arg0(),tf.arg1(),tf.arg2());
+            trace_sync!("madvise:vaddr:{:#X},len:{:#X},advice:{},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_MLOCK => {
+            let ret = sys_mlock(tf.arg0(),tf.arg1(),true);
+            trace_sync!("mlock:vaddr:{:#X},len:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_MUNLOCK => {
+            let ret = sys_mlock(tf.arg0(),tf.arg1(),false);
+            trace_sync!("munlock:vaddr:{:#X},len:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_MINCORE => {
+            let ret = sys_mincore(tf.arg0(),tf.arg1(),tf.arg2());
+            trace_sync!("mincore:vaddr:{:#X},len:{:#X},vec:{:#X},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_SYSINFO => {
+            sys_sysinfo(tf.arg0())
+        }
+        _ => {
+            panic!("mm syscall {} not impl",syscall_id);
+        }
+    };
+    tf.ret(ret as usize);
+}
+
+fn sys_swapon(path_addr:usize,flags:usize)->isize{
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    let path = convert_cstr_from_vaddr(Vaddr(path_addr));
+    let cwd = get_running().lock_irq().unwrap().get_cwd();
+    let inode = match namei(&cwd,&path,true) {
+        Err(e) => {
+            return e;
+        }
+        Ok(i) => i
+    };
+    let prio = if flags&SWAP_FLAG_PREFER != 0 {
+        (flags&SWAP_FLAG_PRIO_MASK) as isize
+    } else {
+        -1
+    };
+    let ret = match swap_blkdev(&inode) {
+        Some(Ok(dev)) => swapon_blk(&path,dev,prio),
+        Some(Err(e)) => Err(e),
+        None if inode.is_file() => swapon_file(&path,inode,prio),
+        None => Err(-EINVAL)
+    };
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 路径是块设备时返回对应的设备，可以是整个磁盘或者分区
+fn swap_blkdev(inode:&Arc<Inode>)->Option<Result<Arc<dyn BlockReadWrite>,isize>>{
+    if inode.get_type() != InodeType::BlockDev {
+        return None;
+    }
+    Some(inode.dev_id().and_then(get_blkdev).ok_or(-ENXIO))
+}
+
+fn sys_swapoff(path_addr:usize)->isize{
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    let path = convert_cstr_from_vaddr(Vaddr(path_addr));
+    let cwd = get_running().lock_irq().unwrap().get_cwd();
+    let inode = match namei(&cwd,&path,true) {
+        Err(e) => {
+            return e;
+        }
+        Ok(i) => i
+    };
+    let ret = match swap_blkdev(&inode) {
+        Some(Ok(dev)) => swapoff_blk(dev),
+        Some(Err(e)) => Err(e),
+        None => swapoff_file(inode)
+    };
+    match ret {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 不持有task锁，持有mm锁期间分配页表页时不能换出
+fn sys_munmap(va:usize,len:usize)->isize{
+    let mm = get_running_mm().unwrap();
+    let _noio = NoReclaimGuard::new();
+    let mut mm = mm.lock_irq().unwrap();
+    match mm.munmap(Vaddr(va),len) {
+        Ok(_) => 0,
+        Err(_) => -EINVAL
+    }
+}
+
+fn sys_mprotect(va:usize,len:usize,prot:usize)->isize{
+    let prot = match MmapProt::from_bits(prot) {
+        None => {
+            return -EINVAL;
+        }
+        Some(p) => p
+    };
+    if va%PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    let mm = get_running_mm().unwrap();
+    let _noio = NoReclaimGuard::new();
+    let mut mm = mm.lock_irq().unwrap();
+    match mm.mprotect(Vaddr(va),len,prot) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// 释放页时持有mm锁，MADV_WILLNEED的读入在锁外进行
+fn sys_madvise(va:usize,len:usize,advice:usize)->isize{
+    let mm = get_running_mm().unwrap();
+    let ret = {
+        let _noio = NoReclaimGuard::new();
+        mm.lock_irq().unwrap().madvise(Vaddr(va),len,advice)
+    };
+    if let Err(e) = ret {
+        return e;
+    }
+    if advice == MADV_WILLNEED {
+        // 只是提示，读入失败不返回错误
+        populate_range(&mm,Vaddr(va),len,true).ok();
+    }
+    0
+}
+
+// mlock和munlock的地址不要求页对齐
+fn sys_mlock(va:usize,len:usize,lock:bool)->isize{
+    let start = va/PAGE_SIZE*PAGE_SIZE;
+    let len = match len.checked_add(va-start) {
+        Some(l) => l,
+        None => {
+            return -ENOMEM;
+        }
+    };
+    let mm = get_running_mm().unwrap();
+    let ret = {
+        let _noio = NoReclaimGuard::new();
+        let mut mm_locked = mm.lock_irq().unwrap();
+        if lock {
+            mm_locked.mlock(Vaddr(start),len)
+        } else {
+            mm_locked.munlock(Vaddr(start),len).map(|_|{Vec::new()})
+        }
+    };
+    let locked = match ret {
+        Ok(l) => l,
+        Err(e) => {
+            return e;
+        }
+    };
+    if !lock {
+        return 0;
+    }
+    // 分配失败时恢复这次锁定的vma
+    if populate_range(&mm,Vaddr(start),len,false).is_err() {
+        mm.lock_irq().unwrap()._undo_mlock(&locked);
+        return -EAGAIN;
+    }
+    0
+}
+
+// 每次处理的页数，结果先放在栈上
+const MINCORE_CHUNK:usize = 64;
+
+fn sys_mincore(va:usize,len:usize,vec:usize)->isize{
+    let mm = get_running_mm().unwrap();
+    let end = match mm.lock_irq().unwrap().mincore_range(Vaddr(va),len) {
+        Ok(e) => e,
+        Err(e) => {
+            return e;
+        }
+    };
+    let nr = (end.0-va)/PAGE_SIZE;
+    if let Err(e) = user_access_ok(vec,nr,true) {
+        return e;
+    }
+    let mut buf = [0u8;MINCORE_CHUNK];
+    let mut done = 0;
+    while done<nr {
+        let n = min(MINCORE_CHUNK,nr-done);
+        mm.lock_irq().unwrap().mincore(Vaddr(va+done*PAGE_SIZE),&mut buf[..n]);
+        // 写用户内存可能缺页，不能持有mm锁
+        if Vaddr(vec+done).write(&buf[..n]).is_err() {
+            return -EFAULT;
+        }
+        done+=n;
+    }
+    0
+}
+
+fn sys_sysinfo(info_addr:usize)->isize{
+    let mem = get_meminfo();
+    let info = SysInfo{
+        uptime: get_time_ms()/1000,
+        totalram: mem.total,
+        freeram: mem.free,
+        bufferram: mem.file,
+        totalswap: mem.swap_total,
+        freeswap: mem.swap_free,
+        procs: all_tasks().len() as u16,
+        mem_unit: 1,
+        ..Default::default()
+    };
+    unsafe { Vaddr(info_addr).write_single(info).unwrap(); }
+    0
+}
@@ -0,0 +1,280 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use fatfs::{Read, Write};
+use crate::fs::dfile::DFile;
+use crate::fs::epoll::{EPOLL_CTL_DEL, EpollEvent};
+use crate::fs::fcntl::OpenFlags;
+use crate::fs::poll::{FD_SETSIZE, NFDBITS, PollEvents, PollFd};
+use crate::mm::addr::Vaddr;
+use crate::syscall::errno::{EBADF, EFAULT, EINVAL, EMFILE};
+use crate::syscall::sys_fs::get_file;
+use crate::syscall::sys_time::{read_timespec, timespec_to_ns, write_timespec};
+use crate::task::info::TimeSpec;
+use crate::task::wait::Waiter;
+use crate::trap::timer::get_time_ns;
+use crate::trap::TrapFrame;
+use super::*;
+
+// epoll_pwait一次最多返回的事件数
+const EP_MAX_EVENTS:usize = 1024;
+const NSEC_PER_MSEC:u64 = 1_000_000;
+
+// select的三个集合对应的poll事件
+const SELECT_READ:u32 = PollEvents::POLLIN.bits() | PollEvents::POLLRDNORM.bits() | PollEvents::POLLHUP.bits() | PollEvents::POLLERR.bits();
+const SELECT_WRITE:u32 = PollEvents::POLLOUT.bits() | PollEvents::POLLWRNORM.bits() | PollEvents::POLLERR.bits();
+const SELECT_EXCEPT:u32 = PollEvents::POLLPRI.bits();
+
+// 还没有信号屏蔽字，sigmask参数被忽略
+pub fn syscall_poll_entry(tf:&mut TrapFrame, syscall_id:usize){
+    let ret = match syscall_id {
+        SYSCALL_PPOLL => {
+            let ret = sys_ppoll(tf.arg0(),tf.arg1(),tf.arg2());
+            trace_sync!("ppoll:fds:{:#X},nfds:{},tmo:{:#X},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_PSELECT6 => {
+            let ret = sys_pselect6(tf.arg0(),tf.arg1(),tf.arg2(),tf.arg3(),tf.arg4());
+            trace_sync!("pselect6:nfds:{},tmo:{:#X},ret:{}",tf.arg0(),tf.arg4(),ret);
+            ret
+        }
+        SYSCALL_EPOLL_CREATE1 => {
+            let ret = sys_epoll_create1(tf.arg0() as u32);
+            info_sync!("epoll_create1:flags:{:#X},ret:{}",tf.arg0(),ret);
+            ret
+        }
+        SYSCALL_EPOLL_CTL => {
+            let ret = sys_epoll_ctl(tf.arg0() as isize,tf.arg1(),tf.arg2() as i32 as isize,tf.arg3());
+            info_sync!("epoll_ctl:epfd:{},op:{},fd:{},ret:{}",tf.arg0() as isize,tf.arg1(),tf.arg2() as i32,ret);
+            ret
+        }
+        SYSCALL_EPOLL_PWAIT => {
+            let ret = sys_epoll_pwait(tf.arg0() as isize,tf.arg1(),tf.arg2() as i32,tf.arg3() as i32);
+            trace_sync!("epoll_pwait:epfd:{},max:{},timeout:{},ret:{}",tf.arg0() as isize,tf.arg2() as i32,tf.arg3() as i32,ret);
+            ret
+        }
+        _ => {
+            panic!("poll syscall {} not impl",syscall_id);
+        }
+    };
+    tf.ret(ret as usize);
+}
+
+// timespec为NULL时一直等待，返回开机以来的到期时间
+fn read_deadline(tmo:usize)->Result<Option<u64>,isize>{
+    if tmo == 0 {
+        return Ok(None);
+    }
+    let ns = timespec_to_ns(&read_timespec(tmo))?;
+    Ok(Some(get_time_ns().saturating_add(ns)))
+}
+
+// 把剩余时间写回timespec
+fn write_remaining(tmo:usize,deadline:Option<u64>){
+    if let Some(d) = deadline {
+        write_timespec(tmo,TimeSpec::from_ns(d.saturating_sub(get_time_ns())));
+    }
+}
+
+// 负的fd被忽略，无效的fd返回POLLNVAL
+fn poll_fd(fd:i32,events:u16,waiter:&Arc<Waiter>)->PollEvents{
+    if fd < 0 {
+        return PollEvents::empty();
+    }
+    match get_file(fd as isize) {
+        Ok(f) => f.poll(Some(waiter)) & (PollEvents::from_bits_truncate(events as u32) | PollEvents::always()),
+        Err(_) => PollEvents::POLLNVAL
+    }
+}
+
+// 超时之后再检查一次
+fn sys_ppoll(fds:usize,nfds:usize,tmo:usize)->isize{
+    if nfds > FD_SETSIZE {
+        return -EINVAL;
+    }
+    let deadline = match read_deadline(tmo) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    let mut pfds = vec![PollFd::default();nfds];
+    for (i,p) in pfds.iter_mut().enumerate() {
+        Vaddr(fds+i*size_of::<PollFd>()).read(p.as_bytes_mut()).unwrap();
+    }
+    let waiter = Waiter::new();
+    let mut timed_out = false;
+    let ready = loop {
+        waiter.prepare();
+        let mut ready = 0;
+        for p in pfds.iter_mut() {
+            p.revents = poll_fd(p.fd,p.events,&waiter).bits() as u16;
+            if p.revents != 0 {
+                ready += 1;
+            }
+        }
+        if ready != 0 || timed_out {
+            break ready;
+        }
+        timed_out = waiter.sleep_until(deadline);
+    };
+    for (i,p) in pfds.iter().enumerate() {
+        Vaddr(fds+i*size_of::<PollFd>()).write(p.as_bytes()).unwrap();
+    }
+    write_remaining(tmo,deadline);
+    ready
+}
+
+// fd_set按字节读写，NULL视为空集合
+fn read_fd_set(addr:usize,words:usize)->Vec<u64>{
+    let mut set = vec![0u64;words];
+    if addr != 0 {
+        for (i,w) in set.iter_mut().enumerate() {
+            let mut bytes = [0u8;8];
+            Vaddr(addr+i*8).read(&mut bytes).unwrap();
+            *w = u64::from_ne_bytes(bytes);
+        }
+    }
+    set
+}
+
+fn write_fd_set(addr:usize,set:&[u64]){
+    if addr != 0 {
+        for (i,w) in set.iter().enumerate() {
+            Vaddr(addr+i*8).write(&w.to_ne_bytes()).unwrap();
+        }
+    }
+}
+
+// 集合中有无效的fd时返回EBADF，返回三个集合中就绪的位数
+fn sys_pselect6(nfds:usize,readfds:usize,writefds:usize,exceptfds:usize,tmo:usize)->isize{
+    if nfds > FD_SETSIZE {
+        return -EINVAL;
+    }
+    let deadline = match read_deadline(tmo) {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    let words = (nfds+NFDBITS-1)/NFDBITS;
+    let addrs = [readfds,writefds,exceptfds];
+    let masks = [SELECT_READ,SELECT_WRITE,SELECT_EXCEPT];
+    let ins:Vec<Vec<u64>> = addrs.iter().map(|a| read_fd_set(*a,words)).collect();
+    let mut files:Vec<(usize,Arc<DFile>)> = Vec::new();
+    for fd in 0..nfds {
+        let (w,bit) = (fd/NFDBITS,1u64 << (fd%NFDBITS));
+        if ins.iter().all(|s| s[w] & bit == 0) {
+            continue;
+        }
+        match get_file(fd as isize) {
+            Ok(f) => files.push((fd,f)),
+            Err(_) => {
+                return -EBADF;
+            }
+        }
+    }
+    let waiter = Waiter::new();
+    let mut timed_out = false;
+    let (outs,ready) = loop {
+        waiter.prepare();
+        let mut outs = vec![vec![0u64;words];3];
+        let mut ready = 0;
+        for (fd,f) in files.iter() {
+            let (w,bit) = (fd/NFDBITS,1u64 << (fd%NFDBITS));
+            let events = f.poll(Some(&waiter)).bits();
+            for k in 0..3 {
+                if ins[k][w] & bit != 0 && events & masks[k] != 0 {
+                    outs[k][w] |= bit;
+                    ready += 1;
+                }
+            }
+        }
+        if ready != 0 || timed_out {
+            break (outs,ready);
+        }
+        timed_out = waiter.sleep_until(deadline);
+    };
+    for k in 0..3 {
+        write_fd_set(addrs[k],&outs[k]);
+    }
+    if tmo != 0 {
+        write_remaining(tmo,deadline);
+    }
+    ready
+}
+
+// 只接受EPOLL_CLOEXEC，值与O_CLOEXEC相同
+fn sys_epoll_create1(flags:u32)->isize{
+    let cloexec = match OpenFlags::from_bits(flags) {
+        Some(f) if (f - OpenFlags::O_CLOEXEC).is_empty() => f.contains(OpenFlags::O_CLOEXEC),
+        _ => {
+            return -EINVAL;
+        }
+    };
+    let file = Arc::new(DFile::new_epoll(cloexec));
+    match get_running().lock_irq().unwrap().alloc_opened(file) {
+        Some(fd) => fd as isize,
+        None => -EMFILE
+    }
+}
+
+// epfd不是epoll或者fd就是epfd时返回EINVAL
+fn sys_epoll_ctl(epfd:isize,op:usize,fd:isize,event:usize)->isize{
+    let ep_file = match get_file(epfd) {
+        Ok(f) => f,
+        Err(e) => {
+            return e;
+        }
+    };
+    let file = match get_file(fd) {
+        Ok(f) => f,
+        Err(e) => {
+            return e;
+        }
+    };
+    let ep = match ep_file.get_epoll() {
+        Some(ep) if epfd != fd => ep,
+        _ => {
+            return -EINVAL;
+        }
+    };
+    let mut ev = EpollEvent::default();
+    if op != EPOLL_CTL_DEL {
+        if event == 0 {
+            return -EFAULT;
+        }
+        Vaddr(event).read(ev.as_bytes_mut()).unwrap();
+    }
+    match ep.ctl(op,fd as usize,&file,&ev) {
+        Ok(_) => 0,
+        Err(e) => e
+    }
+}
+
+// timeout以毫秒为单位，负数时一直等待
+fn sys_epoll_pwait(epfd:isize,events:usize,maxevents:i32,timeout:i32)->isize{
+    if maxevents <= 0 || maxevents as usize > EP_MAX_EVENTS {
+        return -EINVAL;
+    }
+    let ep = match get_file(epfd) {
+        Ok(f) => match f.get_epoll() {
+            Some(ep) => ep,
+            None => {
+                return -EINVAL;
+            }
+        },
+        Err(e) => {
+            return e;
+        }
+    };
+    let deadline = if timeout < 0 {
+        None
+    } else {
+        Some(get_time_ns() + timeout as u64*NSEC_PER_MSEC)
+    };
+    let ready = ep.wait(maxevents as usize,deadline);
+    for (i,e) in ready.iter().enumerate() {
+        Vaddr(events+i*size_of::<EpollEvent>()).write(e.as_bytes()).unwrap();
+    }
+    ready.len() as isize
+}
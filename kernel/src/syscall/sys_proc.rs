@@ -4,17 +4,22 @@ use core::arch::riscv64::fence_i;
 use fatfs::Write;
 use riscv::asm::sfence_vma_all;
 use crate::fs::dfile::DFile;
+use crate::fs::fcntl::MAY_EXEC;
 use crate::fs::inode::Inode;
+use crate::fs::namei::namei;
 use crate::mm::addr::{Addr, Vaddr};
 use crate::mm::vma::{MmapFlags, MmapProt, VMA};
 use crate::pre::{InnerAccess, ReadWriteSingleNoOff};
 use crate::{SpinLock, Task};
 use crate::mm::mm::MmStruct;
-use crate::task::{add_task, scheduler, wait_children, wait_for};
-use crate::task::info::{CloneFlags, Utsname};
-use crate::task::task::do_fork;
+use crate::task::{add_task, all_tasks, find_task, scheduler, wait_children, wait_for};
+use crate::task::cred::current_cred;
+use crate::task::info::{CloneFlags, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD, Rusage, Utsname};
+use crate::task::task::{do_fork, set_running_mm};
 use crate::task::task::TaskStatus::TaskSleeping;
 use crate::trap::TrapFrame;
+use crate::consts::PAGE_SIZE;
+use crate::syscall::errno::{EACCES, EINVAL, EPERM, ESRCH};
 use super::*;
 
 pub fn syscall_proc_entry(tf:&mut TrapFrame, syscall_id:usize) {
@@ -36,6 +41,9 @@ pub fn syscall_proc_entry(tf:&mut TrapFrame, syscall_id:usize) {
         SYSCALL_UNAME=>{
             sys_unmae(tf.arg0())
         }
+        SYSCALL_GETRUSAGE=>{
+            sys_getrusage(tf.arg0() as isize, tf.arg1())
+        }
         SYSCALL_GETPPID=>{
             sys_getppid()
         }
@@ -66,6 +74,22 @@ pub fn syscall_proc_entry(tf:&mut TrapFrame, syscall_id:usize) {
         SYSCALL_SET_TID_ADDRESS=>{
             sys_set_tid_address(tf.arg0())
         }
+        SYSCALL_SETPGID=>{
+            let ret = sys_setpgid(tf.arg0() as i32 as isize,tf.arg1() as i32 as isize);
+            info_sync!("setpgid:pid:{},pgid:{},ret:{}",tf.arg0() as i32,tf.arg1() as i32,ret);
+            ret
+        }
+        SYSCALL_GETPGID=>{
+            sys_getpgid(tf.arg0() as i32 as isize)
+        }
+        SYSCALL_GETSID=>{
+            sys_getsid(tf.arg0() as i32 as isize)
+        }
+        SYSCALL_SETSID=>{
+            let ret = sys_setsid();
+            info_sync!("setsid:ret:{}",ret);
+            ret
+        }
         _ => {
             panic!("fs syscall {} not impl",syscall_id);
         }
@@ -75,9 +99,28 @@ pub fn syscall_proc_entry(tf:&mut TrapFrame, syscall_id:usize) {
 
 fn sys_execve(path:usize, mut argv_ptr:usize, envp_ptr:usize, tf: &mut TrapFrame) ->isize{
     let running = get_running();
-    let mut this_tsk = running.lock_irq().unwrap();
     let path =convert_cstr_from_vaddr(Vaddr(path));
-    let path = this_tsk.pwd_ref().clone() + &path;
+    // 相对路径从工作目录开始解析，解析时不能持有task的锁
+    let cwd = running.lock_irq().unwrap().get_cwd();
+    let cred = current_cred();
+    // 只能执行有执行权限的普通文件
+    let node = namei(&cwd,&path,true).and_then(|node|{
+        if !node.is_file() {
+            return Err(-EACCES);
+        }
+        node.permission(&cred,MAY_EXEC)?;
+        Ok(node)
+    });
+    let node = match node {
+        Ok(v) => v,
+        Err(e) => {
+            return e;
+        }
+    };
+    let attr = node.getattr();
+    let new_cred = cred.for_exec(attr.mode,attr.uid,attr.gid);
+    let path = node.get_path();
+    let mut this_tsk = running.lock_irq().unwrap();
     let mut argv: Vec<String> = Vec::new();
     loop {
         let arg_ptr: usize = unsafe { Vaddr(argv_ptr).read_single().unwrap() };
@@ -89,7 +132,7 @@ fn sys_execve(path:usize, mut argv_ptr:usize, envp_ptr:usize, tf: &mut TrapFrame
     }
     println!("{:?}",argv);
     drop(this_tsk);
-    let tsk_opt = unsafe { Task::create_user_task(&path, argv) };
+    let tsk_opt = unsafe { Task::create_user_task(&path, argv, new_cred) };
     this_tsk = running.lock_irq().unwrap();
     let new_tsk =  match tsk_opt{
         None => {
@@ -100,6 +143,7 @@ fn sys_execve(path:usize, mut argv_ptr:usize, envp_ptr:usize, tf: &mut TrapFrame
         }
     };
     let old_mm = this_tsk.execve_from_tsk(new_tsk.clone());
+    set_running_mm(this_tsk.mm.as_ref().unwrap().clone());
     let tff = new_tsk.lock_irq().unwrap().kernel_stack.get_end() - size_of::<TrapFrame>();
     let old_sp = tf.x2;
     unsafe { *tf = (*(tff as *const TrapFrame)).clone() }
@@ -114,7 +158,8 @@ fn sys_execve(path:usize, mut argv_ptr:usize, envp_ptr:usize, tf: &mut TrapFrame
 fn sys_exit(exit_code:i32)->isize{
     let t = get_running().lock_irq().unwrap().get_tid();
     info_sync!("EXIT:tid {}",t);
-    exit_self(exit_code);
+    // exit_code保存wait4返回的status，正常退出时退出码在8-15位
+    exit_self((exit_code & 0xff) << 8);
     assert!(false);
     0
 }
@@ -150,7 +195,12 @@ fn sys_clone(flags: usize, stack_ptr: usize, ptid: usize, ctid: usize, newtls: u
     // for syscall return
     new_tf.sepc +=4;
     let running = get_running();
-    let mut new_task = do_fork(running.clone(),new_tf);
+    let mut new_task = match do_fork(running.clone(),new_tf) {
+        Ok(t) => t,
+        Err(e) => {
+            return e;
+        }
+    };
 
     if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) && ctid != 0{
         new_task.set_child_tid = ctid;
@@ -182,7 +232,7 @@ fn sys_getcwd(buf:usize,len:usize)->isize{
     if buf==0{
         return -1;
     }
-    let mut s = get_running().lock_irq().unwrap().pwd_ref().clone();
+    let mut s = get_running().lock_irq().unwrap().get_cwd().get_path();
     s.push('\0');
     if s.len()>len{
         return -1;
@@ -209,6 +259,25 @@ fn sys_getppid()->isize{
     }
 }
 
+// 没有cpu时间统计，只报告内存峰值
+fn sys_getrusage(who:isize,usage:usize)->isize{
+    let mut ru = Rusage::default();
+    match who {
+        RUSAGE_SELF|RUSAGE_THREAD => {
+            let mm = get_running().lock_irq().unwrap().mm.clone();
+            if let Some(mm) = mm {
+                ru.ru_maxrss = mm.lock_irq().unwrap().get_hiwater_rss()*PAGE_SIZE/1024;
+            }
+        }
+        RUSAGE_CHILDREN => {}
+        _ => {
+            return -EINVAL;
+        }
+    }
+    unsafe { Vaddr(usage).write_single(ru).unwrap(); }
+    0
+}
+
 fn sys_getpid()->isize{
     get_running().lock_irq().unwrap().get_tgid() as isize
 }
@@ -260,7 +329,7 @@ fn sys_mmap(va:usize,len:usize,prot:MmapProt,flags:MmapFlags,fd:usize,offset:usi
         let file_mmap_ret = mm.alloc_mmap_file(vaddr,len,
                                                inode.clone(),
                                                offset,
-                                               min(len,inode.get_dentry().len() as usize),
+                                               min(len,inode.get_size()),
                                                flags,
                                                prot);
         return match file_mmap_ret {
@@ -315,4 +384,104 @@ fn sys_brk(brk:usize)->isize{
     }
     trace_sync!("BRK arg {} ret {}",brk,ret);
     ret as isize
-}
\ No newline at end of file
+}
+// pid为0时是调用者自己，只能查找进程而不是线程
+fn find_process(pid:isize)->Result<Arc<SpinLock<Task>>,isize>{
+    if pid < 0 {
+        return Err(-ESRCH);
+    }
+    if pid == 0 {
+        return Ok(get_running());
+    }
+    let t = find_task(pid as usize).ok_or(-ESRCH)?;
+    let is_leader = {
+        let t = t.lock_irq().unwrap();
+        t.get_tid() == t.get_tgid()
+    };
+    if is_leader { Ok(t) } else { Err(-ESRCH) }
+}
+
+// 进程组和会话属于整个线程组
+fn for_each_thread(tgid:usize,mut f:impl FnMut(&mut Task)){
+    for t in all_tasks() {
+        let mut t = t.lock_irq().unwrap();
+        if t.get_tgid() == tgid {
+            f(&mut t);
+        }
+    }
+}
+
+// 只能修改自己或者子进程，不能移动会话leader，也不能加入其他会话的进程组
+// 子进程execve之后不能再修改的限制没有实现
+fn sys_setpgid(pid:isize,pgid:isize)->isize{
+    if pgid < 0 {
+        return -EINVAL;
+    }
+    let (self_tgid,self_sid) = {
+        let running = get_running();
+        let tsk = running.lock_irq().unwrap();
+        (tsk.get_tgid(),tsk.get_sid())
+    };
+    let target = match find_process(pid) {
+        Ok(t) => t,
+        Err(e) => {
+            return e;
+        }
+    };
+    let (tgid,sid,parent) = {
+        let t = target.lock_irq().unwrap();
+        (t.get_tgid(),t.get_sid(),t.get_parent())
+    };
+    if tgid != self_tgid {
+        let is_child = match parent {
+            Some(p) => p.lock_irq().unwrap().get_tgid() == self_tgid,
+            None => false
+        };
+        if !is_child {
+            return -ESRCH;
+        }
+        if sid != self_sid {
+            return -EPERM;
+        }
+    }
+    if sid == tgid {
+        return -EPERM;
+    }
+    let pgid = if pgid == 0 { tgid } else { pgid as usize };
+    if pgid != tgid {
+        let exists = all_tasks().iter().any(|t|{
+            let t = t.lock_irq().unwrap();
+            t.get_pgid() == pgid && t.get_sid() == sid
+        });
+        if !exists {
+            return -EPERM;
+        }
+    }
+    for_each_thread(tgid,|t| t.set_pgid(pgid));
+    0
+}
+
+fn sys_getpgid(pid:isize)->isize{
+    match find_process(pid) {
+        Ok(t) => t.lock_irq().unwrap().get_pgid() as isize,
+        Err(e) => e
+    }
+}
+
+fn sys_getsid(pid:isize)->isize{
+    match find_process(pid) {
+        Ok(t) => t.lock_irq().unwrap().get_sid() as isize,
+        Err(e) => e
+    }
+}
+
+// 进程组leader不能创建新会话，新会话没有控制终端
+fn sys_setsid()->isize{
+    let tgid = get_running().lock_irq().unwrap().get_tgid();
+    let is_leader = all_tasks().iter().any(|t| t.lock_irq().unwrap().get_pgid() == tgid);
+    if is_leader {
+        return -EPERM;
+    }
+    for_each_thread(tgid,|t| t.set_sid());
+    tgid as isize
+}
@@ -0,0 +1,199 @@
+use alloc::vec::Vec;
+use fatfs::{Read, Write};
+use crate::SpinLock;
+use crate::fs::dfile::DFile;
+use crate::fs::fcntl::OpenFlags;
+use crate::fs::signalfd::{SFD_CLOEXEC, SFD_NONBLOCK, SignalFd};
+use crate::mm::addr::Vaddr;
+use crate::syscall::errno::{EFAULT, EINVAL, EMFILE, EPERM, ESRCH};
+use crate::syscall::sys_fs::get_file;
+use crate::task::{all_tasks, find_task};
+use crate::task::cred::{Cred, current_cred};
+use crate::task::signal::{NSIG, send_signal, sig_default_ignore, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK, SigAction, SigSet, SIGKILL, SIGSTOP};
+use crate::task::task::{Task, TaskStatus};
+use crate::trap::TrapFrame;
+use super::*;
+
+// 用户态sigset_t的大小
+const SIGSET_SIZE:usize = 8;
+
+pub fn syscall_signal_entry(tf:&mut TrapFrame, syscall_id:usize){
+    let ret = match syscall_id {
+        SYSCALL_SIGPROCMASK => {
+            let ret = sys_rt_sigprocmask(tf.arg0(),tf.arg1(),tf.arg2(),tf.arg3());
+            trace_sync!("rt_sigprocmask:how:{},set:{:#X},oldset:{:#X},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_KILL => {
+            let ret = sys_kill(tf.arg0() as i32 as isize,tf.arg1());
+            info_sync!("kill:pid:{},sig:{},ret:{}",tf.arg0() as i32,tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_SIGACTION => {
+            let ret = sys_rt_sigaction(tf.arg0(),tf.arg1(),tf.arg2(),tf.arg3());
+            trace_sync!("rt_sigaction:sig:{},act:{:#X},oldact:{:#X},ret:{}",tf.arg0(),tf.arg1(),tf.arg2(),ret);
+            ret
+        }
+        SYSCALL_SIGNALFD4 => {
+            let ret = sys_signalfd4(tf.arg0() as i32 as isize,tf.arg1(),tf.arg2(),tf.arg3() as u32);
+            info_sync!("signalfd4:fd:{},mask:{:#X},flags:{:#X},ret:{}",tf.arg0() as i32,tf.arg1(),tf.arg3(),ret);
+            ret
+        }
+        _ => {
+            panic!("signal syscall {} not impl",syscall_id);
+        }
+    };
+    tf.ret(ret as usize);
+}
+
+// sigset_t按字节读写
+fn read_sigset(addr:usize)->SigSet{
+    let mut bytes = [0u8;SIGSET_SIZE];
+    Vaddr(addr).read(&mut bytes).unwrap();
+    SigSet::from_bits(u64::from_ne_bytes(bytes))
+}
+
+fn write_sigset(addr:usize,set:SigSet){
+    Vaddr(addr).write(&set.bits().to_ne_bytes()).unwrap();
+}
+
+// SIGKILL和SIGSTOP的屏蔽被忽略
+fn sys_rt_sigprocmask(how:usize,set:usize,oldset:usize,sigsetsize:usize)->isize{
+    if sigsetsize != SIGSET_SIZE {
+        return -EINVAL;
+    }
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    let old = tsk.blocked;
+    if set != 0 {
+        let set = read_sigset(set).without_unblockable();
+        tsk.blocked = match how {
+            SIG_BLOCK => old.union(set),
+            SIG_UNBLOCK => old.difference(set),
+            SIG_SETMASK => set,
+            _ => {
+                return -EINVAL;
+            }
+        };
+    }
+    drop(tsk);
+    if oldset != 0 {
+        write_sigset(oldset,old);
+    }
+    0
+}
+
+// fd为-1时新建signalfd，否则修改已有signalfd的mask
+fn sys_signalfd4(fd:isize,mask:usize,sizemask:usize,flags:u32)->isize{
+    if sizemask != SIGSET_SIZE || flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    if mask == 0 {
+        return -EFAULT;
+    }
+    let mask = read_sigset(mask);
+    if fd != -1 {
+        return match get_file(fd) {
+            Ok(f) => match f.get_signalfd() {
+                Some(s) => {
+                    s.set_mask(mask);
+                    fd
+                }
+                None => -EINVAL
+            },
+            Err(e) => e
+        };
+    }
+    let file = Arc::new(DFile::new_signalfd(SignalFd::new(mask),OpenFlags::from_bits_truncate(flags)));
+    match get_running().lock_irq().unwrap().alloc_opened(file) {
+        Some(fd) => fd as isize,
+        None => -EMFILE
+    }
+}
+
+// 进程的代表是线程组的leader
+fn is_process(t:&Task)->bool{
+    t.get_tid() == t.get_tgid() && t.get_status() != TaskStatus::TaskZombie
+}
+
+// pid>0为指定进程，0为调用者所在的进程组，-1为除init和调用者以外的所有进程，小于-1为进程组-pid
+fn kill_targets(pid:isize)->Vec<Arc<SpinLock<Task>>>{
+    let (tgid,pgid) = {
+        let running = get_running();
+        let tsk = running.lock_irq().unwrap();
+        (tsk.get_tgid(),tsk.get_pgid())
+    };
+    if pid > 0 {
+        return find_task(pid as usize).into_iter().filter(|t|{
+            let t = t.lock_irq().unwrap();
+            t.get_tid() == t.get_tgid()
+        }).collect();
+    }
+    all_tasks().into_iter().filter(|t|{
+        let t = t.lock_irq().unwrap();
+        is_process(&t) && match pid {
+            0 => t.get_pgid() == pgid,
+            -1 => t.get_tgid() != 1 && t.get_tgid() != tgid,
+            _ => t.get_pgid() == (-pid) as usize
+        }
+    }).collect()
+}
+
+// 特权用户或者实际/有效用户等于目标的实际/保存用户
+fn kill_permitted(cred:&Cred,target:&Cred)->bool{
+    cred.capable() || cred.uid == target.uid || cred.uid == target.suid
+        || cred.euid == target.uid || cred.euid == target.suid
+}
+
+// sig为0时只检查目标是否存在和权限
+fn sys_kill(pid:isize,sig:usize)->isize{
+    if sig > NSIG {
+        return -EINVAL;
+    }
+    let targets = kill_targets(pid);
+    if targets.is_empty() {
+        return -ESRCH;
+    }
+    let cred = current_cred();
+    let mut sent = false;
+    for t in targets.iter() {
+        let permitted = kill_permitted(&cred,t.lock_irq().unwrap().get_cred());
+        if !permitted {
+            continue;
+        }
+        sent = true;
+        if sig != 0 {
+            send_signal(t,sig);
+        }
+    }
+    if sent { 0 } else { -EPERM }
+}
+
+// 处理函数只被记录，设为忽略时丢弃已经pending的信号
+fn sys_rt_sigaction(sig:usize,act:usize,oldact:usize,sigsetsize:usize)->isize{
+    if sigsetsize != SIGSET_SIZE || sig < 1 || sig > NSIG {
+        return -EINVAL;
+    }
+    if act != 0 && (sig == SIGKILL || sig == SIGSTOP) {
+        return -EINVAL;
+    }
+    let mut new = SigAction::default();
+    if act != 0 {
+        Vaddr(act).read(new.as_bytes_mut()).unwrap();
+        new.mask = SigSet::from_bits(new.mask).without_unblockable().bits();
+    }
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    let old = tsk.sigactions[sig-1];
+    if act != 0 {
+        tsk.sigactions[sig-1] = new;
+        if new.handler == SIG_IGN || (new.handler == SIG_DFL && sig_default_ignore(sig)) {
+            tsk.pending.del(sig);
+        }
+    }
+    drop(tsk);
+    if oldact != 0 {
+        Vaddr(oldact).write(old.as_bytes()).unwrap();
+    }
+    0
+}
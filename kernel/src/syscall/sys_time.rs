@@ -0,0 +1,255 @@
+use fatfs::{Read, Write};
+use crate::mm::addr::Vaddr;
+use crate::pre::ReadWriteSingleNoOff;
+use crate::fs::dfile::DFile;
+use crate::fs::fcntl::OpenFlags;
+use crate::fs::timerfd::{TFD_CLOEXEC, TFD_NONBLOCK, TFD_TIMER_ABSTIME, TFD_TIMER_CANCEL_ON_SET, TimerFd};
+use crate::syscall::errno::{EFAULT, EINVAL, EMFILE, EPERM};
+use crate::task::cred::current_cred;
+use crate::syscall::sys_fs::get_file;
+use crate::task::info::{TimeSpec, TimeVal};
+use crate::trap::timer::{get_realtime_ns, get_time_ns, get_time_res_ns, set_realtime_ns, NSEC_PER_SEC};
+use crate::trap::TrapFrame;
+use super::*;
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_MONOTONIC_RAW: usize = 4;
+pub const CLOCK_REALTIME_COARSE: usize = 5;
+pub const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+const NSEC_PER_USEC: u64 = 1000;
+
+pub fn syscall_time_entry(tf:&mut TrapFrame, syscall_id:usize){
+    let ret = match syscall_id {
+        SYSCALL_CLOCK_GETTIME => {
+            let ret = sys_clock_gettime(tf.arg0(),tf.arg1());
+            trace_sync!("clock_gettime:clock:{},tp:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_CLOCK_SETTIME => {
+            let ret = sys_clock_settime(tf.arg0(),tf.arg1());
+            info_sync!("clock_settime:clock:{},tp:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_CLOCK_GETRES => {
+            sys_clock_getres(tf.arg0(),tf.arg1())
+        }
+        SYSCALL_GET_TIME_OF_DAY => {
+            sys_gettimeofday(tf.arg0(),tf.arg1())
+        }
+        SYSCALL_TIMERFD_CREATE => {
+            let ret = sys_timerfd_create(tf.arg0(),tf.arg1() as u32);
+            info_sync!("timerfd_create:clock:{},flags:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        SYSCALL_TIMERFD_SETTIME => {
+            sys_timerfd_settime(tf.arg0() as isize,tf.arg1() as u32,tf.arg2(),tf.arg3())
+        }
+        SYSCALL_TIMERFD_GETTIME => {
+            sys_timerfd_gettime(tf.arg0() as isize,tf.arg1())
+        }
+        SYSCALL_SET_TIME_OF_DAY => {
+            let ret = sys_settimeofday(tf.arg0(),tf.arg1());
+            info_sync!("settimeofday:tv:{:#X},tz:{:#X},ret:{}",tf.arg0(),tf.arg1(),ret);
+            ret
+        }
+        _ => {
+            panic!("time syscall {} not impl",syscall_id);
+        }
+    };
+    tf.ret(ret as usize);
+}
+
+// 不支持的时钟返回None
+fn clock_now_ns(clock:usize)->Option<u64>{
+    match clock {
+        CLOCK_REALTIME|CLOCK_REALTIME_COARSE => Some(get_realtime_ns()),
+        // 没有休眠，BOOTTIME与MONOTONIC相同
+        CLOCK_MONOTONIC|CLOCK_MONOTONIC_RAW|CLOCK_MONOTONIC_COARSE|CLOCK_BOOTTIME => Some(get_time_ns()),
+        _ => None
+    }
+}
+
+pub(super) fn read_timespec(addr:usize)->TimeSpec{
+    let mut ts = TimeSpec::default();
+    Vaddr(addr).read(ts.as_bytes_mut()).unwrap();
+    ts
+}
+
+pub(super) fn write_timespec(addr:usize,ts:TimeSpec){
+    Vaddr(addr).write(ts.as_bytes()).unwrap();
+}
+
+pub(super) fn timespec_to_ns(ts:&TimeSpec)->Result<u64,isize>{
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= NSEC_PER_SEC as i64 {
+        return Err(-EINVAL);
+    }
+    (ts.tv_sec as u64).checked_mul(NSEC_PER_SEC)
+        .and_then(|ns| ns.checked_add(ts.tv_nsec as u64))
+        .ok_or(-EINVAL)
+}
+
+fn sys_clock_gettime(clock:usize,tp:usize)->isize{
+    let ns = match clock_now_ns(clock) {
+        Some(v) => v,
+        None => {
+            return -EINVAL;
+        }
+    };
+    if tp == 0 {
+        return -EFAULT;
+    }
+    write_timespec(tp,TimeSpec::from_ns(ns));
+    0
+}
+
+// 只有墙上时间可以修改
+fn sys_clock_settime(clock:usize,tp:usize)->isize{
+    if clock != CLOCK_REALTIME {
+        return -EINVAL;
+    }
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    if tp == 0 {
+        return -EFAULT;
+    }
+    match timespec_to_ns(&read_timespec(tp)) {
+        Ok(ns) => {
+            set_realtime_ns(ns);
+            0
+        }
+        Err(e) => e
+    }
+}
+
+// res可以为NULL，此时只检查时钟是否存在
+fn sys_clock_getres(clock:usize,res:usize)->isize{
+    if clock_now_ns(clock).is_none() {
+        return -EINVAL;
+    }
+    if res != 0 {
+        write_timespec(res,TimeSpec::from_ns(get_time_res_ns()));
+    }
+    0
+}
+
+// 不支持时区，tz总是填0
+fn sys_gettimeofday(tv:usize,tz:usize)->isize{
+    let ns = get_realtime_ns();
+    if tv != 0 {
+        let val = TimeVal{
+            sec: (ns/NSEC_PER_SEC) as usize,
+            usec: (ns%NSEC_PER_SEC/NSEC_PER_USEC) as usize
+        };
+        unsafe {
+            Vaddr(tv).write_single(val.sec).unwrap();
+            Vaddr(tv+8).write_single(val.usec).unwrap();
+        }
+    }
+    if tz != 0 {
+        unsafe { Vaddr(tz).write_single(0u64).unwrap(); }
+    }
+    0
+}
+
+// tz被忽略
+fn sys_settimeofday(tv:usize,_tz:usize)->isize{
+    if !current_cred().capable() {
+        return -EPERM;
+    }
+    if tv == 0 {
+        return 0;
+    }
+    let (sec,usec):(i64,i64) = unsafe {
+        (Vaddr(tv).read_single().unwrap(),Vaddr(tv+8).read_single().unwrap())
+    };
+    if usec < 0 || usec >= (NSEC_PER_SEC/NSEC_PER_USEC) as i64 {
+        return -EINVAL;
+    }
+    let ts = TimeSpec{
+        tv_sec: sec,
+        tv_nsec: usec*NSEC_PER_USEC as i64
+    };
+    match timespec_to_ns(&ts) {
+        Ok(ns) => {
+            set_realtime_ns(ns);
+            0
+        }
+        Err(e) => e
+    }
+}
+
+// 只支持不需要唤醒能力的时钟
+fn sys_timerfd_create(clock:usize,flags:u32)->isize{
+    let realtime = match clock {
+        CLOCK_REALTIME => true,
+        CLOCK_MONOTONIC|CLOCK_BOOTTIME => false,
+        _ => {
+            return -EINVAL;
+        }
+    };
+    if flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    let file = Arc::new(DFile::new_timerfd(TimerFd::new(realtime),OpenFlags::from_bits_truncate(flags)));
+    match get_running().lock_irq().unwrap().alloc_opened(file) {
+        Some(fd) => fd as isize,
+        None => -EMFILE
+    }
+}
+
+// struct itimerspec是it_interval和it_value两个timespec
+fn write_itimerspec(addr:usize,(interval,value):(u64,u64)){
+    write_timespec(addr,TimeSpec::from_ns(interval));
+    write_timespec(addr+size_of::<TimeSpec>(),TimeSpec::from_ns(value));
+}
+
+fn get_timerfd(fd:isize)->Result<Arc<TimerFd>,isize>{
+    get_file(fd)?.get_timerfd().ok_or(-EINVAL)
+}
+
+// 不支持墙上时间修改时取消，TFD_TIMER_CANCEL_ON_SET只做参数检查
+fn sys_timerfd_settime(fd:isize,flags:u32,new:usize,old:usize)->isize{
+    if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return -EINVAL;
+    }
+    if new == 0 {
+        return -EFAULT;
+    }
+    let t = match get_timerfd(fd) {
+        Ok(t) => t,
+        Err(e) => {
+            return e;
+        }
+    };
+    let interval = timespec_to_ns(&read_timespec(new));
+    let value = timespec_to_ns(&read_timespec(new+size_of::<TimeSpec>()));
+    let (interval,value) = match (interval,value) {
+        (Ok(i),Ok(v)) => (i,v),
+        _ => {
+            return -EINVAL;
+        }
+    };
+    let prev = t.settime(flags & TFD_TIMER_ABSTIME != 0,interval,value);
+    if old != 0 {
+        write_itimerspec(old,prev);
+    }
+    0
+}
+
+fn sys_timerfd_gettime(fd:isize,curr:usize)->isize{
+    let t = match get_timerfd(fd) {
+        Ok(t) => t,
+        Err(e) => {
+            return e;
+        }
+    };
+    if curr == 0 {
+        return -EFAULT;
+    }
+    write_itimerspec(curr,t.gettime());
+    0
+}
@@ -0,0 +1,104 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::task::info::{S_ISGID, S_ISUID, S_IXGRP};
+use crate::task::task::get_running;
+use crate::info_sync;
+
+// 进程的身份
+// fork时复制，execve时根据可执行文件的setuid/setgid位修改有效id
+// 文件访问检查使用fsuid和fsgid，它们跟随有效id变化
+pub const NGROUPS_MAX:usize = 65536;
+
+#[derive(Clone)]
+pub struct Cred{
+    pub uid:u32,
+    pub euid:u32,
+    pub suid:u32,
+    pub fsuid:u32,
+    pub gid:u32,
+    pub egid:u32,
+    pub sgid:u32,
+    pub fsgid:u32,
+    // 附加组
+    pub groups:Vec<u32>,
+}
+
+impl Cred {
+    pub fn root()->Self{
+        Self{
+            uid: 0,
+            euid: 0,
+            suid: 0,
+            fsuid: 0,
+            gid: 0,
+            egid: 0,
+            sgid: 0,
+            fsgid: 0,
+            groups: Vec::new()
+        }
+    }
+    // 没有capability，有效用户为root时拥有所有特权
+    pub fn capable(&self)->bool{
+        self.euid == 0
+    }
+    // 文件访问时的特权
+    pub fn fs_capable(&self)->bool{
+        self.fsuid == 0
+    }
+    pub fn in_group(&self,gid:u32)->bool{
+        self.fsgid == gid || self.groups.contains(&gid)
+    }
+    // execve后的身份，保存的id总是等于新的有效id
+    // setgid位只在组有执行权限时有效，否则表示强制锁
+    pub fn for_exec(&self,mode:u32,file_uid:u32,file_gid:u32)->Self{
+        let mut new = self.clone();
+        if mode & S_ISUID != 0 {
+            new.euid = file_uid;
+        }
+        if mode & S_ISGID != 0 && mode & S_IXGRP != 0 {
+            new.egid = file_gid;
+        }
+        new.suid = new.euid;
+        new.fsuid = new.euid;
+        new.sgid = new.egid;
+        new.fsgid = new.egid;
+        new
+    }
+    pub fn is_setid(&self)->bool{
+        self.uid != self.euid || self.gid != self.egid
+    }
+}
+
+// 不能在持有当前task的锁时调用
+pub fn current_cred()->Cred{
+    get_running().lock_irq().unwrap().get_cred().clone()
+}
+
+pub fn current_umask()->u32{
+    get_running().lock_irq().unwrap().get_umask()
+}
+
+// 普通用户执行setuid/setgid程序后的身份
+pub fn cred_test(){
+    let user = Cred{
+        uid: 1000, euid: 1000, suid: 1000, fsuid: 1000,
+        gid: 100, egid: 100, sgid: 100, fsgid: 100,
+        groups: vec![10,20]
+    };
+    assert!(!user.capable() && !user.fs_capable() && !user.is_setid());
+    assert!(user.in_group(100) && user.in_group(20) && !user.in_group(0));
+
+    let c = user.for_exec(S_ISUID|0o755,0,50);
+    assert_eq!((c.uid,c.euid,c.suid,c.fsuid),(1000,0,0,0));
+    assert_eq!((c.gid,c.egid),(100,100));
+    assert!(c.capable() && c.is_setid());
+
+    let c = user.for_exec(S_ISGID|0o755,0,50);
+    assert_eq!((c.euid,c.egid,c.sgid,c.fsgid),(1000,50,50,50));
+    assert!(!c.capable() && c.is_setid() && c.in_group(50));
+    // 组没有执行权限时setgid位表示强制锁
+    let c = user.for_exec(S_ISGID|0o745,0,50);
+    assert_eq!(c.egid,100);
+    assert!(!c.is_setid());
+    info_sync!("cred test OK!");
+}
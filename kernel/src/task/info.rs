@@ -27,8 +27,11 @@ pub const F_DUPFD: u32 = 0; /*  dup the fd using the lowest-numbered
 pub const F_GETFD: u32 = 1; /* fd flag */
 pub const F_SETFD: u32 = 2;
 pub const F_GETFL: u32 = 3;
+pub const F_SETFL: u32 = 4;
 
 pub const F_DUPFD_CLOEXEC: u32 = 1030;  /* Duplicate file descriptor with close-on-exit set.*/
+pub const F_SETPIPE_SZ: u32 = 1031;
+pub const F_GETPIPE_SZ: u32 = 1032;
 
 /* arg */
 pub const FD_CLOEXEC: u32 = 1;
@@ -186,8 +189,8 @@ impl NewStat {
                      st_ino  :u64,
                      st_mode :u32,
                      st_nlink:u64,
-                     //st_uid  :u32,
-                     //st_gid  :u32,
+                     st_uid  :u32,
+                     st_gid  :u32,
                      //st_rdev :u64,
                      st_size :i64,
                      //st_blksize   :u32,
@@ -209,8 +212,8 @@ impl NewStat {
             st_mode,
             st_nlink:st_nlink as u32,
             //st_nlink,
-            st_uid  :0,
-            st_gid  :0,
+            st_uid,
+            st_gid,
             //st_rdev :0,
             //__pad2  :0,
             st_size : st_size as u64,
@@ -245,3 +248,92 @@ impl NewStat {
         }
     }
 }
+
+/* getrusage */
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TimeSpec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+// 用户传入的timespec只保证8字节对齐，按字节读写
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / crate::trap::timer::NSEC_PER_SEC) as i64,
+            tv_nsec: (ns % crate::trap::timer::NSEC_PER_SEC) as i64,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const _ as usize as *const u8,
+                size,
+            )
+        }
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self as *mut _ as usize as *mut u8,
+                size,
+            )
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Rusage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_maxrss: usize,   // kB
+    pub ru_ixrss: usize,
+    pub ru_idrss: usize,
+    pub ru_isrss: usize,
+    pub ru_minflt: usize,
+    pub ru_majflt: usize,
+    pub ru_nswap: usize,
+    pub ru_inblock: usize,
+    pub ru_oublock: usize,
+    pub ru_msgsnd: usize,
+    pub ru_msgrcv: usize,
+    pub ru_nsignals: usize,
+    pub ru_nvcsw: usize,
+    pub ru_nivcsw: usize,
+}
+
+/* sysinfo */
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct SysInfo {
+    pub uptime: usize,
+    pub loads: [usize; 3],
+    pub totalram: usize,
+    pub freeram: usize,
+    pub sharedram: usize,
+    pub bufferram: usize,
+    pub totalswap: usize,
+    pub freeswap: usize,
+    pub procs: u16,
+    pub pad: u16,
+    pub totalhigh: usize,
+    pub freehigh: usize,
+    pub mem_unit: u32,
+}
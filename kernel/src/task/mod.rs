@@ -1,27 +1,33 @@
-use alloc::collections::LinkedList;
-use alloc::string::String;
-use alloc::sync::Arc;
+use alloc::collections::{BTreeMap, LinkedList};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::global_asm;
 use core::arch::riscv64::fence_i;
 use core::cell::RefCell;
+use core::fmt::Write;
 use core::hint::spin_loop;
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use log::error;
 
 use crate::{error_sync, info_sync, println, SpinLock};
+use crate::asm::{disable_irq, enable_irq};
 use crate::mm::mm::MmStruct;
 use crate::mm::pagetable::PageTable;
 use crate::sbi::shutdown;
 use crate::task::task::{get_running, set_running, Task, TaskContext, TaskStatus};
 use crate::task::task::TaskStatus::{TaskRunning, TaskZombie};
+use crate::task::signal::{send_signal, SIGCHLD};
 use crate::trap::TrapFrame;
 
 pub(crate) mod task;
 pub(crate) mod stack;
 pub(crate) mod info;
+pub(crate) mod cred;
+pub(crate) mod signal;
+pub(crate) mod wait;
 
 extern "C" {
     fn switch_context(cur: *const TaskContext, next: *const TaskContext);
@@ -36,6 +42,8 @@ lazy_static! {
     static ref running_list : SpinLock<LinkedList<Arc<SpinLock<Task>>>> = SpinLock::new(LinkedList::new());
     static ref sleep_list : SpinLock<LinkedList<Arc<SpinLock<Task>>>> = SpinLock::new(LinkedList::new());
     static ref exit_list : SpinLock<LinkedList<Arc<SpinLock<Task>>>> = SpinLock::new(LinkedList::new());
+    // 所有task的索引，sleep在pipe等队列上的task不在上面的list中
+    static ref task_table : SpinLock<BTreeMap<usize,Weak<SpinLock<Task>>>> = SpinLock::new(BTreeMap::new());
 }
 
 fn wake_up_all_sleeping(){
@@ -55,17 +63,112 @@ fn current() -> Option<Arc<SpinLock<Task>>> {
     running_list.lock().unwrap().front().map(|arc_task| arc_task.clone())
 }
 
+// 当前task sleep后是否还有task可以运行，没有idle task，启动阶段不能sleep
+pub fn others_runnable() -> bool {
+    running_list.lock_irq().unwrap().len() > 1
+}
+
 pub fn add_task(task: Arc<SpinLock<Task>>) {
-    running_list.lock().unwrap().push_back(task);
+    let tid = task.lock_irq().unwrap().get_tid();
+    task_table.lock_irq().unwrap().insert(tid,Arc::downgrade(&task));
+    // 可能在时钟中断的定时器回调中调用
+    running_list.lock_irq().unwrap().push_back(task);
+}
+
+// 返回所有未释放的task，按tid排序
+pub fn all_tasks() -> Vec<Arc<SpinLock<Task>>> {
+    let mut table = task_table.lock_irq().unwrap();
+    let mut ret = Vec::new();
+    table.retain(|_,t|{
+        match t.upgrade() {
+            None => false,
+            Some(t) => {
+                ret.push(t);
+                true
+            }
+        }
+    });
+    ret
+}
+
+pub fn find_task(tid:usize) -> Option<Arc<SpinLock<Task>>> {
+    task_table.lock_irq().unwrap().get(&tid).and_then(|t|{t.upgrade()})
+}
+
+// 命令名取argv[0]的文件名部分，没有参数时取可执行文件名
+fn task_comm(task:&Task)->String{
+    let name = match task.get_cmdline().first() {
+        Some(arg) => String::from(arg.as_str()),
+        None => task.get_exe().map_or(String::from("kthread"),|e|{String::from(e.get_name())})
+    };
+    match name.rsplit_once('/') {
+        Some((_,base)) => String::from(base),
+        None => name
+    }
+}
+
+// /proc/<pid>/stat格式，没有统计的字段填0
+pub fn task_stat_text(task:&Arc<SpinLock<Task>>)->String{
+    let (tid,comm,status,parent,mm,pgid,sid) = {
+        let t = task.lock_irq().unwrap();
+        (t.get_tid(),task_comm(&t),t.get_status(),t.get_parent(),t.mm.clone(),t.get_pgid(),t.get_sid())
+    };
+    let ppid = parent.map_or(0,|p|{p.lock_irq().unwrap().get_tgid()});
+    let state = match status {
+        TaskStatus::TaskRunning => 'R',
+        TaskStatus::TaskSleeping => 'S',
+        TaskStatus::TaskZombie => 'Z',
+    };
+    let (vsize,rss) = mm.map_or((0,0),|mm|{
+        let mm = mm.lock_irq().unwrap();
+        (mm.get_vsz()*crate::consts::PAGE_SIZE,mm.get_rss())
+    });
+    let mut s = String::new();
+    writeln!(s,"{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 0 0 0 0 20 0 1 0 0 {} {}",
+             tid,comm,state,ppid,pgid,sid,vsize,rss).unwrap();
+    s
+}
+
+// /proc/<pid>/status格式
+pub fn task_status_text(task:&Arc<SpinLock<Task>>)->String{
+    let (tid,tgid,status,parent,mm,cred) = {
+        let t = task.lock_irq().unwrap();
+        (t.get_tid(),t.get_tgid(),t.get_status(),t.get_parent(),t.mm.clone(),t.get_cred().clone())
+    };
+    // 不能同时持有两个task的锁
+    let ppid = parent.map_or(0,|p|{p.lock_irq().unwrap().get_tgid()});
+    let state = match status {
+        TaskStatus::TaskRunning => "R (running)",
+        TaskStatus::TaskSleeping => "S (sleeping)",
+        TaskStatus::TaskZombie => "Z (zombie)",
+    };
+    let mut s = String::new();
+    writeln!(s,"State:\t{}",state).unwrap();
+    writeln!(s,"Tgid:\t{}",tgid).unwrap();
+    writeln!(s,"Pid:\t{}",tid).unwrap();
+    writeln!(s,"PPid:\t{}",ppid).unwrap();
+    writeln!(s,"Uid:\t{}\t{}\t{}\t{}",cred.uid,cred.euid,cred.suid,cred.fsuid).unwrap();
+    writeln!(s,"Gid:\t{}\t{}\t{}\t{}",cred.gid,cred.egid,cred.sgid,cred.fsgid).unwrap();
+    let groups:Vec<String> = cred.groups.iter().map(|g| g.to_string()).collect();
+    writeln!(s,"Groups:\t{}",groups.join(" ")).unwrap();
+    if let Some(mm) = mm {
+        s.push_str(&mm.lock_irq().unwrap().status_text());
+    }
+    s
 }
 
+// 退出时向父进程发送SIGCHLD
 pub fn exit_self(exit_code:i32){
     let this_task = get_running();
     let mut tsk = this_task.lock_irq().unwrap();
     tsk.exit_code = exit_code;
     tsk.set_status(TaskZombie);
+    let parent = tsk.get_parent();
     wake_up_all_sleeping();
     drop(tsk);
+    if let Some(p) = parent {
+        send_signal(&p,SIGCHLD);
+    }
     scheduler(None);
 }
 
@@ -74,6 +177,13 @@ pub fn sleep_self_in_sleeping_list(){
     scheduler(None);
 }
 
+// 让出cpu但保持可运行，内核线程中调用时先关中断，避免时钟中断重入scheduler
+pub fn yield_self(){
+    let irq = disable_irq();
+    scheduler(None);
+    enable_irq(irq);
+}
+
 pub fn wait_for(tid:usize)->(i32,usize){
     loop {
         let mut locked_sl = sleep_list.lock_irq().unwrap();
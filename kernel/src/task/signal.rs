@@ -0,0 +1,185 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::SpinLock;
+use crate::task::{all_tasks, exit_self};
+use crate::task::task::{get_running, Task};
+use crate::task::task::TaskStatus::TaskZombie;
+
+// 还不能调用用户的信号处理函数，sigaction只记录处置方式
+// 发送的信号记录在task的pending中，返回用户态之前处理
+pub const SIGHUP:usize = 1;
+pub const SIGINT:usize = 2;
+pub const SIGQUIT:usize = 3;
+pub const SIGKILL:usize = 9;
+pub const SIGPIPE:usize = 13;
+pub const SIGTERM:usize = 15;
+pub const SIGCHLD:usize = 17;
+pub const SIGCONT:usize = 18;
+pub const SIGSTOP:usize = 19;
+pub const SIGTSTP:usize = 20;
+pub const SIGTTIN:usize = 21;
+pub const SIGTTOU:usize = 22;
+pub const SIGURG:usize = 23;
+pub const SIGWINCH:usize = 28;
+pub const NSIG:usize = 64;
+
+// rt_sigprocmask how
+pub const SIG_BLOCK:usize = 0;
+pub const SIG_UNBLOCK:usize = 1;
+pub const SIG_SETMASK:usize = 2;
+
+// sa_handler的特殊值
+pub const SIG_DFL:usize = 0;
+pub const SIG_IGN:usize = 1;
+
+// riscv上内核的struct sigaction，没有sa_restorer
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct SigAction{
+    pub handler:usize,
+    pub flags:usize,
+    pub mask:u64,
+}
+
+impl SigAction {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8,core::mem::size_of::<Self>()) }
+    }
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8,core::mem::size_of::<Self>()) }
+    }
+}
+
+// 信号集合，第sig-1位表示信号sig
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct SigSet(u64);
+
+impl SigSet {
+    pub fn empty()->Self{
+        Self(0)
+    }
+    pub fn from_bits(bits:u64)->Self{
+        Self(bits)
+    }
+    pub fn bits(&self)->u64{
+        self.0
+    }
+    // SIGKILL和SIGSTOP不能被屏蔽或者由signalfd接收
+    pub fn without_unblockable(&self)->Self{
+        Self(self.0 & !(1 << (SIGKILL-1)) & !(1 << (SIGSTOP-1)))
+    }
+    pub fn intersect(&self,other:SigSet)->Self{
+        Self(self.0 & other.0)
+    }
+    pub fn union(&self,other:SigSet)->Self{
+        Self(self.0 | other.0)
+    }
+    pub fn difference(&self,other:SigSet)->Self{
+        Self(self.0 & !other.0)
+    }
+    pub fn add(&mut self,sig:usize){
+        self.0 |= 1 << (sig-1);
+    }
+    pub fn del(&mut self,sig:usize){
+        self.0 &= !(1 << (sig-1));
+    }
+    pub fn contains(&self,sig:usize)->bool{
+        self.0 & (1 << (sig-1)) != 0
+    }
+    pub fn is_empty(&self)->bool{
+        self.0 == 0
+    }
+    // 编号最小的信号
+    pub fn first(&self)->Option<usize>{
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize + 1)
+        }
+    }
+}
+
+// 默认动作为忽略的信号
+// 还不支持停止进程，默认动作为停止的信号也被忽略
+pub fn sig_default_ignore(sig:usize)->bool{
+    matches!(sig,SIGCHLD|SIGCONT|SIGURG|SIGWINCH|SIGSTOP|SIGTSTP|SIGTTIN|SIGTTOU)
+}
+
+// 送达后什么也不做的信号，设置了处理函数的信号也按忽略处理
+fn sig_ignored(task:&Task,sig:usize)->bool{
+    match task.sigactions[sig-1].handler {
+        SIG_DFL => sig_default_ignore(sig),
+        _ => sig != SIGKILL && sig != SIGSTOP
+    }
+}
+
+// 没有被屏蔽时丢弃会被忽略的信号，避免打断阻塞的系统调用
+// 唤醒在signalfd上等待的task
+pub fn send_signal(task:&Arc<SpinLock<Task>>,sig:usize){
+    assert!(sig >= 1 && sig <= NSIG);
+    let mut tsk = task.lock_irq().unwrap();
+    let handler = tsk.sigactions[sig-1].handler;
+    if !tsk.blocked.contains(sig) && (handler == SIG_IGN || (handler == SIG_DFL && sig_default_ignore(sig))) {
+        return;
+    }
+    tsk.pending.add(sig);
+    let wq = tsk.sig_wait.clone();
+    drop(tsk);
+    wq.wake_all();
+}
+
+pub fn send_signal_self(sig:usize){
+    send_signal(&get_running(),sig);
+}
+
+// 进程组中未退出的task，pgrp为0时为空
+pub fn pgrp_tasks(pgrp:usize)->Vec<Arc<SpinLock<Task>>>{
+    if pgrp == 0 {
+        return Vec::new();
+    }
+    all_tasks().into_iter().filter(|t|{
+        let t = t.lock_irq().unwrap();
+        t.get_pgid() == pgrp && t.get_status() != TaskZombie
+    }).collect()
+}
+
+// 终端产生的信号发给前台进程组，返回是否有接收者
+pub fn send_signal_pgrp(pgrp:usize,sig:usize)->bool{
+    let tasks = pgrp_tasks(pgrp);
+    for t in tasks.iter() {
+        send_signal(t,sig);
+    }
+    !tasks.is_empty()
+}
+
+// 有未屏蔽的待处理信号，阻塞的系统调用据此返回EINTR
+pub fn signal_pending()->bool{
+    let running = get_running();
+    let tsk = running.lock_irq().unwrap();
+    !tsk.pending.difference(tsk.blocked).is_empty()
+}
+
+// 从当前task取出mask中编号最小的待处理信号
+pub fn dequeue_signal(mask:SigSet)->Option<usize>{
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    let sig = tsk.pending.intersect(mask).first()?;
+    tsk.pending.del(sig);
+    Some(sig)
+}
+
+// 系统调用返回前处理当前task未被屏蔽的信号，被屏蔽的信号保持pending
+// 默认动作为终止的信号使进程退出，wait4得到的status是信号编号
+pub fn do_signal(){
+    let running = get_running();
+    let mut tsk = running.lock_irq().unwrap();
+    while let Some(sig) = tsk.pending.difference(tsk.blocked).first() {
+        tsk.pending.del(sig);
+        if sig_ignored(&tsk,sig) {
+            continue;
+        }
+        drop(tsk);
+        exit_self(sig as i32);
+        unreachable!();
+    }
+}
@@ -1,9 +1,11 @@
 use alloc::sync::Arc;
 use core::mem::size_of;
-use crate::consts::{KERNEL_STACK_SIZE_ORDER, PAGE_SIZE, STACK_MAGIC};
+use crate::consts::{KERNEL_STACK_SIZE, KERNEL_STACK_SIZE_ORDER, PAGE_SIZE};
 use crate::info_sync;
-use crate::mm::alloc_pages;
+use crate::mm::{alloc_pages, get_kernel_pagetable};
+use crate::mm::addr::Vaddr;
 use crate::mm::page::Page;
+use crate::mm::stat::{mem_stat_add, mem_stat_sub, MemStatItem};
 use crate::pre::{InnerAccess, ReadWriteSingleNoOff, ReadWriteSingleOff};
 use crate::utils::order2pages;
 
@@ -14,6 +16,7 @@ pub struct Stack{
 }
 
 impl Stack {
+    // 栈最低的一页作为guard page，溢出时触发page fault
     pub fn new(is_boot_task:bool,start:usize,end:usize)->Self{
         let s = Stack{
             start,
@@ -22,10 +25,13 @@ impl Stack {
                 None
             } else {
                 let mut p = Some(alloc_pages(KERNEL_STACK_SIZE_ORDER).unwrap());
-                unsafe { p.as_mut().unwrap().front().write_single_off(STACK_MAGIC as u64, 0); }
+                mem_stat_add(MemStatItem::KernelStack,order2pages(KERNEL_STACK_SIZE_ORDER));
                 p
             }
         };
+        // trap入口按栈大小对齐检查溢出
+        assert_eq!(s.get_guard().get_inner()%KERNEL_STACK_SIZE,0);
+        get_kernel_pagetable().unmap_kernel_guard(s.get_guard()).unwrap();
         s
     }
     pub fn new_by_copy_from(old:&Self)->Self{
@@ -34,10 +40,10 @@ impl Stack {
         let old_pgs = old.pages.as_ref().unwrap();
         let new_pgs = new.pages.as_ref().unwrap();
         assert_eq!(old_pgs.get_order(), new_pgs.get_order());
-        let len = order2pages(old_pgs.get_order())*PAGE_SIZE;
-        let ll = old.get_end() - old.get_start();
-        let old_start = old_pgs.get_vaddr();
-        let new_start = new_pgs.get_vaddr();
+        // guard page不能访问，不需要复制
+        let len = old.get_end() - old.get_start();
+        let old_start = Vaddr(old.get_start());
+        let new_start = Vaddr(new.get_start());
         let mut i = 0;
         while i < len {
             unsafe {
@@ -48,27 +54,24 @@ impl Stack {
         }
         new
     }
-    pub unsafe fn _check_magic(&self)->bool{
-        let mut magic:u64 = 0;
+    fn get_guard(&self)->Vaddr{
         match &self.pages {
             None => {
-                magic = (self.start as *const u64).read_volatile();
+                Vaddr(self.start)
             }
             Some(p) => {
-                magic = p.front().read_single_off(0).unwrap();
+                p.front().get_vaddr()
             }
         }
-        magic == STACK_MAGIC
     }
+    // sp是否位于guard page中
+    pub fn in_guard(&self,sp:usize)->bool{
+        let g = self.get_guard().get_inner();
+        sp >= g && sp < g+PAGE_SIZE
+    }
+    // 可用部分的起始地址，不包括guard page
     pub fn get_start(&self)->usize{
-        match &self.pages {
-            None => {
-                self.start
-            }
-            Some(p) => {
-                p.front().get_vaddr().get_inner()
-            }
-        }
+        self.get_guard().get_inner()+PAGE_SIZE
     }
     pub fn get_end(&self)->usize{
         match &self.pages {
@@ -82,3 +85,12 @@ impl Stack {
     }
 }
 
+impl Drop for Stack {
+    fn drop(&mut self) {
+        // 页面释放前恢复direct map中的映射
+        get_kernel_pagetable().remap_kernel_guard(self.get_guard()).unwrap();
+        if self.pages.is_some() {
+            mem_stat_sub(MemStatItem::KernelStack,order2pages(KERNEL_STACK_SIZE_ORDER));
+        }
+    }
+}
@@ -9,10 +9,11 @@ use core::mem;
 use core::mem::size_of;
 use core::ops::{Add, Index};
 use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
+use core::sync::atomic::AtomicUsize;
 use fatfs::Read;
 use log::error;
 use crate::asm::{disable_irq, enable_irq, r_sp, r_sstatus, r_tp, SSTATUS_SIE, SSTATUS_SPIE, SSTATUS_SPP};
-use crate::consts::{BOOT_STACK_NR_PAGES, MAX_ORDER, PAGE_SIZE, STACK_MAGIC, USER_STACK_MAX_ADDR};
+use crate::consts::{BOOT_STACK_NR_PAGES, MAX_ORDER, PAGE_SIZE, USER_STACK_MAX_ADDR};
 use crate::mm::mm::{MmStruct, new_mm_by_old};
 use crate::mm::pagetable::PageTable;
 use crate::{error_sync, info_sync, println, SpinLock, trace_sync, warn_sync};
@@ -23,10 +24,13 @@ use crate::mm::aux::*;
 use xmas_elf::symbol_table::Visibility::Default;
 use crate::fs::dfile::{ DFile};
 use crate::fs::dfile::DFILE_TYPE::DFTYPE_STDIN;
-use crate::fs::fat::get_fatfs;
 use crate::fs::fcntl::OpenFlags;
-use crate::fs::get_dentry_from_dir;
 use crate::fs::inode::{ Inode};
+use crate::fs::superblock::PinnedInode;
+use crate::io::tty::console_tty;
+use crate::task::cred::Cred;
+use crate::task::signal::{NSIG, SIG_IGN, SigAction, SigSet};
+use crate::task::wait::WaitQueue;
 use crate::mm::{alloc_one_page, alloc_pages, get_kernel_pagetable};
 use crate::mm::addr::{Addr, PageAlign, Vaddr};
 use crate::mm::kmap::KmapToken;
@@ -39,6 +43,9 @@ use crate::task::task::TaskStatus::TaskRunning;
 use crate::trap::TrapFrame;
 
 const MAX_OPENED:usize = 64;
+// 分配内存时不能进行换出I/O，见mm::swap::NoReclaimGuard
+pub const PF_MEMALLOC_NOIO:usize = 1<<0;
+const DEFAULT_UMASK:u32 = 0o022;
 
 extern "C" {
     fn switch_context(cur: *const TaskContext, next: *const TaskContext);
@@ -55,22 +62,37 @@ pub enum TaskStatus {
     TaskZombie,
 }
 
-struct RunningMut(Option<Arc<SpinLock<Task>>>);
+// 正在运行的task以及缺页路径需要的状态
+// 缺页可能发生在持有task锁的syscall中，mm和栈guard在切换时缓存在这里，不需要再加task锁
+struct RunningMut{
+    task:Option<Arc<SpinLock<Task>>>,
+    mm:Option<Arc<SpinLock<MmStruct>>>,
+    guard:usize,
+    flags:Option<Arc<AtomicUsize>>,
+}
 
 impl RunningMut {
     fn new()->Self {
-        RunningMut(None)
+        RunningMut{
+            task: None,
+            mm: None,
+            guard: 0,
+            flags: None
+        }
     }
-    fn set(&mut self,v:Arc<SpinLock<Task>>){
-        self.0 = Some(v);
+    fn set(&mut self,v:Arc<SpinLock<Task>>,tsk:&Task){
+        self.mm = tsk.mm.clone();
+        self.guard = tsk.kernel_stack.get_start()-PAGE_SIZE;
+        self.flags = Some(tsk.flags.clone());
+        self.task = Some(v);
     }
     fn get(&self)->Arc<SpinLock<Task>>{
-        self.0.as_ref().unwrap().clone()
+        self.task.as_ref().unwrap().clone()
     }
     fn clear(&mut self)->Option<Arc<SpinLock<Task>>>{
-        let ret = self.0.clone();
-        self.0 = None;
-        ret
+        self.mm = None;
+        self.flags = None;
+        self.task.take()
     }
 }
 
@@ -79,17 +101,39 @@ lazy_static!{
 }
 
 pub fn set_running(running:Arc<SpinLock<Task>>){
-    RUNNING.lock().unwrap().set(running);
+    let tsk = running.lock_irq().unwrap();
+    RUNNING.lock_irq().unwrap().set(running.clone(),&tsk);
 }
 
 pub fn get_running()->Arc<SpinLock<Task>>{
-    RUNNING.lock().unwrap().get()
+    RUNNING.lock_irq().unwrap().get()
 }
 
 pub fn RUNNING_TASK()->Arc<SpinLock<Task>>{
     get_running()
 }
 
+// 当前task的地址空间，不加task锁，缺页处理使用
+pub fn get_running_mm()->Option<Arc<SpinLock<MmStruct>>>{
+    RUNNING.lock_irq().unwrap().mm.clone()
+}
+
+// execve替换地址空间后更新缓存
+pub fn set_running_mm(mm:Arc<SpinLock<MmStruct>>){
+    RUNNING.lock_irq().unwrap().mm = Some(mm);
+}
+
+// addr是否位于当前task内核栈的guard page中
+pub fn running_in_stack_guard(addr:usize)->bool{
+    let g = RUNNING.lock_irq().unwrap().guard;
+    g != 0 && addr >= g && addr < g+PAGE_SIZE
+}
+
+// 当前task的PF_*标志，启动阶段还没有task时返回None
+pub fn running_flags()->Option<Arc<AtomicUsize>>{
+    RUNNING.lock_irq().unwrap().flags.clone()
+}
+
 #[derive(Clone)]
 #[repr(C)]
 pub struct TaskContext {
@@ -147,15 +191,30 @@ pub struct Task {
     status: TaskStatus,
     pub mm: Option<Arc<SpinLock<MmStruct>>>,
     opened: Vec<Option<Arc<DFile>>>,
-    pwd:String,
-    pub pwd_dfile:Arc<DFile>,
+    // 工作目录
+    cwd:PinnedInode,
     pub set_child_tid: usize,
     pub clear_child_tid: usize,
-    pub exit_code:i32
-}
-
-fn get_init_pwd()->String {
-    String::from("/")
+    pub exit_code:i32,
+    // execve的参数和可执行文件，内核线程为空
+    cmdline:Vec<String>,
+    exe:Option<Arc<Inode>>,
+    cred:Cred,
+    // 创建文件时从mode中去掉的权限位
+    umask:u32,
+    // 已发送还未处理的信号
+    pub pending:SigSet,
+    // 屏蔽的信号，fork和execve时保留
+    pub blocked:SigSet,
+    // 每个信号的处置方式，execve时处理函数恢复为默认
+    pub sigactions:[SigAction;NSIG],
+    // 进程组和会话
+    pgid:usize,
+    sid:usize,
+    // 收到信号时唤醒，signalfd在这里等待
+    pub sig_wait:Arc<WaitQueue>,
+    // PF_*标志，缺页和回收路径通过RUNNING不加锁访问
+    pub flags:Arc<AtomicUsize>,
 }
 
 impl Task {
@@ -165,10 +224,10 @@ impl Task {
         while addr<(boot_stack_top as usize) {
             if sp>addr&&sp<=addr+(PAGE_SIZE*BOOT_STACK_NR_PAGES){
                 // find
-                if sp-addr<=8 {
-                    panic!("can`t insert stack magic for boot thread");
+                // 最低的一页会作为guard page
+                if sp-addr<=PAGE_SIZE {
+                    panic!("can`t insert guard page for boot thread");
                 } else {
-                    unsafe { (addr as *mut u64).write_volatile(STACK_MAGIC); }
                     break;
                 }
             }
@@ -183,11 +242,21 @@ impl Task {
             status: TaskStatus::TaskRunning,
             mm: None,
             opened: vec![None;MAX_OPENED],
-            pwd:get_init_pwd(),
-            pwd_dfile:DFile::get_root(),
+            cwd:PinnedInode::new(Inode::get_root()),
             set_child_tid: 0,
             clear_child_tid: 0,
-            exit_code: 0
+            exit_code: 0,
+            cmdline: Vec::new(),
+            exe: None,
+            cred: Cred::root(),
+            umask: DEFAULT_UMASK,
+            pending: SigSet::empty(),
+            blocked: SigSet::empty(),
+            sigactions: [SigAction::default();NSIG],
+            pgid: 0,
+            sid: 0,
+            sig_wait: Arc::new(WaitQueue::new()),
+            flags: Arc::new(AtomicUsize::new(0))
         };
         sscratch::write(0);
         unsafe {
@@ -205,6 +274,20 @@ impl Task {
     pub fn get_tgid(&self)->usize{
         self.tgid
     }
+    pub fn get_pgid(&self)->usize{
+        self.pgid
+    }
+    pub fn set_pgid(&mut self,pgid:usize){
+        self.pgid = pgid;
+    }
+    pub fn get_sid(&self)->usize{
+        self.sid
+    }
+    // 成为新会话和进程组的leader
+    pub fn set_sid(&mut self){
+        self.sid = self.tgid;
+        self.pgid = self.tgid;
+    }
     pub fn get_parent(&self)->Option<Arc<SpinLock<Task>>>{
         if self.parent.is_none() {
             return None;
@@ -271,8 +354,34 @@ impl Task {
         }
         None
     }
-    pub fn get_pwd_opened(&mut self) ->Arc<DFile>{
-        self.pwd_dfile.clone()
+    // 已打开的fd
+    pub fn opened_fds(&self)->Vec<usize>{
+        (0..self.opened.len()).filter(|i| self.opened[*i].is_some()).collect()
+    }
+    pub fn get_cmdline(&self)->&Vec<String>{
+        &self.cmdline
+    }
+    pub fn get_exe(&self)->Option<Arc<Inode>>{
+        self.exe.clone()
+    }
+    pub fn get_cwd(&self)->Arc<Inode>{
+        self.cwd.get()
+    }
+    pub fn set_cwd(&mut self,cwd:Arc<Inode>){
+        self.cwd = PinnedInode::new(cwd);
+    }
+    pub fn get_cred(&self)->&Cred{
+        &self.cred
+    }
+    pub fn set_cred(&mut self,cred:Cred){
+        self.cred = cred;
+    }
+    pub fn get_umask(&self)->u32{
+        self.umask
+    }
+    // 返回原来的umask
+    pub fn set_umask(&mut self,umask:u32)->u32{
+        core::mem::replace(&mut self.umask,umask & 0o777)
     }
     pub fn is_kern(&self)->bool {
         match self.mm {
@@ -294,11 +403,21 @@ impl Task {
             status: TaskStatus::TaskRunning,
             mm: None,
             opened:vec![None;MAX_OPENED],
-            pwd:get_init_pwd(),
-            pwd_dfile: DFile::get_root(),
+            cwd:PinnedInode::new(Inode::get_root()),
             set_child_tid: 0,
             clear_child_tid: 0,
-            exit_code: 0
+            exit_code: 0,
+            cmdline: Vec::new(),
+            exe: None,
+            cred: Cred::root(),
+            umask: DEFAULT_UMASK,
+            pending: SigSet::empty(),
+            blocked: SigSet::empty(),
+            sigactions: [SigAction::default();NSIG],
+            pgid: 0,
+            sid: 0,
+            sig_wait: Arc::new(WaitQueue::new()),
+            flags: Arc::new(AtomicUsize::new(0))
         };
         tsk.context.ra = kern_trap_ret as usize;
         unsafe { tsk.context.sp = tsk.kernel_stack.get_end() - size_of::<TrapFrame>(); }
@@ -317,16 +436,26 @@ impl Task {
         let tsk = tsk.lock_irq().unwrap();
         let old_mm = self.mm.as_ref().unwrap().clone();
         self.mm = Some(tsk.mm.as_ref().unwrap().clone());
-        for i in 0..tsk.opened.len(){
-            self.set_opened(i,tsk.opened[i].as_ref().map(|x|{x.clone()}));
+        // 保留打开的文件，只关闭设置了close-on-exec的
+        for i in 0..self.opened.len(){
+            if self.opened[i].as_ref().map_or(false,|f| f.get_cloexec()) {
+                self.opened[i] = None;
+            }
+        }
+        self.cmdline = tsk.cmdline.clone();
+        self.exe = tsk.exe.clone();
+        self.cred = tsk.cred.clone();
+        for act in self.sigactions.iter_mut() {
+            if act.handler != SIG_IGN {
+                *act = SigAction::default();
+            }
         }
-        self.pwd_dfile = tsk.pwd_dfile.clone();
-        self.pwd = tsk.pwd.clone();
 
         old_mm
     }
 
-    pub unsafe fn create_user_task(path:&str,args:Vec<String>)->Option<Arc<SpinLock<Task>>>{
+    // cred是execve之后的身份，已经处理了setuid/setgid
+    pub unsafe fn create_user_task(path:&str,args:Vec<String>,cred:Cred)->Option<Arc<SpinLock<Task>>>{
         let node = match Inode::get_root().get_node_by_path(path) {
             Some(node)=> {
                 node
@@ -335,32 +464,58 @@ impl Task {
                 return None;
             }
         };
-        let file_len = node.get_dentry().len() as usize;
+        let file_len = node.get_size();
         let kmap_len = Vaddr(file_len).ceil().get_inner();
         let kmap_token = KmapToken::new_file(kmap_len,
                                              node.clone(),0, file_len).unwrap();
         let read_buf = kmap_token.get_buf();
         let cnt = kmap_token.get_len();
         let (mm_struct, mut auxv, entry_point) = MmStruct::new_from_elf(&(*read_buf)[..cnt],node.clone());
+        for aux in auxv.iter_mut() {
+            aux.value = match aux.aux_type {
+                AT_UID => cred.uid as usize,
+                AT_EUID => cred.euid as usize,
+                AT_GID => cred.gid as usize,
+                AT_EGID => cred.egid as usize,
+                // 动态链接器据此忽略LD_*环境变量
+                AT_SECURE => cred.is_setid() as usize,
+                _ => aux.value
+            };
+        }
         // kamp 会占用kernel pagetable 使用期间不能够切换页表
         drop(kmap_token);
 
         trace_sync!("New User Task: entry point={:#X}",entry_point);
+        // 作为新进程运行时是自己会话和进程组的leader，execve只取用其中的地址空间等
+        let tid = generate_tid();
         let mut tsk = Task {
-            tid: generate_tid(),
-            tgid: 0,
+            tid,
+            tgid: tid,
             kernel_stack: Stack::new(false,0,0),
             context: TaskContext::new(),
             parent: None,
             status: TaskStatus::TaskRunning,
             mm: Some(Arc::new(SpinLock::new(mm_struct))),
             opened:vec![None;MAX_OPENED],
-            pwd:get_init_pwd(),
-            pwd_dfile: DFile::get_root(),
+            cwd:PinnedInode::new(Inode::get_root()),
             set_child_tid: 0,
             clear_child_tid: 0,
-            exit_code: 0
+            exit_code: 0,
+            cmdline: Vec::new(),
+            exe: None,
+            cred: Cred::root(),
+            umask: DEFAULT_UMASK,
+            pending: SigSet::empty(),
+            blocked: SigSet::empty(),
+            sigactions: [SigAction::default();NSIG],
+            pgid: tid,
+            sid: tid,
+            sig_wait: Arc::new(WaitQueue::new()),
+            flags: Arc::new(AtomicUsize::new(0))
         };
+        tsk.cmdline = args.clone();
+        tsk.exe = Some(node.clone());
+        tsk.cred = cred;
         tsk.opened[0] = Some(Arc::new(DFile::new_stdin()));
         tsk.opened[1] = Some(Arc::new(DFile::new_stdout()));
         tsk.opened[2] = Some(Arc::new(DFile::new_stderr()));
@@ -531,40 +686,31 @@ impl Task {
         drop(mm);
         Some(Arc::new(SpinLock::new(tsk)))
     }
+    // 第一个用户进程是控制台所在会话的首进程
     pub unsafe fn create_user_task_and_run(path:&str,args:Vec<String>)->Result<(),()>{
-        add_task(
-            match Self::create_user_task(path,args) {
-                None => {
-                    return Err(());
-                }
-                Some(tsk) => {
-                    tsk
-                }
+        let tsk = match Self::create_user_task(path,args,Cred::root()) {
+            None => {
+                return Err(());
+            }
+            Some(tsk) => {
+                tsk
             }
-        );
+        };
+        let tid = tsk.lock_irq().unwrap().tid;
+        console_tty().set_session(tid,tid);
+        add_task(tsk);
         Ok(())
     }
-    pub fn pwd_mut_ref(&mut self)->&mut String{
-        &mut self.pwd
-    }
-    pub fn pwd_ref(& self)->&String{
-        &self.pwd
-    }
     pub fn get_ctx_mut_ref(&mut self)->&mut TaskContext{
         &mut self.context
     }
-    pub unsafe fn check_magic(&self){
-        if !self.kernel_stack._check_magic(){
-            error_sync!("stack overflow");
-            shutdown();
-        }
-    }
     pub unsafe fn install_pagetable(&self) {
         self.mm.as_ref().unwrap().lock_irq().unwrap().install_pagetable();
     }
     // fork一个cow的新进程
     // 但是结束后需要手动填充parent
-    fn __vfork_step_one(&self, mut tf:TrapFrame) ->Self{
+    fn __vfork_step_one(&self, mut tf:TrapFrame) ->Result<Self,isize>{
+        let mm = new_mm_by_old(self.mm.as_ref().unwrap().clone())?;
         let new_tid = generate_tid();
         let mut new_tsk = Self{
             tid: new_tid,
@@ -573,13 +719,23 @@ impl Task {
             context: self.context.clone(),
             parent: None,
             status: TaskStatus::TaskRunning,
-            mm: Some(Arc::new(SpinLock::new(new_mm_by_old(self.mm.as_ref().unwrap().clone())))),
+            mm: Some(Arc::new(SpinLock::new(mm))),
             opened: vec![],
-            pwd: self.pwd.clone(),
-            pwd_dfile: self.pwd_dfile.clone(),
+            cwd: self.cwd.clone(),
             set_child_tid: 0,
             clear_child_tid: 0,
-            exit_code: 0
+            exit_code: 0,
+            cmdline: self.cmdline.clone(),
+            exe: self.exe.clone(),
+            cred: self.cred.clone(),
+            umask: self.umask,
+            pending: SigSet::empty(),
+            blocked: self.blocked,
+            sigactions: self.sigactions,
+            pgid: self.pgid,
+            sid: self.sid,
+            sig_wait: Arc::new(WaitQueue::new()),
+            flags: Arc::new(AtomicUsize::new(0))
         };
         // clone opened fd table
         for i in 0..self.opened.len(){
@@ -598,14 +754,14 @@ impl Task {
         // shutdown();
         new_tsk.context.ra = user_trap_ret as usize;
         new_tsk.context.sp = new_kstack_top;
-        new_tsk
+        Ok(new_tsk)
     }
 }
 
-pub fn do_fork(tsk:Arc<SpinLock<Task>>,tf:TrapFrame)->Task{
-    let mut new = tsk.lock_irq().unwrap().__vfork_step_one(tf);
+pub fn do_fork(tsk:Arc<SpinLock<Task>>,tf:TrapFrame)->Result<Task,isize>{
+    let mut new = tsk.lock_irq().unwrap().__vfork_step_one(tf)?;
     new.parent = Some(Arc::downgrade(&tsk));
-    new
+    Ok(new)
 }
 
 pub fn task_cpu_init(){
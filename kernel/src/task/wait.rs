@@ -0,0 +1,143 @@
+use alloc::boxed::Box;
+use alloc::collections::LinkedList;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::default::default;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::{SpinLock, Task};
+use crate::asm::{disable_irq, enable_irq};
+use crate::task::{add_task, scheduler};
+use crate::task::task::get_running;
+use crate::task::task::TaskStatus::{TaskRunning, TaskSleeping};
+use crate::trap::timer::{add_timer, del_timer, get_time_ns};
+
+// 等待事件的一方，可以同时挂在多个WaitQueue上
+// 先prepare并注册，再检查条件，条件不满足时sleep，期间的唤醒不会丢失
+pub struct Waiter {
+    woken:AtomicBool,
+    // sleep中的task，最多一个
+    park:SpinLock<LinkedList<Arc<SpinLock<Task>>>>,
+    // 唤醒时的回调，epoll用它记录就绪的项
+    notify:Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl Waiter {
+    pub fn new()->Arc<Self>{
+        Arc::new(Self{
+            woken: AtomicBool::new(false),
+            park: SpinLock::new(default()),
+            notify: None
+        })
+    }
+    pub fn with_notify(f:Box<dyn Fn() + Send + Sync>)->Arc<Self>{
+        Arc::new(Self{
+            woken: AtomicBool::new(false),
+            park: SpinLock::new(default()),
+            notify: Some(f)
+        })
+    }
+    // 开始新一轮等待
+    pub fn prepare(&self){
+        self.woken.store(false,Ordering::SeqCst);
+    }
+    pub fn is_woken(&self)->bool{
+        self.woken.load(Ordering::SeqCst)
+    }
+    // 取得并清除唤醒标志
+    pub fn take_woken(&self)->bool{
+        self.woken.swap(false,Ordering::SeqCst)
+    }
+    pub fn wake(&self){
+        self.woken.store(true,Ordering::SeqCst);
+        if let Some(f) = &self.notify {
+            f();
+        }
+        loop {
+            let t = match self.park.lock_irq().unwrap().pop_front() {
+                None => {
+                    return;
+                }
+                Some(t) => t
+            };
+            t.lock_irq().unwrap().set_status(TaskRunning);
+            add_task(t);
+        }
+    }
+    // prepare之后已经被唤醒时直接返回
+    // 检查和sleep之间关中断，单核上唤醒者不会在中间运行
+    pub fn sleep(&self){
+        let irq = disable_irq();
+        if !self.is_woken() {
+            get_running().lock_irq().unwrap().set_status(TaskSleeping);
+            scheduler(Some(&self.park));
+        }
+        enable_irq(irq);
+    }
+    // deadline是开机以来的纳秒，返回是否超时
+    pub fn sleep_until(self:&Arc<Self>,deadline:Option<u64>)->bool{
+        let deadline = match deadline {
+            None => {
+                self.sleep();
+                return false;
+            }
+            Some(d) => d
+        };
+        if deadline <= get_time_ns() {
+            return true;
+        }
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let (w,t) = (self.clone(),timed_out.clone());
+        let timer = add_timer(deadline,Box::new(move ||{
+            t.store(true,Ordering::SeqCst);
+            w.wake();
+        }));
+        self.sleep();
+        del_timer(timer);
+        timed_out.load(Ordering::SeqCst)
+    }
+}
+
+// 等待某个对象的Waiter，只保存弱引用，Waiter释放后自动移出
+pub struct WaitQueue {
+    waiters:SpinLock<Vec<Weak<Waiter>>>,
+}
+
+impl WaitQueue {
+    pub fn new()->Self{
+        Self{
+            waiters: SpinLock::new(Vec::new())
+        }
+    }
+    // 重复加入同一个Waiter只保留一项
+    pub fn add(&self,waiter:&Arc<Waiter>){
+        let mut waiters = self.waiters.lock_irq().unwrap();
+        waiters.retain(|w| w.strong_count() != 0);
+        let p = Arc::as_ptr(waiter);
+        if !waiters.iter().any(|w| w.as_ptr() == p) {
+            waiters.push(Arc::downgrade(waiter));
+        }
+    }
+    // 在锁外唤醒，回调中可能访问其他WaitQueue
+    pub fn wake_all(&self){
+        let waiters:Vec<Arc<Waiter>> = {
+            let mut waiters = self.waiters.lock_irq().unwrap();
+            waiters.retain(|w| w.strong_count() != 0);
+            waiters.iter().filter_map(|w| w.upgrade()).collect()
+        };
+        for w in waiters {
+            w.wake();
+        }
+    }
+    // 等待cond成立，cond在每次唤醒后重新检查
+    pub fn wait_until(&self,cond:impl Fn()->bool){
+        let waiter = Waiter::new();
+        loop {
+            waiter.prepare();
+            self.add(&waiter);
+            if cond() {
+                return;
+            }
+            waiter.sleep();
+        }
+    }
+}
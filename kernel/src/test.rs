@@ -9,10 +9,10 @@ use xmas_elf::ElfFile;
 use crate::io::virtio::{virtio_test, VirtioDev};
 use crate::mm::addr::{OldAddr, PageAlign, Vaddr};
 use crate::mm::{alloc_pages, get_kernel_pagetable, mm_test};
+use crate::fs::fs_test;
 use crate::{info_sync, println, SpinLock, Task};
 use crate::asm::{enable_irq, r_sstatus, SSTATUS_SIE};
 use crate::fs::dfile::DFileClass::ClassInode;
-use crate::fs::fat::get_fatfs;
 use crate::fs::inode::Inode;
 use crate::fs::pipe::Pipe;
 use crate::io::BlockRead;
@@ -21,10 +21,12 @@ use crate::mm::kmap::KmapToken;
 use crate::mm::mm::MmStruct;
 use crate::pre::InnerAccess;
 use crate::sbi::shutdown;
+use crate::utils::time_test;
+use crate::task::cred::cred_test;
 
 unsafe fn test_kmap(){
     let node = Inode::get_root().get_sub_node("2.txt").unwrap();
-    let file_len = node.get_dentry().len() as usize;
+    let file_len = node.get_size();
     let token = KmapToken::new_file(Vaddr(file_len).ceil().0,node,0,file_len-40).unwrap();
     let vaddr = token.get_vaddr();
     let v = vaddr.get_inner() as *const u8;
@@ -51,8 +53,9 @@ fn reader(){
     info_sync!("start reader!");
     let mut pipe = g_pipe.clone();
     pipe.0.inc_write();
+    pipe.0.inc_read();
     let mut buf = [0u8;20];
-    pipe.0.read_exact(&mut buf);
+    pipe.0.read(&mut buf,false);
     println!("{:?}",buf);
     loop {
 
@@ -63,7 +66,7 @@ fn writer(){
     info_sync!("start writer!");
     let pipe = g_pipe.clone();
     let buf = [1u8;19];
-    pipe.0.write_exact(&buf);
+    pipe.0.write(&buf,false);
     println!("{:?}",buf);
     pipe.0.dec_write();
     loop {
@@ -84,6 +87,9 @@ fn test_pipe(){
 pub unsafe fn do_test(){
     // test_pipe();
     // mm_test();
+    // time_test();
+    // cred_test();
+    // Task::create_kern_task_and_run(fs_test);
     // test_kmap();
     // Task::create_user_task_and_run("clone",vec![]);
     // virtio_test();
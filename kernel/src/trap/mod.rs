@@ -1,4 +1,5 @@
 pub mod timer;
+pub mod plic;
 
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
@@ -11,9 +12,9 @@ use riscv::register::{sie, sstatus, stvec, scause};
 use riscv::register::scause::{Exception, Interrupt, Scause, Trap};
 use riscv::register::sstatus::Sstatus;
 use riscv::register::stvec::TrapMode;
-use crate::{debug_sync, info_sync, print, println, r_sstatus, trace_sync, warn_sync};
+use crate::{debug_sync, error_sync, info_sync, print, println, r_sstatus, trace_sync, warn_sync};
 use crate::asm::{disable_irq, enable_irq, r_satp, r_scause, r_stval, SSTATUS_SPP};
-use crate::consts::PHY_MEM_OFFSET;
+use crate::consts::{PHY_MEM_OFFSET, USER_SPACE_END};
 use crate::mm::{alloc_one_page, get_kernel_mm, get_kernel_pagetable};
 use crate::mm::addr::{Paddr, PageAlign, Vaddr};
 use crate::mm::page::Page;
@@ -22,7 +23,8 @@ use crate::mm::vma::{_vma_flags_2_pte_flags, MmapProt, VMA, VmFlags};
 use crate::pre::{InnerAccess, ReadWriteSingleNoOff, ShowRdWrEx};
 use crate::sbi::shutdown;
 use crate::syscall::syscall_entry;
-use crate::task::task::{get_running, RUNNING_TASK};
+use crate::task::task::{get_running, get_running_mm, running_in_stack_guard, RUNNING_TASK};
+use crate::trap::plic::plic_handle_irq;
 use crate::trap::timer::timer_entry;
 use crate::utils::{memcpy, set_usize_by_addr};
 global_asm!(include_str!("trap_asm.s"));
@@ -160,7 +162,6 @@ impl TrapFrame {
 
 #[no_mangle]
 fn irq_handler(trap_frame:&mut TrapFrame){
-    unsafe { RUNNING_TASK().lock_irq().unwrap().check_magic(); }
     // trace_sync!("IRQ\n{:?}",trap_frame);
     match scause::read().cause() {
         Trap::Interrupt(irq) => {
@@ -182,7 +183,7 @@ fn irq_handler(trap_frame:&mut TrapFrame){
                     todo!()
                 }
                 Interrupt::SupervisorExternal => {
-                    todo!()
+                    plic_handle_irq();
                 }
                 Interrupt::Unknown => {
                     panic!("unrecognized interrupt");
@@ -202,7 +203,14 @@ fn exc_handler(trap_frame:&mut TrapFrame){
     let spp = r_sstatus()&SSTATUS_SPP;
 
     debug_sync!("spp:{}",spp);
-    unsafe { RUNNING_TASK().lock_irq().unwrap().check_magic(); }
+    // 内核态访问guard page说明内核栈溢出
+    // 内核态异常时task锁可能已被持有，guard page范围从RUNNING中读取
+    let stval = r_stval();
+    if spp != 0 && matches!(scause::read().cause(),
+        Trap::Exception(Exception::LoadPageFault|Exception::StorePageFault))
+        && running_in_stack_guard(stval) {
+        kernel_stack_overflow(trap_frame.sepc,stval);
+    }
     match scause::read().cause() {
         Trap::Exception(exc) => {
             unsafe {
@@ -265,6 +273,13 @@ fn exc_handler(trap_frame:&mut TrapFrame){
     enable_irq(irq_state);
 }
 
+// trap_kern检查到栈溢出后在overflow stack上调用
+#[no_mangle]
+fn kernel_stack_overflow(sepc:usize, stval:usize)->!{
+    error_sync!("kernel stack overflow, sepc:{:#X}, stval:{:#X}",sepc,stval);
+    shutdown();
+}
+
 pub fn trap_init(){
     extern "C" { fn trap_entry(); }
     unsafe {
@@ -285,10 +300,11 @@ enum PgFaultProt{
 #[cfg(not(feature = "copy_on_write"))]
 fn trap_page_fault_handler(vaddr:Vaddr,prot:PgFaultProt) ->bool {
     let v = vaddr.floor();
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
+    // syscall读写用户缓冲区时可能缺页(lazy alloc或者已被换出)，此时task锁可能已经被syscall持有
+    // 所以不从task中取mm，使用切换时缓存的地址空间
+    let user_mm = get_running_mm();
     let mut mm = get_kernel_mm();
-    let is_kern = r_sstatus()&SSTATUS_SPP!=0;
+    let is_kern = r_sstatus()&SSTATUS_SPP!=0 && !(user_mm.is_some() && v.get_inner()<USER_SPACE_END);
     if is_kern{
         let vma_opt = mm.find_vma(v);
         match vma_opt{
@@ -306,7 +322,7 @@ fn trap_page_fault_handler(vaddr:Vaddr,prot:PgFaultProt) ->bool {
             }
         }
     } else {
-        let mut mm_locked = tsk.mm.as_mut().unwrap().lock_irq().unwrap();
+        let mut mm_locked = user_mm.as_ref().unwrap().lock_irq().unwrap();
         let mut vma_opt = mm_locked.find_vma(v);
         match vma_opt{
             None => {
@@ -340,10 +356,11 @@ fn trap_page_fault_handler(vaddr:Vaddr,prot:PgFaultProt) ->bool{
         }
     }
     let v = vaddr.floor();
-    let running = get_running();
-    let mut tsk = running.lock_irq().unwrap();
+    // syscall读写用户缓冲区时可能缺页(lazy alloc或者已被换出)，此时task锁可能已经被syscall持有
+    // 所以不从task中取mm，使用切换时缓存的地址空间
+    let user_mm = get_running_mm();
     let mut mm = get_kernel_mm();
-    let is_kern = r_sstatus()&SSTATUS_SPP!=0;
+    let is_kern = r_sstatus()&SSTATUS_SPP!=0 && !(user_mm.is_some() && v.get_inner()<USER_SPACE_END);
     if is_kern{
         let vma_opt = mm.find_vma(v);
         match vma_opt{
@@ -372,7 +389,7 @@ fn trap_page_fault_handler(vaddr:Vaddr,prot:PgFaultProt) ->bool{
             }
         }
     } else {
-        let mut tsk_mm = tsk.mm.as_mut().unwrap().lock_irq().unwrap();
+        let mut tsk_mm = user_mm.as_ref().unwrap().lock_irq().unwrap();
         let is_cow = tsk_mm.cow_target.is_some();
         if is_cow {
             let mut cow_mm_nolock = tsk_mm.cow_target.as_ref().unwrap().clone();
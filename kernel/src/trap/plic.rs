@@ -0,0 +1,156 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use riscv::register::sie;
+use crate::consts::{CPUS, DEV_REMAP_START};
+use crate::fdt::get_fdt_info;
+use crate::sync::cpu_local::get_core_id;
+use crate::syscall::errno::{EBUSY, EINVAL, ENODEV};
+use crate::{info_sync, warn_sync, SpinLock};
+
+// 平台级中断控制器
+// qemu virt上hart的context 2*hart是M态，2*hart+1是S态，内核只使用S态context
+const PLIC_PRIORITY:usize = 0x0;
+const PLIC_ENABLE:usize = 0x2000;
+const PLIC_ENABLE_STRIDE:usize = 0x80;
+const PLIC_CONTEXT:usize = 0x200000;
+const PLIC_CONTEXT_STRIDE:usize = 0x1000;
+const PLIC_THRESHOLD:usize = 0x0;
+const PLIC_CLAIM:usize = 0x4;
+// 中断源0保留
+pub const PLIC_MAX_IRQ:usize = 1023;
+// 注册的中断默认优先级，threshold为0时大于0的优先级都会被接收
+const IRQ_DEFAULT_PRIORITY:u32 = 1;
+
+// 在中断上下文中调用，不能sleep
+pub type IrqHandler = Arc<dyn Fn() + Send + Sync>;
+
+lazy_static!{
+    static ref IRQ_HANDLERS:SpinLock<BTreeMap<usize,IrqHandler>> = SpinLock::new(BTreeMap::new());
+}
+
+// 没有plic时为None
+fn plic_base()->Option<usize>{
+    let base = get_fdt_info().plic_base;
+    if base == 0 { None } else { Some(base+DEV_REMAP_START) }
+}
+
+fn s_context(hart:usize)->usize{
+    2*hart+1
+}
+
+unsafe fn read_reg(addr:usize)->u32{
+    (addr as *const u32).read_volatile()
+}
+
+unsafe fn write_reg(addr:usize,v:u32){
+    (addr as *mut u32).write_volatile(v)
+}
+
+// 优先级为0的中断不会被送达
+pub fn plic_set_priority(irq:usize,priority:u32){
+    if let Some(base) = plic_base() {
+        unsafe { write_reg(base+PLIC_PRIORITY+4*irq,priority); }
+    }
+}
+
+fn enable_reg(base:usize,hart:usize,irq:usize)->usize{
+    base+PLIC_ENABLE+PLIC_ENABLE_STRIDE*s_context(hart)+4*(irq/32)
+}
+
+// 允许irq发送到hart的S态context
+pub fn plic_enable(hart:usize,irq:usize){
+    if let Some(base) = plic_base() {
+        let reg = enable_reg(base,hart,irq);
+        unsafe { write_reg(reg,read_reg(reg) | (1 << (irq%32))); }
+    }
+}
+
+pub fn plic_disable(hart:usize,irq:usize){
+    if let Some(base) = plic_base() {
+        let reg = enable_reg(base,hart,irq);
+        unsafe { write_reg(reg,read_reg(reg) & !(1 << (irq%32))); }
+    }
+}
+
+fn context_reg(base:usize,hart:usize,off:usize)->usize{
+    base+PLIC_CONTEXT+PLIC_CONTEXT_STRIDE*s_context(hart)+off
+}
+
+pub fn plic_set_threshold(hart:usize,threshold:u32){
+    if let Some(base) = plic_base() {
+        unsafe { write_reg(context_reg(base,hart,PLIC_THRESHOLD),threshold); }
+    }
+}
+
+// 取得优先级最高的待处理中断，没有时为0
+fn plic_claim(base:usize,hart:usize)->usize{
+    unsafe { read_reg(context_reg(base,hart,PLIC_CLAIM)) as usize }
+}
+
+fn plic_complete(base:usize,hart:usize,irq:usize){
+    unsafe { write_reg(context_reg(base,hart,PLIC_CLAIM),irq as u32); }
+}
+
+// 当前hart接收所有已经允许的中断，每个hart启动时调用
+pub fn plic_init_hart(){
+    if plic_base().is_none() {
+        return;
+    }
+    plic_set_threshold(get_core_id(),0);
+    unsafe { sie::set_sext(); }
+    info_sync!("plic init on hart {}",get_core_id());
+}
+
+// 中断只发送到注册时所在的hart
+pub fn register_irq(irq:usize,handler:IrqHandler)->Result<(),isize>{
+    if plic_base().is_none() {
+        return Err(-ENODEV);
+    }
+    if irq == 0 || irq > PLIC_MAX_IRQ {
+        return Err(-EINVAL);
+    }
+    let mut handlers = IRQ_HANDLERS.lock_irq().unwrap();
+    if handlers.contains_key(&irq) {
+        return Err(-EBUSY);
+    }
+    handlers.insert(irq,handler);
+    drop(handlers);
+    plic_set_priority(irq,IRQ_DEFAULT_PRIORITY);
+    plic_enable(get_core_id(),irq);
+    Ok(())
+}
+
+pub fn unregister_irq(irq:usize){
+    for hart in 0..CPUS {
+        plic_disable(hart,irq);
+    }
+    plic_set_priority(irq,0);
+    IRQ_HANDLERS.lock_irq().unwrap().remove(&irq);
+}
+
+// S态外部中断入口，处理完所有已经pending的中断
+// 没有处理函数的中断被关闭，避免反复触发
+pub fn plic_handle_irq(){
+    let base = match plic_base() {
+        Some(b) => b,
+        None => {
+            return;
+        }
+    };
+    let hart = get_core_id();
+    loop {
+        let irq = plic_claim(base,hart);
+        if irq == 0 {
+            break;
+        }
+        let handler = IRQ_HANDLERS.lock_irq().unwrap().get(&irq).cloned();
+        match handler {
+            Some(h) => h(),
+            None => {
+                warn_sync!("unexpected irq {} on hart {}",irq,hart);
+                plic_disable(hart,irq);
+            }
+        }
+        plic_complete(base,hart,irq);
+    }
+}
@@ -1,7 +1,11 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::cmp::max;
+use core::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use log::info;
 use riscv::register::time;
-use crate::info_sync;
+use crate::{info_sync, SpinLock};
+use crate::io::tty::console_poll;
 use crate::sbi::set_timer;
 use crate::task::scheduler;
 use crate::trap::TrapFrame;
@@ -10,11 +14,21 @@ const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
 const CLOCK_FREQ: usize = 12500000;
 const TIC_MAX: usize = 10;
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
 
 lazy_static!{
     static ref tic_counter:AtomicUsize = AtomicUsize::new(0);
+    // 墙上时间与开机以来时间的差，由RTC初始化，settimeofday修改
+    static ref realtime_offset:AtomicI64 = AtomicI64::new(0);
+    // 按到期时间排序的内核定时器，id区分同时到期的定时器
+    static ref timers:SpinLock<BTreeMap<TimerId,Box<dyn FnOnce() + Send>>> = SpinLock::new(BTreeMap::new());
+    static ref timer_id:AtomicUsize = AtomicUsize::new(0);
 }
 
+// (到期时间，id)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TimerId(u64,usize);
+
 fn tic()->bool{
     let cnt = tic_counter.fetch_add(1, Ordering::SeqCst);
     if cnt==TIC_MAX{
@@ -29,10 +43,65 @@ fn get_time() -> usize {
     time::read()
 }
 
-fn get_time_ms() -> usize {
+pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+// 开机以来的纳秒，分开计算避免乘法溢出
+pub fn get_time_ns() -> u64 {
+    let t = get_time() as u64;
+    let freq = CLOCK_FREQ as u64;
+    t / freq * NSEC_PER_SEC + t % freq * NSEC_PER_SEC / freq
+}
+
+pub fn get_time_res_ns() -> u64 {
+    max(NSEC_PER_SEC / CLOCK_FREQ as u64, 1)
+}
+
+// unix纪元以来的纳秒
+pub fn get_realtime_ns() -> u64 {
+    max(realtime_offset.load(Ordering::SeqCst) + get_time_ns() as i64, 0) as u64
+}
+
+pub fn set_realtime_ns(ns: u64) {
+    realtime_offset.store(ns as i64 - get_time_ns() as i64, Ordering::SeqCst);
+}
+
+// 文件时间戳
+pub fn get_realtime_sec() -> u64 {
+    get_realtime_ns() / NSEC_PER_SEC
+}
+
+// deadline是开机以来的纳秒，到期后在时钟中断中调用f，精度为一个tick
+pub fn add_timer(deadline:u64,f:Box<dyn FnOnce() + Send>)->TimerId{
+    let id = TimerId(deadline,timer_id.fetch_add(1,Ordering::SeqCst));
+    timers.lock_irq().unwrap().insert(id,f);
+    id
+}
+
+// 已经到期的定时器不受影响
+pub fn del_timer(id:TimerId){
+    timers.lock_irq().unwrap().remove(&id);
+}
+
+// 回调在锁外调用，可以再添加定时器
+fn run_timers(){
+    let now = get_time_ns();
+    loop {
+        let f = {
+            let mut t = timers.lock_irq().unwrap();
+            let id = match t.keys().next() {
+                Some(id) if id.0 <= now => *id,
+                _ => {
+                    return;
+                }
+            };
+            t.remove(&id).unwrap()
+        };
+        f();
+    }
+}
+
 fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
@@ -43,6 +112,9 @@ pub fn timer_startup(){
 
 pub fn timer_entry(trap_frame:&mut TrapFrame){
     set_next_trigger();
+    run_timers();
+    // 没有串口接收中断时每个时钟中断轮询一次控制台输入
+    console_poll();
     if tic() {
         scheduler(None);
     }
@@ -12,6 +12,19 @@ trap_entry:
   csrrw sp, sscratch ,sp
 
 trap_kern:
+    # 内核栈按32KB(KERNEL_STACK_SIZE)对齐，由mm_init对齐buddy的起始地址保证，最低的一页是guard page
+    # 剩余空间不足以保存trap frame时说明栈溢出，不能继续使用当前栈
+    # sscratch在内核态为0，借用来保存t0
+    csrw sscratch, t0
+    addi t0, sp, -1
+    slli t0, t0, 64-15
+    srli t0, t0, 64-15
+    addi t0, t0, -35*8
+    srai t0, t0, 12
+    blez t0, .kern_stack_overflow
+    csrr t0, sscratch
+    csrw sscratch, x0
+
     addi sp, sp, -35*8
     sd x1, 1*8(sp)
     sd x2, 2*8(sp)
@@ -73,6 +86,18 @@ trap_kern:
     j kern_trap_ret
   .kern_exc_handler:
     jal exc_handler
+    j kern_trap_ret
+
+  .kern_stack_overflow:
+    # 切换到当前hart的overflow stack，不再返回
+    csrw sscratch, x0
+    la sp, overflow_stack
+    addi t0, tp, 1
+    slli t0, t0, 12
+    add sp, sp, t0
+    csrr a0, sepc
+    csrr a1, stval
+    call kernel_stack_overflow
 
   kern_trap_ret:
 
@@ -240,3 +265,8 @@ user_trap_ret:
   addi sp, sp, 35*8
   csrrw sp, sscratch ,sp
   sret
+
+    .section .bss
+    .align 12
+overflow_stack:
+    .space 4096 * 2
@@ -1,11 +1,12 @@
 use alloc::string::String;
 use core::ptr::{addr_of, addr_of_mut};
-use fatfs::{Date, DateTime};
+use fatfs::{Date, DateTime, Time};
 use log::error;
 use xmas_elf::header::Data;
 use crate::consts::{DIRECT_MAP_START, MAX_ORDER, PAGE_OFFSET, PAGE_SIZE};
 use crate::mm::addr::Vaddr;
 use crate::pre::InnerAccess;
+use crate::info_sync;
 
 pub fn addr_page_align_upper(addr:usize) ->usize{
     let mut ret = addr-(addr&PAGE_SIZE);
@@ -92,18 +93,80 @@ pub fn convert_cstr_from_vaddr(vaddr:Vaddr)->String{
 }
 
 const D_SECOND:u64 = 3600*24;
-const M_SECOND:u64 = D_SECOND*30;
-const Y_SECOND:u64 = D_SECOND*365;
+// FAT的时间范围
+const FAT_YEAR_MIN:i64 = 1980;
+const FAT_YEAR_MAX:i64 = 2107;
+
+// 公历日期与1970-01-01以来的天数互相转换
+fn days_from_civil(y:i64,m:u32,d:u32)->i64{
+    let y = if m <= 2 { y-1 } else { y };
+    let era = (if y >= 0 { y } else { y-399 })/400;
+    let yoe = (y-era*400) as u64;
+    // 从三月开始计月
+    let mp = (m as u64+9)%12;
+    let doy = (153*mp+2)/5+d as u64-1;
+    let doe = yoe*365+yoe/4-yoe/100+doy;
+    era*146097+doe as i64-719468
+}
+
+fn civil_from_days(z:i64)->(i64,u32,u32){
+    let z = z+719468;
+    let era = (if z >= 0 { z } else { z-146096 })/146097;
+    let doe = (z-era*146097) as u64;
+    let yoe = (doe-doe/1460+doe/36524-doe/146096)/365;
+    let doy = doe-(365*yoe+yoe/4-yoe/100);
+    let mp = (5*doy+2)/153;
+    let d = (doy-(153*mp+2)/5+1) as u32;
+    let m = (if mp < 10 { mp+3 } else { mp-9 }) as u32;
+    let y = yoe as i64+era*400;
+    (if m <= 2 { y+1 } else { y },m,d)
+}
 
+// FAT中的时间按UTC处理
 pub fn date2second(d:Date)->u64{
-    let y = d.year - 1970;
-    let m = d.month - 1;
-    let d = d.day - 1;
-    y as u64*Y_SECOND + m as u64 *M_SECOND + d  as u64 *D_SECOND
+    days_from_civil(d.year as i64,d.month as u32,d.day as u32) as u64*D_SECOND
 }
 
 pub fn datetime2second(d:DateTime)->u64{
     let datesecond = date2second(d.date);
     let timesecond = d.time.hour as u64 * 3600 + d.time.min as u64 * 60 + d.time.sec as u64;
     datesecond + timesecond
-}
\ No newline at end of file
+}
+
+// 超出FAT范围的时间取最近的边界
+pub fn second2datetime(s:u64)->DateTime{
+    let (y,m,d) = civil_from_days((s/D_SECOND) as i64);
+    if y < FAT_YEAR_MIN {
+        return DateTime::new(Date::new(1980,1,1),Time::new(0,0,0,0));
+    }
+    if y > FAT_YEAR_MAX {
+        return DateTime::new(Date::new(2107,12,31),Time::new(23,59,59,0));
+    }
+    let t = s%D_SECOND;
+    DateTime::new(Date::new(y as u16,m as u16,d as u16),
+                  Time::new((t/3600) as u16,(t/60%60) as u16,(t%60) as u16,0))
+}
+
+fn datetime_tuple(d:DateTime)->(u16,u16,u16,u16,u16,u16){
+    (d.date.year,d.date.month,d.date.day,d.time.hour,d.time.min,d.time.sec)
+}
+
+// 公历转换和FAT时间范围
+pub fn time_test(){
+    assert_eq!(days_from_civil(1970,1,1),0);
+    assert_eq!(days_from_civil(2000,3,1),11017);
+    assert_eq!(days_from_civil(1969,12,31),-1);
+    assert_eq!(civil_from_days(11016),(2000,2,29));
+    assert_eq!(civil_from_days(19782),(2024,2,29));
+    assert_eq!(civil_from_days(-1),(1969,12,31));
+
+    let s = 1623760496;
+    let dt = second2datetime(s);
+    assert_eq!(datetime_tuple(dt),(2021,6,15,12,34,56));
+    assert_eq!(datetime2second(dt),s);
+    assert_eq!(date2second(dt.date),s-(12*3600+34*60+56));
+    // 超出范围时取边界
+    assert_eq!(datetime_tuple(second2datetime(0)),(1980,1,1,0,0,0));
+    assert_eq!(datetime_tuple(second2datetime(u64::MAX/2)),(2107,12,31,23,59,59));
+    info_sync!("time test OK!");
+}